@@ -0,0 +1,393 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use cornifer::errors::{CorniferError, ErrorKind};
+use cornifer::sink::HashAlgorithm;
+use thiserror::Error;
+
+/*
+ * Working out which files to checkpoint, and where each one's checkpoints go.
+ *
+ * Without --output-checkpoint, a file's checkpoints go next to it, in <file>.cornifer. A URL's go in the
+ * current directory, named after the last part of it.
+ *
+ * Also what to tell the user, and exit with, when something goes wrong.
+ */
+
+pub const CHECKPOINT_EXTENSION: &str = "cornifer";
+
+#[derive(Error, Debug)]
+pub enum PathError {
+    #[error("No files to generate checkpoints for")]
+    NoInputs,
+
+    #[error("{} doesn't exist", .0.display())]
+    NotFound(PathBuf),
+
+    #[error("{} is a directory, not a file", .0.display())]
+    IsDirectory(PathBuf),
+
+    #[error("{} is a checkpoint file, not a compressed file", .0.display())]
+    InputIsCheckpoint(PathBuf),
+
+    #[error("Bad --glob pattern {pattern}: {source}")]
+    BadGlob {
+        pattern: String,
+        source: glob::PatternError,
+    },
+
+    #[error("--glob {0} didn't match any files")]
+    NoMatches(String),
+
+    #[error("--output-checkpoint only works with a single file, each file gets <file>.{CHECKPOINT_EXTENSION} instead")]
+    OutputWithSeveralFiles,
+
+    #[error("The directory for the checkpoint file {} doesn't exist", .0.display())]
+    NoOutputDirectory(PathBuf),
+}
+
+/// Whether an input is a URL like s3://bucket/file.gz, rather than a path.
+pub fn is_url(input: &str) -> bool {
+    input.split_once("://").is_some_and(|(scheme, _)| {
+        !scheme.is_empty() && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+    })
+}
+
+/// Where a file's checkpoints go by default: the same path with .cornifer on the end.
+pub fn derived_checkpoint_path(input: &Path) -> PathBuf {
+    let input = match input.to_str().is_some_and(is_url) {
+        true => Path::new(input.file_name().unwrap_or_default()),
+        false => input,
+    };
+    let mut path = input.as_os_str().to_owned();
+    path.push(".");
+    path.push(CHECKPOINT_EXTENSION);
+    PathBuf::from(path)
+}
+
+fn is_checkpoint_path(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == CHECKPOINT_EXTENSION)
+}
+
+/// The files named on the command line, then the ones matching each pattern.
+/// Checkpoint files from an earlier run that a pattern matches are left out.
+pub fn expand_inputs(files: &[String], globs: &[String]) -> Result<Vec<PathBuf>, PathError> {
+    let mut inputs = Vec::new();
+    for file in files {
+        let path = PathBuf::from(file);
+        // whether it's there is up to the store, when it's opened.
+        if is_url(file) {
+            inputs.push(path);
+            continue;
+        }
+        if !path.exists() {
+            return Err(PathError::NotFound(path));
+        }
+        if path.is_dir() {
+            return Err(PathError::IsDirectory(path));
+        }
+        if is_checkpoint_path(&path) {
+            return Err(PathError::InputIsCheckpoint(path));
+        }
+        inputs.push(path);
+    }
+    for pattern in globs {
+        let paths = glob::glob(pattern).map_err(|source| PathError::BadGlob {
+            pattern: pattern.clone(),
+            source,
+        })?;
+        let matched: Vec<_> = paths
+            .filter_map(Result::ok)
+            .filter(|p| p.is_file() && !is_checkpoint_path(p))
+            .collect();
+        if matched.is_empty() {
+            return Err(PathError::NoMatches(pattern.clone()));
+        }
+        inputs.extend(matched);
+    }
+    if inputs.is_empty() {
+        return Err(PathError::NoInputs);
+    }
+    Ok(inputs)
+}
+
+/// Pair each input with the path its checkpoints go to.
+pub fn checkpoint_paths(
+    inputs: Vec<PathBuf>,
+    output: Option<&str>,
+) -> Result<Vec<(PathBuf, PathBuf)>, PathError> {
+    let Some(output) = output else {
+        return Ok(inputs
+            .into_iter()
+            .map(|input| {
+                let checkpoint = derived_checkpoint_path(&input);
+                (input, checkpoint)
+            })
+            .collect());
+    };
+    if inputs.len() > 1 {
+        return Err(PathError::OutputWithSeveralFiles);
+    }
+    let output = PathBuf::from(output);
+    // a bare file name has an empty parent, which is the current directory.
+    if let Some(dir) = output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        if !dir.is_dir() {
+            return Err(PathError::NoOutputDirectory(output));
+        }
+    }
+    Ok(inputs.into_iter().map(|input| (input, output.clone())).collect())
+}
+
+/// Where `cornifer cat --output` sends the data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputSink {
+    Stdout,
+    File(PathBuf),
+    Hash(HashAlgorithm),
+}
+
+/// `-` or `stdout`, `hash:sha256` or `hash:crc32`, or a file, which can also be given as `file:PATH`.
+pub fn parse_output_sink(s: &str) -> Result<OutputSink, String> {
+    if let Some(algorithm) = s.strip_prefix("hash:") {
+        return algorithm.parse().map(OutputSink::Hash);
+    }
+    match s.strip_prefix("file:").unwrap_or(s) {
+        "" => Err("no file name".to_string()),
+        "-" | "stdout" if !s.starts_with("file:") => Ok(OutputSink::Stdout),
+        path => Ok(OutputSink::File(PathBuf::from(path))),
+    }
+}
+
+/// A number of bytes, with an optional K, M, G or T after it for KiB, MiB, GiB or TiB, e.g. `1G`.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let (digits, shift) = match s.char_indices().last() {
+        Some((i, unit)) if unit.is_ascii_alphabetic() => {
+            let shift = match unit.to_ascii_uppercase() {
+                'K' => 10,
+                'M' => 20,
+                'G' => 30,
+                'T' => 40,
+                _ => return Err(format!("unknown unit {unit}, expected K, M, G or T")),
+            };
+            (&s[..i], shift)
+        }
+        _ => (s, 0),
+    };
+    let n: u64 = digits.parse().map_err(|e| format!("not a size: {e}"))?;
+    n.checked_mul(1 << shift).ok_or_else(|| "too big".to_string())
+}
+
+/// What the process exits with. 2 is clap's, for bad arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Exit {
+    /// Anything else, including some of the files in a batch failing.
+    Failed = 1,
+    /// Reading or writing a file failed.
+    Io = 3,
+    /// The compressed file is corrupt, or too big in some way.
+    CorruptInput = 4,
+    /// The checkpoint file already exists, or something else is writing to it.
+    IndexExists = 5,
+    /// The file isn't what --expect-crc32/--expect-size or the checkpoint file says it should be.
+    Mismatch = 6,
+    /// The checkpoint file is corrupt or doesn't cover the whole file.
+    CorruptIndex = 7,
+    /// The file is fine, but Cornifer can't handle it.
+    Unsupported = 8,
+    Cancelled = 9,
+}
+
+impl From<&CorniferError> for Exit {
+    fn from(err: &CorniferError) -> Self {
+        match err {
+            CorniferError::IndexAlreadyExists { .. } | CorniferError::IndexBusy { .. } => Exit::IndexExists,
+            CorniferError::UnexpectedLength { .. }
+            | CorniferError::UnexpectedCrc32 { .. }
+            | CorniferError::IndexLengthMismatch { .. }
+            | CorniferError::IndexCrcMismatch { .. }
+            | CorniferError::CorruptIndexOrData { .. }
+            | CorniferError::CompressedCrcMismatch { .. }
+            | CorniferError::FileChanged { .. } => Exit::Mismatch,
+            // it's --memory-budget that's wrong, not the file.
+            CorniferError::MemoryBudgetTooSmall { .. } => Exit::Failed,
+            err => match err.kind() {
+                ErrorKind::Io => Exit::Io,
+                ErrorKind::CorruptStream | ErrorKind::Limit => Exit::CorruptInput,
+                ErrorKind::CorruptIndex => Exit::CorruptIndex,
+                ErrorKind::Unsupported => Exit::Unsupported,
+                ErrorKind::Cancelled => Exit::Cancelled,
+            },
+        }
+    }
+}
+
+/// Why a command failed: what to tell the user, and what to exit with.
+#[derive(Debug)]
+pub struct Failure {
+    pub exit: Exit,
+    pub message: String,
+}
+
+impl Failure {
+    pub fn new(exit: Exit, message: impl Into<String>) -> Self {
+        Self {
+            exit,
+            message: message.into(),
+        }
+    }
+
+    /// Add where it happened, e.g. the offsets decoding had got to.
+    pub fn context(mut self, context: impl fmt::Display) -> Self {
+        self.message = format!("{} ({context})", self.message);
+        self
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<CorniferError> for Failure {
+    fn from(err: CorniferError) -> Self {
+        Self::new(Exit::from(&err), err.to_string())
+    }
+}
+
+// a CorniferError that went through a Read impl is still in there.
+impl From<std::io::Error> for Failure {
+    fn from(err: std::io::Error) -> Self {
+        match err.get_ref().and_then(|inner| inner.downcast_ref::<CorniferError>()) {
+            Some(inner) => Self::new(Exit::from(inner), inner.to_string()),
+            None => Self::new(Exit::Io, err.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for Failure {
+    fn from(err: serde_json::Error) -> Self {
+        Self::new(Exit::Failed, err.to_string())
+    }
+}
+
+/**
+ * TESTS
+ */
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+
+    use rstest::rstest;
+
+    use cornifer::errors::CorniferError;
+
+    use cornifer::sink::HashAlgorithm;
+
+    use super::{
+        checkpoint_paths, derived_checkpoint_path, expand_inputs, is_url, parse_output_sink, parse_size, Exit, Failure,
+        OutputSink, PathError,
+    };
+
+    #[rstest]
+    #[case("foo.gz", "foo.gz.cornifer")]
+    #[case("logs/foo.tar.gz", "logs/foo.tar.gz.cornifer")]
+    #[case("foo", "foo.cornifer")]
+    #[case::url("s3://bucket/logs/foo.gz", "foo.gz.cornifer")]
+    fn test_derived_checkpoint_path(#[case] input: &str, #[case] expected: &str) {
+        assert_eq!(derived_checkpoint_path(Path::new(input)), PathBuf::from(expected));
+    }
+
+    #[rstest]
+    fn test_expand_inputs() {
+        let inputs = expand_inputs(
+            &["../cornifer/testfiles/1080-0.txt.gz".to_string()],
+            &["../cornifer/testfiles/1080-0*.bz2".to_string()],
+        )
+        .unwrap();
+        assert_eq!(inputs[0], PathBuf::from("../cornifer/testfiles/1080-0.txt.gz"));
+        assert!(inputs.len() > 1);
+        assert!(inputs[1..].iter().all(|p| p.extension().unwrap() == "bz2"));
+    }
+
+    #[rstest]
+    #[case("s3://bucket/foo.gz", true)]
+    #[case("gs://bucket/logs/foo.gz", true)]
+    #[case("file:///tmp/foo.gz", true)]
+    #[case("foo.gz", false)]
+    #[case("logs/s3:/foo.gz", false)]
+    #[case("://foo.gz", false)]
+    #[case("a b://foo.gz", false)]
+    fn test_is_url(#[case] input: &str, #[case] expected: bool) {
+        assert_eq!(is_url(input), expected);
+    }
+
+    #[rstest]
+    #[case::missing(&["../cornifer/testfiles/nope.gz"], &[])]
+    #[case::directory(&["../cornifer/testfiles"], &[])]
+    #[case::checkpoint(&["foo.gz.cornifer"], &[])]
+    #[case::no_matches(&[], &["../cornifer/testfiles/*.nope"])]
+    #[case::bad_glob(&[], &["../cornifer/testfiles/[*.gz"])]
+    #[case::nothing(&[], &[])]
+    fn test_expand_inputs_errors(#[case] files: &[&str], #[case] globs: &[&str]) {
+        let files: Vec<_> = files.iter().map(|f| f.to_string()).collect();
+        let globs: Vec<_> = globs.iter().map(|g| g.to_string()).collect();
+        assert!(expand_inputs(&files, &globs).is_err());
+    }
+
+    #[rstest]
+    fn test_checkpoint_paths() {
+        let inputs = vec![PathBuf::from("a.gz"), PathBuf::from("b.gz")];
+        let paths = checkpoint_paths(inputs.clone(), None).unwrap();
+        assert_eq!(paths[1], (PathBuf::from("b.gz"), PathBuf::from("b.gz.cornifer")));
+        assert!(matches!(
+            checkpoint_paths(inputs, Some("out.sqlite3")),
+            Err(PathError::OutputWithSeveralFiles)
+        ));
+
+        let paths = checkpoint_paths(vec![PathBuf::from("a.gz")], Some("out.sqlite3")).unwrap();
+        assert_eq!(paths[0].1, PathBuf::from("out.sqlite3"));
+        assert!(matches!(
+            checkpoint_paths(vec![PathBuf::from("a.gz")], Some("nope/out.sqlite3")),
+            Err(PathError::NoOutputDirectory(_))
+        ));
+    }
+
+    #[rstest]
+    #[case::dash("-", Ok(OutputSink::Stdout))]
+    #[case::stdout("stdout", Ok(OutputSink::Stdout))]
+    #[case::file("out.txt", Ok(OutputSink::File(PathBuf::from("out.txt"))))]
+    #[case::file_called_stdout("file:stdout", Ok(OutputSink::File(PathBuf::from("stdout"))))]
+    #[case::sha256("hash:sha256", Ok(OutputSink::Hash(HashAlgorithm::Sha256)))]
+    #[case::crc32("hash:crc32", Ok(OutputSink::Hash(HashAlgorithm::Crc32)))]
+    #[case::unknown_hash("hash:md5", Err(()))]
+    #[case::empty("file:", Err(()))]
+    fn test_parse_output_sink(#[case] s: &str, #[case] expected: Result<OutputSink, ()>) {
+        assert_eq!(parse_output_sink(s).map_err(|_| ()), expected);
+    }
+
+    #[rstest]
+    #[case::bytes("4096", Ok(4096))]
+    #[case::kib("64K", Ok(64 << 10))]
+    #[case::gib("1G", Ok(1 << 30))]
+    #[case::lowercase("2m", Ok(2 << 20))]
+    #[case::unknown_unit("1Q", Err(()))]
+    #[case::no_number("G", Err(()))]
+    #[case::too_big("16777216T", Err(()))]
+    fn test_parse_size(#[case] s: &str, #[case] expected: Result<u64, ()>) {
+        assert_eq!(parse_size(s).map_err(|_| ()), expected);
+    }
+
+    #[rstest]
+    #[case::exists(CorniferError::IndexAlreadyExists { path: "x".to_string() }, Exit::IndexExists)]
+    #[case::mismatch(CorniferError::UnexpectedCrc32 { expected: 1, found: 2 }, Exit::Mismatch)]
+    #[case::corrupt(CorniferError::InvalidBlockType, Exit::CorruptInput)]
+    #[case::index(CorniferError::IndexIncomplete, Exit::CorruptIndex)]
+    #[case::io(CorniferError::IOError(std::io::ErrorKind::NotFound.into()), Exit::Io)]
+    fn test_exit_codes(#[case] err: CorniferError, #[case] exit: Exit) {
+        assert_eq!(Exit::from(&err), exit);
+        // the same, after going through a Read impl.
+        assert_eq!(Failure::from(std::io::Error::from(err)).exit, exit);
+    }
+}
@@ -0,0 +1,79 @@
+use std::io::{self, Write};
+use std::path::Path;
+
+use clap::Command;
+use clap_complete::Shell;
+use clap_mangen::Man;
+
+/*
+ * Shell completions and man pages, made by clap_complete and clap_mangen from the same clap definitions the
+ * arguments are parsed with, so they can't go out of date.
+ *
+ * Packages make them once cornifer is built, with `cargo xtask assets`, which runs the two subcommands below.
+ */
+
+/// Write the completion script for `shell`. `cmd` should be the whole of cornifer's, from `Cli::command()`.
+pub fn completions(mut cmd: Command, shell: Shell, out: &mut dyn Write) {
+    let name = cmd.get_name().to_string();
+    clap_complete::generate(shell, &mut cmd, name, out);
+}
+
+/// Write cornifer(1), which lists the subcommands.
+pub fn man_page(cmd: Command, out: &mut dyn Write) -> io::Result<()> {
+    Man::new(cmd).render(out)
+}
+
+/// Write cornifer.1, and a cornifer-<subcommand>.1 for each subcommand with its flags, into `dir`.
+pub fn man_pages(cmd: Command, dir: &Path) -> io::Result<()> {
+    clap_mangen::generate_to(cmd, dir)
+}
+
+/**
+ * TESTS
+ */
+#[cfg(test)]
+mod test {
+    use clap::CommandFactory;
+    use clap_complete::Shell;
+    use rstest::rstest;
+
+    use crate::Cli;
+
+    use super::{completions, man_page, man_pages};
+
+    #[rstest]
+    #[case::bash(Shell::Bash, "complete -F _cornifer")]
+    #[case::zsh(Shell::Zsh, "#compdef cornifer")]
+    #[case::fish(Shell::Fish, "complete -c cornifer -n \"__fish_cornifer_using_subcommand cat\" -l source")]
+    fn test_completions(#[case] shell: Shell, #[case] expected: &str) {
+        let mut script = Vec::new();
+        completions(Cli::command(), shell, &mut script);
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains(expected), "{script}");
+        // subcommands, flags of the subcommands, and the global ones.
+        for word in ["estimate", "max-output", "database", "json"] {
+            assert!(script.contains(word), "{word}");
+        }
+    }
+
+    #[rstest]
+    fn test_man_pages() {
+        let mut page = Vec::new();
+        man_page(Cli::command(), &mut page).unwrap();
+        let page = String::from_utf8(page).unwrap();
+        assert!(page.starts_with(".ie \\n(.g .ds Aq \\(aq"), "{page}");
+        assert!(page.contains(".TH cornifer 1"), "{page}");
+        assert!(page.contains("estimate"));
+        assert!(!page.contains("bench"));
+
+        let dir = std::env::temp_dir().join(format!("cornifer-man-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        man_pages(Cli::command(), &dir).unwrap();
+        let cat = std::fs::read_to_string(dir.join("cornifer-cat.1"));
+        let bench = dir.join("cornifer-bench.1").exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(cat.unwrap().contains("\\fB\\-\\-source\\fR \\fI<SOURCE>\\fR"));
+        // hidden, like it is from --help.
+        assert!(!bench);
+    }
+}
@@ -0,0 +1,70 @@
+use std::ops::Range;
+
+/*
+ * Showing bytes the way `hexdump -C` does: the offset, sixteen bytes in hex, and the same bytes as text.
+ */
+
+pub const BYTES_PER_LINE: usize = 16;
+
+// what a byte looks like on the right, where anything that isn't printable is a dot.
+pub fn printable(byte: u8) -> char {
+    match byte.is_ascii_graphic() || byte == b' ' {
+        true => byte as char,
+        false => '.',
+    }
+}
+
+/// One line of a hexdump of `bytes`, which start at `offset`. Short lines are padded so the text lines up.
+pub fn hex_line(offset: u64, bytes: &[u8]) -> String {
+    let mut hex = String::new();
+    for i in 0..BYTES_PER_LINE {
+        match bytes.get(i) {
+            Some(byte) => hex.push_str(&format!("{byte:02x} ")),
+            None => hex.push_str("   "),
+        }
+        if i == BYTES_PER_LINE / 2 - 1 {
+            hex.push(' ');
+        }
+    }
+    let text: String = bytes.iter().copied().map(printable).collect();
+    format!("{offset:08x}  {hex} |{text}|")
+}
+
+/// The `len` bytes to show around `offset` in a file `file_len` long: the offset in the middle where there's room,
+/// and starting on a line so the offsets on the left are round.
+pub fn window(offset: u64, len: u64, file_len: u64) -> Range<u64> {
+    let row = BYTES_PER_LINE as u64;
+    let from = offset.saturating_sub(len / 2).min(file_len.saturating_sub(len));
+    let from = from - from % row;
+    from..(from + len).min(file_len)
+}
+
+/**
+ * TESTS
+ */
+#[cfg(test)]
+mod test {
+    use std::ops::Range;
+
+    use rstest::rstest;
+
+    use super::{hex_line, window};
+
+    #[rstest]
+    #[case::full(0, b"Hello, world!\n\0\xff", "00000000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 ff  |Hello, world!...|")]
+    #[case::short(0x1234, b"abc", "00001234  61 62 63                                          |abc|")]
+    #[case::big_offset(1 << 36, b"", "1000000000                                                    ||")]
+    fn test_hex_line(#[case] offset: u64, #[case] bytes: &[u8], #[case] expected: &str) {
+        assert_eq!(hex_line(offset, bytes), expected);
+    }
+
+    #[rstest]
+    #[case::middle(1000, 256, 10000, 864..1120)]
+    #[case::start(10, 256, 10000, 0..256)]
+    #[case::end(9990, 256, 10000, 9744..10000)]
+    #[case::small_file(5, 256, 100, 0..100)]
+    #[case::past_the_end(20000, 256, 10000, 9744..10000)]
+    fn test_window(#[case] offset: u64, #[case] len: u64, #[case] file_len: u64, #[case] expected: Range<u64>) {
+        assert_eq!(window(offset, len, file_len), expected);
+    }
+}
@@ -0,0 +1,2092 @@
+mod cli;
+mod completions;
+mod hexdump;
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+mod mount;
+mod progress;
+mod selftest;
+mod serve;
+#[cfg(test)]
+#[path = "../../cornifer/tests/common/mod.rs"]
+mod testutil;
+#[cfg(feature = "tui")]
+mod tui;
+
+use cli::{Exit, Failure, OutputSink};
+use progress::{FileProgress, ProgressStream};
+
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use flate2::read::MultiGzDecoder;
+use flate2::Compression;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use cornifer::archive::{read_zip_entries, ArchiveEntry, TarScanner};
+use cornifer::checkpoint::{Checkpointer, CreateMode, Expected, IndexOptions, DEFAULT_WRITER_QUEUE};
+use cornifer::checkpointed::CheckpointedReader;
+use cornifer::store::{CheckpointStore, Member};
+use cornifer::errors::CorniferError;
+use cornifer::estimate::{estimate_from_index, estimate_size, SizeEstimate};
+use cornifer::decompress::{CheckpointSpacing, Deflator, DeflatorOptions, FooterChecks, GzipMember, HeaderValidation, TrailingData};
+use cornifer::format::{detect_format, Format};
+use cornifer::fsck::{check_index, Fix};
+use cornifer::header::GzipHeader;
+use cornifer::positional::PositionalReader;
+use cornifer::reader::CorniferByteReader;
+#[cfg(feature = "remote")]
+use cornifer::remote::ObjectReader;
+use cornifer::report::CorruptionReport;
+use cornifer::schema::SCHEMA;
+use cornifer::sink::{HashSink, Sink, Tee};
+use cornifer::slice::ExtractedMember;
+use cornifer::stats::Stats;
+use cornifer::throttle::ThrottledReader;
+use cornifer::writer::{GzipWriter, DEFAULT_FLUSH_INTERVAL};
+use regex::bytes::Regex;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::io::{sink, Write};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+// with no subcommand, we generate checkpoints, like we always have.
+#[derive(Parser, Debug)]
+#[command(name = "cornifer", author, version, about, long_about = None, args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    index: IndexArgs,
+
+    /// Print the results as JSON, for other programs to read
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Only print errors. No progress bars either.
+    #[arg(short, long, global = true)]
+    quiet: bool,
+}
+
+// how the results should be printed.
+#[derive(Debug, Clone, Copy)]
+struct Output {
+    json: bool,
+    quiet: bool,
+}
+
+impl Output {
+    fn show_progress(&self) -> bool {
+        !self.json && !self.quiet
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the GZIP header of every member in a file
+    Header(HeaderArgs),
+    /// Decompress a gzip or zlib file and print how well it's compressed
+    Info(InfoArgs),
+    /// Estimate how big a gzip file is decompressed from its members' footers, without decompressing it
+    Estimate(EstimateArgs),
+    /// List the files in an archive, or the named members of a gzip file, as recorded in its checkpoint file
+    Ls(LsArgs),
+    /// Print the lines matching a pattern, decompressing only the range asked for
+    Grep(GrepArgs),
+    /// Decompress a file, or part of it, to stdout, files and hashes at once
+    Cat(CatArgs),
+    /// Print the bytes around an uncompressed offset, decompressing only the blocks they're in
+    Peek(PeekArgs),
+    /// Compress a file, or recompress a gzip or zlib one, with flush points that make its checkpoint file as it goes
+    Recompress(RecompressArgs),
+    /// Copy the compressed blocks covering a range into a new gzip file, without recompressing them
+    Extract(ExtractArgs),
+    /// Cut a file into gzip files of about the same size decompressed, each of which decompresses on its own
+    Split(SplitArgs),
+    /// Check a file against the length and CRC recorded in its checkpoint file
+    Verify(VerifyArgs),
+    /// Check a checkpoint file is consistent with itself, without the compressed file
+    Fsck(FsckArgs),
+    /// Finish a checkpoint file that indexing stopped partway through, e.g. because it was killed
+    Repair(RepairArgs),
+    /// Add checkpoints for the gzip members appended to a file since it was checkpointed
+    Update(UpdateArgs),
+    /// Decompress a file with Cornifer and with flate2 and report the first byte they disagree on
+    Selftest(SelftestArgs),
+    /// Print the tables in a checkpoint file and what each column means, for programs that read them
+    Schema(SchemaArgs),
+    /// Serve the decompressed files over HTTP, answering Range requests from their checkpoint files
+    Serve(ServeArgs),
+    /// Mount the decompressed file read-only with FUSE, so any program can seek around in it
+    #[cfg(all(feature = "fuse", target_os = "linux"))]
+    Mount(MountArgs),
+    /// Look around a checkpointed file in the terminal: its blocks, and the data at any offset or line
+    #[cfg(feature = "tui")]
+    Tui(TuiArgs),
+    /// Print a completion script for a shell, e.g. `cornifer completions bash > /etc/bash_completion.d/cornifer`
+    Completions(CompletionsArgs),
+    /// Print the man page, in roff, or write one for every subcommand into a directory
+    Man(ManArgs),
+    /// Time decompressing a file with Cornifer and with flate2. See benches/ for the full suite.
+    #[command(hide = true)]
+    Bench(BenchArgs),
+}
+
+#[derive(Args, Debug)]
+struct IndexArgs {
+    /// Files to generate checkpoints for. Built with the remote feature, these can be s3://, gs:// or az:// URLs.
+    #[arg(required_unless_present = "glob")]
+    file_names: Vec<String>,
+
+    /// Also generate checkpoints for the files matching this pattern, e.g. 'logs/*.gz'
+    #[arg(long)]
+    glob: Vec<String>,
+
+    /// File to write the checkpoints to, for a single file. Defaults to <file>.cornifer, which is also where
+    /// each file's checkpoints go when there are several. Should not already exist, unless --overwrite or
+    /// --append is given.
+    #[arg(short, long)]
+    output_checkpoint: Option<String>,
+
+    /// Put every file's checkpoints in this one checkpoint file, each under the name it was given here, e.g. for a
+    /// whole directory of archives. It's added to if it already exists, and a file that's already in it is
+    /// checkpointed again. Read them back with `cornifer cat --source`.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["output_checkpoint", "overwrite"])]
+    database: Option<PathBuf>,
+
+    /// Number of files to generate checkpoints for at once.
+    #[arg(short, long, default_value_t = 1)]
+    jobs: usize,
+
+    /// Replace the checkpoint file if it already exists.
+    #[arg(long, visible_alias = "force", conflicts_with = "append")]
+    overwrite: bool,
+
+    /// Add to the checkpoint file if it already exists.
+    #[arg(long)]
+    append: bool,
+
+    /// Also record how many literals and matches each DEFLATE block has, and its longest match, in the checkpoint
+    /// file, for looking at how files compress with SQL.
+    #[arg(long)]
+    stats: bool,
+
+    /// Also record a CRC32 of each DEFLATE block's compressed bytes in the checkpoint file, so `cornifer verify
+    /// --fast` can check the compressed file without decompressing it.
+    #[arg(long)]
+    hash_blocks: bool,
+
+    /// Write straight to the checkpoint file, keeping what was checkpointed if it fails partway. Otherwise it's
+    /// written to <checkpoint file>.tmp and only renamed once it's complete.
+    #[arg(long)]
+    keep_partial: bool,
+
+    /// Format of the file to generate checkpoints for. Worked out from the file if not given.
+    #[arg(short, long)]
+    format: Option<Format>,
+
+    /// What to do with data after the last gzip member: ignore, error or return.
+    #[arg(long, default_value = "error")]
+    trailing_data: TrailingData,
+
+    /// What to do when a gzip member's CRC or ISIZE doesn't match: strict, lenient or warn.
+    #[arg(long, default_value = "strict")]
+    footer_checks: FooterChecks,
+
+    /// What to do when a gzip member's header CRC doesn't match: strict, warn or ignore.
+    #[arg(long, default_value = "strict")]
+    header_checks: HeaderValidation,
+
+    /// Skip a gzip member that can't be decoded and carry on from the next one, instead of failing. What was
+    /// skipped is recorded in the checkpoint file.
+    #[arg(long)]
+    recover: bool,
+
+    /// Reject deflate streams that break RFC 1951 in ways most decoders let go, like incomplete Huffman codes.
+    #[arg(long)]
+    strict: bool,
+
+    /// Give up once the file has decompressed to more than this many bytes, to guard against decompression bombs.
+    #[arg(long, value_name = "BYTES")]
+    max_output: Option<u64>,
+
+    /// Give up once the file has decompressed to more than this many times the size of what's been read of it.
+    /// Only checked after the first MiB.
+    #[arg(long, value_name = "N")]
+    max_ratio: Option<f64>,
+
+    /// Give up after this many seconds. With --keep-partial, what was checkpointed by then is kept.
+    #[arg(long, value_name = "SECONDS")]
+    deadline: Option<f64>,
+
+    /// Read the file no faster than this many MB/s, to go easy on shared storage.
+    #[arg(long, value_name = "MB/s")]
+    throttle: Option<f64>,
+
+    /// Fail if the CRC32 of the decompressed file isn't this, in hex.
+    #[arg(long, value_name = "HEX", value_parser = parse_crc32)]
+    expect_crc32: Option<u32>,
+
+    /// Fail if the decompressed file isn't this many bytes.
+    #[arg(long, value_name = "BYTES")]
+    expect_size: Option<u64>,
+
+    /// Only store a window every this many bytes of output, for a much smaller checkpoint file that's slower to
+    /// read from. Without a number, only at the start of each gzip member.
+    #[arg(long, value_name = "BYTES", require_equals = true)]
+    sparse: Option<Option<u64>>,
+
+    /// Space checkpoints out by how much they decompress to: no closer than MIN bytes where the blocks are
+    /// small, and ticks inside blocks so they're no further apart than MAX. Defaults to 65536:4194304.
+    #[arg(long, value_name = "MIN:MAX", require_equals = true, value_parser = parse_spacing, conflicts_with = "sparse")]
+    spacing: Option<Option<CheckpointSpacing>>,
+
+    /// Put the checkpoint file in WAL mode, so it can be read while it's still being written.
+    #[arg(long)]
+    wal: bool,
+
+    /// Keep writing the checkpoint file to roughly this much memory, per file being indexed. About 0.3MB of it
+    /// goes on compressing windows whatever the budget; the sqlite page cache gets the rest.
+    #[arg(long, value_name = "MB")]
+    memory_budget: Option<f64>,
+
+    /// Write the windows for ticks inside blocks to <checkpoint file>.ticks uncompressed, rather than into the
+    /// checkpoint file. Faster to write when there are a lot of them; the two files have to be kept together.
+    #[arg(long)]
+    tick_sidecar: bool,
+
+    /// Write the checkpoint file from a thread of its own, so decoding doesn't wait for the disk, with up to this
+    /// many checkpoints queued for it. Defaults to 64.
+    #[arg(long, value_name = "CHECKPOINTS", require_equals = true)]
+    background_writer: Option<Option<usize>>,
+
+    /// Also write progress, and each block and member as it's decoded, as JSON lines to this file descriptor
+    #[arg(long, value_name = "FD", conflicts_with = "progress_socket", value_parser = clap::value_parser!(i32).range(0..))]
+    progress_fd: Option<i32>,
+
+    /// Connect to this Unix socket and write progress, and each block and member as it's decoded, as JSON lines
+    #[arg(long, value_name = "PATH")]
+    progress_socket: Option<PathBuf>,
+}
+
+fn parse_crc32(s: &str) -> Result<u32, String> {
+    let digits = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).unwrap_or(s);
+    u32::from_str_radix(digits, 16).map_err(|e| format!("not a 32-bit hex number: {e}"))
+}
+
+fn parse_spacing(s: &str) -> Result<CheckpointSpacing, String> {
+    let (min, max) = s.split_once(':').ok_or("expected MIN:MAX")?;
+    let spacing = CheckpointSpacing {
+        min: min.parse().map_err(|e| format!("bad MIN: {e}"))?,
+        max: max.parse().map_err(|e| format!("bad MAX: {e}"))?,
+    };
+    if spacing.max <= spacing.min {
+        return Err("MAX has to be bigger than MIN".to_string());
+    }
+    Ok(spacing)
+}
+
+#[derive(Args, Debug)]
+struct HeaderArgs {
+    /// GZIP file to read the headers of
+    file_name: String,
+}
+
+#[derive(Args, Debug)]
+struct InfoArgs {
+    /// gzip or zlib file to look at
+    file_name: String,
+
+    /// Also count the blocks, literals and matches, e.g. to see why a file makes a big checkpoint file
+    #[arg(long)]
+    stats: bool,
+}
+
+#[derive(Args, Debug)]
+struct LsArgs {
+    /// Checkpoint file made from a tar.gz or zip file, or a gzip file with names in its members' headers
+    checkpoint_file: String,
+}
+
+#[derive(Args, Debug)]
+struct GrepArgs {
+    /// Regular expression to search for
+    pattern: String,
+
+    /// Compressed file to search
+    file_name: String,
+
+    /// Checkpoint file made from the compressed file. Defaults to <file>.cornifer.
+    #[arg(short, long)]
+    index: Option<String>,
+
+    /// Uncompressed offset to start searching from. A line that starts before this is skipped.
+    #[arg(long, default_value_t = 0)]
+    from: u64,
+
+    /// Uncompressed offset to stop searching at. A line that starts before this is finished. Defaults to the end.
+    #[arg(long)]
+    to: Option<u64>,
+
+    /// Number of threads to search with. The range is split between them at checkpoints.
+    #[arg(short, long, default_value_t = 1)]
+    jobs: usize,
+}
+
+#[derive(Args, Debug)]
+struct CatArgs {
+    /// Compressed file to decompress, or its URL
+    file_name: String,
+
+    /// Checkpoint file made from the compressed file. Defaults to <file>.cornifer.
+    #[arg(short, long)]
+    index: Option<String>,
+
+    /// Uncompressed offset to start at
+    #[arg(long, default_value_t = 0)]
+    from: u64,
+
+    /// Uncompressed offset to stop at. Defaults to the end.
+    #[arg(long)]
+    to: Option<u64>,
+
+    /// Where the data goes: `-` for stdout, a file, or `hash:sha256` or `hash:crc32` to print its hash. Give it
+    /// more than once to write to several at once. Defaults to stdout.
+    #[arg(short, long, value_name = "SINK", value_parser = cli::parse_output_sink)]
+    output: Vec<OutputSink>,
+
+    /// The name the file was given in a checkpoint file made with `cornifer index --database`.
+    #[arg(long)]
+    source: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct PeekArgs {
+    /// Compressed file to look in, or its URL
+    file_name: String,
+
+    /// Checkpoint file made from the compressed file. Defaults to <file>.cornifer.
+    #[arg(short, long)]
+    index: Option<String>,
+
+    /// Uncompressed offset to look at. The bytes printed are around it.
+    #[arg(long)]
+    offset: u64,
+
+    /// How many bytes to print
+    #[arg(long, default_value_t = 256)]
+    len: u64,
+
+    /// Print them like `hexdump -C` instead of as they are
+    #[arg(long)]
+    hex: bool,
+
+    /// The name the file was given in a checkpoint file made with `cornifer index --database`.
+    #[arg(long)]
+    source: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct RecompressArgs {
+    /// File to compress, or its URL. gzip and zlib files are decompressed first, anything else is taken as it is.
+    file_name: String,
+
+    /// The gzip file to write
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Checkpoint file to write the flush points to. Defaults to <output>.cornifer.
+    #[arg(short, long)]
+    index: Option<PathBuf>,
+
+    /// Bytes of uncompressed data between flush points
+    #[arg(long, default_value_t = DEFAULT_FLUSH_INTERVAL)]
+    interval: u64,
+
+    /// Compression level, from 0 (none) to 9 (best)
+    #[arg(short, long, default_value_t = 6, value_parser = clap::value_parser!(u32).range(0..=9))]
+    level: u32,
+
+    /// Replace the checkpoint file if it already exists.
+    #[arg(long)]
+    force: bool,
+}
+
+#[derive(Args, Debug)]
+struct ExtractArgs {
+    /// Compressed file to take the blocks from, or its URL. Only gzip.
+    file_name: String,
+
+    /// Checkpoint file made from the compressed file. Defaults to <file>.cornifer.
+    #[arg(short, long)]
+    index: Option<String>,
+
+    /// Extract the gzip member with this file name in its header, instead of a range
+    #[arg(long, conflicts_with_all = ["from", "to"])]
+    member: Option<String>,
+
+    /// Uncompressed offset the range starts at
+    #[arg(long, default_value_t = 0)]
+    from: u64,
+
+    /// Uncompressed offset the range ends at. Defaults to the end.
+    #[arg(long)]
+    to: Option<u64>,
+
+    /// Put the 32KB before the first block in front of it, so blocks that refer back to it still decode
+    #[arg(short, long)]
+    window: bool,
+
+    /// The gzip file to write
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct SplitArgs {
+    /// Compressed file to split, or its URL
+    file_name: String,
+
+    /// Checkpoint file made from the compressed file. Defaults to <file>.cornifer.
+    #[arg(short, long)]
+    index: Option<String>,
+
+    /// How much of the decompressed file goes in each part, at least. Parts end on the next block after it.
+    #[arg(long, value_name = "SIZE", value_parser = cli::parse_size)]
+    chunk_size: u64,
+
+    /// Where the parts go: <prefix>.000.gz, <prefix>.001.gz, and so on. Defaults to the file name, without .gz.
+    #[arg(short, long)]
+    prefix: Option<PathBuf>,
+
+    /// Compression level for the parts that have to be recompressed, from 0 (none) to 9 (best)
+    #[arg(short, long, default_value_t = 6, value_parser = clap::value_parser!(u32).range(0..=9))]
+    level: u32,
+}
+
+#[derive(Args, Debug)]
+struct VerifyArgs {
+    /// Compressed file to check, or its URL
+    file_name: String,
+
+    /// Checkpoint file made from the compressed file. Defaults to <file>.cornifer.
+    #[arg(short, long)]
+    index: Option<String>,
+
+    /// If the file doesn't match, write what's damaged and what can still be read to this file, as JSON.
+    /// Every block is decoded again to find out.
+    #[arg(long, value_name = "PATH")]
+    report: Option<String>,
+
+    /// Only check the compressed bytes of each block against the CRC32s in the checkpoint file, without
+    /// decompressing anything. Needs a checkpoint file made with --hash-blocks.
+    #[arg(long, conflicts_with = "report")]
+    fast: bool,
+}
+
+#[derive(Args, Debug)]
+struct SelftestArgs {
+    /// gzip or zlib file to decompress both ways
+    file_name: String,
+}
+
+#[derive(Args, Debug)]
+struct EstimateArgs {
+    /// Compressed file to estimate the size of, or its URL
+    file_name: String,
+
+    /// Checkpoint file made from the compressed file, which has the exact size. Defaults to <file>.cornifer if
+    /// there is one.
+    #[arg(short, long)]
+    index: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct FsckArgs {
+    /// Checkpoint file to check
+    index: String,
+}
+
+#[derive(Args, Debug)]
+struct RepairArgs {
+    /// gzip file the checkpoint file was being made from
+    file_name: String,
+
+    /// Checkpoint file to finish. Defaults to <file>.cornifer
+    #[arg(short, long)]
+    index: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct SchemaArgs {
+    /// Print the CREATE TABLE statements instead
+    #[arg(long)]
+    sql: bool,
+}
+
+#[cfg(feature = "tui")]
+#[derive(Args, Debug)]
+struct TuiArgs {
+    /// Compressed file to look at, or its URL
+    file_name: String,
+
+    /// Checkpoint file made from the compressed file. Defaults to <file>.cornifer.
+    #[arg(short, long)]
+    index: Option<String>,
+
+    /// The name the file was given in a checkpoint file made with `cornifer index --database`.
+    #[arg(long)]
+    source: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct CompletionsArgs {
+    /// Shell to complete for
+    #[arg(value_enum)]
+    shell: clap_complete::Shell,
+}
+
+#[derive(Args, Debug)]
+struct ManArgs {
+    /// Write cornifer.1 and a page for each subcommand into this directory, rather than cornifer.1 to stdout
+    #[arg(long, value_name = "DIR")]
+    out_dir: Option<PathBuf>,
+}
+
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+#[derive(Args, Debug)]
+struct MountArgs {
+    /// Compressed file to mount
+    file_name: String,
+
+    /// Checkpoint file made from the compressed file. Defaults to <file>.cornifer.
+    #[arg(short, long)]
+    index: Option<String>,
+
+    /// Empty directory to mount it on. Unmount with `fusermount -u` (or `umount`) to stop.
+    mountpoint: String,
+
+    /// Name of the file in the mount. Defaults to the compressed file's name without its extension.
+    #[arg(long)]
+    name: Option<String>,
+
+    /// Keep up to this many MB of decompressed blocks in memory, for reads that go back and forth.
+    #[arg(long, value_name = "MB", default_value_t = 64)]
+    cache: usize,
+}
+
+#[derive(Args, Debug)]
+struct ServeArgs {
+    /// Compressed files to serve, each at /<its name without the extension>
+    #[arg(required = true)]
+    file_names: Vec<String>,
+
+    /// Checkpoint file for each file, in the same order. Defaults to <file>.cornifer.
+    #[arg(short, long)]
+    index: Vec<String>,
+
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    listen: String,
+
+    /// Number of requests to answer at once
+    #[arg(short, long, default_value_t = 4)]
+    jobs: usize,
+}
+
+#[derive(Args, Debug)]
+struct UpdateArgs {
+    /// gzip file that's been added to
+    file_name: String,
+
+    /// Checkpoint file made from the file before it was added to. Defaults to <file>.cornifer
+    #[arg(short, long)]
+    index: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct BenchArgs {
+    /// GZIP file to decompress
+    file_name: String,
+
+    /// Number of times to decompress the file with each decoder
+    #[arg(short, long, default_value_t = 5)]
+    iterations: u32,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let output = Output {
+        json: cli.json,
+        quiet: cli.quiet,
+    };
+    let result = match cli.command {
+        Some(Command::Header(args)) => header(args, output),
+        Some(Command::Info(args)) => info(args, output),
+        Some(Command::Ls(args)) => ls(args, output),
+        Some(Command::Grep(args)) => grep(args),
+        Some(Command::Cat(args)) => cat(args, output),
+        Some(Command::Peek(args)) => peek(args),
+        Some(Command::Recompress(args)) => recompress(args, output),
+        Some(Command::Extract(args)) => extract(args, output),
+        Some(Command::Split(args)) => split(args, output),
+        Some(Command::Verify(args)) => verify(args, output),
+        Some(Command::Fsck(args)) => fsck(args, output),
+        Some(Command::Repair(args)) => repair(args, output),
+        Some(Command::Update(args)) => update(args, output),
+        Some(Command::Selftest(args)) => selftest(args, output),
+        Some(Command::Schema(args)) => schema(args, output),
+        Some(Command::Estimate(args)) => estimate(args, output),
+        Some(Command::Serve(args)) => serve(args, output),
+        #[cfg(all(feature = "fuse", target_os = "linux"))]
+        Some(Command::Mount(args)) => mount(args, output),
+        #[cfg(feature = "tui")]
+        Some(Command::Tui(args)) => tui(args),
+        Some(Command::Completions(args)) => {
+            completions::completions(Cli::command(), args.shell, &mut std::io::stdout());
+            Ok(())
+        }
+        Some(Command::Man(args)) => match args.out_dir {
+            Some(dir) => completions::man_pages(Cli::command(), &dir).map_err(Failure::from),
+            None => completions::man_page(Cli::command(), &mut std::io::stdout()).map_err(Failure::from),
+        },
+        Some(Command::Bench(args)) => bench(args),
+        None => index(cli.index, output),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => {
+            eprintln!("error: {failure}");
+            ExitCode::from(failure.exit as u8)
+        }
+    }
+}
+
+fn index(cli: IndexArgs, output: Output) -> Result<(), Failure> {
+    let paths = cli::expand_inputs(&cli.file_names, &cli.glob)
+        .and_then(|inputs| cli::checkpoint_paths(inputs, cli.output_checkpoint.as_deref()));
+    let paths = match (paths, &cli.database) {
+        (Ok(paths), Some(database)) => paths.into_iter().map(|(file_name, _)| (file_name, database.clone())).collect(),
+        (Ok(paths), None) => paths,
+        (Err(e), _) => Cli::command().error(clap::error::ErrorKind::ValueValidation, e).exit(),
+    };
+    let stream = ProgressStream::open(cli.progress_fd, cli.progress_socket.as_deref())?;
+    if let [(file_name, checkpoint_file_name)] = paths.as_slice() {
+        let progress_bar = match output.show_progress() {
+            true => ProgressBar::new(0),
+            false => ProgressBar::hidden(),
+        };
+        let result = index_file(&cli, file_name, checkpoint_file_name, &progress_bar, stream.as_ref());
+        if output.json {
+            println!("{}", serde_json::to_string_pretty(&IndexReport::new(file_name, checkpoint_file_name, &result))?);
+            return result.map(|_| ());
+        }
+        match result? {
+            indexed if !output.quiet => {
+                for note in &indexed.notes {
+                    println!("{note}");
+                }
+                println!("🎉🎉🎉 Done! 🎉🎉🎉");
+                println!("The checkpoints are in {}.", checkpoint_file_name.display());
+                match indexed.crc32 {
+                    Some(crc32) => println!("The CRC of the decompressed file is {:#x}. It's recorded in the checkpoint file, `cornifer verify` checks it.", crc32),
+                    None => println!("The files in the zip itself aren't checkpointed."),
+                }
+            }
+            _ => (),
+        }
+        return Ok(());
+    }
+    if cli.expect_crc32.is_some() || cli.expect_size.is_some() {
+        Cli::command().error(clap::error::ErrorKind::ArgumentConflict, "--expect-crc32 and --expect-size only work with a single file").exit();
+    }
+
+    // each thread takes the next file that nobody has started yet.
+    let multi = match output.show_progress() {
+        true => MultiProgress::new(),
+        false => MultiProgress::with_draw_target(ProgressDrawTarget::hidden()),
+    };
+    let next = AtomicUsize::new(0);
+    // they'd only wait for each other to write to the same file.
+    let jobs = match cli.database {
+        Some(_) => 1,
+        None => cli.jobs.clamp(1, paths.len()),
+    };
+    let mut results: Vec<_> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..jobs)
+            .map(|_| {
+                let (cli, paths, multi, next, stream) = (&cli, &paths, &multi, &next, stream.as_ref());
+                scope.spawn(move || {
+                    let mut results = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some((file_name, checkpoint_file_name)) = paths.get(i) else {
+                            return results;
+                        };
+                        let progress_bar = multi.add(ProgressBar::new(0));
+                        progress_bar.set_message(file_name.display().to_string());
+                        let result = index_file(cli, file_name, checkpoint_file_name, &progress_bar, stream);
+                        progress_bar.finish();
+                        results.push((i, result));
+                    }
+                })
+            })
+            .collect();
+        handles.into_iter().flat_map(|h| h.join().expect("index thread panicked")).collect()
+    });
+    results.sort_by_key(|(i, ..)| *i);
+    let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+
+    if output.json {
+        let reports: Vec<_> = results
+            .iter()
+            .map(|(i, result)| IndexReport::new(&paths[*i].0, &paths[*i].1, result))
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&BatchReport { files: reports, failed })?);
+    } else {
+        let name_width = paths.iter().map(|(f, _)| f.display().to_string().len()).max().unwrap_or(0).max(4);
+        if !output.quiet {
+            println!("{:name_width$}  {:6}  {:10}  RESULT", "FILE", "FORMAT", "CRC32");
+        }
+        for (i, result) in &results {
+            let (file_name, checkpoint_file_name) = &paths[*i];
+            let (file_name, checkpoint_file_name) = (file_name.display().to_string(), checkpoint_file_name.display());
+            match result {
+                Ok(_) if output.quiet => (),
+                Ok(indexed) => {
+                    let crc32 = indexed.crc32.map(|c| format!("{c:#010x}")).unwrap_or_else(|| "-".to_string());
+                    println!("{file_name:name_width$}  {:6}  {crc32:10}  ok, {checkpoint_file_name}", indexed.format);
+                    for note in &indexed.notes {
+                        println!("{:name_width$}  {note}", "");
+                    }
+                }
+                Err(e) => println!("{file_name:name_width$}  {:6}  {:10}  failed: {e}", "-", "-"),
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(Failure::new(Exit::Failed, format!("{failed} of {} files failed", results.len())));
+    }
+    Ok(())
+}
+
+// what indexing a file found, for the user.
+#[derive(Serialize)]
+struct Indexed {
+    format: String,
+    compressed_len: u64,
+    // none for a zip, since its files aren't checkpointed.
+    uncompressed_len: Option<u64>,
+    #[serde(serialize_with = "serialize_crc32")]
+    crc32: Option<u32>,
+    blocks: u64,
+    duration_secs: f64,
+    notes: Vec<String>,
+}
+
+// the same way the checkpoint file has them, as hex.
+fn serialize_crc32<S: serde::Serializer>(crc32: &Option<u32>, serializer: S) -> Result<S::Ok, S::Error> {
+    match crc32 {
+        Some(crc32) => serializer.serialize_str(&format!("{crc32:08x}")),
+        None => serializer.serialize_none(),
+    }
+}
+
+// one file's worth of --json output.
+#[derive(Serialize)]
+struct IndexReport<'a> {
+    file: String,
+    checkpoint: String,
+    ok: bool,
+    error: Option<&'a str>,
+    #[serde(flatten)]
+    indexed: Option<&'a Indexed>,
+}
+
+// --json output for several files.
+#[derive(Serialize)]
+struct BatchReport<'a> {
+    files: Vec<IndexReport<'a>>,
+    failed: usize,
+}
+
+impl<'a> IndexReport<'a> {
+    fn new(file_name: &Path, checkpoint_file_name: &Path, result: &'a Result<Indexed, Failure>) -> Self {
+        IndexReport {
+            file: file_name.display().to_string(),
+            checkpoint: checkpoint_file_name.display().to_string(),
+            ok: result.is_ok(),
+            error: result.as_ref().err().map(|failure| failure.message.as_str()),
+            indexed: result.as_ref().ok(),
+        }
+    }
+}
+
+fn open_file<P: AsRef<Path>>(path: P) -> Result<fs::File, Failure> {
+    let path = path.as_ref();
+    fs::File::open(path).map_err(|e| Failure::new(Exit::Io, format!("Could not open {}: {e}", path.display())))
+}
+
+trait Input: Read + Seek {}
+impl<R: Read + Seek> Input for R {}
+
+// a file, or an s3://, gs://, az:// or file:// URL, and how long it is. Reading it all in order is one GET that
+// streams it when `streaming`, otherwise each read is a ranged GET.
+fn open_input(file_name: &str, streaming: bool) -> Result<(Box<dyn Input>, u64), Failure> {
+    if cli::is_url(file_name) {
+        return open_url(file_name, streaming);
+    }
+    let file = open_file(file_name)?;
+    let len = file.metadata()?.len();
+    Ok((Box::new(file), len))
+}
+
+#[cfg(feature = "remote")]
+fn open_url(url: &str, streaming: bool) -> Result<(Box<dyn Input>, u64), Failure> {
+    let mut reader = ObjectReader::open(url).map_err(|e| Failure::from(e).context(format_args!("couldn't open {url}")))?;
+    reader.set_streaming(streaming);
+    let len = reader.len();
+    Ok((Box::new(reader), len))
+}
+
+#[cfg(not(feature = "remote"))]
+fn open_url(url: &str, _streaming: bool) -> Result<(Box<dyn Input>, u64), Failure> {
+    Err(Failure::new(Exit::Unsupported, format!("Can't read {url}, cornifer was built without the remote feature")))
+}
+
+// the file not matching --expect-crc32 or --expect-size isn't something wrong with Cornifer, so say so plainly.
+fn describe_failure(failure: Failure) -> Failure {
+    match failure.exit {
+        Exit::Mismatch => failure.context("the checkpoint file isn't marked as complete"),
+        _ => failure,
+    }
+}
+
+fn index_file(
+    cli: &IndexArgs,
+    file_name: &Path,
+    checkpoint_file_name: &Path,
+    progress_bar: &ProgressBar,
+    stream: Option<&ProgressStream>,
+) -> Result<Indexed, Failure> {
+    let mut progress = FileProgress::new(stream, file_name);
+    let result = index_file_with(cli, file_name, checkpoint_file_name, progress_bar, &mut progress);
+    match &result {
+        Ok(indexed) => progress.finished(indexed.uncompressed_len, None),
+        Err(failure) => progress.finished(None, Some(&failure.message)),
+    }
+    result
+}
+
+fn index_file_with(
+    cli: &IndexArgs,
+    file_name: &Path,
+    checkpoint_file_name: &Path,
+    progress_bar: &ProgressBar,
+    progress: &mut FileProgress,
+) -> Result<Indexed, Failure> {
+    let start = Instant::now();
+    let (file, file_len) = open_input(&file_name.to_string_lossy(), true)?;
+    progress_bar.set_length(file_len);
+    progress.set_len(file_len);
+    // indicatif works out the rate and the ETA from a moving average, so they settle down after a few seconds.
+    let template = "[{elapsed_precise}] {bar:80.cyan/blue} {pos}/{len} ({binary_bytes_per_sec}, {eta} left) {msg}";
+    progress_bar.set_style(ProgressStyle::with_template(template).unwrap().progress_chars("=>."));
+
+    let file = ThrottledReader::new(file, cli.throttle.map(|mb| mb * 1_000_000.0));
+    let mut bf = BufReader::new(file);
+    let format = match cli.format {
+        Some(format) => format,
+        None => detect_format(&mut bf).map_err(|_| {
+            Failure::new(Exit::CorruptInput, "Could not work out the format of the file, use --format to say what it is")
+        })?,
+    };
+    let create_mode = match (cli.overwrite, cli.append || cli.database.is_some()) {
+        (true, _) => CreateMode::Overwrite,
+        (_, true) => CreateMode::Append,
+        _ => CreateMode::CreateNew,
+    };
+    let mut checkpointer = Checkpointer::open(checkpoint_file_name, create_mode).map_err(|e| match e {
+        CorniferError::IndexAlreadyExists { .. } => Failure::from(e).context("pass --force to replace it, or --append to add to it"),
+        e => Failure::from(e).context("couldn't create the checkpoint file"),
+    })?;
+    if cli.database.is_some() {
+        checkpointer.set_source(&file_name.to_string_lossy())?;
+    }
+    checkpointer.set_expected(Expected {
+        len: cli.expect_size,
+        crc32: cli.expect_crc32,
+    });
+    if cli.keep_partial {
+        checkpointer.keep_partial()?;
+    }
+    if cli.wal {
+        checkpointer.use_wal()?;
+    }
+    if let Some(spacing) = cli.sparse {
+        checkpointer.set_window_spacing(spacing.unwrap_or(u64::MAX));
+    }
+    checkpointer.set_options(IndexOptions {
+        memory_budget: cli.memory_budget.map(|mb| (mb * 1_000_000.0) as usize),
+        tick_sidecar: cli.tick_sidecar,
+        background_writer: cli.background_writer.map(|queue| queue.unwrap_or(DEFAULT_WRITER_QUEUE)),
+    })?;
+    progress_bar.set_message(format!("{} ({format})", file_name.display()));
+    let mut notes = Vec::new();
+    if format == Format::Zip {
+        // each file in a zip is its own DEFLATE stream, which we don't checkpoint yet. We can still list them.
+        let entries = read_zip_entries(&mut bf)?;
+        for entry in &entries {
+            checkpointer.on_archive_entry(entry)?;
+        }
+        notes.push(format!("Recorded {} files from the zip's central directory.", entries.len()));
+        return Ok(Indexed {
+            format: format.to_string(),
+            compressed_len: file_len,
+            uncompressed_len: None,
+            crc32: None,
+            blocks: 0,
+            duration_secs: start.elapsed().as_secs_f64(),
+            notes,
+        });
+    }
+    let (uncompressed_len, crc32, blocks) = match format {
+        Format::Gzip | Format::Bgzf | Format::Zlib | Format::Zip => {
+            let mut decompressor = Deflator::with_format(CorniferByteReader::new(bf), checkpointer, format)?;
+            decompressor.set_options(DeflatorOptions {
+                trailing_data: cli.trailing_data,
+                footer_checks: cli.footer_checks,
+                header_checks: cli.header_checks,
+                recover: cli.recover,
+                max_output_bytes: cli.max_output,
+                max_expansion_ratio: cli.max_ratio,
+                deadline: cli.deadline.map(Duration::from_secs_f64),
+                strict: cli.strict,
+                // only needed for the warnings about them below, which strict checks never get to.
+                keep_members: cli.footer_checks != FooterChecks::Strict || cli.header_checks == HeaderValidation::Warn,
+            });
+            if let Some(spacing) = cli.spacing {
+                decompressor.set_checkpoint_spacing(spacing.unwrap_or_default())?;
+            }
+            if cli.stats {
+                decompressor.collect_stats();
+            }
+            if cli.hash_blocks {
+                decompressor.hash_blocks();
+            }
+            if progress.wants_events() {
+                decompressor.record_events();
+            }
+
+            // if it's a tar file, pick out where each file is on the way past.
+            let mut dest = TarScanner::new();
+
+            let len = copy_with_progress(&mut decompressor, &mut dest, progress_bar, progress).map_err(|e| match Failure::from(e) {
+                failure if failure.exit == Exit::Mismatch => describe_failure(failure),
+                failure => {
+                    let position = decompressor.position();
+                    failure.context(format_args!(
+                        "at byte {:#x} bit {} of the compressed file, byte {:#x} of the decompressed file",
+                        position.compressed_byte, position.compressed_bit, position.uncompressed_byte
+                    ))
+                }
+            })?;
+            let entries = dest.into_entries();
+            for entry in &entries {
+                decompressor.checkpointer_mut().on_archive_entry(entry)?;
+            }
+            if !entries.is_empty() {
+                notes.push(format!("Found {} files in the tar archive.", entries.len()));
+            }
+            if let Some(trailing) = decompressor.trailing_data() {
+                notes.push(format!("Found {} bytes of data after the last member.", trailing.len()));
+            }
+            for (i, member) in decompressor.members().iter().enumerate() {
+                if !member.crc32_matches || !member.isize_matches {
+                    notes.push(format!("Warning: the footer of member {i} doesn't match its contents ({} bytes).", member.len));
+                }
+                if !member.hcrc_matches && cli.header_checks == HeaderValidation::Warn {
+                    notes.push(format!("Warning: the header CRC of member {i} is incorrect."));
+                }
+            }
+            for gap in decompressor.gaps() {
+                notes.push(format!(
+                    "Warning: skipped bytes {:#x} to {:#x} of the file after: {}",
+                    gap.from_byte, gap.to_byte, gap.error
+                ));
+            }
+            let unfinished = decompressor.checkpointer_mut().unfinished_blocks()?;
+            if unfinished > 0 {
+                notes.push(format!("Warning: {unfinished} checkpoints were started but never finished, and can't be used."));
+            }
+            (len, decompressor.total_crc32(), decompressor.checkpointer_mut().finished_blocks())
+        }
+        Format::Bzip2 => {
+            let mut reader = CorniferByteReader::new(progress.wrap_read(progress_bar.wrap_read(bf)));
+            let summary = cornifer::bzip2::index(&mut reader, &mut checkpointer)
+                .map_err(|e| describe_failure(Failure::from(e)))?;
+            (summary.len as u64, summary.crc32, summary.blocks as u64)
+        }
+        Format::Xz => {
+            let summary = cornifer::xz::index(&mut progress.wrap_read(progress_bar.wrap_read(bf)), &mut checkpointer)
+                .map_err(|e| describe_failure(Failure::from(e)))?;
+            (summary.len as u64, summary.crc32, summary.blocks as u64)
+        }
+    };
+    Ok(Indexed {
+        format: format.to_string(),
+        compressed_len: file_len,
+        uncompressed_len: Some(uncompressed_len),
+        crc32: Some(crc32),
+        blocks,
+        duration_secs: start.elapsed().as_secs_f64(),
+        notes,
+    })
+}
+
+// io::copy, moving the progress bar along to where the decompressor has got to in the compressed file. How much
+// has been read from the file would run ahead of that by however much is buffered. The --progress-fd lines get the
+// same, and the events since the last read.
+fn copy_with_progress<R: Read>(
+    decompressor: &mut Deflator<R>,
+    dest: &mut impl Write,
+    progress_bar: &ProgressBar,
+    progress: &mut FileProgress,
+) -> std::io::Result<u64> {
+    let mut buf = vec![0; 64 * 1024];
+    let mut len = 0;
+    loop {
+        let n = match decompressor.read(&mut buf) {
+            Ok(0) => return Ok(len),
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        dest.write_all(&buf[..n])?;
+        len += n as u64;
+        let position = decompressor.position();
+        progress_bar.set_position(position.compressed_byte as u64);
+        progress.events(&decompressor.take_events());
+        progress.position(position.compressed_byte as u64, Some(position.uncompressed_byte as u64));
+    }
+}
+
+#[derive(Serialize)]
+struct MemberHeader<'a> {
+    member: usize,
+    from_byte: usize,
+    mtime_utc: Option<String>,
+    hcrc_matches: bool,
+    #[serde(flatten)]
+    header: &'a GzipHeader,
+}
+
+fn member_header(i: usize, member: &GzipMember) -> MemberHeader<'_> {
+    // an mtime of 0 means there isn't one.
+    let mtime_utc = match member.header.mtime {
+        0 => None,
+        secs => Some(humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(secs as u64)).to_string()),
+    };
+    MemberHeader {
+        member: i,
+        from_byte: member.from_byte,
+        mtime_utc,
+        hcrc_matches: member.hcrc_matches,
+        header: &member.header,
+    }
+}
+
+fn header(args: HeaderArgs, output: Output) -> Result<(), Failure> {
+    let file = open_file(&args.file_name)?;
+    let mut bf = BufReader::new(file);
+    if !matches!(detect_format(&mut bf), Ok(Format::Gzip | Format::Bgzf)) {
+        return Err(Failure::new(Exit::CorruptInput, "This doesn't look like a GZIP file"));
+    }
+    // members have to be decompressed to find where the next one starts. The checkpoints are thrown away.
+    let checkpointer = Checkpointer::init_memory()?;
+    let mut decompressor = Deflator::new(CorniferByteReader::new(bf), checkpointer);
+    // a wrong header CRC is one of the things worth looking at the headers for.
+    decompressor.set_options(DeflatorOptions {
+        trailing_data: TrailingData::Ignore,
+        footer_checks: FooterChecks::Lenient,
+        header_checks: HeaderValidation::Ignore,
+        recover: false,
+        keep_members: true,
+        ..Default::default()
+    });
+    std::io::copy(&mut decompressor, &mut sink()).map_err(|e| {
+        let position = decompressor.position();
+        Failure::from(e).context(format_args!("at byte {:#x} of the file", position.compressed_byte))
+    })?;
+
+    let headers: Vec<MemberHeader> = decompressor.members().iter().enumerate().map(|(i, m)| member_header(i, m)).collect();
+    if output.json {
+        println!("{}", serde_json::to_string_pretty(&headers)?);
+        return Ok(());
+    }
+    for h in headers {
+        println!("Member {} at {:#x}", h.member, h.from_byte);
+        println!("  name: {}", h.header.name.as_deref().unwrap_or("(none)"));
+        println!("  comment: {}", h.header.comment.as_deref().unwrap_or("(none)"));
+        println!("  mtime: {}", h.mtime_utc.as_deref().unwrap_or("(none)"));
+        println!("  os: {:?} ({})", h.header.os, h.header.os_byte());
+        println!("  xfl: {:?} ({})", h.header.extra, h.header.xfl_byte());
+        println!("  header length: {} bytes", h.header.span.len());
+        println!("  text: {}", h.header.text);
+        match (h.header.has_hcrc, h.hcrc_matches) {
+            (true, false) => println!("  header crc: true (incorrect)"),
+            (has_hcrc, _) => println!("  header crc: {has_hcrc}"),
+        }
+        for field in &h.header.extra_fields {
+            println!("  extra subfield {}: {} bytes", String::from_utf8_lossy(&field.id), field.data.len());
+        }
+    }
+    Ok(())
+}
+
+// what decompressing a file found, for --json.
+#[derive(Serialize)]
+struct InfoReport {
+    file: String,
+    format: String,
+    members: usize,
+    compressed_len: u64,
+    uncompressed_len: u64,
+    #[serde(serialize_with = "serialize_crc32")]
+    crc32: Option<u32>,
+    stats: Option<Stats>,
+}
+
+fn info(args: InfoArgs, output: Output) -> Result<(), Failure> {
+    let file = open_file(&args.file_name)?;
+    let compressed_len = file.metadata()?.len();
+    let mut bf = BufReader::new(file);
+    let format = detect_format(&mut bf)?;
+    let mut decompressor = Deflator::new_auto(bf, Checkpointer::none())?;
+    if args.stats {
+        decompressor.collect_stats();
+    }
+    std::io::copy(&mut decompressor, &mut sink()).map_err(|e| {
+        let position = decompressor.position();
+        Failure::from(e).context(format_args!("at byte {:#x} of the file", position.compressed_byte))
+    })?;
+    let report = InfoReport {
+        file: args.file_name,
+        format: format.to_string(),
+        members: decompressor.member_count(),
+        compressed_len,
+        uncompressed_len: decompressor.position().uncompressed_byte as u64,
+        crc32: Some(decompressor.total_crc32()),
+        stats: decompressor.stats().cloned(),
+    };
+    if output.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+    match (format, report.members) {
+        // zlib doesn't have members.
+        (Format::Zlib, _) => println!("{}: {}", report.file, report.format),
+        (_, 1) => println!("{}: {}, 1 member", report.file, report.format),
+        (_, n) => println!("{}: {}, {} members", report.file, report.format, n),
+    }
+    println!(
+        "{} bytes compressed, {} bytes decompressed ({:.2}x)",
+        report.compressed_len,
+        report.uncompressed_len,
+        report.uncompressed_len as f64 / report.compressed_len.max(1) as f64
+    );
+    if let Some(stats) = &report.stats {
+        print_stats(stats);
+    }
+    Ok(())
+}
+
+fn print_stats(stats: &Stats) {
+    println!(
+        "blocks: {} stored, {} fixed, {} dynamic",
+        stats.stored_blocks, stats.fixed_blocks, stats.dynamic_blocks
+    );
+    let matched: u64 = stats.match_lengths.iter().enumerate().map(|(len, n)| len as u64 * n).sum();
+    println!(
+        "literals: {}, matches: {} (average length {:.1})",
+        stats.literals,
+        stats.matches,
+        matched as f64 / stats.matches.max(1) as f64
+    );
+    // in powers of two, since there are 256 possible lengths.
+    println!("match lengths:");
+    for (from, to) in [(3, 4), (5, 8), (9, 16), (17, 32), (33, 64), (65, 128), (129, 258)] {
+        let n: u64 = stats.match_lengths[from..=to].iter().sum();
+        println!("  {from:>3}-{to:<3} {n}");
+    }
+    let mut worst: Vec<_> = stats.blocks.iter().filter(|b| b.uncompressed_len > 0).collect();
+    worst.sort_by(|a, b| a.ratio().total_cmp(&b.ratio()));
+    println!("least compressed blocks:");
+    for block in worst.iter().take(5) {
+        println!(
+            "  {:?} block at byte {:#x} bit {}: {} bytes from {} bits ({:.2}x), {} literals, {} matches, longest {}",
+            block.block_type,
+            block.from_byte,
+            block.from_bit,
+            block.uncompressed_len,
+            block.compressed_bits,
+            block.ratio(),
+            block.literals,
+            block.matches,
+            block.longest_match
+        );
+    }
+}
+
+// the fastest of a few runs, in MB/s of decompressed output.
+fn time_decoder<F: FnMut() -> Result<u64, std::io::Error>>(iterations: u32, mut decode: F) -> Result<f64, std::io::Error> {
+    let mut best = f64::MAX;
+    let mut len = 0;
+    for _ in 0..iterations.max(1) {
+        let start = Instant::now();
+        len = decode()?;
+        best = best.min(start.elapsed().as_secs_f64());
+    }
+    Ok(len as f64 / best / 1_000_000.0)
+}
+
+// what verifying a file found, for --json.
+#[derive(Serialize)]
+struct VerifyReport {
+    file: String,
+    index: String,
+    ok: bool,
+    error: Option<String>,
+    uncompressed_len: Option<u64>,
+    #[serde(serialize_with = "serialize_crc32")]
+    crc32: Option<u32>,
+    // with --fast.
+    blocks_checked: Option<usize>,
+    corruption: Option<CorruptionReport>,
+    duration_secs: f64,
+}
+
+// what `cornifer recompress` wrote, for --json.
+#[derive(Serialize)]
+struct RecompressReport {
+    output: String,
+    index: String,
+    uncompressed_len: u64,
+    compressed_len: u64,
+    checkpoints: u64,
+}
+
+// where the blocks `cornifer extract` copied are, for --json.
+#[derive(Serialize)]
+struct ExtractReport {
+    from: u64,
+    prefix_len: u64,
+    len: u64,
+    #[serde(serialize_with = "serialize_crc32")]
+    crc32: Option<u32>,
+}
+
+impl From<ExtractedMember> for ExtractReport {
+    fn from(extracted: ExtractedMember) -> Self {
+        Self {
+            from: extracted.from,
+            prefix_len: extracted.prefix_len,
+            len: extracted.len,
+            crc32: Some(extracted.crc32),
+        }
+    }
+}
+
+// a file `cornifer split` wrote, for --json.
+#[derive(Serialize)]
+struct SplitReport {
+    path: PathBuf,
+    from: u64,
+    to: u64,
+    compressed_len: u64,
+}
+
+// the hashes `cornifer cat` worked out, for --json.
+#[derive(Serialize)]
+struct CatReport {
+    len: u64,
+    hashes: BTreeMap<String, String>,
+}
+
+fn cat(args: CatArgs, output: Output) -> Result<(), Failure> {
+    let index = match &args.index {
+        Some(index) => index.clone(),
+        None => cli::derived_checkpoint_path(Path::new(&args.file_name)).to_string_lossy().into_owned(),
+    };
+    let (file, _) = open_input(&args.file_name, false)?;
+    let mut reader = match &args.source {
+        Some(name) => CheckpointedReader::open_source(BufReader::new(file), &index, name)?,
+        None => CheckpointedReader::open(BufReader::new(file), &index)?,
+    };
+    let to = args.to.unwrap_or(u64::MAX).min(reader.uncompressed_len());
+    if args.from > to {
+        return Err(Failure::new(Exit::Failed, format!("--from {} is after the end, {to}", args.from)));
+    }
+
+    let outputs = match args.output.is_empty() {
+        true => vec![OutputSink::Stdout],
+        false => args.output,
+    };
+    let mut tee = Tee::default();
+    for sink in &outputs {
+        tee.push(match sink {
+            OutputSink::Stdout => Sink::Writer(Box::new(std::io::stdout().lock())),
+            OutputSink::File(path) => {
+                let file = fs::File::create(path)
+                    .map_err(|e| Failure::new(Exit::Io, format!("Could not create {}: {e}", path.display())))?;
+                Sink::Writer(Box::new(std::io::BufWriter::new(file)))
+            }
+            OutputSink::Hash(algorithm) => Sink::Hash(HashSink::new(*algorithm)),
+        });
+    }
+    reader.seek(SeekFrom::Start(args.from))?;
+    let len = std::io::copy(&mut reader.take(to - args.from), &mut tee)?;
+    let hashes = tee.finish()?;
+
+    // the hashes go to stdout, unless the data did.
+    let mut report: Box<dyn Write> = match outputs.contains(&OutputSink::Stdout) {
+        true => Box::new(std::io::stderr()),
+        false => Box::new(std::io::stdout()),
+    };
+    if output.json {
+        let hashes = hashes.into_iter().map(|h| (h.algorithm.to_string(), h.hex)).collect();
+        writeln!(report, "{}", serde_json::to_string_pretty(&CatReport { len, hashes })?)?;
+    } else {
+        // asked for, so not left out by --quiet.
+        for hash in hashes {
+            writeln!(report, "{}:{}", hash.algorithm, hash.hex)?;
+        }
+    }
+    Ok(())
+}
+
+fn peek(args: PeekArgs) -> Result<(), Failure> {
+    let index = match &args.index {
+        Some(index) => index.clone(),
+        None => cli::derived_checkpoint_path(Path::new(&args.file_name)).to_string_lossy().into_owned(),
+    };
+    let (file, _) = open_input(&args.file_name, false)?;
+    let mut reader = match &args.source {
+        Some(name) => CheckpointedReader::open_source(BufReader::new(file), &index, name)?,
+        None => CheckpointedReader::open(BufReader::new(file), &index)?,
+    };
+    if args.offset >= reader.uncompressed_len() {
+        return Err(Failure::new(
+            Exit::Failed,
+            format!("--offset {} is past the end, {}", args.offset, reader.uncompressed_len()),
+        ));
+    }
+    let window = hexdump::window(args.offset, args.len, reader.uncompressed_len());
+    let mut data = vec![0; (window.end - window.start) as usize];
+    reader.seek(SeekFrom::Start(window.start))?;
+    reader.read_exact(&mut data)?;
+
+    let mut stdout = std::io::stdout().lock();
+    if args.hex {
+        for (i, row) in data.chunks(hexdump::BYTES_PER_LINE).enumerate() {
+            writeln!(stdout, "{}", hexdump::hex_line(window.start + (i * hexdump::BYTES_PER_LINE) as u64, row))?;
+        }
+    } else {
+        stdout.write_all(&data)?;
+    }
+    Ok(())
+}
+
+fn recompress(args: RecompressArgs, output: Output) -> Result<(), Failure> {
+    let (file, _) = open_input(&args.file_name, true)?;
+    let mut bf = BufReader::new(file);
+    let mut input: Box<dyn Read> = match detect_format(&mut bf) {
+        Ok(Format::Gzip | Format::Bgzf | Format::Zlib) => Box::new(Deflator::new_auto(bf, Checkpointer::none())?),
+        Ok(format) => return Err(Failure::new(Exit::Unsupported, format!("Can only recompress gzip and zlib files, not {format}"))),
+        // not compressed, or at least not in a way we know about.
+        Err(_) => Box::new(bf),
+    };
+    let index = args.index.clone().unwrap_or_else(|| cli::derived_checkpoint_path(&args.output));
+    let create_mode = if args.force { CreateMode::Overwrite } else { CreateMode::CreateNew };
+    let checkpointer = Checkpointer::open(&index, create_mode).map_err(|e| match e {
+        CorniferError::IndexAlreadyExists { .. } => Failure::from(e).context("pass --force to replace it"),
+        e => Failure::from(e).context("couldn't create the checkpoint file"),
+    })?;
+    let file = fs::File::create(&args.output)
+        .map_err(|e| Failure::new(Exit::Io, format!("Could not create {}: {e}", args.output.display())))?;
+    let level = Compression::new(args.level);
+    let mut writer = GzipWriter::new(std::io::BufWriter::new(file), checkpointer, level, args.interval)?;
+    std::io::copy(&mut input, &mut writer)?;
+    let (_, member) = writer.finish()?;
+
+    let report = RecompressReport {
+        output: args.output.display().to_string(),
+        index: index.display().to_string(),
+        uncompressed_len: member.len,
+        compressed_len: fs::metadata(&args.output)?.len(),
+        checkpoints: member.len.div_ceil(args.interval.max(1)),
+    };
+    if output.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else if !output.quiet {
+        println!(
+            "Wrote {} bytes to {}, {} compressed, with {} checkpoints in {}",
+            report.uncompressed_len, report.output, report.compressed_len, report.checkpoints, report.index
+        );
+    }
+    Ok(())
+}
+
+fn extract(args: ExtractArgs, output: Output) -> Result<(), Failure> {
+    let index = match &args.index {
+        Some(index) => index.clone(),
+        None => cli::derived_checkpoint_path(Path::new(&args.file_name)).to_string_lossy().into_owned(),
+    };
+    let (file, _) = open_input(&args.file_name, false)?;
+    let mut reader = CheckpointedReader::open(BufReader::new(file), &index)?;
+    let range = match &args.member {
+        Some(name) => reader.member_by_name(name)?.uncompressed_range(),
+        None => args.from..args.to.unwrap_or(u64::MAX).min(reader.uncompressed_len()),
+    };
+    let (member, extracted) = reader.extract_member(range.start, range.end, args.window)?;
+    fs::write(&args.output, member)
+        .map_err(|e| Failure::new(Exit::Io, format!("Could not write {}: {e}", args.output.display())))?;
+
+    if output.json {
+        println!("{}", serde_json::to_string_pretty(&ExtractReport::from(extracted))?);
+    } else if !output.quiet {
+        // the blocks don't start and end exactly where asked, so say where they do.
+        let blocks_from = extracted.from;
+        let blocks_to = blocks_from + extracted.len - extracted.prefix_len;
+        println!("Wrote {}..{blocks_to} of the decompressed file to {}", blocks_from, args.output.display());
+        if extracted.prefix_len > 0 {
+            println!("It starts with the {} bytes before that, as the window", extracted.prefix_len);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct EstimateReport {
+    #[serde(flatten)]
+    estimate: SizeEstimate,
+    exact: bool,
+}
+
+fn split(args: SplitArgs, output: Output) -> Result<(), Failure> {
+    let index = match &args.index {
+        Some(index) => index.clone(),
+        None => cli::derived_checkpoint_path(Path::new(&args.file_name)).to_string_lossy().into_owned(),
+    };
+    let prefix = match args.prefix {
+        Some(prefix) => prefix,
+        None => {
+            let name = Path::new(&args.file_name).file_name().map_or(args.file_name.clone(), |n| n.to_string_lossy().into_owned());
+            PathBuf::from(name.strip_suffix(".gz").unwrap_or(&name))
+        }
+    };
+    if args.chunk_size == 0 {
+        return Err(Failure::new(Exit::Failed, "--chunk-size has to be more than 0"));
+    }
+    let (file, _) = open_input(&args.file_name, false)?;
+    let mut reader = CheckpointedReader::open(BufReader::new(file), &index)?;
+
+    let mut reports = Vec::new();
+    for (i, range) in reader.split_points(args.chunk_size).into_iter().enumerate() {
+        let part = reader.split_part(range.clone(), Compression::new(args.level))?;
+        let path = PathBuf::from(format!("{}.{i:03}.gz", prefix.display()));
+        fs::write(&path, &part).map_err(|e| Failure::new(Exit::Io, format!("Could not write {}: {e}", path.display())))?;
+        if !output.json && !output.quiet {
+            println!("Wrote {}..{} of the decompressed file to {}", range.start, range.end, path.display());
+        }
+        reports.push(SplitReport {
+            path,
+            from: range.start,
+            to: range.end,
+            compressed_len: part.len() as u64,
+        });
+    }
+    if output.json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    }
+    Ok(())
+}
+
+fn estimate(args: EstimateArgs, output: Output) -> Result<(), Failure> {
+    let from_index = match &args.index {
+        Some(index) => Some(estimate_from_index(index)?),
+        // one left over from before might not be for this file any more, so it's only used if it's complete.
+        None => estimate_from_index(cli::derived_checkpoint_path(Path::new(&args.file_name))).ok(),
+    };
+    let estimate = match from_index {
+        Some(estimate) => estimate,
+        None => {
+            let (file, _) = open_input(&args.file_name, true)?;
+            estimate_size(file)?
+        }
+    };
+    if output.json {
+        let exact = estimate.is_exact();
+        println!("{}", serde_json::to_string_pretty(&EstimateReport { estimate, exact })?);
+        return Ok(());
+    }
+    let about = if estimate.is_exact() { "" } else { "about " };
+    println!(
+        "{about}{} bytes decompressed, in {} members (from the {})",
+        estimate.uncompressed_len, estimate.members, estimate.method
+    );
+    if estimate.ambiguous_members > 0 && !output.quiet {
+        println!(
+            "{} members are big enough to be over 4GiB decompressed, which their footers can't say, so it could be \
+             more by a multiple of 4GiB.",
+            estimate.ambiguous_members
+        );
+    }
+    Ok(())
+}
+
+fn verify(args: VerifyArgs, output: Output) -> Result<(), Failure> {
+    let start = Instant::now();
+    let index = match &args.index {
+        Some(index) => index.clone(),
+        None => cli::derived_checkpoint_path(Path::new(&args.file_name)).to_string_lossy().into_owned(),
+    };
+    let (file, _) = open_input(&args.file_name, false)?;
+    let meta = CheckpointStore::open(&index).and_then(|store| store.meta()).unwrap_or_default();
+    let (result, corruption) = match CheckpointedReader::open(BufReader::new(file), &index) {
+        Ok(mut reader) if args.fast => (reader.verify_compressed().map(Some), None),
+        Ok(mut reader) => {
+            let result = reader.verify().map(|()| None);
+            let corruption = match (&result, &args.report) {
+                (Err(_), Some(_)) => Some(reader.corruption_report()?),
+                _ => None,
+            };
+            (result, corruption)
+        }
+        // the checkpoint file can't be read, so there's nothing to go on for a report.
+        Err(err) => (Err(err), None),
+    };
+    if let (Some(corruption), Some(path)) = (&corruption, &args.report) {
+        std::fs::write(path, serde_json::to_string_pretty(corruption)?)?;
+        if !output.quiet && !output.json {
+            eprintln!(
+                "Wrote a report to {path}: {} damaged ranges, {} bytes can still be read.",
+                corruption.damaged.len(),
+                corruption.recoverable.iter().map(|r| r.to - r.from).sum::<u64>()
+            );
+        }
+    }
+    if output.json {
+        let report = VerifyReport {
+            file: args.file_name,
+            index,
+            ok: result.is_ok(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+            uncompressed_len: meta.uncompressed_len,
+            crc32: meta.crc32,
+            blocks_checked: result.as_ref().ok().copied().flatten(),
+            corruption,
+            duration_secs: start.elapsed().as_secs_f64(),
+        };
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    }
+    let checked = result.map_err(|e| Failure::from(e).context("the file doesn't match its checkpoint file"))?;
+    match checked {
+        _ if output.quiet || output.json => (),
+        Some(blocks) => println!("OK, the compressed bytes of {blocks} blocks match the checkpoint file."),
+        None => println!("OK, the file matches its checkpoint file."),
+    }
+    Ok(())
+}
+
+fn fsck(args: FsckArgs, output: Output) -> Result<(), Failure> {
+    let problems = check_index(&args.index)?;
+    if output.json {
+        println!("{}", serde_json::to_string_pretty(&problems)?);
+    } else if !output.quiet {
+        for problem in &problems {
+            println!("{problem}");
+        }
+    }
+    if problems.is_empty() {
+        if !output.quiet && !output.json {
+            println!("OK, nothing wrong with {}.", args.index);
+        }
+        return Ok(());
+    }
+    let message = match problems.iter().any(|p| p.fix == Fix::Rebuild) {
+        true => format!("{} problems, index the file again to fix them", problems.len()),
+        false => format!("{} problems, deleting the rows above or running cornifer repair would fix them", problems.len()),
+    };
+    Err(Failure::new(Exit::CorruptIndex, message))
+}
+
+fn serve(args: ServeArgs, output: Output) -> Result<(), Failure> {
+    if !args.index.is_empty() && args.index.len() != args.file_names.len() {
+        return Err(Failure::new(Exit::Failed, "Give an --index for every file, or none of them"));
+    }
+    let mut files: Vec<serve::Served> = Vec::new();
+    for (i, file_name) in args.file_names.iter().enumerate() {
+        let path = Path::new(file_name);
+        let name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        if files.iter().any(|f| f.name == name) {
+            return Err(Failure::new(Exit::Failed, format!("Two of the files would both be at /{name}")));
+        }
+        let index = match args.index.get(i) {
+            Some(index) => index.clone(),
+            None => cli::derived_checkpoint_path(path).to_string_lossy().into_owned(),
+        };
+        files.push(serve::Served { name, file_name: file_name.clone(), index });
+    }
+    // opened here first, so a file that can't be served is found out before anything's listening.
+    let readers = serve::open_all(&files)?;
+    let server = tiny_http::Server::http(&args.listen)
+        .map_err(|e| Failure::new(Exit::Io, format!("Could not listen on {}: {e}", args.listen)))?;
+    if !output.quiet {
+        eprintln!("Serving {} files at http://{}/", files.len(), args.listen);
+    }
+    std::thread::scope(|s| {
+        for _ in 1..args.jobs.max(1) {
+            s.spawn(|| match serve::open_all(&files) {
+                Ok(readers) => serve::answer_requests(&server, readers),
+                Err(e) => eprintln!("error: {e}"),
+            });
+        }
+        serve::answer_requests(&server, readers);
+    });
+    Ok(())
+}
+
+#[cfg(feature = "tui")]
+fn tui(args: TuiArgs) -> Result<(), Failure> {
+    let term = console::Term::stdout();
+    if !term.is_term() {
+        return Err(Failure::new(Exit::Failed, "cornifer tui needs a terminal, try cornifer peek instead"));
+    }
+    let index = match &args.index {
+        Some(index) => index.clone(),
+        None => cli::derived_checkpoint_path(Path::new(&args.file_name)).to_string_lossy().into_owned(),
+    };
+    let (file, _) = open_input(&args.file_name, false)?;
+    let reader = match &args.source {
+        Some(name) => CheckpointedReader::open_source(BufReader::new(file), &index, name)?,
+        None => CheckpointedReader::open(BufReader::new(file), &index)?,
+    };
+    let name = Path::new(&args.file_name).file_name().map_or(args.file_name.clone(), |n| n.to_string_lossy().into_owned());
+    tui::Explorer::new(reader, &name).run(&term)?;
+    Ok(())
+}
+
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+fn mount(args: MountArgs, output: Output) -> Result<(), Failure> {
+    let index = match &args.index {
+        Some(index) => index.clone(),
+        None => cli::derived_checkpoint_path(Path::new(&args.file_name)).to_string_lossy().into_owned(),
+    };
+    let file = open_file(&args.file_name)?;
+    let mtime = file.metadata()?.modified()?.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let mut reader = CheckpointedReader::open(BufReader::new(file), &index)?;
+    reader.set_cache(args.cache << 20);
+    let len = reader.uncompressed_len();
+    let name = match args.name {
+        Some(name) => name,
+        None => match Path::new(&args.file_name).file_stem() {
+            Some(stem) => stem.to_string_lossy().into_owned(),
+            None => return Err(Failure::new(Exit::Failed, "Can't tell what to call the file, use --name")),
+        },
+    };
+    let mountpoint = Path::new(&args.mountpoint);
+    let mut dev = mount::mount(mountpoint).map_err(|e| Failure::from(e).context("couldn't mount it"))?;
+    if !output.quiet {
+        eprintln!(
+            "Mounted {} at {}, unmount with `fusermount -u {}` to stop.",
+            name,
+            mountpoint.display(),
+            mountpoint.display()
+        );
+    }
+    mount::Filesystem::new(reader, &name, len, mtime).serve(&mut dev)?;
+    Ok(())
+}
+
+// what comparing the two decoders found, for --json.
+#[derive(Serialize)]
+struct SelftestReport {
+    file: String,
+    ok: bool,
+    uncompressed_len: Option<u64>,
+    divergence: Option<selftest::Divergence>,
+}
+
+fn selftest(args: SelftestArgs, output: Output) -> Result<(), Failure> {
+    let mut bf = BufReader::new(open_file(&args.file_name)?);
+    let format = detect_format(&mut bf)?;
+    // each decoder gets its own handle on the file.
+    let other = BufReader::new(open_file(&args.file_name)?);
+    let mut theirs: Box<dyn std::io::Read> = match format {
+        Format::Gzip | Format::Bgzf => Box::new(MultiGzDecoder::new(other)),
+        Format::Zlib => Box::new(flate2::read::ZlibDecoder::new(other)),
+        format => return Err(Failure::new(Exit::Unsupported, format!("Can only compare gzip and zlib files, not {format}"))),
+    };
+    let mut ours = Deflator::new_auto(bf, Checkpointer::none())?;
+    let result = selftest::compare(&mut ours, &mut theirs);
+    if output.json {
+        let report = SelftestReport {
+            file: args.file_name.clone(),
+            ok: result.is_ok(),
+            uncompressed_len: result.as_ref().ok().copied(),
+            divergence: result.as_ref().err().cloned(),
+        };
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    }
+    let divergence = match result {
+        Ok(len) => {
+            if !output.quiet && !output.json {
+                println!("OK, both decoders agree on all {len} bytes.");
+            }
+            return Ok(());
+        }
+        Err(divergence) => divergence,
+    };
+    let message = match &divergence {
+        selftest::Divergence::Byte { ours, theirs, .. } => format!("Cornifer decoded {ours:#04x} but flate2 decoded {theirs:#04x}"),
+        selftest::Divergence::Length { ours_ended: true, .. } => "Cornifer stopped but flate2 kept going".to_string(),
+        selftest::Divergence::Length { ours_ended: false, .. } => "flate2 stopped but Cornifer kept going".to_string(),
+        selftest::Divergence::Error { ours: true, error, .. } => format!("Cornifer failed: {error}"),
+        selftest::Divergence::Error { ours: false, error, .. } => format!("flate2 failed: {error}"),
+    };
+    Err(Failure::new(Exit::Mismatch, message).context(format_args!("at byte {} of the decompressed output", divergence.offset())))
+}
+
+// what repairing a checkpoint file had to decode again, for --json.
+#[derive(Serialize)]
+struct RepairReport {
+    file: String,
+    index: String,
+    repaired: bool,
+    from_uncompressed_byte: u64,
+    uncompressed_len: u64,
+    #[serde(serialize_with = "serialize_crc32")]
+    crc32: Option<u32>,
+    duration_secs: f64,
+}
+
+fn repair(args: RepairArgs, output: Output) -> Result<(), Failure> {
+    let start = Instant::now();
+    let index = match args.index {
+        Some(index) => index.into(),
+        None => cli::derived_checkpoint_path(Path::new(&args.file_name)),
+    };
+    let repaired = !CheckpointStore::open(&index)?.meta()?.complete;
+    let file = open_file(&args.file_name)?;
+    let mut decompressor = Deflator::repair(BufReader::new(file), &index)
+        .map_err(|e| Failure::from(e).context("couldn't carry on from the checkpoint file"))?;
+    let from = decompressor.position().uncompressed_byte as u64;
+    std::io::copy(&mut decompressor, &mut sink()).map_err(|e| {
+        let position = decompressor.position();
+        Failure::from(e).context(format_args!(
+            "at byte {:#x} bit {} of the compressed file, byte {:#x} of the decompressed file",
+            position.compressed_byte, position.compressed_bit, position.uncompressed_byte
+        ))
+    })?;
+    let report = RepairReport {
+        file: args.file_name,
+        index: index.display().to_string(),
+        repaired,
+        from_uncompressed_byte: from,
+        uncompressed_len: decompressor.position().uncompressed_byte as u64,
+        crc32: Some(decompressor.total_crc32()),
+        duration_secs: start.elapsed().as_secs_f64(),
+    };
+    if output.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else if !output.quiet && !repaired {
+        println!("Nothing to repair, {} is already complete", report.index);
+    } else if !output.quiet {
+        println!(
+            "Repaired {} from byte {} of {} decompressed in {:.2?}",
+            report.index,
+            report.from_uncompressed_byte,
+            report.uncompressed_len,
+            start.elapsed()
+        );
+    }
+    Ok(())
+}
+
+// what updating a checkpoint file added, for --json.
+#[derive(Serialize)]
+struct UpdateReport {
+    file: String,
+    index: String,
+    new_members: usize,
+    new_uncompressed_len: u64,
+    uncompressed_len: u64,
+    #[serde(serialize_with = "serialize_crc32")]
+    crc32: Option<u32>,
+    duration_secs: f64,
+}
+
+fn update(args: UpdateArgs, output: Output) -> Result<(), Failure> {
+    let start = Instant::now();
+    let index = match args.index {
+        Some(index) => index.into(),
+        None => cli::derived_checkpoint_path(Path::new(&args.file_name)),
+    };
+    let file = open_file(&args.file_name)?;
+    let mut decompressor = Deflator::update(BufReader::new(file), &index)
+        .map_err(|e| Failure::from(e).context("couldn't carry on from the checkpoint file"))?;
+    let from = decompressor.position().uncompressed_byte as u64;
+    std::io::copy(&mut decompressor, &mut sink()).map_err(|e| {
+        let position = decompressor.position();
+        Failure::from(e).context(format_args!(
+            "at byte {:#x} bit {} of the compressed file, byte {:#x} of the decompressed file",
+            position.compressed_byte, position.compressed_bit, position.uncompressed_byte
+        ))
+    })?;
+    let report = UpdateReport {
+        file: args.file_name,
+        index: index.display().to_string(),
+        new_members: decompressor.member_count(),
+        new_uncompressed_len: decompressor.position().uncompressed_byte as u64 - from,
+        uncompressed_len: decompressor.position().uncompressed_byte as u64,
+        crc32: Some(decompressor.total_crc32()),
+        duration_secs: start.elapsed().as_secs_f64(),
+    };
+    if output.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else if !output.quiet {
+        println!(
+            "Added {} members ({} bytes decompressed) to {} in {:.2?}",
+            report.new_members,
+            report.new_uncompressed_len,
+            report.index,
+            start.elapsed()
+        );
+    }
+    Ok(())
+}
+
+fn bench(args: BenchArgs) -> Result<(), Failure> {
+    let input = fs::read(args.file_name)?;
+    let cornifer = time_decoder(args.iterations, || {
+        let checkpointer = Checkpointer::init_memory().map_err(std::io::Error::other)?;
+        let mut decompressor = Deflator::new(CorniferByteReader::new(input.as_slice()), checkpointer);
+        std::io::copy(&mut decompressor, &mut sink())
+    })?;
+    let flate2 = time_decoder(args.iterations, || std::io::copy(&mut MultiGzDecoder::new(input.as_slice()), &mut sink()))?;
+    println!("cornifer: {cornifer:.1} MB/s");
+    println!("flate2:   {flate2:.1} MB/s");
+    Ok(())
+}
+
+fn ls(args: LsArgs, output: Output) -> Result<(), Failure> {
+    let store = CheckpointStore::open(&args.checkpoint_file)?;
+    let mut entries = store.entries()?;
+    if entries.is_empty() {
+        // catted gzip files are an archive too, if their members have names.
+        entries = store.members()?.into_iter().filter_map(member_entry).collect();
+    }
+    if output.json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+    for entry in entries {
+        let mtime = match entry.mtime {
+            Some(secs) if secs >= 0 => humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(secs as u64)).to_string(),
+            _ => "-".to_string(),
+        };
+        println!("{:>12} {:>12} {:20} {}", entry.size, entry.offset, mtime, entry.name);
+    }
+    Ok(())
+}
+
+fn member_entry(member: Member) -> Option<ArchiveEntry> {
+    Some(ArchiveEntry {
+        name: member.name?,
+        size: member.len,
+        offset: member.to_byte,
+        // 0 means there isn't one.
+        mtime: (member.mtime != 0).then_some(member.mtime as i64),
+    })
+}
+
+fn schema(args: SchemaArgs, output: Output) -> Result<(), Failure> {
+    if output.json {
+        println!("{}", serde_json::to_string_pretty(&SCHEMA)?);
+        return Ok(());
+    }
+    if args.sql {
+        for table in SCHEMA.tables {
+            println!("{};", table.create_sql());
+        }
+        println!("PRAGMA user_version = {};", SCHEMA.version);
+        return Ok(());
+    }
+    println!("Checkpoint files are sqlite databases. This is version {} (PRAGMA user_version).", SCHEMA.version);
+    for table in SCHEMA.tables {
+        println!("\n{}: {}", table.name, table.description);
+        for column in table.columns {
+            let mut kind = column.sql_type.to_string();
+            if column.primary_key {
+                kind.push_str(" PRIMARY KEY");
+            }
+            if column.not_null {
+                kind.push_str(" NOT NULL");
+            }
+            println!("  {:18} {:20} {}", column.name, kind, column.description);
+        }
+    }
+    println!("\nMeta keys:");
+    for meta in SCHEMA.meta_keys {
+        println!("  {:18} {}", meta.key, meta.description);
+    }
+    Ok(())
+}
+
+// the lines that start in [from, to), and their offsets. Each thread has its own cursor on the same file.
+fn grep_range(index: &str, file: &fs::File, regex: &Regex, from: u64, to: u64) -> Result<Vec<(u64, Vec<u8>)>, std::io::Error> {
+    let source = BufReader::new(PositionalReader::new(file));
+    let reader = CheckpointedReader::open(source, index).map_err(std::io::Error::other)?;
+    // an empty range, or one starting at or past the end, has no lines in it.
+    if from >= to || from >= reader.uncompressed_len() {
+        return Ok(Vec::new());
+    }
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+    let mut pos = from;
+    if from > 0 {
+        // we're in the middle of a line unless the byte before is a newline.
+        reader.seek(SeekFrom::Start(from - 1))?;
+        let n = reader.read_until(b'\n', &mut line)?;
+        pos = pos.saturating_add((n as u64).saturating_sub(1));
+    }
+    let mut matches = Vec::new();
+    while pos < to {
+        line.clear();
+        let n = reader.read_until(b'\n', &mut line)?;
+        if n == 0 {
+            break;
+        }
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+        if regex.is_match(&line) {
+            matches.push((pos, line.clone()));
+        }
+        pos = pos.saturating_add(n as u64);
+    }
+    Ok(matches)
+}
+
+fn grep(args: GrepArgs) -> Result<(), Failure> {
+    let regex = Regex::new(&args.pattern).map_err(|e| Failure::new(Exit::Failed, e.to_string()))?;
+    let index = match &args.index {
+        Some(index) => index.clone(),
+        None => cli::derived_checkpoint_path(Path::new(&args.file_name)).to_string_lossy().into_owned(),
+    };
+    let file = open_file(&args.file_name)?;
+    let reader = CheckpointedReader::open(BufReader::new(PositionalReader::new(&file)), &index)?;
+    let to = args.to.unwrap_or(u64::MAX).min(reader.uncompressed_len());
+
+    // split at the checkpoints in the range, so each thread starts decoding where its share starts.
+    let starts: Vec<u64> = reader.blocks().iter().map(|b| b.to_byte).filter(|b| *b > args.from && *b < to).collect();
+    let jobs = args.jobs.clamp(1, starts.len() + 1);
+    let mut bounds = vec![args.from];
+    bounds.extend((1..jobs).map(|i| starts[i * starts.len() / jobs]));
+    bounds.push(to);
+
+    let results = std::thread::scope(|scope| {
+        let handles: Vec<_> = bounds
+            .windows(2)
+            .map(|w| {
+                let (index, file, regex) = (&index, &file, &regex);
+                scope.spawn(move || grep_range(index, file, regex, w[0], w[1]))
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join()).collect::<Vec<_>>()
+    });
+    let mut stdout = std::io::stdout().lock();
+    for result in results {
+        let result = result.map_err(|_| Failure::new(Exit::Failed, "a grep thread panicked"))?;
+        for (offset, line) in result? {
+            stdout.write_all(format!("{offset}:").as_bytes())?;
+            stdout.write_all(&line)?;
+            stdout.write_all(b"\n")?;
+        }
+    }
+    Ok(())
+}
+
+/**
+ * TESTS
+ */
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use regex::bytes::Regex;
+    use rstest::rstest;
+
+    use cornifer::checkpoint::Checkpointer;
+    use cornifer::decompress::Deflator;
+    use cornifer::reader::CorniferByteReader;
+
+    use crate::testutil::index_with_spacing;
+
+    use super::{grep, grep_range, verify, GrepArgs, Output, VerifyArgs};
+
+    #[rstest]
+    fn test_grep_range_at_the_end() {
+        let file_name = concat!(env!("CARGO_MANIFEST_DIR"), "/../cornifer/testfiles/1080-0.txt.gz");
+        let len = include_bytes!("../../cornifer/testfiles/1080-0.txt").len() as u64;
+        let index = index_with_spacing(&fs::read(file_name).unwrap(), 0);
+        let index_name = index.to_string_lossy().into_owned();
+
+        let args = GrepArgs {
+            pattern: "the".to_string(),
+            file_name: file_name.to_string(),
+            index: Some(index_name.clone()),
+            from: len,
+            to: None,
+            jobs: 1,
+        };
+        let file = fs::File::open(file_name).unwrap();
+        let regex = Regex::new(&args.pattern).unwrap();
+        let at_end = grep_range(&index_name, &file, &regex, len, len);
+        let past_end = grep_range(&index_name, &file, &regex, len + 10, u64::MAX);
+        let whole = grep_range(&index_name, &file, &regex, 0, len);
+        let from_end = grep(args);
+
+        assert!(at_end.unwrap().is_empty());
+        assert!(past_end.unwrap().is_empty());
+        assert!(!whole.unwrap().is_empty());
+        assert!(from_end.is_ok());
+    }
+
+    // without --index, it's the checkpoint file next to the file, like everything else.
+    #[rstest]
+    fn test_verify_default_index() {
+        let dir = std::env::temp_dir().join(format!("cornifer-verify-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file_name = dir.join("1080-0.txt.gz");
+        let input = include_bytes!("../../cornifer/testfiles/1080-0.txt.gz");
+        fs::write(&file_name, input).unwrap();
+        let checkpointer = Checkpointer::init(dir.join("1080-0.txt.gz.cornifer")).unwrap();
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), checkpointer);
+        std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        drop(deflator);
+
+        let args = VerifyArgs {
+            file_name: file_name.to_string_lossy().into_owned(),
+            index: None,
+            report: None,
+            fast: false,
+        };
+        let result = verify(args, Output { json: false, quiet: true });
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(result.is_ok());
+    }
+}
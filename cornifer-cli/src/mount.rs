@@ -0,0 +1,411 @@
+use std::ffi::CString;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::process::Command;
+
+/*
+ * A read-only FUSE filesystem with one file in it: the decompressed contents of a compressed file, read through
+ * its checkpoint file. Only the handful of requests a single read-only file needs are answered, so this speaks
+ * the kernel's protocol on /dev/fuse itself. The structs are laid out as in linux's include/uapi/linux/fuse.h.
+ */
+
+// the protocol version we speak, which is linux 5.4's. Newer kernels still speak it.
+const FUSE_KERNEL_VERSION: u32 = 7;
+const FUSE_KERNEL_MINOR_VERSION: u32 = 31;
+
+const FUSE_LOOKUP: u32 = 1;
+const FUSE_FORGET: u32 = 2;
+const FUSE_GETATTR: u32 = 3;
+const FUSE_OPEN: u32 = 14;
+const FUSE_READ: u32 = 15;
+const FUSE_STATFS: u32 = 17;
+const FUSE_RELEASE: u32 = 18;
+const FUSE_FLUSH: u32 = 25;
+const FUSE_INIT: u32 = 26;
+const FUSE_OPENDIR: u32 = 27;
+const FUSE_READDIR: u32 = 28;
+const FUSE_RELEASEDIR: u32 = 29;
+const FUSE_INTERRUPT: u32 = 36;
+const FUSE_DESTROY: u32 = 38;
+const FUSE_BATCH_FORGET: u32 = 42;
+
+// the kernel can keep what it's read, since the file never changes.
+const FOPEN_KEEP_CACHE: u32 = 1 << 1;
+
+const ROOT_ID: u64 = 1;
+const FILE_ID: u64 = 2;
+
+const IN_HEADER_LEN: usize = 40;
+const OUT_HEADER_LEN: usize = 16;
+// nothing is ever written, but the read buffer has to be big enough for the biggest write the kernel could send.
+const MAX_WRITE: u32 = 1 << 17;
+const BUFFER_LEN: usize = MAX_WRITE as usize + 4096;
+// how long the kernel can remember names and attributes for. Nothing ever changes, so a while.
+const TTL_SECS: u64 = 3600;
+
+// a reply body, in the kernel's byte order.
+#[derive(Default)]
+struct Out(Vec<u8>);
+
+impl Out {
+    fn u16(mut self, n: u16) -> Self {
+        self.0.extend(n.to_ne_bytes());
+        self
+    }
+
+    fn u32(mut self, n: u32) -> Self {
+        self.0.extend(n.to_ne_bytes());
+        self
+    }
+
+    fn u64(mut self, n: u64) -> Self {
+        self.0.extend(n.to_ne_bytes());
+        self
+    }
+
+    fn with(mut self, other: Out) -> Self {
+        self.0.extend(other.0);
+        self
+    }
+}
+
+fn u32_at(body: &[u8], at: usize) -> Result<u32, i32> {
+    let bytes = body.get(at..at + 4).ok_or(libc::EINVAL)?;
+    Ok(u32::from_ne_bytes(bytes.try_into().expect("Slice is 4 bytes")))
+}
+
+fn u64_at(body: &[u8], at: usize) -> Result<u64, i32> {
+    let bytes = body.get(at..at + 8).ok_or(libc::EINVAL)?;
+    Ok(u64::from_ne_bytes(bytes.try_into().expect("Slice is 8 bytes")))
+}
+
+/// Serves one decompressed file, as `name` in the root of the mount.
+pub struct Filesystem<R> {
+    reader: R,
+    name: Vec<u8>,
+    len: u64,
+    // of the compressed file, in seconds since the epoch.
+    mtime: u64,
+}
+
+impl<R: Read + Seek> Filesystem<R> {
+    pub fn new(reader: R, name: &str, len: u64, mtime: u64) -> Self {
+        Self {
+            reader,
+            name: name.as_bytes().to_vec(),
+            len,
+            mtime,
+        }
+    }
+
+    /// Answer requests until the filesystem is unmounted.
+    pub fn serve(&mut self, dev: &mut File) -> io::Result<()> {
+        let mut buf = vec![0; BUFFER_LEN];
+        loop {
+            let n = match dev.read(&mut buf) {
+                Ok(n) => n,
+                // unmounted.
+                Err(e) if e.raw_os_error() == Some(libc::ENODEV) => return Ok(()),
+                // the request was interrupted before we got to it.
+                Err(e) if matches!(e.raw_os_error(), Some(libc::ENOENT | libc::EINTR | libc::EAGAIN)) => continue,
+                Err(e) => return Err(e),
+            };
+            if n < IN_HEADER_LEN {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "short FUSE request"));
+            }
+            let opcode = u32::from_ne_bytes(buf[4..8].try_into().expect("Slice is 4 bytes"));
+            let unique = u64::from_ne_bytes(buf[8..16].try_into().expect("Slice is 8 bytes"));
+            let node = u64::from_ne_bytes(buf[16..24].try_into().expect("Slice is 8 bytes"));
+            let body = &buf[IN_HEADER_LEN..n];
+            let reply = match self.handle(opcode, node, body) {
+                Some(reply) => reply,
+                // forgets and interrupts don't get an answer.
+                None => continue,
+            };
+            let (error, body) = match reply {
+                Ok(body) => (0, body.0),
+                Err(errno) => (-errno, Vec::new()),
+            };
+            let out = Out::default()
+                .u32((OUT_HEADER_LEN + body.len()) as u32)
+                .u32(error as u32)
+                .u64(unique);
+            let mut message = out.0;
+            message.extend(body);
+            match dev.write(&message) {
+                Ok(_) => (),
+                // the request was interrupted while we were answering it.
+                Err(e) if e.raw_os_error() == Some(libc::ENOENT) => (),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn handle(&mut self, opcode: u32, node: u64, body: &[u8]) -> Option<Result<Out, i32>> {
+        let reply = match opcode {
+            FUSE_FORGET | FUSE_BATCH_FORGET | FUSE_INTERRUPT => return None,
+            FUSE_INIT => self.init(body),
+            FUSE_LOOKUP => self.lookup(node, body),
+            // attr_valid, attr_valid_nsec, dummy
+            FUSE_GETATTR => self.attr(node).map(|attr| Out::default().u64(TTL_SECS).u32(0).u32(0).with(attr)),
+            FUSE_OPEN if node != FILE_ID => Err(libc::EISDIR),
+            // O_RDONLY is 0, anything else wants to write.
+            FUSE_OPEN if u32_at(body, 0).map(|flags| flags & libc::O_ACCMODE as u32) != Ok(0) => Err(libc::EROFS),
+            FUSE_OPEN => Ok(Out::default().u64(0).u32(FOPEN_KEEP_CACHE).u32(0)),
+            FUSE_OPENDIR if node != ROOT_ID => Err(libc::ENOTDIR),
+            FUSE_OPENDIR => Ok(Out::default().u64(0).u32(0).u32(0)),
+            FUSE_READ => self.read(body),
+            FUSE_READDIR => self.readdir(body),
+            FUSE_STATFS => Ok(self.statfs()),
+            FUSE_RELEASE | FUSE_RELEASEDIR | FUSE_FLUSH | FUSE_DESTROY => Ok(Out::default()),
+            _ => Err(libc::ENOSYS),
+        };
+        Some(reply)
+    }
+
+    fn init(&self, body: &[u8]) -> Result<Out, i32> {
+        let major = u32_at(body, 0)?;
+        let max_readahead = u32_at(body, 8)?;
+        if major < FUSE_KERNEL_VERSION {
+            return Err(libc::EPROTO);
+        }
+        // if the kernel's major version is newer, it'll send INIT again with ours.
+        let out = Out::default()
+            .u32(FUSE_KERNEL_VERSION)
+            .u32(FUSE_KERNEL_MINOR_VERSION)
+            .u32(max_readahead)
+            // flags: none of the optional features.
+            .u32(0)
+            // max_background, congestion_threshold
+            .u16(16)
+            .u16(12)
+            .u32(MAX_WRITE)
+            // time_gran, max_pages, map_alignment, flags2, then 7 unused.
+            .u32(1)
+            .u16(0)
+            .u16(0);
+        Ok((0..8).fold(out, |out, _| out.u32(0)))
+    }
+
+    fn lookup(&self, node: u64, body: &[u8]) -> Result<Out, i32> {
+        let name = body.split(|b| *b == 0).next().unwrap_or_default();
+        if node != ROOT_ID || name != self.name {
+            return Err(libc::ENOENT);
+        }
+        let attr = self.attr(FILE_ID)?;
+        // nodeid, generation, entry_valid, attr_valid, entry_valid_nsec, attr_valid_nsec
+        Ok(Out::default().u64(FILE_ID).u64(0).u64(TTL_SECS).u64(TTL_SECS).u32(0).u32(0).with(attr))
+    }
+
+    fn attr(&self, node: u64) -> Result<Out, i32> {
+        let (size, mode, nlink) = match node {
+            ROOT_ID => (0, libc::S_IFDIR | 0o555, 2),
+            FILE_ID => (self.len, libc::S_IFREG | 0o444, 1),
+            _ => return Err(libc::ENOENT),
+        };
+        // SAFETY: these can't fail.
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        Ok(Out::default()
+            .u64(node)
+            .u64(size)
+            .u64(size.div_ceil(512))
+            // atime, mtime, ctime, then their nanoseconds.
+            .u64(self.mtime)
+            .u64(self.mtime)
+            .u64(self.mtime)
+            .u32(0)
+            .u32(0)
+            .u32(0)
+            .u32(mode)
+            .u32(nlink)
+            .u32(uid)
+            .u32(gid)
+            // rdev, blksize, flags
+            .u32(0)
+            .u32(4096)
+            .u32(0))
+    }
+
+    fn read(&mut self, body: &[u8]) -> Result<Out, i32> {
+        let offset = u64_at(body, 8)?;
+        let size = u32_at(body, 16)? as u64;
+        let len = size.min(self.len.saturating_sub(offset)) as usize;
+        let mut data = vec![0; len];
+        self.reader
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| self.reader.read_exact(&mut data))
+            .map_err(|_| libc::EIO)?;
+        Ok(Out(data))
+    }
+
+    fn readdir(&self, body: &[u8]) -> Result<Out, i32> {
+        let offset = u64_at(body, 8)?;
+        let size = u32_at(body, 16)? as usize;
+        let entries: &[(u64, &[u8], u8)] =
+            &[(ROOT_ID, b".", libc::DT_DIR), (ROOT_ID, b"..", libc::DT_DIR), (FILE_ID, &self.name, libc::DT_REG)];
+        Ok(dirents(entries, offset, size))
+    }
+
+    fn statfs(&self) -> Out {
+        // blocks, bfree, bavail, files, ffree
+        Out::default()
+            .u64(self.len.div_ceil(4096))
+            .u64(0)
+            .u64(0)
+            .u64(1)
+            .u64(0)
+            // bsize, namelen, frsize, padding, then 6 spare.
+            .u32(4096)
+            .u32(255)
+            .u32(4096)
+            .with((0..7).fold(Out::default(), |out, _| out.u32(0)))
+    }
+}
+
+// the directory entries after offset that fit in size bytes. Each entry's offset is where the next one starts.
+fn dirents(entries: &[(u64, &[u8], u8)], offset: u64, size: usize) -> Out {
+    let mut out = Out::default();
+    for (i, (node, name, kind)) in entries.iter().enumerate().skip(offset as usize) {
+        // ino, off, namelen, type, then the name padded to 8 bytes.
+        let len = (24 + name.len()).next_multiple_of(8);
+        if out.0.len() + len > size {
+            break;
+        }
+        out = out.u64(*node).u64(i as u64 + 1).u32(name.len() as u32).u32(*kind as u32);
+        out.0.extend(*name);
+        out.0.resize(out.0.len().next_multiple_of(8), 0);
+    }
+    out
+}
+
+/// Mount an empty FUSE filesystem at `mountpoint`, returning /dev/fuse to serve it from. Mounting directly needs
+/// root; anyone else goes through fusermount3 (or fusermount), like libfuse does.
+pub fn mount(mountpoint: &Path) -> io::Result<File> {
+    match mount_directly(mountpoint) {
+        Err(e) if e.raw_os_error() == Some(libc::EPERM) => mount_with_fusermount(mountpoint),
+        result => result,
+    }
+}
+
+fn mount_directly(mountpoint: &Path) -> io::Result<File> {
+    let dev = OpenOptions::new().read(true).write(true).open("/dev/fuse")?;
+    // SAFETY: these can't fail.
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    let options = CString::new(format!("fd={},rootmode=40000,user_id={uid},group_id={gid}", dev.as_raw_fd()))?;
+    let target = CString::new(mountpoint.as_os_str().as_bytes())?;
+    // SAFETY: the strings are all NUL terminated, and live until it returns.
+    let result = unsafe {
+        libc::mount(
+            c"cornifer".as_ptr(),
+            target.as_ptr(),
+            c"fuse.cornifer".as_ptr(),
+            libc::MS_NOSUID | libc::MS_NODEV | libc::MS_RDONLY,
+            options.as_ptr().cast(),
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(dev)
+}
+
+// fusermount mounts it, then sends the /dev/fuse descriptor back over the socket in _FUSE_COMMFD.
+fn mount_with_fusermount(mountpoint: &Path) -> io::Result<File> {
+    let mut fds = [0; 2];
+    // SAFETY: fds has room for the two descriptors.
+    if unsafe { libc::socketpair(libc::AF_UNIX, libc::SOCK_STREAM, 0, fds.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: socketpair just opened them, and nothing else owns them.
+    let (ours, theirs) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+    let run = |program: &str| {
+        Command::new(program)
+            .args(["-o", "ro,nosuid,nodev,fsname=cornifer,subtype=cornifer", "--"])
+            .arg(mountpoint)
+            .env("_FUSE_COMMFD", theirs.as_raw_fd().to_string())
+            .status()
+    };
+    let status = match run("fusermount3") {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => run("fusermount")?,
+        result => result?,
+    };
+    drop(theirs);
+    if !status.success() {
+        return Err(io::Error::other(format!("fusermount couldn't mount it ({status})")));
+    }
+    receive_fd(&ours)
+}
+
+fn receive_fd(socket: &OwnedFd) -> io::Result<File> {
+    let mut byte = [0u8; 1];
+    let mut iov = libc::iovec {
+        iov_base: byte.as_mut_ptr().cast(),
+        iov_len: byte.len(),
+    };
+    // u64s, since the control messages have to be aligned.
+    let mut control = [0u64; 8];
+    // SAFETY: all zeroes is a valid msghdr.
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = mem::size_of_val(&control) as _;
+    // SAFETY: msg points at buffers that live until it returns.
+    if unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, 0) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: recvmsg filled in the control messages, and the header is checked before the data is read.
+    unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        if cmsg.is_null() || (*cmsg).cmsg_level != libc::SOL_SOCKET || (*cmsg).cmsg_type != libc::SCM_RIGHTS {
+            return Err(io::Error::other("fusermount didn't send back /dev/fuse"));
+        }
+        let fd = std::ptr::read_unaligned(libc::CMSG_DATA(cmsg).cast::<libc::c_int>());
+        Ok(File::from_raw_fd(fd))
+    }
+}
+
+/**
+ * TESTS
+ */
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use rstest::rstest;
+
+    use super::{dirents, Filesystem, FILE_ID, FUSE_LOOKUP, FUSE_READ, ROOT_ID};
+
+    #[rstest]
+    fn test_dirents() {
+        let entries: &[(u64, &[u8], u8)] = &[(1, b".", 4), (1, b"..", 4), (2, b"file.txt", 8)];
+        // each entry is 24 bytes and the name, padded to 8.
+        assert_eq!(dirents(entries, 0, 4096).0.len(), 32 + 32 + 32);
+        // only room for the first.
+        assert_eq!(dirents(entries, 0, 40).0.len(), 32);
+        // carrying on from where that stopped.
+        let rest = dirents(entries, 1, 4096).0;
+        assert_eq!(rest.len(), 64);
+        assert_eq!(u64::from_ne_bytes(rest[32..40].try_into().unwrap()), 2);
+        assert_eq!(&rest[56..64], b"file.txt");
+    }
+
+    #[rstest]
+    fn test_lookup_and_read() {
+        let mut fs = Filesystem::new(Cursor::new(b"hello world".to_vec()), "hello.txt", 11, 0);
+        assert!(fs.handle(FUSE_LOOKUP, ROOT_ID, b"hello.txt\0").unwrap().is_ok());
+        assert_eq!(fs.handle(FUSE_LOOKUP, ROOT_ID, b"other\0").unwrap().err(), Some(libc::ENOENT));
+        // fh, offset, size, then the rest of fuse_read_in.
+        let mut read = Vec::new();
+        read.extend(0u64.to_ne_bytes());
+        read.extend(6u64.to_ne_bytes());
+        read.extend(100u32.to_ne_bytes());
+        read.extend([0; 20]);
+        let data = fs.handle(FUSE_READ, FILE_ID, &read).unwrap().unwrap();
+        assert_eq!(data.0, b"world");
+    }
+}
@@ -0,0 +1,356 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use cornifer::decompress::DecodeEvent;
+use serde::Serialize;
+
+use crate::cli::{Exit, Failure};
+
+/*
+ * --progress-fd and --progress-socket: how far indexing has got, and the blocks and members as they're decoded,
+ * as JSON lines for whatever is running us. One object per line, each with a "type":
+ *
+ *   {"type":"progress","file":"a.gz","compressed_byte":65536,"compressed_len":1048576,"uncompressed_byte":262144}
+ *   {"type":"event","file":"a.gz","event":"block_started","position":{...},"block_type":"DynamicHuffman"}
+ *   {"type":"finished","file":"a.gz","ok":true,"error":null}
+ *
+ * The events are the Deflator's DecodeEvents, so only gzip, BGZF and zlib files have them. Progress lines come at
+ * most every PROGRESS_INTERVAL, and once more at the end.
+ */
+
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Line<'a> {
+    Progress {
+        file: &'a str,
+        compressed_byte: u64,
+        compressed_len: u64,
+        // not known while bzip2 and xz files are being read.
+        uncompressed_byte: Option<u64>,
+    },
+    Event {
+        file: &'a str,
+        #[serde(flatten)]
+        event: &'a DecodeEvent,
+    },
+    Finished {
+        file: &'a str,
+        ok: bool,
+        error: Option<&'a str>,
+    },
+}
+
+/// Where the JSON lines go. Shared by the threads indexing files, each line is written in one go.
+pub struct ProgressStream {
+    // none once a write has failed, since whoever was reading has probably gone away.
+    out: Mutex<Option<Box<dyn Write + Send>>>,
+}
+
+impl ProgressStream {
+    pub fn new(out: Box<dyn Write + Send>) -> Self {
+        Self { out: Mutex::new(Some(out)) }
+    }
+
+    /// The stream for --progress-fd or --progress-socket, if either was given.
+    pub fn open(fd: Option<i32>, socket: Option<&Path>) -> Result<Option<Self>, Failure> {
+        match (fd, socket) {
+            (Some(fd), _) => open_fd(fd).map(|out| Some(Self::new(out))),
+            (None, Some(socket)) => open_socket(socket).map(|out| Some(Self::new(out))),
+            (None, None) => Ok(None),
+        }
+    }
+
+    fn send(&self, line: &Line) {
+        let mut out = self.out.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(writer) = out.as_mut() else {
+            return;
+        };
+        let mut buf = serde_json::to_vec(line).expect("progress lines serialize");
+        buf.push(b'\n');
+        if let Err(e) = writer.write_all(&buf).and_then(|()| writer.flush()) {
+            // indexing carries on without it.
+            eprintln!("warning: stopped sending progress: {e}");
+            *out = None;
+        }
+    }
+}
+
+#[cfg(unix)]
+fn open_fd(fd: i32) -> Result<Box<dyn Write + Send>, Failure> {
+    use std::os::fd::{FromRawFd, OwnedFd};
+
+    // our own copy, so it's checked to be open here rather than on the first write. fcntl fails with EBADF rather
+    // than us ever holding a descriptor that isn't open.
+    // SAFETY: fcntl doesn't touch memory, and any fd number is fine to pass it.
+    let copy = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+    if copy < 0 {
+        let e = io::Error::last_os_error();
+        return Err(Failure::new(Exit::Io, format!("Could not use --progress-fd {fd}: {e}")));
+    }
+    // SAFETY: fcntl just made this descriptor, and nothing else has it.
+    let owned = unsafe { OwnedFd::from_raw_fd(copy) };
+    Ok(Box::new(std::fs::File::from(owned)))
+}
+
+#[cfg(unix)]
+fn open_socket(path: &Path) -> Result<Box<dyn Write + Send>, Failure> {
+    let stream = std::os::unix::net::UnixStream::connect(path)
+        .map_err(|e| Failure::new(Exit::Io, format!("Could not connect to {}: {e}", path.display())))?;
+    Ok(Box::new(stream))
+}
+
+#[cfg(not(unix))]
+fn open_fd(_fd: i32) -> Result<Box<dyn Write + Send>, Failure> {
+    Err(Failure::new(Exit::Unsupported, "--progress-fd only works on unix"))
+}
+
+#[cfg(not(unix))]
+fn open_socket(_path: &Path) -> Result<Box<dyn Write + Send>, Failure> {
+    Err(Failure::new(Exit::Unsupported, "--progress-socket only works on unix"))
+}
+
+/// One file's lines. Does nothing without a stream, so indexing doesn't have to check.
+pub struct FileProgress<'a> {
+    stream: Option<&'a ProgressStream>,
+    file: String,
+    compressed_len: u64,
+    last_sent: Option<Instant>,
+}
+
+impl<'a> FileProgress<'a> {
+    pub fn new(stream: Option<&'a ProgressStream>, file_name: &Path) -> Self {
+        Self {
+            stream,
+            file: file_name.display().to_string(),
+            compressed_len: 0,
+            last_sent: None,
+        }
+    }
+
+    /// How long the compressed file is, once it's been opened.
+    pub fn set_len(&mut self, compressed_len: u64) {
+        self.compressed_len = compressed_len;
+    }
+
+    /// Whether anything is listening, so there's any point recording events.
+    pub fn wants_events(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// How far we've got, unless a line went out less than PROGRESS_INTERVAL ago.
+    pub fn position(&mut self, compressed_byte: u64, uncompressed_byte: Option<u64>) {
+        if self.last_sent.is_some_and(|sent| sent.elapsed() < PROGRESS_INTERVAL) {
+            return;
+        }
+        self.send_position(compressed_byte, uncompressed_byte);
+    }
+
+    fn send_position(&mut self, compressed_byte: u64, uncompressed_byte: Option<u64>) {
+        let Some(stream) = self.stream else {
+            return;
+        };
+        stream.send(&Line::Progress {
+            file: &self.file,
+            compressed_byte,
+            compressed_len: self.compressed_len,
+            uncompressed_byte,
+        });
+        self.last_sent = Some(Instant::now());
+    }
+
+    pub fn events(&mut self, events: &[DecodeEvent]) {
+        let Some(stream) = self.stream else {
+            return;
+        };
+        for event in events {
+            stream.send(&Line::Event { file: &self.file, event });
+        }
+    }
+
+    /// The last progress line, however soon after the one before, and whether it worked.
+    pub fn finished(&mut self, uncompressed_len: Option<u64>, error: Option<&str>) {
+        if error.is_none() {
+            self.send_position(self.compressed_len, uncompressed_len);
+        }
+        if let Some(stream) = self.stream {
+            stream.send(&Line::Finished {
+                file: &self.file,
+                ok: error.is_none(),
+                error,
+            });
+        }
+    }
+
+    /// Progress lines as the file is read, for the formats whose decoders don't say where they are.
+    pub fn wrap_read<R: Read>(&mut self, reader: R) -> ProgressReader<'_, 'a, R> {
+        ProgressReader {
+            inner: reader,
+            progress: self,
+            offset: 0,
+        }
+    }
+}
+
+pub struct ProgressReader<'p, 'a, R> {
+    inner: R,
+    progress: &'p mut FileProgress<'a>,
+    // where we are in the file, which is the progress for a reader.
+    offset: u64,
+}
+
+impl<R: Read> Read for ProgressReader<'_, '_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.offset += n as u64;
+        self.progress.position(self.offset, None);
+        Ok(n)
+    }
+}
+
+// xz files are read from the index at the end first.
+impl<R: Seek> Seek for ProgressReader<'_, '_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.offset = self.inner.seek(pos)?;
+        Ok(self.offset)
+    }
+}
+
+/**
+ * TESTS
+ */
+#[cfg(test)]
+mod test {
+    use std::io::{self, Read, Write};
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+
+    use cornifer::decompress::{BlockType, DecodeEvent, StreamPosition};
+    use rstest::rstest;
+    use serde_json::{json, Value};
+
+    use super::{FileProgress, ProgressStream};
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Shared {
+        fn lines(&self) -> Vec<Value> {
+            let buf = self.0.lock().unwrap();
+            buf.split(|&b| b == b'\n')
+                .filter(|line| !line.is_empty())
+                .map(|line| serde_json::from_slice(line).unwrap())
+                .collect()
+        }
+    }
+
+    struct Broken;
+
+    impl Write for Broken {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[rstest]
+    fn test_lines() {
+        let out = Shared::default();
+        let stream = ProgressStream::new(Box::new(out.clone()));
+        let mut progress = FileProgress::new(Some(&stream), Path::new("a.gz"));
+        progress.set_len(100);
+        progress.position(10, Some(40));
+        // too soon after the last one.
+        progress.position(20, Some(80));
+        let position = StreamPosition {
+            compressed_byte: 10,
+            compressed_bit: 3,
+            uncompressed_byte: 40,
+        };
+        progress.events(&[DecodeEvent::BlockStarted {
+            position,
+            block_type: BlockType::FixedHuffman,
+        }]);
+        progress.finished(Some(400), None);
+
+        assert_eq!(
+            out.lines(),
+            [
+                json!({"type": "progress", "file": "a.gz", "compressed_byte": 10, "compressed_len": 100, "uncompressed_byte": 40}),
+                json!({
+                    "type": "event",
+                    "file": "a.gz",
+                    "event": "block_started",
+                    "position": {"compressed_byte": 10, "compressed_bit": 3, "uncompressed_byte": 40},
+                    "block_type": "FixedHuffman",
+                }),
+                json!({"type": "progress", "file": "a.gz", "compressed_byte": 100, "compressed_len": 100, "uncompressed_byte": 400}),
+                json!({"type": "finished", "file": "a.gz", "ok": true, "error": null}),
+            ]
+        );
+    }
+
+    #[rstest]
+    fn test_failed() {
+        let out = Shared::default();
+        let stream = ProgressStream::new(Box::new(out.clone()));
+        let mut progress = FileProgress::new(Some(&stream), Path::new("a.gz"));
+        progress.set_len(100);
+        progress.finished(None, Some("oh no"));
+        assert_eq!(out.lines(), [json!({"type": "finished", "file": "a.gz", "ok": false, "error": "oh no"})]);
+    }
+
+    #[rstest]
+    fn test_wrap_read() {
+        let out = Shared::default();
+        let stream = ProgressStream::new(Box::new(out.clone()));
+        let mut progress = FileProgress::new(Some(&stream), Path::new("a.bz2"));
+        progress.set_len(5);
+        let mut buf = Vec::new();
+        progress.wrap_read(&b"hello"[..]).read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b"hello");
+        assert_eq!(out.lines()[0]["compressed_byte"], 5);
+        assert_eq!(out.lines()[0]["uncompressed_byte"], Value::Null);
+    }
+
+    #[cfg(unix)]
+    #[rstest]
+    fn test_fd_not_open() {
+        // well past anything the test process has open.
+        let result = ProgressStream::open(Some(1 << 20), None);
+        assert!(result.is_err_and(|e| e.to_string().contains("--progress-fd")));
+    }
+
+    #[rstest]
+    fn test_stops_after_write_fails() {
+        let stream = ProgressStream::new(Box::new(Broken));
+        let mut progress = FileProgress::new(Some(&stream), Path::new("a.gz"));
+        progress.set_len(100);
+        progress.finished(Some(1), None);
+        assert!(stream.out.lock().unwrap().is_none());
+    }
+
+    #[rstest]
+    fn test_no_stream() {
+        let mut progress = FileProgress::new(None, Path::new("a.gz"));
+        assert!(!progress.wants_events());
+        progress.position(1, None);
+        progress.finished(None, Some("oh no"));
+    }
+}
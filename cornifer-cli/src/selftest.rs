@@ -0,0 +1,137 @@
+use std::io::{self, Read};
+
+use serde::Serialize;
+
+/*
+ * Decompressing a file with Cornifer and with flate2 side by side, a chunk at a time, to find the first byte
+ * they disagree on. For chasing decoder bugs on files people send in.
+ */
+
+const CHUNK_SIZE: usize = 1 << 16;
+
+/// Where the two decoders first disagreed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Divergence {
+    /// Both decoded a byte at offset, but not the same one.
+    Byte { offset: u64, ours: u8, theirs: u8 },
+    /// One of them ended at offset and the other kept going.
+    Length { offset: u64, ours_ended: bool },
+    /// One of them failed at offset (the other got at least that far).
+    Error { offset: u64, ours: bool, error: String },
+}
+
+impl Divergence {
+    pub fn offset(&self) -> u64 {
+        match self {
+            Divergence::Byte { offset, .. } | Divergence::Length { offset, .. } | Divergence::Error { offset, .. } => {
+                *offset
+            }
+        }
+    }
+}
+
+// fill as much of buf as there is, stopping early only at the end or an error. What was read before the error
+// still counts.
+fn read_chunk<R: Read>(reader: &mut R, buf: &mut [u8]) -> (usize, Option<io::Error>) {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return (filled, Some(e)),
+        }
+    }
+    (filled, None)
+}
+
+/// Read both to the end, comparing as we go. Ok(len) if they decoded the same len bytes.
+pub fn compare<A: Read, B: Read>(ours: &mut A, theirs: &mut B) -> Result<u64, Divergence> {
+    let mut ours_buf = vec![0; CHUNK_SIZE];
+    let mut theirs_buf = vec![0; CHUNK_SIZE];
+    let mut offset = 0;
+    loop {
+        let (ours_len, ours_err) = read_chunk(ours, &mut ours_buf);
+        let (theirs_len, theirs_err) = read_chunk(theirs, &mut theirs_buf);
+        let common = ours_len.min(theirs_len);
+        if let Some(i) = (0..common).find(|&i| ours_buf[i] != theirs_buf[i]) {
+            return Err(Divergence::Byte {
+                offset: offset + i as u64,
+                ours: ours_buf[i],
+                theirs: theirs_buf[i],
+            });
+        }
+        for (err, len, is_ours) in [(ours_err, ours_len, true), (theirs_err, theirs_len, false)] {
+            if let Some(err) = err {
+                return Err(Divergence::Error {
+                    offset: offset + len as u64,
+                    ours: is_ours,
+                    error: err.to_string(),
+                });
+            }
+        }
+        if ours_len != theirs_len {
+            return Err(Divergence::Length {
+                offset: offset + common as u64,
+                ours_ended: ours_len < theirs_len,
+            });
+        }
+        if ours_len == 0 {
+            return Ok(offset);
+        }
+        offset += ours_len as u64;
+    }
+}
+
+/**
+ * TESTS
+ */
+#[cfg(test)]
+mod test {
+    use std::io::{self, Read};
+
+    use flate2::read::MultiGzDecoder;
+    use rstest::rstest;
+
+    use cornifer::{checkpoint::Checkpointer, decompress::Deflator};
+
+    use super::{compare, Divergence};
+
+    // gives back what it's given, then fails.
+    struct FailsAfter<'a>(&'a [u8]);
+
+    impl Read for FailsAfter<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.read(buf)? {
+                0 => Err(io::Error::other("broken")),
+                n => Ok(n),
+            }
+        }
+    }
+
+    #[rstest]
+    fn test_same() {
+        let input = include_bytes!("../../cornifer/testfiles/1080-0.txt.gz");
+        let mut ours = Deflator::new_auto(input.as_slice(), Checkpointer::none()).unwrap();
+        let mut theirs = MultiGzDecoder::new(input.as_slice());
+        assert_eq!(compare(&mut ours, &mut theirs), Ok(39819));
+    }
+
+    #[rstest]
+    fn test_divergence() {
+        let text = vec![7; 100000];
+        let mut changed = text.clone();
+        changed[70000] = 8;
+        assert_eq!(
+            compare(&mut text.as_slice(), &mut changed.as_slice()),
+            Err(Divergence::Byte { offset: 70000, ours: 7, theirs: 8 })
+        );
+        assert_eq!(
+            compare(&mut &text[..90000], &mut text.as_slice()),
+            Err(Divergence::Length { offset: 90000, ours_ended: true })
+        );
+        let err = compare(&mut FailsAfter(&text[..100]), &mut text.as_slice()).unwrap_err();
+        assert!(matches!(err, Divergence::Error { offset: 100, ours: true, .. }));
+    }
+}
@@ -0,0 +1,178 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+
+use cornifer::checkpointed::CheckpointedReader;
+use cornifer::errors::CorniferError;
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+
+/*
+ * Serving the decompressed contents of checkpointed files over HTTP, so clients that ask for ranges (with a
+ * Range header) only cost the blocks those ranges are in. Each thread has its own readers, and answers
+ * requests one at a time until the server goes away.
+ */
+
+/// A file to serve, at /name.
+#[derive(Debug, Clone)]
+pub struct Served {
+    pub name: String,
+    pub file_name: String,
+    pub index: String,
+}
+
+/// What part of the file a request asked for.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Wanted {
+    Whole,
+    /// From, and to (not included).
+    Range(u64, u64),
+    /// A range that starts past the end.
+    Unsatisfiable,
+}
+
+/// Work out what a Range header asks for. Anything we don't understand, including asking for several ranges at
+/// once, gets the whole file, which RFC 9110 allows.
+pub fn wanted(range: Option<&str>, len: u64) -> Wanted {
+    let Some(spec) = range.and_then(|r| r.trim().strip_prefix("bytes=")) else {
+        return Wanted::Whole;
+    };
+    let Some((from, to)) = spec.trim().split_once('-').filter(|_| !spec.contains(',')) else {
+        return Wanted::Whole;
+    };
+    match (from.parse::<u64>(), to.parse::<u64>()) {
+        // the last n bytes.
+        (Err(_), Ok(n)) if from.is_empty() => match n.min(len) {
+            0 => Wanted::Unsatisfiable,
+            n => Wanted::Range(len - n, len),
+        },
+        (Ok(from), _) if from >= len => Wanted::Unsatisfiable,
+        (Ok(from), Err(_)) if to.is_empty() => Wanted::Range(from, len),
+        (Ok(from), Ok(to)) if from <= to => Wanted::Range(from, to.saturating_add(1).min(len)),
+        _ => Wanted::Whole,
+    }
+}
+
+// each file's name, and a reader for it.
+pub type Readers<R> = Vec<(String, CheckpointedReader<R>)>;
+
+/// Open a reader for each file, for one thread's worth of requests.
+pub fn open_all(files: &[Served]) -> Result<Readers<BufReader<File>>, CorniferError> {
+    files
+        .iter()
+        .map(|f| {
+            let reader = CheckpointedReader::open(BufReader::new(File::open(&f.file_name)?), &f.index)?;
+            Ok((f.name.clone(), reader))
+        })
+        .collect()
+}
+
+/// Answer requests until the server is unblocked.
+pub fn answer_requests<R: Read + Seek>(server: &Server, mut readers: Readers<R>) {
+    while let Ok(request) = server.recv() {
+        // a client going away halfway through isn't worth stopping for.
+        let _ = respond(request, &mut readers);
+    }
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field.as_bytes(), value.as_bytes()).expect("Header names and values are ASCII")
+}
+
+fn respond<R: Read + Seek>(request: Request, readers: &mut [(String, CheckpointedReader<R>)]) -> io::Result<()> {
+    if !matches!(request.method(), Method::Get | Method::Head) {
+        return request.respond(Response::empty(405).with_header(header("Allow", "GET, HEAD")));
+    }
+    let path = request.url().split('?').next().unwrap_or_default().trim_start_matches('/').to_string();
+    // the root lists what's there.
+    if path.is_empty() {
+        let list: String = readers.iter().map(|(name, r)| format!("{name}\t{}\n", r.uncompressed_len())).collect();
+        return request.respond(Response::from_string(list).with_header(header("Content-Type", "text/plain")));
+    }
+    let Some((_, reader)) = readers.iter_mut().find(|(name, _)| *name == path) else {
+        return request.respond(Response::empty(404));
+    };
+    let len = reader.uncompressed_len();
+    let range = request.headers().iter().find(|h| h.field.equiv("Range")).map(|h| h.value.as_str());
+    let mut headers = vec![
+        header("Accept-Ranges", "bytes"),
+        header("Content-Type", "application/octet-stream"),
+    ];
+    let (status, from, to) = match wanted(range, len) {
+        Wanted::Whole => (200, 0, len),
+        Wanted::Range(from, to) => {
+            headers.push(header("Content-Range", &format!("bytes {from}-{}/{len}", to - 1)));
+            (206, from, to)
+        }
+        Wanted::Unsatisfiable => {
+            let response = Response::empty(416).with_header(header("Content-Range", &format!("bytes */{len}")));
+            return request.respond(response);
+        }
+    };
+    reader.seek(SeekFrom::Start(from))?;
+    let body = reader.take(to - from);
+    request.respond(Response::new(StatusCode(status), headers, body, Some((to - from) as usize), None))
+}
+
+/**
+ * TESTS
+ */
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Read, Write};
+    use std::net::TcpStream;
+
+    use rstest::rstest;
+    use tiny_http::Server;
+
+    use cornifer::checkpoint::Checkpointer;
+    use cornifer::checkpointed::CheckpointedReader;
+    use cornifer::decompress::Deflator;
+    use cornifer::reader::CorniferByteReader;
+
+    use super::{answer_requests, wanted, Wanted};
+
+    #[rstest]
+    #[case::none(None, Wanted::Whole)]
+    #[case::from_to(Some("bytes=10-19"), Wanted::Range(10, 20))]
+    #[case::past_the_end(Some("bytes=90-200"), Wanted::Range(90, 100))]
+    #[case::from(Some("bytes=50-"), Wanted::Range(50, 100))]
+    #[case::last(Some("bytes=-30"), Wanted::Range(70, 100))]
+    #[case::more_than_there_is(Some("bytes=-300"), Wanted::Range(0, 100))]
+    #[case::starts_past_the_end(Some("bytes=100-"), Wanted::Unsatisfiable)]
+    #[case::last_none(Some("bytes=-0"), Wanted::Unsatisfiable)]
+    #[case::several(Some("bytes=0-1,5-6"), Wanted::Whole)]
+    #[case::backwards(Some("bytes=20-10"), Wanted::Whole)]
+    #[case::other_unit(Some("lines=1-2"), Wanted::Whole)]
+    fn test_wanted(#[case] range: Option<&str>, #[case] expected: Wanted) {
+        assert_eq!(wanted(range, 100), expected);
+    }
+
+    #[rstest]
+    fn test_range_request() {
+        let input = include_bytes!("../../cornifer/testfiles/1080-0.txt.gz");
+        let text = include_bytes!("../../cornifer/testfiles/1080-0.txt");
+        let path = std::env::temp_dir().join(format!("cornifer-serve-test-{}.sqlite3", std::process::id()));
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), Checkpointer::init(&path).unwrap());
+        std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        drop(deflator);
+        let reader = CheckpointedReader::open(Cursor::new(input.as_slice()), &path).unwrap();
+
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_ip().unwrap();
+        let response = std::thread::scope(|s| {
+            s.spawn(|| answer_requests(&server, vec![("1080-0.txt".to_string(), reader)]));
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream
+                .write_all(b"GET /1080-0.txt HTTP/1.1\r\nHost: x\r\nRange: bytes=1000-1099\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            let mut response = Vec::new();
+            stream.read_to_end(&mut response).unwrap();
+            server.unblock();
+            response
+        });
+        let response = String::from_utf8_lossy(&response);
+        assert!(response.starts_with("HTTP/1.1 206"), "{response}");
+        assert!(response.contains("Content-Range: bytes 1000-1099/39819"), "{response}");
+        assert!(response.ends_with(&*String::from_utf8_lossy(&text[1000..1100])));
+        std::fs::remove_file(path).unwrap();
+    }
+}
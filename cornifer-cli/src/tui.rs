@@ -0,0 +1,388 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+use console::{Key, Term};
+use cornifer::checkpointed::CheckpointedReader;
+use cornifer::store::Block;
+
+use crate::hexdump::{hex_line, printable, BYTES_PER_LINE};
+
+/*
+ * Looking around a checkpointed file in the terminal: where its blocks are, and what's at any offset. Everything
+ * shown is read through the CheckpointedReader, so moving around only decodes the blocks on screen, which is
+ * what makes it any use on a file too big to decompress. The screen is redrawn in full after every key.
+ */
+
+const ROW: u64 = BYTES_PER_LINE as u64;
+// how far back to look for the start of a line, when moving up in the text view.
+const MAX_LINE: u64 = 64 << 10;
+// the title, the block map, the block, a blank line and the help at the bottom.
+const CHROME_LINES: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum View {
+    Hex,
+    Text,
+}
+
+/// What a key wants done that the explorer can't do by itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Continue,
+    Quit,
+    AskOffset,
+    AskLine,
+}
+
+/// A character for each part of the file, from the start to the end: `|` where a gzip member starts, `#` where
+/// there's a block that can be decoded from, `.` for the rest, and `^` for where `pos` is.
+pub fn block_map(blocks: &[Block], len: u64, pos: u64, width: usize) -> String {
+    if len == 0 || width == 0 {
+        return String::new();
+    }
+    let mut map = vec!['.'; width];
+    let cell = |at: u64| ((at as u128 * width as u128 / len as u128) as usize).min(width - 1);
+    for block in blocks.iter().filter(|b| b.len > 0) {
+        let c = &mut map[cell(block.to_byte)];
+        if block.member_start {
+            *c = '|';
+        } else if block.has_window && *c != '|' {
+            *c = '#';
+        }
+    }
+    map[cell(pos)] = '^';
+    map.into_iter().collect()
+}
+
+pub struct Explorer<R> {
+    reader: CheckpointedReader<R>,
+    name: String,
+    pos: u64,
+    view: View,
+    // what went wrong with the last key, shown at the bottom until the next one.
+    message: Option<String>,
+}
+
+impl<R: Read + Seek> Explorer<R> {
+    pub fn new(reader: CheckpointedReader<R>, name: &str) -> Self {
+        Self {
+            reader,
+            name: name.to_string(),
+            pos: 0,
+            view: View::Hex,
+            message: None,
+        }
+    }
+
+    fn len(&self) -> u64 {
+        self.reader.uncompressed_len()
+    }
+
+    // up to len bytes from `from`, fewer at the end of the file.
+    fn read_at(&mut self, from: u64, len: u64) -> io::Result<Vec<u8>> {
+        self.reader.seek(SeekFrom::Start(from))?;
+        let mut data = Vec::new();
+        (&mut self.reader).take(len).read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    fn block_line(&self) -> String {
+        let blocks = self.reader.blocks();
+        let index = blocks.partition_point(|b| b.to_byte <= self.pos).checked_sub(1);
+        let Some(block) = index.map(|i| &blocks[i]).filter(|b| self.pos < b.to_byte + b.len) else {
+            return "not in a block".to_string();
+        };
+        let window = match (block.member_start, block.has_window) {
+            (true, _) => "starts a member",
+            (false, true) => "has a window",
+            (false, false) => "no window",
+        };
+        format!(
+            "block {} of {}: {} at {}:{} compressed, {}..{} decompressed, {window}",
+            index.unwrap_or(0) + 1,
+            blocks.len(),
+            block.block_type,
+            block.from_byte,
+            block.from_bit,
+            block.to_byte,
+            block.to_byte + block.len,
+        )
+    }
+
+    /// What's on the screen, a line at a time, for a terminal this big.
+    pub fn render(&mut self, width: usize, height: usize) -> Vec<String> {
+        let rows = height.saturating_sub(CHROME_LINES).max(1);
+        let len = self.len();
+        let mut lines = vec![
+            format!("{}: {} bytes, offset {} ({:.1}%)", self.name, len, self.pos, self.pos as f64 * 100.0 / len.max(1) as f64),
+            block_map(self.reader.blocks(), len, self.pos, width),
+            self.block_line(),
+            String::new(),
+        ];
+        let page = match self.view {
+            View::Hex => {
+                let from = self.pos - self.pos % ROW;
+                self.read_at(from, rows as u64 * ROW).map(|data| {
+                    data.chunks(BYTES_PER_LINE)
+                        .enumerate()
+                        .map(|(i, row)| {
+                            let offset = from + i as u64 * ROW;
+                            let marker = if (offset..offset + ROW).contains(&self.pos) { '>' } else { ' ' };
+                            format!("{marker} {}", hex_line(offset, row))
+                        })
+                        .collect::<Vec<_>>()
+                })
+            }
+            View::Text => self.read_at(self.pos, (rows * width) as u64).map(|data| {
+                data.split(|b| *b == b'\n')
+                    .take(rows)
+                    .map(|line| line.iter().copied().map(printable).take(width).collect())
+                    .collect()
+            }),
+        };
+        match page {
+            Ok(page) => lines.extend(page),
+            Err(e) => lines.push(format!("can't read here: {e}")),
+        }
+        lines.resize(height.saturating_sub(1).max(lines.len()), String::new());
+        lines.push(match &self.message {
+            Some(message) => message.clone(),
+            None => "arrows/pgup/pgdn move  g offset  l line  n/p next/previous block  t text/hex  q quit".to_string(),
+        });
+        lines
+    }
+
+    fn go_to(&mut self, pos: u64) {
+        self.pos = pos.min(self.len().saturating_sub(1));
+    }
+
+    // where the line after the one at pos starts.
+    fn next_line(&mut self) -> io::Result<u64> {
+        let data = self.read_at(self.pos, MAX_LINE)?;
+        Ok(match data.iter().position(|b| *b == b'\n') {
+            Some(i) => self.pos + i as u64 + 1,
+            None => self.pos,
+        })
+    }
+
+    // where the line before the one at pos starts.
+    fn previous_line(&mut self) -> io::Result<u64> {
+        let from = self.pos.saturating_sub(MAX_LINE);
+        let data = self.read_at(from, self.pos - from)?;
+        // the newline just before pos ends the previous line, the one before that starts it.
+        let end = data.len().saturating_sub(1);
+        Ok(match data[..end].iter().rposition(|b| *b == b'\n') {
+            Some(i) => from + i as u64 + 1,
+            None => from,
+        })
+    }
+
+    /// Where the 1-based line `n` starts, by reading from the start. None if there aren't that many.
+    pub fn find_line(&mut self, n: u64) -> io::Result<Option<u64>> {
+        if n <= 1 {
+            return Ok(Some(0));
+        }
+        self.reader.seek(SeekFrom::Start(0))?;
+        let mut buf = vec![0; 1 << 20];
+        let (mut seen, mut offset) = (1, 0);
+        loop {
+            let read = self.reader.read(&mut buf)?;
+            if read == 0 {
+                return Ok(None);
+            }
+            for (i, _) in buf[..read].iter().enumerate().filter(|(_, b)| **b == b'\n') {
+                seen += 1;
+                if seen == n {
+                    return Ok(Some(offset + i as u64 + 1).filter(|at| *at < self.len()));
+                }
+            }
+            offset += read as u64;
+        }
+    }
+
+    /// Do what `key` says, with `rows` of data on the screen.
+    pub fn on_key(&mut self, key: Key, rows: u64) -> io::Result<Action> {
+        let blocks = self.reader.blocks();
+        let block = blocks.partition_point(|b| b.to_byte <= self.pos);
+        // how many lines to go in the text view.
+        let lines = if matches!(key, Key::PageUp | Key::PageDown) { rows } else { 1 };
+        match (key, self.view) {
+            (Key::Char('q') | Key::Escape | Key::CtrlC, _) => return Ok(Action::Quit),
+            (Key::Char('g'), _) => return Ok(Action::AskOffset),
+            (Key::Char('l'), _) => return Ok(Action::AskLine),
+            (Key::Char('t') | Key::Tab, View::Hex) => self.view = View::Text,
+            (Key::Char('t') | Key::Tab, View::Text) => self.view = View::Hex,
+            (Key::Char('n'), _) => {
+                if let Some(next) = blocks[block..].iter().find(|b| b.len > 0) {
+                    let to = next.to_byte;
+                    self.go_to(to);
+                }
+            }
+            (Key::Char('p'), _) => {
+                // the start of this block, or the one before if we're already there.
+                let mut starts = blocks[..block].iter().rev().filter(|b| b.len > 0).map(|b| b.to_byte);
+                if let Some(to) = starts.find(|to| *to < self.pos) {
+                    self.go_to(to);
+                }
+            }
+            (Key::Home, _) => self.pos = 0,
+            (Key::End, _) => self.go_to(u64::MAX),
+            (Key::ArrowDown, View::Hex) => self.go_to(self.pos + ROW),
+            (Key::ArrowUp, View::Hex) => self.pos = self.pos.saturating_sub(ROW),
+            (Key::PageDown, View::Hex) => self.go_to(self.pos + rows * ROW),
+            (Key::PageUp, View::Hex) => self.pos = self.pos.saturating_sub(rows * ROW),
+            (Key::ArrowRight, _) => self.go_to(self.pos + 1),
+            (Key::ArrowLeft, _) => self.pos = self.pos.saturating_sub(1),
+            (Key::ArrowDown | Key::PageDown, View::Text) => {
+                for _ in 0..lines {
+                    let next = self.next_line()?;
+                    self.go_to(next);
+                }
+            }
+            (Key::ArrowUp | Key::PageUp, View::Text) => {
+                for _ in 0..lines {
+                    self.pos = self.previous_line()?;
+                }
+            }
+            _ => (),
+        }
+        Ok(Action::Continue)
+    }
+
+    fn ask(&mut self, term: &Term, height: usize, action: Action) -> io::Result<()> {
+        let prompt = match action {
+            Action::AskOffset => "offset (or 0x...): ",
+            _ => "line: ",
+        };
+        term.move_cursor_to(0, height.saturating_sub(1))?;
+        term.clear_line()?;
+        term.write_str(prompt)?;
+        term.show_cursor()?;
+        let answer = term.read_line()?;
+        term.hide_cursor()?;
+        let answer = answer.trim();
+        let number = match answer.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => answer.parse(),
+        };
+        let Ok(number) = number else {
+            self.message = Some(format!("{answer:?} isn't a number"));
+            return Ok(());
+        };
+        match action {
+            Action::AskOffset if number >= self.len() => {
+                self.message = Some(format!("{number} is past the end, {}", self.len()));
+            }
+            Action::AskOffset => self.pos = number,
+            _ => match self.find_line(number)? {
+                Some(at) => self.pos = at,
+                None => self.message = Some(format!("there's no line {number}")),
+            },
+        }
+        Ok(())
+    }
+
+    /// Take over the terminal until q is pressed.
+    pub fn run(&mut self, term: &Term) -> io::Result<()> {
+        term.hide_cursor()?;
+        let result = self.run_inner(term);
+        term.clear_screen()?;
+        term.show_cursor()?;
+        result
+    }
+
+    fn run_inner(&mut self, term: &Term) -> io::Result<()> {
+        loop {
+            let (height, width) = term.size();
+            let (height, width) = (height as usize, width as usize);
+            let lines = self.render(width, height);
+            term.clear_screen()?;
+            term.write_str(&lines.join("\n"))?;
+            let key = term.read_key()?;
+            self.message = None;
+            let rows = height.saturating_sub(CHROME_LINES).max(1) as u64;
+            // a read that fails is shown instead of the data, rather than ending it.
+            match self.on_key(key, rows) {
+                Ok(Action::Quit) => return Ok(()),
+                Ok(Action::Continue) => (),
+                Ok(action) => self.ask(term, height, action)?,
+                Err(e) => self.message = Some(e.to_string()),
+            }
+        }
+    }
+}
+
+/**
+ * TESTS
+ */
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use console::Key;
+    use cornifer::checkpointed::CheckpointedReader;
+    use rstest::rstest;
+
+    use crate::testutil::{flushed_gzip, index_with_spacing, TempIndex};
+
+    use super::{block_map, Action, Explorer};
+
+    // the text gzipped twice over, with a block every 4000 bytes.
+    fn explorer() -> (Explorer<Cursor<Vec<u8>>>, Vec<u8>, TempIndex) {
+        let text = include_bytes!("../../cornifer/testfiles/1080-0.txt");
+        let input = flushed_gzip(text, 2, 4000);
+        let path = index_with_spacing(&input, 10000);
+        let reader = CheckpointedReader::open(Cursor::new(input), &path).unwrap();
+        (Explorer::new(reader, "test.gz"), text.repeat(2), path)
+    }
+
+    #[rstest]
+    fn test_block_map() {
+        let (explorer, text, _path) = explorer();
+        let map = block_map(explorer.reader.blocks(), text.len() as u64, 45000, 40);
+        assert_eq!(map.chars().count(), 40);
+        // a member starts at the beginning and halfway, and the cursor's just after halfway.
+        assert!(map.starts_with('|'));
+        assert_eq!(map.chars().nth(20), Some('|'));
+        assert_eq!(map.chars().nth(22), Some('^'));
+        assert!(map.contains('#') && map.contains('.'));
+    }
+
+    #[rstest]
+    fn test_render() {
+        let (mut explorer, text, _path) = explorer();
+        explorer.pos = 20;
+        let lines = explorer.render(100, 10);
+        assert_eq!(lines.len(), 10);
+        assert!(lines[2].starts_with("block 1 of"), "{}", lines[2]);
+        // the row with the cursor in it is at the top, and marked.
+        assert!(lines[4].starts_with("> 00000010  "), "{}", lines[4]);
+        assert!(lines[5].starts_with("  00000020  "));
+        assert!(lines[4].ends_with(&format!("|{}|", String::from_utf8_lossy(&text[16..32]).replace(['\r', '\n'], "."))));
+        assert!(lines[9].contains("q quit"));
+    }
+
+    #[rstest]
+    #[case::down(vec![Key::ArrowDown, Key::ArrowDown], 32)]
+    #[case::end(vec![Key::End], 79637)]
+    #[case::next_block(vec![Key::Char('n'), Key::Char('n')], 8000)]
+    #[case::previous_block(vec![Key::End, Key::Char('p'), Key::Char('p')], 71819)]
+    // the first line ends at 71, and the second is just "\r\n".
+    #[case::text_lines(vec![Key::Char('t'), Key::ArrowDown, Key::ArrowDown, Key::ArrowUp], 72)]
+    fn test_keys(#[case] keys: Vec<Key>, #[case] expected: u64) {
+        let (mut explorer, _, _path) = explorer();
+        for key in keys {
+            assert_eq!(explorer.on_key(key, 10).unwrap(), Action::Continue);
+        }
+        assert_eq!(explorer.pos, expected);
+        assert_eq!(explorer.on_key(Key::Char('q'), 10).unwrap(), Action::Quit);
+    }
+
+    #[rstest]
+    fn test_find_line() {
+        let (mut explorer, text, _path) = explorer();
+        let at = explorer.find_line(3).unwrap().unwrap();
+        let newlines: Vec<_> = text.iter().enumerate().filter(|(_, b)| **b == b'\n').map(|(i, _)| i as u64).collect();
+        assert_eq!(at, newlines[1] + 1);
+        assert_eq!(explorer.find_line(1_000_000).unwrap(), None);
+    }
+}
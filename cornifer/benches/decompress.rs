@@ -0,0 +1,138 @@
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+use cornifer::checkpoint::Checkpointer;
+use cornifer::checkpointed::CheckpointedReader;
+use cornifer::decompress::Deflator;
+use cornifer::reader::CorniferByteReader;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use common::TempIndex;
+
+/*
+ * Throughput of the hot paths (bit reader, huffman decoding, window) against flate2 and
+ * libdeflate on the same inputs, plus the cost of writing checkpoints and of seeking with
+ * them afterwards.
+ */
+
+const TEXT: &[u8] = include_bytes!("../testfiles/1080-0.txt");
+
+fn corpus() -> Vec<(&'static str, Vec<u8>, usize)> {
+    let mut files = vec![(
+        "1080-0.txt.gz",
+        include_bytes!("../testfiles/1080-0.txt.gz").to_vec(),
+        TEXT.len(),
+    )];
+    // something big enough to have lots of blocks.
+    let big = TEXT.repeat(50);
+    let mut e = GzEncoder::new(Vec::new(), Compression::default());
+    e.write_all(&big).unwrap();
+    files.push(("1080-0x50.txt.gz", e.finish().unwrap(), big.len()));
+    files
+}
+
+fn sequential(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sequential");
+    for (name, input, len) in corpus() {
+        group.throughput(Throughput::Bytes(len as u64));
+        group.bench_with_input(BenchmarkId::new("cornifer", name), &input, |b, input| {
+            b.iter(|| {
+                let reader = CorniferByteReader::new(input.as_slice());
+                let mut deflator = Deflator::new(reader, Checkpointer::init_memory().unwrap());
+                let mut dest = Vec::with_capacity(len);
+                deflator.read_to_end(&mut dest).unwrap();
+                dest
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("flate2", name), &input, |b, input| {
+            b.iter(|| {
+                let mut dest = Vec::with_capacity(len);
+                MultiGzDecoder::new(input.as_slice())
+                    .read_to_end(&mut dest)
+                    .unwrap();
+                dest
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("libdeflate", name), &input, |b, input| {
+            let mut decompressor = libdeflater::Decompressor::new();
+            b.iter(|| {
+                let mut dest = vec![0; len];
+                decompressor.gzip_decompress(input, &mut dest).unwrap();
+                dest
+            })
+        });
+    }
+    group.finish();
+}
+
+// the same decode as above, but with the checkpoints going to a file, like the CLI does.
+fn indexing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("indexing");
+    group.sample_size(20);
+    for (name, input, len) in corpus() {
+        group.throughput(Throughput::Bytes(len as u64));
+        group.bench_with_input(BenchmarkId::new("gzip", name), &input, |b, input| {
+            b.iter(|| {
+                let path = TempIndex::new();
+                let reader = CorniferByteReader::new(input.as_slice());
+                let mut deflator = Deflator::new(reader, Checkpointer::init(&path).unwrap());
+                std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+                drop(deflator);
+            })
+        });
+    }
+    let bz2: &[u8] = include_bytes!("../testfiles/1080-0x3.txt.bz2");
+    group.throughput(Throughput::Bytes(TEXT.len() as u64 * 3));
+    group.bench_function("bzip2/1080-0x3.txt.bz2", |b| {
+        b.iter(|| {
+            let mut checkpointer = Checkpointer::init_memory().unwrap();
+            cornifer::bzip2::index(&mut CorniferByteReader::new(bz2), &mut checkpointer).unwrap()
+        })
+    });
+    let xz: &[u8] = include_bytes!("../testfiles/1080-0-anthems.txt.xz");
+    group.bench_function("xz/1080-0-anthems.txt.xz", |b| {
+        b.iter(|| {
+            let mut checkpointer = Checkpointer::init_memory().unwrap();
+            cornifer::xz::index(&mut Cursor::new(xz), &mut checkpointer).unwrap()
+        })
+    });
+    group.finish();
+}
+
+// time to read a little after a seek, which decodes the block the seek lands in.
+fn seek(c: &mut Criterion) {
+    let mut group = c.benchmark_group("seek");
+    let bz2: &[u8] = include_bytes!("../testfiles/1080-0x3.txt.bz2");
+    let xz: &[u8] = include_bytes!("../testfiles/1080-0-anthems.txt.xz");
+    let bz2_index = TempIndex::new();
+    let mut checkpointer = Checkpointer::init(&bz2_index).unwrap();
+    cornifer::bzip2::index(&mut CorniferByteReader::new(bz2), &mut checkpointer).unwrap();
+    drop(checkpointer);
+    let xz_index = TempIndex::new();
+    let mut checkpointer = Checkpointer::init(&xz_index).unwrap();
+    cornifer::xz::index(&mut Cursor::new(xz), &mut checkpointer).unwrap();
+    drop(checkpointer);
+
+    for (name, input, index) in [("bzip2", bz2, &bz2_index), ("xz", xz, &xz_index)] {
+        group.bench_function(name, |b| {
+            let mut reader = CheckpointedReader::open(Cursor::new(input), index).unwrap();
+            let end = reader.uncompressed_len() - 64;
+            let mut dest = [0; 64];
+            b.iter(|| {
+                // the first and last blocks, so the block cache doesn't help.
+                reader.seek(SeekFrom::Start(0)).unwrap();
+                reader.read_exact(&mut dest).unwrap();
+                reader.seek(SeekFrom::Start(end)).unwrap();
+                reader.read_exact(&mut dest).unwrap();
+                dest
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, sequential, indexing, seek);
+criterion_main!(benches);
@@ -0,0 +1,468 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::errors::CorniferError;
+
+/*
+ * A compressed file is often an archive, and it's much more useful to know where each file
+ * in the archive is than where each block is. We pick the entries out while indexing so they
+ * can be listed later without decompressing anything.
+ *
+ * For a tar inside gzip, the decompressed output is run through a TarScanner. A zip has its own central directory at the end, so that's just read.
+ */
+
+const TAR_BLOCK: usize = 512;
+// "ustar" followed by either NUL (POSIX) or a space (old GNU).
+const USTAR_MAGIC: &[u8] = b"ustar";
+// GNU long names and pax headers bigger than this are skipped rather than kept, since their size comes from the
+// archive and could be anything. Real ones are a few KiB at most.
+const MAX_EXTENDED_LEN: u64 = 1 << 20;
+
+/// A file in an archive.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ArchiveEntry {
+    pub name: String,
+    /// Uncompressed size of the file.
+    pub size: u64,
+    /// For tar, where the file's contents start in the decompressed stream.
+    /// For zip, where the file's local header starts in the zip file.
+    pub offset: u64,
+    /// Seconds since the epoch. zip times have no timezone, so they're taken as UTC.
+    pub mtime: Option<i64>,
+}
+
+/// Read an octal number out of a tar header field. GNU tar uses base-256 for big numbers,
+/// marked by the top bit of the first byte.
+fn parse_octal(field: &[u8]) -> Option<u64> {
+    if field.first().is_some_and(|b| b & 0x80 != 0) {
+        return Some(
+            field[1..]
+                .iter()
+                .fold((field[0] & 0x7F) as u64, |acc, b| (acc << 8) | *b as u64),
+        );
+    }
+    let s = std::str::from_utf8(field).ok()?;
+    let s = s.trim_matches(|c: char| c == '\0' || c == ' ');
+    if s.is_empty() {
+        return Some(0);
+    }
+    u64::from_str_radix(s, 8).ok()
+}
+
+fn parse_string(field: &[u8]) -> String {
+    let end = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// Pick the path out of a pax extended header, which is records like "30 path=some/long/file/name\n", where the
+/// length at the start counts the whole record. Anything malformed ends it.
+fn parse_pax(data: &[u8]) -> Option<String> {
+    let mut path = None;
+    let mut rest = data;
+    while let Some(space) = rest.iter().position(|b| *b == b' ') {
+        let Some(len) = std::str::from_utf8(&rest[..space]).ok().and_then(|s| s.parse::<usize>().ok()) else {
+            break;
+        };
+        if len <= space + 1 || len > rest.len() || rest[len - 1] != b'\n' {
+            break;
+        }
+        if let Some(value) = rest[space + 1..len - 1].strip_prefix(b"path=") {
+            path = Some(String::from_utf8_lossy(value).into_owned());
+        }
+        rest = &rest[len..];
+    }
+    path
+}
+
+/// The checksum is the sum of the header bytes, with the checksum field itself counted as spaces.
+fn is_tar_header(header: &[u8]) -> bool {
+    let Some(expected) = parse_octal(&header[148..156]) else {
+        return false;
+    };
+    let sum: u64 = header
+        .iter()
+        .enumerate()
+        .map(|(i, b)| if (148..156).contains(&i) { b' ' } else { *b } as u64)
+        .sum();
+    sum == expected
+}
+
+enum TarState {
+    // collecting the next 512 byte header.
+    Header,
+    // skipping over a file's contents, and the padding after them.
+    Skip { remaining: u64 },
+    // collecting a GNU long name or a pax extended header, which come before the header they belong to.
+    Extended { remaining: u64, typeflag: u8 },
+    // the end of the archive, or it wasn't a tar file to begin with.
+    Done,
+}
+
+/**
+ * Picks the entries out of a tar file as it's written. Writes never fail, so this can sit next
+ * to whatever else the decompressed output goes to. If the first header isn't a tar header, it
+ * stops looking and `entries()` will be empty.
+ */
+pub struct TarScanner {
+    entries: Vec<ArchiveEntry>,
+    state: TarState,
+    header: Vec<u8>,
+    extended: Vec<u8>,
+    // the name the extended headers gave the next entry.
+    next_name: Option<String>,
+    pos: u64,
+}
+
+impl Default for TarScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TarScanner {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            state: TarState::Header,
+            header: Vec::with_capacity(TAR_BLOCK),
+            extended: Vec::new(),
+            next_name: None,
+            pos: 0,
+        }
+    }
+
+    pub fn entries(&self) -> &[ArchiveEntry] {
+        &self.entries
+    }
+
+    pub fn into_entries(self) -> Vec<ArchiveEntry> {
+        self.entries
+    }
+
+    fn on_header(&mut self) {
+        let header = std::mem::take(&mut self.header);
+        if header.iter().all(|b| *b == 0) {
+            // two zero blocks end the archive, but one is enough to know we're done.
+            self.state = TarState::Done;
+            return;
+        }
+        if !is_tar_header(&header) {
+            self.state = TarState::Done;
+            return;
+        }
+        let typeflag = header[156];
+        let size = parse_octal(&header[124..136]).unwrap_or(0);
+        // 'L' is a GNU long name and 'x' a pax header, for the entry after them. Global pax headers ('g') are
+        // skipped like anything else, and so are the others if they're too big to keep.
+        if matches!(typeflag, b'L' | b'x') && size > 0 && size <= MAX_EXTENDED_LEN {
+            self.state = TarState::Extended { remaining: size, typeflag };
+            self.extended.clear();
+            return;
+        }
+        if matches!(typeflag, b'L' | b'x' | b'g') {
+            self.skip_contents(size);
+            return;
+        }
+
+        let name = match self.next_name.take() {
+            Some(name) => name,
+            None => {
+                let name = parse_string(&header[0..100]);
+                let prefix = parse_string(&header[345..500]);
+                if header[257..262] == *USTAR_MAGIC && !prefix.is_empty() {
+                    format!("{prefix}/{name}")
+                } else {
+                    name
+                }
+            }
+        };
+        // regular files, and "contiguous" files which are regular files for everyone but one vendor.
+        if matches!(typeflag, b'0' | b'\0' | b'7') {
+            self.entries.push(ArchiveEntry {
+                name,
+                size,
+                offset: self.pos,
+                mtime: parse_octal(&header[136..148]).map(|t| t as i64),
+            });
+        }
+        // other types (directories, links) have no contents we care about.
+        self.skip_contents(size);
+    }
+
+    // skip an entry's contents and the padding after them, on to the next header.
+    fn skip_contents(&mut self, size: u64) {
+        let padded = size.div_ceil(TAR_BLOCK as u64) * TAR_BLOCK as u64;
+        self.state = if padded == 0 {
+            TarState::Header
+        } else {
+            TarState::Skip { remaining: padded }
+        };
+    }
+}
+
+impl Write for TarScanner {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+        while !rest.is_empty() {
+            let n = match &mut self.state {
+                TarState::Done => break,
+                TarState::Header => {
+                    let n = rest.len().min(TAR_BLOCK - self.header.len());
+                    self.header.extend(&rest[..n]);
+                    n
+                }
+                TarState::Skip { remaining } => {
+                    let n = (rest.len() as u64).min(*remaining);
+                    *remaining -= n;
+                    n as usize
+                }
+                TarState::Extended { remaining, .. } => {
+                    let n = (rest.len() as u64).min(*remaining) as usize;
+                    self.extended.extend(&rest[..n]);
+                    *remaining -= n as u64;
+                    n
+                }
+            };
+            rest = &rest[n..];
+            self.pos += n as u64;
+            match self.state {
+                TarState::Header if self.header.len() == TAR_BLOCK => self.on_header(),
+                TarState::Skip { remaining: 0 } => self.state = TarState::Header,
+                TarState::Extended { remaining: 0, typeflag } => {
+                    let extended = std::mem::take(&mut self.extended);
+                    let name = match typeflag {
+                        b'L' => Some(parse_string(&extended)),
+                        _ => parse_pax(&extended),
+                    };
+                    self.next_name = name.or(self.next_name.take());
+                    // skip the padding after it.
+                    let padding =
+                        (TAR_BLOCK as u64 - self.pos % TAR_BLOCK as u64) % TAR_BLOCK as u64;
+                    self.state = if padding == 0 {
+                        TarState::Header
+                    } else {
+                        TarState::Skip { remaining: padding }
+                    };
+                }
+                _ => (),
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Convert an MS-DOS date and time to seconds since the epoch.
+fn dos_to_unix(date: u16, time: u16) -> Option<i64> {
+    let year = 1980 + (date >> 9) as i64;
+    let month = ((date >> 5) & 0x0F) as i64;
+    let day = (date & 0x1F) as i64;
+    if !(1..=12).contains(&month) || day == 0 {
+        return None;
+    }
+    // days since the epoch, from Howard Hinnant's days_from_civil.
+    let (y, m) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * m + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    let secs = ((time >> 11) as i64) * 3600
+        + (((time >> 5) & 0x3F) as i64) * 60
+        + ((time & 0x1F) as i64) * 2;
+    Some(days * 86400 + secs)
+}
+
+fn u16_at(buf: &[u8], i: usize) -> u16 {
+    u16::from_le_bytes([buf[i], buf[i + 1]])
+}
+
+fn u32_at(buf: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]])
+}
+
+/// Read the entries of a zip file from its central directory.
+pub fn read_zip_entries<R: Read + Seek>(
+    source: &mut R,
+) -> Result<Vec<ArchiveEntry>, CorniferError> {
+    // the end of central directory record is at least 22 bytes, plus a comment of up to 64KB.
+    let len = source.seek(SeekFrom::End(0))?;
+    let tail_len = len.min(22 + 0xFFFF);
+    let tail_start = len - tail_len;
+    let mut tail = vec![0; tail_len as usize];
+    source.seek(SeekFrom::Start(tail_start))?;
+    source.read_exact(&mut tail)?;
+    let eocd = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|i| tail[*i..].starts_with(b"PK\x05\x06"))
+        .ok_or(CorniferError::InvalidZipDirectory { position: len })?;
+
+    let count = u16_at(&tail, eocd + 10) as usize;
+    let cd_len = u32_at(&tail, eocd + 12) as u64;
+    let cd_start = u32_at(&tail, eocd + 16) as u64;
+    if cd_start == 0xFFFF_FFFF || count == 0xFFFF {
+        // zip64, which we don't read yet.
+        return Err(CorniferError::InvalidZipDirectory {
+            position: tail_start + eocd as u64,
+        });
+    }
+    let mut cd = vec![0; cd_len as usize];
+    source.seek(SeekFrom::Start(cd_start))?;
+    source.read_exact(&mut cd)?;
+
+    let mut entries = Vec::with_capacity(count);
+    let mut i = 0;
+    for _ in 0..count {
+        if i + 46 > cd.len() || !cd[i..].starts_with(b"PK\x01\x02") {
+            return Err(CorniferError::InvalidZipDirectory {
+                position: cd_start + i as u64,
+            });
+        }
+        let time = u16_at(&cd, i + 12);
+        let date = u16_at(&cd, i + 14);
+        let size = u32_at(&cd, i + 24) as u64;
+        let name_len = u16_at(&cd, i + 28) as usize;
+        let extra_len = u16_at(&cd, i + 30) as usize;
+        let comment_len = u16_at(&cd, i + 32) as usize;
+        let offset = u32_at(&cd, i + 42) as u64;
+        let name = cd
+            .get(i + 46..i + 46 + name_len)
+            .ok_or(CorniferError::InvalidZipDirectory {
+                position: cd_start + i as u64,
+            })?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        // directories have a trailing slash and nothing in them.
+        if !name.ends_with('/') {
+            entries.push(ArchiveEntry {
+                name,
+                size,
+                offset,
+                mtime: dos_to_unix(date, time),
+            });
+        }
+        i += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(entries)
+}
+
+/**
+ * TESTS
+ */
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Read, Write};
+
+    use flate2::read::GzDecoder;
+    use rstest::rstest;
+
+    use super::{read_zip_entries, ArchiveEntry, TarScanner, MAX_EXTENDED_LEN};
+
+    fn expected_entries(offsets: [u64; 2]) -> Vec<ArchiveEntry> {
+        vec![
+            ArchiveEntry {
+                name: "texts/anthems.txt".to_string(),
+                size: 1983,
+                offset: offsets[0],
+                mtime: Some(1681000000),
+            },
+            ArchiveEntry {
+                name: "texts/1080-0.txt".to_string(),
+                size: 39819,
+                offset: offsets[1],
+                mtime: Some(1681000100),
+            },
+        ]
+    }
+
+    #[rstest]
+    #[case::all_at_once(usize::MAX)]
+    #[case::in_pieces(100)]
+    fn test_tar_scanner(#[case] chunk_size: usize) {
+        let mut tar = Vec::new();
+        GzDecoder::new(&include_bytes!("../testfiles/texts.tar.gz")[..])
+            .read_to_end(&mut tar)
+            .unwrap();
+        let mut scanner = TarScanner::new();
+        for chunk in tar.chunks(chunk_size.min(tar.len())) {
+            scanner.write_all(chunk).unwrap();
+        }
+        assert_eq!(scanner.into_entries(), expected_entries([512, 3072]));
+    }
+
+    // a tar entry: a ustar header with the checksum filled in, then the contents padded to a whole block.
+    fn tar_entry(tar: &mut Vec<u8>, name: &str, typeflag: u8, contents: &[u8]) {
+        let mut header = vec![0; 512];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[124..136].copy_from_slice(format!("{:011o}\0", contents.len()).as_bytes());
+        header[136..148].copy_from_slice(b"00000000000\0");
+        header[148..156].fill(b' ');
+        header[156] = typeflag;
+        header[257..263].copy_from_slice(b"ustar\0");
+        let sum: u64 = header.iter().map(|b| *b as u64).sum();
+        header[148..155].copy_from_slice(format!("{sum:06o}\0").as_bytes());
+        tar.extend(header);
+        tar.extend(contents);
+        tar.resize(tar.len().div_ceil(512) * 512, 0);
+    }
+
+    // a pax record, whose length counts itself.
+    fn pax_record(key: &str, value: &str) -> String {
+        let len = key.len() + value.len() + 3;
+        let len = len + format!("{len}").len();
+        format!("{len} {key}={value}\n")
+    }
+
+    #[rstest]
+    fn test_tar_scanner_extended_headers() {
+        let long_name = format!("texts/{}/1080-0.txt", "a".repeat(150));
+        let mut tar = Vec::new();
+        // a pax header names the first file.
+        let pax = pax_record("mtime", "1681000000.5") + &pax_record("path", &long_name);
+        tar_entry(&mut tar, "PaxHeaders/1080-0.txt", b'x', pax.as_bytes());
+        tar_entry(&mut tar, "texts/truncated", b'0', b"hello");
+        // a GNU long name too big to keep is skipped, and the file after it keeps its own name.
+        tar_entry(&mut tar, "././@LongLink", b'L', &vec![b'a'; MAX_EXTENDED_LEN as usize + 1]);
+        let second_offset = tar.len() as u64 + 512;
+        tar_entry(&mut tar, "texts/anthems.txt", b'0', b"world!");
+        // and one that isn't.
+        tar_entry(&mut tar, "././@LongLink", b'L', long_name.as_bytes());
+        tar_entry(&mut tar, "texts/truncated", b'0', b"");
+        tar.extend([0; 1024]);
+
+        let mut scanner = TarScanner::new();
+        for chunk in tar.chunks(1000) {
+            scanner.write_all(chunk).unwrap();
+        }
+        let found: Vec<_> = scanner.entries().iter().map(|e| (e.name.as_str(), e.size)).collect();
+        assert_eq!(found, [(long_name.as_str(), 5), ("texts/anthems.txt", 6), (long_name.as_str(), 0)]);
+        assert_eq!(scanner.entries()[0].offset, 1024 + 512);
+        assert_eq!(scanner.entries()[1].offset, second_offset);
+    }
+
+    #[rstest]
+    fn test_tar_scanner_ignores_non_tar() {
+        let mut scanner = TarScanner::new();
+        scanner
+            .write_all(include_bytes!("../testfiles/1080-0.txt"))
+            .unwrap();
+        assert!(scanner.entries().is_empty());
+    }
+
+    #[rstest]
+    fn test_read_zip_entries() {
+        let input: &[u8] = include_bytes!("../testfiles/texts.zip");
+        let entries = read_zip_entries(&mut Cursor::new(input)).unwrap();
+        assert_eq!(entries, expected_entries([0, 285]));
+    }
+}
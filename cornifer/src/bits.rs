@@ -0,0 +1,128 @@
+/*
+ * Writing bits the way DEFLATE packs them, for putting together blocks instead of just reading them: copying
+ * blocks into a new member (see slice.rs), and the round trip tests, which encode with a tree and check the
+ * decoder gets the same symbols back.
+ *
+ * Everything goes in least significant bit first, except Huffman codes, which DEFLATE packs most significant bit
+ * first (RFC 1951 section 3.1.1). put_code turns them round.
+ */
+
+/// Bits, least significant first, like DEFLATE wants them.
+#[derive(Default)]
+pub struct BitWriter {
+    out: Vec<u8>,
+    acc: u64,
+    n: u32,
+}
+
+impl BitWriter {
+    /// The low `bits` bits of `value`, at most 32 of them.
+    pub fn put(&mut self, value: u64, bits: u32) {
+        debug_assert!(bits <= 32);
+        self.acc |= (value & ((1 << bits) - 1)) << self.n;
+        self.n += bits;
+        while self.n >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.n -= 8;
+        }
+    }
+
+    /// A Huffman code `len` bits long, e.g. from `HuffmanTree::encode`, most significant bit first.
+    pub fn put_code(&mut self, code: u16, len: u8) {
+        if len > 0 {
+            self.put((code.reverse_bits() >> (16 - len as u32)) as u64, len as u32);
+        }
+    }
+
+    /// Pad with zeros to the next byte, e.g. before a stored block's lengths.
+    pub fn align(&mut self) {
+        if self.n > 0 {
+            self.put(0, 8 - self.n);
+        }
+    }
+
+    /// Whole bytes, which have to start on a byte.
+    pub fn put_bytes(&mut self, bytes: &[u8]) {
+        debug_assert_eq!(self.n, 0);
+        self.out.extend_from_slice(bytes);
+    }
+
+    /// `len` bits of `src`, starting `from` bits in.
+    pub fn copy(&mut self, src: &[u8], mut from: u64, len: u64) {
+        let end = from + len;
+        while from < end {
+            let bits = (end - from).min(32) as u32;
+            let byte = (from / 8) as usize;
+            let mut word = [0; 8];
+            let available = (src.len() - byte).min(8);
+            word[..available].copy_from_slice(&src[byte..byte + available]);
+            self.put(u64::from_le_bytes(word) >> (from % 8), bits);
+            from += bits as u64;
+        }
+    }
+
+    /// How many bits have been written.
+    pub fn bit_len(&self) -> u64 {
+        self.out.len() as u64 * 8 + self.n as u64
+    }
+
+    /// Everything written, with the last byte padded with zeros.
+    pub fn finish(mut self) -> Vec<u8> {
+        self.align();
+        self.out
+    }
+}
+
+/**
+ * TESTS
+ */
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::BitWriter;
+    use crate::decompress::Deflator;
+    use crate::huffman::HuffmanTree;
+    use crate::reader::CorniferByteReader;
+
+    #[rstest]
+    #[case::aligned(0, 16)]
+    #[case::unaligned(3, 13)]
+    #[case::long(5, 100)]
+    #[case::one_bit(7, 1)]
+    fn test_copy_bits(#[case] from: u64, #[case] len: u64) {
+        let src: Vec<u8> = (0..20u8).map(|i| i.wrapping_mul(37) ^ 0x5a).collect();
+        let bit = |bytes: &[u8], i: u64| (bytes[(i / 8) as usize] >> (i % 8)) & 1;
+        let mut writer = BitWriter::default();
+        // start the copy partway through a byte too.
+        writer.put(0b101, 3);
+        writer.copy(&src, from, len);
+        assert_eq!(writer.bit_len(), 3 + len);
+        let out = writer.finish();
+        for i in 0..len {
+            assert_eq!(bit(&out, i + 3), bit(&src, from + i), "bit {i}");
+        }
+        assert_eq!(out[0] & 0b111, 0b101);
+    }
+
+    // codes written with put_code come back out of the decoder as the symbols they're for.
+    #[rstest]
+    #[case::complete(&[3, 3, 3, 3, 3, 2, 4, 4])]
+    #[case::with_gaps(&[0, 3, 3, 3, 0, 3, 3, 2, 0, 4, 4, 0])]
+    #[case::longest(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 15])]
+    fn test_put_code(#[case] bit_lengths: &[u8]) {
+        let tree = HuffmanTree::new(bit_lengths);
+        let symbols: Vec<u16> = tree.codes().map(|code| code.symbol).collect();
+        let mut writer = BitWriter::default();
+        for &symbol in symbols.iter().chain(symbols.iter().rev()) {
+            let (code, len) = tree.encode(symbol).unwrap();
+            writer.put_code(code, len);
+        }
+        let out = writer.finish();
+        let mut reader = CorniferByteReader::new(out.as_slice());
+        for &symbol in symbols.iter().chain(symbols.iter().rev()) {
+            assert_eq!(Deflator::decode_code(&mut reader, &tree).unwrap().symbol, symbol);
+        }
+    }
+}
@@ -0,0 +1,475 @@
+use std::io::{self, Read, Seek, SeekFrom};
+#[cfg(feature = "sqlite")]
+use std::path::Path;
+
+use bzip2::read::BzDecoder;
+use crc::{Crc, CRC_32_ISO_HDLC};
+#[cfg(feature = "sqlite")]
+use rusqlite::{Connection, OpenFlags};
+
+use crate::checkpoint::{Checkpointer, IndependentBlock};
+use crate::errors::CorniferError;
+use crate::reader::CorniferByteReader;
+
+/*
+ * bzip2 files are already split into blocks which don't depend on each other, so unlike
+ * DEFLATE there is no window to keep track of. The catch is that blocks aren't byte aligned
+ * and there's no index of where they are, so we find them by scanning for the 48 bit
+ * block magic, the same way bzip2recover and seek-bzip do.
+ *
+ * To decode a single block, we wrap its bits up into a standalone single-block stream and
+ * hand that to libbzip2.
+ */
+
+static CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+// BCD pi, marks the start of a compressed block.
+const BLOCK_MAGIC: u64 = 0x3141_5926_5359;
+// BCD sqrt(pi), marks the end of a bzip2 stream.
+const END_OF_STREAM_MAGIC: u64 = 0x1772_4538_5090;
+const MAGIC_MASK: u64 = 0xFFFF_FFFF_FFFF;
+const MAGIC_BITS: usize = 48;
+// the block magic is followed by the CRC of the block.
+const BLOCK_HEADER_BITS: usize = MAGIC_BITS + 32;
+
+/// A string of bits, packed MSB-first (which is the order bzip2 uses).
+#[derive(Default)]
+struct BitBuffer {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl BitBuffer {
+    fn push(&mut self, bit: u8) {
+        if self.len.is_multiple_of(8) {
+            self.bytes.push(0);
+        }
+        if bit == 1 {
+            let last = self.bytes.len() - 1;
+            self.bytes[last] |= 0x80 >> (self.len % 8);
+        }
+        self.len += 1;
+    }
+
+    fn push_bits(&mut self, value: u64, n: usize) {
+        for i in (0..n).rev() {
+            self.push(((value >> i) & 1) as u8);
+        }
+    }
+
+    fn get(&self, index: usize) -> u8 {
+        (self.bytes[index / 8] >> (7 - index % 8)) & 1
+    }
+
+    fn get_bits(&self, start: usize, n: usize) -> u64 {
+        (start..start + n).fold(0, |acc, i| (acc << 1) | self.get(i) as u64)
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.len = len;
+        self.bytes.truncate(len.div_ceil(8));
+        if !len.is_multiple_of(8) {
+            let last = self.bytes.len() - 1;
+            self.bytes[last] &= 0xFF << (8 - len % 8);
+        }
+    }
+}
+
+/// Reads bits MSB-first from a byte reader.
+struct MsbBitReader<'a, R> {
+    reader: &'a mut CorniferByteReader<R>,
+    byte: u8,
+    remaining: u8,
+}
+
+impl<'a, R: Read> MsbBitReader<'a, R> {
+    fn new(reader: &'a mut CorniferByteReader<R>) -> Self {
+        Self {
+            reader,
+            byte: 0,
+            remaining: 0,
+        }
+    }
+
+    fn read_bit(&mut self) -> Result<u8, CorniferError> {
+        if self.remaining == 0 {
+            self.byte = self.reader.read_u8()?;
+            self.remaining = 8;
+        }
+        self.remaining -= 1;
+        Ok((self.byte >> self.remaining) & 1)
+    }
+
+    fn read_bits(&mut self, n: usize) -> Result<u64, CorniferError> {
+        let mut value = 0;
+        for _ in 0..n {
+            value = (value << 1) | self.read_bit()? as u64;
+        }
+        Ok(value)
+    }
+
+    /// Position of the next bit to be read, in bits from the start of the input.
+    fn position(&self) -> usize {
+        self.reader.current_byte * 8 - self.remaining as usize
+    }
+
+    fn discard_until_next_byte(&mut self) {
+        self.remaining = 0;
+    }
+}
+
+/// What we found out about the decompressed file while indexing it.
+#[derive(Debug, PartialEq)]
+pub struct Bzip2Summary {
+    pub crc32: u32,
+    pub len: usize,
+    pub blocks: usize,
+}
+
+/// A bzip2 block as recorded in the checkpoint database.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Bzip2Block {
+    pub from_byte: u64,
+    pub from_bit: u8,
+    pub to_byte: u64,
+    pub len: u64,
+    pub block_len_bits: u64,
+}
+
+/// Read the "BZh1".."BZh9" stream header. EOF before the first byte means there are no more streams.
+fn read_stream_header<R: Read>(bits: &mut MsbBitReader<R>) -> Result<(), CorniferError> {
+    let b = match bits.read_bits(8) {
+        Ok(byte) => byte,
+        Err(CorniferError::EOF) => return Err(CorniferError::CleanEof),
+        Err(err) => return Err(err),
+    };
+    let z = bits.read_bits(8)?;
+    let h = bits.read_bits(8)?;
+    let level = bits.read_bits(8)?;
+    if b != b'B' as u64
+        || z != b'Z' as u64
+        || h != b'h' as u64
+        || !(b'1'..=b'9').contains(&(level as u8))
+    {
+        return Err(CorniferError::NotBZIP2Header);
+    }
+    Ok(())
+}
+
+/// Decode the first `len` bits of a block (starting with its block magic) by turning them into a standalone stream.
+fn decode_standalone(block: &BitBuffer, len: usize) -> Result<Vec<u8>, io::Error> {
+    let mut stream = BitBuffer::default();
+    // the block size in the header is only an upper bound, so 9 fits anything.
+    for byte in b"BZh9" {
+        stream.push_bits(*byte as u64, 8);
+    }
+    for i in 0..len {
+        stream.push(block.get(i));
+    }
+    stream.push_bits(END_OF_STREAM_MAGIC, MAGIC_BITS);
+    // the combined CRC of a single block stream is just the CRC of the block.
+    stream.push_bits(block.get_bits(MAGIC_BITS, 32), 32);
+
+    let mut out = Vec::new();
+    BzDecoder::new(stream.bytes.as_slice()).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+/// Scan a bzip2 file (which may contain several concatenated streams), writing a checkpoint for every block.
+pub fn index<R: Read>(
+    reader: &mut CorniferByteReader<R>,
+    checkpointer: &mut Checkpointer,
+) -> Result<Bzip2Summary, CorniferError> {
+    let mut bits = MsbBitReader::new(reader);
+    let mut digest = CRC32.digest();
+    let mut to_byte = 0;
+    let mut blocks = 0;
+
+    let mut finish_block = |start: usize, block: &BitBuffer, data: Vec<u8>| -> Result<(), CorniferError> {
+        digest.update(&data);
+        checkpointer.on_independent_block(
+            "bzip2",
+            IndependentBlock {
+                from_byte: start / 8,
+                from_bit: (start % 8) as u8,
+                to_byte,
+                header_len_bits: BLOCK_HEADER_BITS as isize,
+                block_len_bits: block.len as isize,
+                len: data.len(),
+                crc32: CRC32.checksum(&data),
+                data: Vec::new(),
+            },
+        )?;
+        to_byte += data.len();
+        blocks += 1;
+        Ok(())
+    };
+
+    loop {
+        match read_stream_header(&mut bits) {
+            Ok(()) => (),
+            Err(CorniferError::CleanEof) => break,
+            Err(err) => return Err(err),
+        }
+        let mut register: u64 = 0;
+        let mut bits_read = 0;
+        // start of the block we're currently in, and all of its bits so far.
+        let mut pending: Option<(usize, BitBuffer)> = None;
+        loop {
+            let bit = match bits.read_bit() {
+                Ok(bit) => bit,
+                // the block never decoded, whatever magic came after it.
+                Err(CorniferError::EOF) => {
+                    return Err(match pending {
+                        Some((start, _)) => CorniferError::InvalidBzip2Block {
+                            position: start / 8,
+                            bit: (start % 8) as u8,
+                        },
+                        None => CorniferError::EOF,
+                    })
+                }
+                Err(err) => return Err(err),
+            };
+            register = ((register << 1) | bit as u64) & MAGIC_MASK;
+            bits_read += 1;
+            if let Some((_, block)) = &mut pending {
+                block.push(bit);
+            }
+            if bits_read < MAGIC_BITS
+                || (register != BLOCK_MAGIC && register != END_OF_STREAM_MAGIC)
+            {
+                continue;
+            }
+            // either magic ends the previous block. The magic could also turn up inside the
+            // compressed data by chance, in which case what's before it won't decode, and the
+            // block carries on past it.
+            if let Some((start, block)) = &mut pending {
+                let len = block.len - MAGIC_BITS;
+                let Ok(data) = decode_standalone(block, len) else {
+                    continue;
+                };
+                block.truncate(len);
+                finish_block(*start, block, data)?;
+            }
+            if register == BLOCK_MAGIC {
+                let mut block = BitBuffer::default();
+                block.push_bits(BLOCK_MAGIC, MAGIC_BITS);
+                pending = Some((bits.position() - MAGIC_BITS, block));
+            } else {
+                // combined CRC of the stream. Each block has already been checked on its own.
+                bits.read_bits(32)?;
+                bits.discard_until_next_byte();
+                break;
+            }
+        }
+    }
+
+    let crc32 = digest.finalize();
+    checkpointer.finalize(to_byte as u64, crc32)?;
+    Ok(Bzip2Summary {
+        crc32,
+        len: to_byte,
+        blocks,
+    })
+}
+
+/// Load the bzip2 blocks recorded in a checkpoint database, in uncompressed order.
+#[cfg(feature = "sqlite")]
+pub fn read_blocks(conn: &Connection) -> Result<Vec<Bzip2Block>, CorniferError> {
+    // rows for blocks that never finished can't be read back, so leave them out.
+    let mut stmt = conn.prepare(
+        "
+        SELECT from_byte, from_bit, to_byte, len, block_len_bits
+        FROM DeflateBlock
+        WHERE block_type = 'bzip2' AND len IS NOT NULL
+        ORDER BY to_byte
+    ",
+    )?;
+    let blocks = stmt
+        .query_map((), |row| {
+            Ok(Bzip2Block {
+                from_byte: row.get(0)?,
+                from_bit: row.get(1)?,
+                to_byte: row.get(2)?,
+                len: row.get(3)?,
+                block_len_bits: row.get(4)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(blocks)
+}
+
+/// Decode one block straight out of the compressed file, without reading anything else.
+pub fn decode_block<R: Read + Seek>(
+    source: &mut R,
+    from_byte: u64,
+    from_bit: u8,
+    block_len_bits: u64,
+) -> Result<Vec<u8>, CorniferError> {
+    source.seek(SeekFrom::Start(from_byte))?;
+    let num_bytes = (from_bit as u64 + block_len_bits).div_ceil(8);
+    let mut raw = BitBuffer {
+        bytes: vec![0; num_bytes as usize],
+        len: num_bytes as usize * 8,
+    };
+    source.read_exact(&mut raw.bytes)?;
+
+    let mut bits = BitBuffer::default();
+    let start = from_bit as usize;
+    for i in start..start + block_len_bits as usize {
+        bits.push(raw.get(i));
+    }
+    decode_standalone(&bits, bits.len).map_err(|_| CorniferError::InvalidBzip2Block {
+        position: from_byte as usize,
+        bit: from_bit,
+    })
+}
+
+/// Random access to the decompressed contents of an indexed bzip2 file. `CheckpointedReader` does the same for
+/// any format; this is the bzip2 one on its own, for callers that already have the blocks.
+#[cfg(feature = "sqlite")]
+pub struct Bzip2Reader<R> {
+    source: R,
+    blocks: Vec<Bzip2Block>,
+    pos: u64,
+    // index of the block we last decoded, and its contents.
+    cached: Option<(usize, Vec<u8>)>,
+}
+
+#[cfg(feature = "sqlite")]
+impl<R: Read + Seek> Bzip2Reader<R> {
+    pub fn new(source: R, blocks: Vec<Bzip2Block>) -> Self {
+        Self {
+            source,
+            blocks,
+            pos: 0,
+            cached: None,
+        }
+    }
+
+    pub fn open<P: AsRef<Path>>(source: R, index: P) -> Result<Self, CorniferError> {
+        let conn = Connection::open_with_flags(index, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let blocks = read_blocks(&conn)?;
+        Ok(Self::new(source, blocks))
+    }
+
+    /// Length of the entire decompressed file.
+    pub fn uncompressed_len(&self) -> u64 {
+        self.blocks.last().map(|b| b.to_byte + b.len).unwrap_or(0)
+    }
+
+    fn read_internal(&mut self, buf: &mut [u8]) -> Result<usize, CorniferError> {
+        if self.pos >= self.uncompressed_len() || buf.is_empty() {
+            return Ok(0);
+        }
+        // the last block that starts at or before pos.
+        let index = self.blocks.partition_point(|b| b.to_byte <= self.pos) - 1;
+        if !matches!(&self.cached, Some((i, _)) if *i == index) {
+            let block = &self.blocks[index];
+            let data = decode_block(&mut self.source, block.from_byte, block.from_bit, block.block_len_bits)?;
+            self.cached = Some((index, data));
+        }
+        let (_, data) = self.cached.as_ref().expect("Block was just decoded");
+
+        let offset = (self.pos - self.blocks[index].to_byte) as usize;
+        let num_bytes = buf.len().min(data.len() - offset);
+        buf[..num_bytes].copy_from_slice(&data[offset..offset + num_bytes]);
+        self.pos += num_bytes as u64;
+        Ok(num_bytes)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl<R: Read + Seek> Read for Bzip2Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_internal(buf).map_err(io::Error::from)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl<R: Read + Seek> Seek for Bzip2Reader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = crate::checkpointed::seek_position(self.pos, self.uncompressed_len(), pos)?;
+        Ok(self.pos)
+    }
+}
+
+/**
+ * TESTS
+ */
+#[cfg(all(test, feature = "sqlite"))]
+mod test {
+    use crc::{Crc, CRC_32_ISO_HDLC};
+    use rstest::rstest;
+
+    use crate::{checkpoint::Checkpointer, errors::CorniferError, reader::CorniferByteReader};
+
+    use super::index;
+
+    #[rstest]
+    fn test_index_summary() {
+        let input: &[u8] = include_bytes!("../testfiles/1080-0x3.txt.bz2");
+        let mut checkpointer = Checkpointer::init_memory().unwrap();
+        let summary = index(&mut CorniferByteReader::new(input), &mut checkpointer).unwrap();
+
+        let expected = include_bytes!("../testfiles/1080-0.txt").repeat(3);
+        assert_eq!(summary.blocks, 2);
+        assert_eq!(summary.len, expected.len());
+        assert_eq!(
+            summary.crc32,
+            Crc::<u32>::new(&CRC_32_ISO_HDLC).checksum(&expected)
+        );
+    }
+
+    // a block that doesn't decode can't be told apart from block magic turning up inside it by chance, so
+    // indexing keeps going past the magic after it, and only gives up at the end of the file.
+    #[rstest]
+    fn test_index_reports_block_that_never_decodes() {
+        let mut input = include_bytes!("../testfiles/1080-0x3.txt.bz2").to_vec();
+        input[1000] ^= 0x55;
+        let mut checkpointer = Checkpointer::init_memory().unwrap();
+        let result = index(&mut CorniferByteReader::new(input.as_slice()), &mut checkpointer);
+        assert!(
+            matches!(result, Err(CorniferError::InvalidBzip2Block { position: 4, bit: 0 })),
+            "{result:?}"
+        );
+    }
+
+    #[rstest]
+    fn test_ranged_read_across_blocks() {
+        use std::io::{Cursor, Read, Seek, SeekFrom};
+
+        use crate::testutil::TempIndex;
+
+        use super::Bzip2Reader;
+
+        let input: &[u8] = include_bytes!("../testfiles/1080-0x3.txt.bz2");
+        let path = TempIndex::new();
+        let mut checkpointer = Checkpointer::init(&path).unwrap();
+        index(&mut CorniferByteReader::new(input), &mut checkpointer).unwrap();
+        drop(checkpointer);
+
+        let expected = include_bytes!("../testfiles/1080-0.txt").repeat(3);
+        let mut reader = Bzip2Reader::open(Cursor::new(input), &path).unwrap();
+        assert_eq!(reader.uncompressed_len(), expected.len() as u64);
+        let boundary = reader.blocks[1].to_byte as usize;
+
+        reader.seek(SeekFrom::Start(boundary as u64 - 100)).unwrap();
+        let mut dest = vec![0; 200];
+        reader.read_exact(&mut dest).unwrap();
+        assert_eq!(dest, &expected[boundary - 100..boundary + 100]);
+
+        reader.seek(SeekFrom::End(-10)).unwrap();
+        let mut dest = Vec::new();
+        reader.read_to_end(&mut dest).unwrap();
+        assert_eq!(dest, &expected[expected.len() - 10..]);
+    }
+
+    #[rstest]
+    fn test_index_bails_on_non_bzip2_header() {
+        let input: &[u8] = include_bytes!("../testfiles/helloworld.gz");
+        let mut checkpointer = Checkpointer::init_memory().unwrap();
+        let result = index(&mut CorniferByteReader::new(input), &mut checkpointer);
+        assert!(matches!(result, Err(CorniferError::NotBZIP2Header)));
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// How well a SegmentCache is doing, for tuning its budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    /// Number of blocks in the cache right now.
+    pub entries: usize,
+    /// Total size of the blocks in the cache right now.
+    pub bytes: usize,
+}
+
+/**
+ * Decoded blocks, or the parts of them between ticks, keyed by where they start in the decompressed file. They're
+ * kept until they add up to more than the byte budget, at which point the least recently used ones are dropped. A
+ * block bigger than the whole budget isn't kept at all.
+ */
+#[derive(Debug)]
+pub struct SegmentCache {
+    budget: usize,
+    // where it starts -> (contents, when it was last used).
+    entries: HashMap<u64, (Arc<Vec<u8>>, u64)>,
+    // when it was last used -> where it starts, so the least recently used one is the first.
+    by_use: BTreeMap<u64, u64>,
+    clock: u64,
+    stats: CacheStats,
+}
+
+impl SegmentCache {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            entries: HashMap::new(),
+            by_use: BTreeMap::new(),
+            clock: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn get(&mut self, from: u64) -> Option<Arc<Vec<u8>>> {
+        self.clock += 1;
+        match self.entries.get_mut(&from) {
+            Some((data, last_used)) => {
+                self.by_use.remove(last_used);
+                self.by_use.insert(self.clock, from);
+                *last_used = self.clock;
+                self.stats.hits += 1;
+                Some(data.clone())
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn insert(&mut self, from: u64, data: Arc<Vec<u8>>) {
+        if data.len() > self.budget {
+            return;
+        }
+        self.clock += 1;
+        if let Some((old, last_used)) = self.entries.insert(from, (data.clone(), self.clock)) {
+            self.by_use.remove(&last_used);
+            self.stats.bytes -= old.len();
+        }
+        self.by_use.insert(self.clock, from);
+        self.stats.bytes += data.len();
+        while self.stats.bytes > self.budget {
+            let (_, oldest) = self.by_use.pop_first().expect("Cache is over budget, so it isn't empty");
+            let (old, _) = self.entries.remove(&oldest).expect("Every block in by_use is in entries");
+            self.stats.bytes -= old.len();
+            self.stats.evictions += 1;
+        }
+        self.stats.entries = self.entries.len();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+}
+
+/**
+ * TESTS
+ */
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use rstest::rstest;
+
+    use super::{CacheStats, SegmentCache};
+
+    #[rstest]
+    fn test_least_recently_used_goes_first() {
+        let mut cache = SegmentCache::new(30);
+        cache.insert(1, Arc::new(vec![1; 10]));
+        cache.insert(2, Arc::new(vec![2; 10]));
+        cache.insert(3, Arc::new(vec![3; 10]));
+        // 1 is used again, so 2 is the one to go.
+        assert_eq!(cache.get(1).unwrap().as_slice(), [1; 10]);
+        cache.insert(4, Arc::new(vec![4; 10]));
+        assert!(cache.get(2).is_none());
+        assert!(cache.get(1).is_some());
+        assert!(cache.get(3).is_some());
+        // too big to keep.
+        cache.insert(5, Arc::new(vec![5; 31]));
+        assert!(cache.get(5).is_none());
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 3,
+                misses: 2,
+                evictions: 1,
+                entries: 3,
+                bytes: 30
+            }
+        );
+    }
+
+    #[rstest]
+    fn test_insert_again() {
+        let mut cache = SegmentCache::new(30);
+        cache.insert(1, Arc::new(vec![1; 10]));
+        cache.insert(2, Arc::new(vec![2; 10]));
+        // replaces 1 and makes it the most recently used, so 2 goes instead.
+        cache.insert(1, Arc::new(vec![1; 15]));
+        cache.insert(3, Arc::new(vec![3; 10]));
+        assert!(cache.get(2).is_none());
+        assert_eq!(cache.get(1).unwrap().len(), 15);
+        assert_eq!(cache.stats().bytes, 25);
+        assert_eq!(cache.stats().entries, 2);
+    }
+}
@@ -1,11 +1,28 @@
-use std::io::{Cursor, Read};
+#[cfg(feature = "sqlite")]
+use std::fs::File;
+#[cfg(feature = "sqlite")]
+use std::io::{BufWriter, Write};
+#[cfg(feature = "sqlite")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "sqlite")]
+use std::sync::mpsc::{sync_channel, SyncSender};
+#[cfg(feature = "sqlite")]
+use std::thread::JoinHandle;
 
-use flate2::{read::DeflateEncoder, Compression};
-use rusqlite::{blob::ZeroBlob, Connection, DatabaseName};
+#[cfg(feature = "sqlite")]
+use flate2::{write::DeflateEncoder, Compression};
+#[cfg(feature = "sqlite")]
+use rusqlite::{Connection, ErrorCode, OpenFlags};
 
-use crate::{decompress::BlockType, errors::CorniferError};
+use crate::{
+    archive::ArchiveEntry,
+    decompress::{BlockType, Gap, GzipMember},
+    errors::CorniferError,
+};
+#[cfg(feature = "sqlite")]
+use crate::schema::SCHEMA;
 
-/**
+/*
  * Handles writing "checkpoints" (rows in an sqlite table).
  *
  * There are two types of checkpoints. Blocks and ticks.
@@ -16,10 +33,42 @@ use crate::{decompress::BlockType, errors::CorniferError};
  * Ticks occur during a DEFLATE block (but never in the middle of a symbol being decoded). These
  * only get emitted if a single deflate block is particularly big and we want random access inside it.
  * 
- * For now, I haven't implemented Ticks yet. It looks like most mainstream GZIP compressors tend to produce
- * blocks fairly regularly, but I'll need to do more research...
+ * It looks like most mainstream GZIP compressors tend to produce blocks fairly regularly, so nothing emits
+ * Ticks by default. Embedders can ask for them with Deflator::set_tick_trigger.
+ *
+ * Checkpointer::none doesn't write anything, for using the decoders without an index. Without the sqlite
+ * feature, it's the only kind there is.
+ *
+ * A new checkpoint file is written to <path>.tmp, and only renamed to <path> once finalize has marked it as
+ * complete, so a run that fails halfway leaves nothing behind (and an old file it was replacing is still there).
+ * Appending, WAL mode and keep_partial write to <path> directly instead. <path>.tmp is locked while it's written,
+ * so two runs for the same file can't both use it, and a new file (CreateMode::CreateNew) never replaces one that
+ * turned up at <path> in the meantime.
+ *
+ * With IndexOptions::tick_sidecar, tick windows are appended to <path>.ticks as they are, and the Tick rows only
+ * say where. It goes through <path>.tmp.ticks the same way the database does.
+ *
+ * With IndexOptions::background_writer, the database and the sidecar move to a thread of their own while
+ * checkpoints are being written, and each checkpoint is sent to it as a job, with a copy of its window. The queue
+ * is bounded, so decoding waits for the disk once it's that far ahead. Anything that needs an answer from the
+ * database (set_source, finalize, ...) stops the thread first and takes them back, so the rest of the code
+ * doesn't need to know. Block ids are worked out here rather than left to sqlite, since nothing comes back.
+ *
+ * One checkpoint file can hold several files' checkpoints, e.g. a whole directory of them, if each one is given
+ * a name with set_source. Every row is then tagged with the file's id in the Source table.
  */
 
+// windows are stored deflated, by whichever thread is writing the database.
+#[cfg(feature = "sqlite")]
+fn compress_window(window: &[&[u8]]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+    for part in window {
+        encoder.write_all(part)?;
+    }
+    encoder.finish()
+}
+
+#[cfg(feature = "sqlite")]
 fn dist_in_bits(byte1: usize, bit1: u8, byte2: usize, bit2: u8) -> isize {
     let bit2 = bit2 as isize;
     let bit1 = bit1 as isize;
@@ -28,102 +77,625 @@ fn dist_in_bits(byte1: usize, bit1: u8, byte2: usize, bit2: u8) -> isize {
     ((byte2 - byte1) * 8) + (bit2 - bit1)
 }
 
+/// What to do if the checkpoint database already exists.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum CreateMode {
+    /// Fail with `CorniferError::IndexAlreadyExists`.
+    #[default]
+    CreateNew,
+    /// Delete it and start again.
+    Overwrite,
+    /// Add to it, e.g. to put several files' checkpoints in one database.
+    Append,
+}
+
+/// Known-good values for the decompressed file, e.g. from a manifest. If it doesn't match them, finalize fails
+/// and the index is left incomplete.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Expected {
+    pub len: Option<u64>,
+    pub crc32: Option<u32>,
+}
+
+/// How the Checkpointer writes the index. See `Checkpointer::set_options`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct IndexOptions {
+    /// Roughly how many bytes writing the index can use, e.g. in a container with little memory. Compressing
+    /// windows takes the same 320KiB or so whatever the budget (see `MIN_MEMORY_BUDGET`), so only the sqlite page
+    /// cache scales with it: it gets the rest, and checkpoints are committed in batches that fit in half of it.
+    /// None leaves sqlite's defaults and commits each checkpoint as it's written. A CheckpointedReader on an index
+    /// that's still being written only sees whole batches.
+    pub memory_budget: Option<usize>,
+    /// Append tick windows to a sidecar file next to the index, uncompressed, rather than storing them in the
+    /// database. For dense ticks, where the rows and compressing each window cost more than the windows do.
+    /// Needs an index file, rather than one in memory.
+    pub tick_sidecar: bool,
+    /// Write the index from a thread of its own, so decoding doesn't wait on the disk, with up to this many
+    /// checkpoints queued for it (each one holding a copy of its window) before decoding waits after all.
+    pub background_writer: Option<usize>,
+}
+
+/// A queue for `IndexOptions::background_writer` that's usually enough to smooth out a slow disk.
+pub const DEFAULT_WRITER_QUEUE: usize = 64;
+
+// compressing a window takes the window, its compressed copy, and the compressor's state, which is the same
+// size whatever the level. There's no way to give it less, so this comes off the top of every budget.
+const WINDOW_COMPRESSION_MEMORY: usize = 2 * 32768 + 256 * 1024;
+// the biggest a checkpoint gets: a window that didn't compress at all, and the rest of the row.
+const MAX_CHECKPOINT_LEN: usize = 32768 + 1024;
+// past this, batching doesn't get any faster.
+const MAX_BATCH_SIZE: usize = 256;
+
+/// The smallest `IndexOptions::memory_budget` there is: compressing windows, and a page cache of 64KiB.
+pub const MIN_MEMORY_BUDGET: usize = WINDOW_COMPRESSION_MEMORY + 64 * 1024;
+
+// without sqlite, nothing reads where the blocks are.
+#[cfg_attr(not(feature = "sqlite"), allow(dead_code))]
 pub struct Checkpointer {
-    conn: Connection,
+    // None if we're not writing checkpoints anywhere.
+    #[cfg(feature = "sqlite")]
+    conn: Option<Connection>,
     emit_block_type: BlockType,
+    // the packed Huffman trees of the block, if it's a dynamic one. See huffman::encode_trees.
+    emit_trees: Option<Vec<u8>>,
     emit_byte: usize,
     emit_bit: u8,
     to_byte: usize,
     current_block_id: i64,
+    // the id the next DeflateBlock row gets. 0 until it's been looked up.
+    next_block_id: i64,
+    // the Source row everything's written for, if set_source was called.
+    source_id: Option<i64>,
+    // set once finalize has been called.
+    finalized: bool,
+    // set by suspend, so the block in progress is still there for whoever carries on.
+    keep_unfinished: bool,
+    // unfinished blocks that finalize has already removed.
+    removed_unfinished: u64,
+    finished_blocks: u64,
+    expected: Expected,
+    // only store a window this many bytes after the last one. 0 for every block.
+    window_spacing: u64,
+    last_window_to_byte: Option<usize>,
+    // checkpoints per transaction, from the memory budget. 0 for no transactions, i.e. one per checkpoint.
+    batch_size: usize,
+    // checkpoints written since the last commit.
+    batched: usize,
+    // where the checkpoint file goes, and where it's being written until then if that's somewhere else.
+    #[cfg(feature = "sqlite")]
+    path: Option<PathBuf>,
+    #[cfg(feature = "sqlite")]
+    temp_path: Option<PathBuf>,
+    // held while temp_path is being written, so another run for the same file is turned away. See lock_temp_file.
+    #[cfg(feature = "sqlite")]
+    temp_lock: Option<File>,
+    // whether to fail rather than replace a checkpoint file that's turned up at path while this one was written.
+    #[cfg(feature = "sqlite")]
+    no_replace: bool,
+    #[cfg(feature = "sqlite")]
+    tick_sidecar: Option<TickSidecar>,
+    // how long the background writer's queue is, if there's meant to be one, and the thread if it's running.
+    // While it is, it has conn and tick_sidecar.
+    writer_queue: Option<usize>,
+    #[cfg(feature = "sqlite")]
+    writer: Option<Writer>,
+    // the page cache set_options asked for, in KiB, to ask for again if the file is reopened.
+    cache_kib: Option<usize>,
+}
+
+// where tick windows go with IndexOptions::tick_sidecar, and how long the file is so far.
+#[cfg(feature = "sqlite")]
+type TickSidecar = (BufWriter<File>, u64);
+
+// something to do to the database, on whichever thread has it.
+#[cfg(feature = "sqlite")]
+type Job = Box<dyn FnOnce(&mut Connection, &mut Option<TickSidecar>) -> Result<(), CorniferError> + Send>;
+
+// the thread writing the database with IndexOptions::background_writer.
+#[cfg(feature = "sqlite")]
+struct Writer {
+    jobs: SyncSender<Job>,
+    // hands the database back when the queue is closed, with the error it stopped at if there was one.
+    thread: JoinHandle<(Connection, Option<TickSidecar>, Result<(), CorniferError>)>,
 }
 
+/// The block a Checkpointer is in the middle of, for carrying on with it in another process. See
+/// `Deflator::save_state`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OpenBlock {
+    pub block_type: BlockType,
+    /// A dynamic block's trees, packed by `huffman::encode_trees`, once they've been read.
+    pub trees: Option<Vec<u8>>,
+    /// Where the block starts in the compressed stream.
+    pub from_byte: usize,
+    pub from_bit: u8,
+    /// Where it starts in the uncompressed stream.
+    pub to_byte: usize,
+    /// The block's DeflateBlock row, once its data has started.
+    pub id: Option<i64>,
+}
+
+/// A block which is fully decoded by the time it's checkpointed, so the whole row can be
+/// written at once. bzip2 and xz blocks are like this: they don't depend on any earlier data,
+/// so there's no window to store either.
+pub struct IndependentBlock {
+    pub from_byte: usize,
+    pub from_bit: u8,
+    pub to_byte: usize,
+    pub header_len_bits: isize,
+    pub block_len_bits: isize,
+    pub len: usize,
+    pub crc32: u32,
+    // anything else needed to decode the block, goes in the data column.
+    pub data: Vec<u8>,
+}
+
+#[cfg(feature = "sqlite")]
 fn setup_connection(conn: &Connection) -> Result<(), CorniferError> {
-    // id: id of the block. not guaranteed to be sequential.
-    // from_byte:
-    // from_bit  : the byte and bit of the input (i.e. compressed stream) this checkpoint starts at.
-    // to_byte   : the byte of the uncompressed output this checkpoint starts at.
-    // block_type: "NOCOMPRESSION", "FIXED", or "DYNAMIC"
-    // crc32: crc32 of the decompressed data.
-    // header_len_bits: length of the header, in bits!!
-    // block_len_bits: length of the entire block, including the header, in bits, in the compressed stream.
-    // len: length of the entire block, in bytes, in the uncompressed stream.
-    // data      : previous bytes of data before this block.
-    conn.execute(
-        "
-    CREATE TABLE DeflateBlock (
-        id  INTEGER PRIMARY KEY AUTOINCREMENT,
-        from_byte INTEGER NOT NULL,
-        from_bit INTEGER NOT NULL,
-        to_byte INTEGER NOT NULL,
-        block_type TEXT NOT NULL,
-        crc32 TEXT,
-        len INTEGER,
-        header_len_bits INTEGER,
-        block_len_bits INTEGER,
-        data BLOB NOT NULL
-    )",
-        (),
-    )?;
-
-    // // id
-    // // from_byte
-    // // from_bit
-    // // to_byte  : same as DeflateBlock
-    // // block: FK to DeflateBlock. We need this to get the required huffman trees.
-    // // data: previous bytes of data before this tick.
-    // conn.execute(
-    //     "
-    // CREATE TABLE Tick (
-    //     id  INTEGER PRIMARY KEY AUTOINCREMENT,
-    //     from_byte INTEGER NOT NULL,
-    //     from_bit INTEGER NOT NULL,
-    //     to_byte INTEGER NOT NULL,
-    //     block_id INTEGER NOT NULL,
-    //     data BLOB NOT NULL,
-    //     FOREIGN KEY (block_id) REFERENCES DeflateBlock (id)
-    // )",
-    //     (),
-    // )?;
+    // what the tables and their columns are is in schema.rs.
+    for table in SCHEMA.tables {
+        conn.execute(&table.create_sql(), ())?;
+    }
+    // databases from before a column was added, which we might be appending to. New columns can always be NULL.
+    for table in SCHEMA.tables {
+        for column in table.columns.iter().filter(|c| !c.not_null && !c.primary_key) {
+            let exists: i64 = conn.query_row(
+                "SELECT COUNT(*) FROM pragma_table_info(?1) WHERE name = ?2",
+                (table.name, column.name),
+                |row| row.get(0),
+            )?;
+            if exists == 0 {
+                conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table.name, column.name, column.sql_type), ())?;
+            }
+        }
+    }
+    conn.execute_batch(&format!("PRAGMA user_version = {}", SCHEMA.version))?;
+    // if we crash (or are appending to an index), it shouldn't look finished.
+    conn.execute("INSERT OR REPLACE INTO Meta (key, value) VALUES ('complete', '0')", ())?;
 
     Ok(())
 }
 
-impl Checkpointer {
-    // Initialize a Checkpointer using an sqlite database in file.
-    pub fn init(path: String) -> Result<Self, CorniferError> {
-        let conn = Connection::open(path)?;
-
-        setup_connection(&conn)?;
+// Take the write lock straight away, so another process writing to the same database is found out now and
+// not halfway through indexing.
+#[cfg(feature = "sqlite")]
+fn check_not_busy(conn: &Connection, path: &Path) -> Result<(), CorniferError> {
+    match conn.execute_batch("BEGIN IMMEDIATE; COMMIT;") {
+        Err(rusqlite::Error::SqliteFailure(e, _))
+            if matches!(e.code, ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked) =>
+        {
+            Err(CorniferError::IndexBusy {
+                path: path.display().to_string(),
+            })
+        }
+        Err(e) => Err(e.into()),
+        Ok(()) => Ok(()),
+    }
+}
 
-        Ok(Self {
-            conn,
-            emit_block_type: BlockType::NoCompression, // gets set on the first BlockHeader state.
+impl Checkpointer {
+    // A Checkpointer that doesn't write anything, for when all that's wanted is the decompressed data.
+    pub fn none() -> Self {
+        Self {
+            #[cfg(feature = "sqlite")]
+            conn: None,
+            emit_block_type: BlockType::NoCompression,
+            emit_trees: None,
             emit_byte: 0,
             emit_bit: 0,
             to_byte: 0,
             current_block_id: 0,
-        })
+            next_block_id: 0,
+            source_id: None,
+            finalized: false,
+            keep_unfinished: false,
+            removed_unfinished: 0,
+            finished_blocks: 0,
+            expected: Expected::default(),
+            window_spacing: 0,
+            last_window_to_byte: None,
+            batch_size: 0,
+            batched: 0,
+            #[cfg(feature = "sqlite")]
+            path: None,
+            #[cfg(feature = "sqlite")]
+            temp_path: None,
+            #[cfg(feature = "sqlite")]
+            temp_lock: None,
+            #[cfg(feature = "sqlite")]
+            no_replace: false,
+            #[cfg(feature = "sqlite")]
+            tick_sidecar: None,
+            writer_queue: None,
+            #[cfg(feature = "sqlite")]
+            writer: None,
+            cache_kib: None,
+        }
+    }
+
+    // Initialize a Checkpointer using a new sqlite database in file. It's an error if the file already exists.
+    #[cfg(feature = "sqlite")]
+    pub fn init<P: AsRef<Path>>(path: P) -> Result<Self, CorniferError> {
+        Self::open(path, CreateMode::CreateNew)
+    }
+
+    // Initialize a Checkpointer using an sqlite database in file, which might already exist.
+    #[cfg(feature = "sqlite")]
+    pub fn open<P: AsRef<Path>>(path: P, mode: CreateMode) -> Result<Self, CorniferError> {
+        let path = path.as_ref();
+        if mode == CreateMode::CreateNew {
+            // checked by creating it, so it's found out now rather than once indexing is done. It's written to
+            // <path>.tmp until then.
+            match std::fs::OpenOptions::new().write(true).create_new(true).open(path) {
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    return Err(CorniferError::IndexAlreadyExists {
+                        path: path.display().to_string(),
+                    })
+                }
+                Err(e) => return Err(e.into()),
+                Ok(_) => std::fs::remove_file(path)?,
+            }
+        }
+        let (temp_path, temp_lock) = match mode {
+            CreateMode::Append => (None, None),
+            // anything already there stays until this one is finished.
+            CreateMode::CreateNew | CreateMode::Overwrite => {
+                let temp_path = temp_path(path);
+                let temp_lock = lock_temp_file(&temp_path, path)?;
+                (Some(temp_path), Some(temp_lock))
+            }
+        };
+        let flags = OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE;
+        let conn = Connection::open_with_flags(temp_path.as_deref().unwrap_or(path), flags)?;
+        check_not_busy(&conn, path)?;
+
+        setup_connection(&conn)?;
+
+        let mut checkpointer = Self::none();
+        checkpointer.conn = Some(conn);
+        checkpointer.path = Some(path.to_path_buf());
+        checkpointer.temp_path = temp_path;
+        checkpointer.temp_lock = temp_lock;
+        checkpointer.no_replace = mode == CreateMode::CreateNew;
+        Ok(checkpointer)
+    }
+
+    // Write straight to the checkpoint file from now on, rather than to <path>.tmp until finalize. If it fails
+    // partway, the checkpoints written so far are kept, in a file that's marked as incomplete.
+    #[cfg(feature = "sqlite")]
+    pub fn keep_partial(&mut self) -> Result<(), CorniferError> {
+        self.move_into_place()
+    }
+
+    // rename <path>.tmp to <path>, if that's where we're writing. The connection is closed for it, and
+    // opened again afterwards.
+    #[cfg(feature = "sqlite")]
+    fn move_into_place(&mut self) -> Result<(), CorniferError> {
+        self.stop_writer()?;
+        let (Some(temp_path), Some(path)) = (self.temp_path.take(), self.path.clone()) else {
+            return Ok(());
+        };
+        self.commit_batch()?;
+        if let Some(conn) = self.conn.take() {
+            conn.close().map_err(|(_, e)| e)?;
+        }
+        if self.no_replace {
+            // a link can't replace anything, unlike a rename.
+            match std::fs::hard_link(&temp_path, &path) {
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    // left for drop to clean up.
+                    self.temp_path = Some(temp_path);
+                    return Err(CorniferError::IndexAlreadyExists {
+                        path: path.display().to_string(),
+                    });
+                }
+                result => result?,
+            }
+            std::fs::remove_file(&temp_path)?;
+        } else {
+            std::fs::rename(&temp_path, &path)?;
+        }
+        // only once the database is closed, since closing any handle to a file drops this process's sqlite locks
+        // on it.
+        drop(self.temp_lock.take());
+        // an old index's sidecar would be left over otherwise.
+        let sidecar = tick_sidecar_path(&path);
+        if self.tick_sidecar.is_some() {
+            std::fs::rename(tick_sidecar_path(&temp_path), &sidecar)?;
+        } else if sidecar.try_exists()? {
+            std::fs::remove_file(&sidecar)?;
+        }
+        let conn = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_WRITE)?;
+        if let Some(kib) = self.cache_kib {
+            conn.execute_batch(&format!("PRAGMA cache_size = -{kib}"))?;
+        }
+        self.conn = Some(conn);
+        Ok(())
     }
 
     // Initialize a Checkpointer using an sqlite database in memory.
     // I only expect this to be useful for tests.
+    #[cfg(feature = "sqlite")]
     pub fn init_memory() -> Result<Self, CorniferError> {
         let conn = Connection::open_in_memory()?;
 
         setup_connection(&conn)?;
 
-        Ok(Self {
-            conn,
-            emit_block_type: BlockType::NoCompression, // gets set on the first BlockHeader state.
-            emit_byte: 0,
-            emit_bit: 0,
-            to_byte: 0,
-            current_block_id: 0
+        let mut checkpointer = Self::none();
+        checkpointer.conn = Some(conn);
+        Ok(checkpointer)
+    }
+
+    // Commit everything written so far, and leave it there when the Checkpointer is dropped, unfinished block and
+    // all, for carrying on from in another process with Deflator::load_state. A new checkpoint file is moved into
+    // place for it, like keep_partial.
+    #[cfg(feature = "sqlite")]
+    pub fn suspend(&mut self) -> Result<(), CorniferError> {
+        self.move_into_place()?;
+        self.commit_batch()?;
+        self.keep_unfinished = true;
+        Ok(())
+    }
+
+    // Put the database in WAL mode, so a CheckpointedReader (in this process or another) can read the checkpoints
+    // written so far without either of them waiting for the other. The database stays in WAL mode afterwards,
+    // which needs readers to be able to write the -shm file next to it. Readers need to know where it is, so
+    // this writes to it directly, like keep_partial.
+    #[cfg(feature = "sqlite")]
+    pub fn use_wal(&mut self) -> Result<(), CorniferError> {
+        self.move_into_place()?;
+        if let Some(conn) = &self.conn {
+            conn.query_row("PRAGMA journal_mode = WAL", (), |_| Ok(()))?;
+        }
+        Ok(())
+    }
+
+    // Write the checkpoints for the file called `name`, so it can be read back from a checkpoint file with
+    // several files in it with CheckpointStore::open_source. If there's already one called that, it's replaced.
+    #[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+    pub fn set_source(&mut self, name: &str) -> Result<(), CorniferError> {
+        #[cfg(feature = "sqlite")]
+        self.stop_writer()?;
+        #[cfg(feature = "sqlite")]
+        if let Some(conn) = &self.conn {
+            conn.execute(
+                "INSERT INTO Source (name, complete) VALUES (?1, 0)
+                ON CONFLICT (name) DO UPDATE SET complete = 0, uncompressed_len = NULL, crc32 = NULL",
+                (name,),
+            )?;
+            let id: i64 = conn.query_row("SELECT id FROM Source WHERE name = ?1", (name,), |row| row.get(0))?;
+            // whatever was checkpointed for it before is out of date.
+            for table in ["Tick", "DeflateBlock", "GzipMember", "Gap"] {
+                conn.execute(&format!("DELETE FROM {table} WHERE source_id = ?1"), (id,))?;
+            }
+            self.source_id = Some(id);
+        }
+        Ok(())
+    }
+
+    // What finalize should check the decompressed file against.
+    pub fn set_expected(&mut self, expected: Expected) {
+        self.expected = expected;
+    }
+
+    // Only store a block's window if it's at least this many bytes of output after the last block that has one,
+    // for a much smaller index. Reading a block without one means decoding from the last block that has one.
+    // Blocks at the start of a member always get theirs, since it's empty. 0 (the default) stores every window,
+    // and u64::MAX only the ones at the start of each member.
+    pub fn set_window_spacing(&mut self, spacing: u64) {
+        self.window_spacing = spacing;
+    }
+
+    // Size the page cache and batches to fit in options.memory_budget. Fails if the budget is below
+    // MIN_MEMORY_BUDGET (plus the background writer's queue), rather than going over it.
+    pub fn set_options(&mut self, options: IndexOptions) -> Result<(), CorniferError> {
+        #[cfg(feature = "sqlite")]
+        self.stop_writer()?;
+        #[cfg(feature = "sqlite")]
+        if options.tick_sidecar && self.conn.is_some() {
+            self.open_tick_sidecar()?;
+        }
+        self.writer_queue = options.background_writer;
+        let Some(budget) = options.memory_budget else {
+            return Ok(());
+        };
+        // every checkpoint in the queue has its window with it.
+        let queue = options.background_writer.map_or(0, |len| len * MAX_CHECKPOINT_LEN);
+        if budget < MIN_MEMORY_BUDGET + queue {
+            return Err(CorniferError::MemoryBudgetTooSmall {
+                budget,
+                minimum: MIN_MEMORY_BUDGET + queue,
+            });
+        }
+        let page_cache = budget - WINDOW_COMPRESSION_MEMORY - queue;
+        self.cache_kib = Some(page_cache / 1024);
+        #[cfg(feature = "sqlite")]
+        if let Some(conn) = &self.conn {
+            // negative means KiB rather than pages.
+            conn.execute_batch(&format!("PRAGMA cache_size = -{}", page_cache / 1024))?;
+        }
+        self.batch_size = (page_cache / 2 / MAX_CHECKPOINT_LEN).clamp(1, MAX_BATCH_SIZE);
+        Ok(())
+    }
+
+    // ticks written from now on go to the end of the sidecar, which might already have some from appending.
+    #[cfg(feature = "sqlite")]
+    fn open_tick_sidecar(&mut self) -> Result<(), CorniferError> {
+        let Some(path) = self.temp_path.as_ref().or(self.path.as_ref()) else {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "no file to put a tick sidecar next to").into());
+        };
+        let file = File::options().create(true).append(true).open(tick_sidecar_path(path))?;
+        let len = file.metadata()?.len();
+        self.tick_sidecar = Some((BufWriter::new(file), len));
+        Ok(())
+    }
+
+    // whether there's a database being written, here or on the background writer.
+    #[cfg(feature = "sqlite")]
+    fn writing(&self) -> bool {
+        self.conn.is_some() || self.writer.is_some()
+    }
+
+    // Do something to the database: on the background writer if it's running, or right here if not.
+    #[cfg(feature = "sqlite")]
+    fn run<F>(&mut self, job: F) -> Result<(), CorniferError>
+    where
+        F: FnOnce(&mut Connection, &mut Option<TickSidecar>) -> Result<(), CorniferError> + Send + 'static,
+    {
+        if let Some(writer) = &self.writer {
+            if writer.jobs.send(Box::new(job)).is_ok() {
+                return Ok(());
+            }
+            // it's stopped at an error, which is the one to give.
+            return self.stop_writer();
+        }
+        match self.conn.as_mut() {
+            Some(conn) => job(conn, &mut self.tick_sidecar),
+            None => Ok(()),
+        }
+    }
+
+    // Write a checkpoint, or something about one. With a memory budget, checkpoints are written in transactions of
+    // batch_size, so this starts one if there isn't one going. Starts the background writer, if there's meant to
+    // be one and something stopped it.
+    #[cfg(feature = "sqlite")]
+    fn write<F>(&mut self, job: F) -> Result<(), CorniferError>
+    where
+        F: FnOnce(&mut Connection, &mut Option<TickSidecar>) -> Result<(), CorniferError> + Send + 'static,
+    {
+        if let (Some(queue), None) = (self.writer_queue, &self.writer) {
+            self.start_writer(queue)?;
+        }
+        let batching = self.batch_size > 0;
+        self.run(move |conn, tick_sidecar| {
+            if batching && conn.is_autocommit() {
+                conn.execute_batch("BEGIN")?;
+            }
+            job(conn, tick_sidecar)
+        })
+    }
+
+    #[cfg(feature = "sqlite")]
+    fn start_writer(&mut self, queue: usize) -> Result<(), CorniferError> {
+        // the database is about to go, and this needs it.
+        self.find_next_block_id()?;
+        let Some(mut conn) = self.conn.take() else {
+            return Ok(());
+        };
+        let mut tick_sidecar = self.tick_sidecar.take();
+        let (jobs, queued) = sync_channel::<Job>(queue);
+        let thread = std::thread::Builder::new().name("cornifer-writer".to_string()).spawn(move || {
+            // the queue is dropped at the first error, so the next send fails.
+            let result = queued.into_iter().try_for_each(|job| job(&mut conn, &mut tick_sidecar));
+            (conn, tick_sidecar, result)
+        });
+        match thread {
+            Ok(thread) => self.writer = Some(Writer { jobs, thread }),
+            Err(e) => {
+                // spawn doesn't hand the closure back, with the database in it.
+                self.writer_queue = None;
+                return Err(e.into());
+            }
+        }
+        Ok(())
+    }
+
+    // Wait for the background writer to get through its queue, and take the database back from it.
+    #[cfg(feature = "sqlite")]
+    fn stop_writer(&mut self) -> Result<(), CorniferError> {
+        self.join_writer().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    }
+
+    // stop_writer, but with a panic on the background writer handed back rather than carried on with. The database
+    // went with it.
+    #[cfg(feature = "sqlite")]
+    fn join_writer(&mut self) -> std::thread::Result<Result<(), CorniferError>> {
+        let Some(Writer { jobs, thread }) = self.writer.take() else {
+            return Ok(Ok(()));
+        };
+        drop(jobs);
+        let (conn, tick_sidecar, result) = thread.join()?;
+        self.conn = Some(conn);
+        self.tick_sidecar = tick_sidecar;
+        Ok(result)
+    }
+
+    // The database, if jobs are run right here rather than on the background writer, so they can borrow what they
+    // write instead of copying it. Starts a batch, like write.
+    #[cfg(feature = "sqlite")]
+    fn write_here(&mut self) -> Result<Option<(&mut Connection, &mut Option<TickSidecar>)>, CorniferError> {
+        if self.writer_queue.is_some() || self.writer.is_some() {
+            return Ok(None);
+        }
+        let Some(conn) = self.conn.as_mut() else {
+            return Ok(None);
+        };
+        if self.batch_size > 0 && conn.is_autocommit() {
+            conn.execute_batch("BEGIN")?;
+        }
+        Ok(Some((conn, &mut self.tick_sidecar)))
+    }
+
+    // AUTOINCREMENT never hands out an id twice, even after its row is deleted, so nor does new_block_id.
+    #[cfg(feature = "sqlite")]
+    fn find_next_block_id(&mut self) -> Result<(), CorniferError> {
+        if self.next_block_id == 0 {
+            if let Some(conn) = &self.conn {
+                self.next_block_id = conn.query_row(
+                    "SELECT max(
+                        coalesce((SELECT seq FROM sqlite_sequence WHERE name = 'DeflateBlock'), 0),
+                        coalesce((SELECT max(id) FROM DeflateBlock), 0)
+                    ) + 1",
+                    (),
+                    |row| row.get(0),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    // The id for a new DeflateBlock row.
+    #[cfg(feature = "sqlite")]
+    fn new_block_id(&mut self) -> Result<i64, CorniferError> {
+        self.find_next_block_id()?;
+        self.next_block_id += 1;
+        Ok(self.next_block_id - 1)
+    }
+
+    // Call once a checkpoint has been written.
+    #[cfg(feature = "sqlite")]
+    fn end_checkpoint(&mut self) -> Result<(), CorniferError> {
+        self.batched += 1;
+        if self.batched >= self.batch_size {
+            self.commit_batch()?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "sqlite")]
+    fn commit_batch(&mut self) -> Result<(), CorniferError> {
+        self.batched = 0;
+        self.run(|conn, tick_sidecar| {
+            // the windows have to be there before the rows that point at them are.
+            if let Some((sidecar, _)) = tick_sidecar.as_mut() {
+                sidecar.flush()?;
+            }
+            if !conn.is_autocommit() {
+                conn.execute_batch("COMMIT")?;
+            }
+            Ok(())
         })
     }
 
     pub fn set_block_type(&mut self, block_type: BlockType) {
         self.emit_block_type = block_type;
+        self.emit_trees = None;
+    }
+
+    // The trees of the dynamic block just started, from huffman::encode_trees. Call after set_block_type.
+    pub fn set_trees(&mut self, trees: Vec<u8>) {
+        self.emit_trees = Some(trees);
     }
 
     // Should be called just where the block starts.
@@ -139,45 +711,108 @@ impl Checkpointer {
     }
 
     // Should be called just where the block data starts (after the header)
+    #[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
     pub fn on_block_data_start(
         &mut self,
         curr_byte: usize,
         bit: u8,
-        data: Vec<u8>,
+        window: &[&[u8]],
     ) -> Result<(), CorniferError> {
-        let curr_byte = if bit == 0 { curr_byte } else { curr_byte - 1 };
-        let block_header_size_bits = dist_in_bits(self.emit_byte, self.emit_bit, curr_byte, bit);
-
-        // block_type string to write to the database.
-        let block_type = match self.emit_block_type {
-            BlockType::NoCompression => "nocompression",
-            BlockType::FixedHuffman => "fixed",
-            BlockType::DynamicHuffman => "dynamic",
-        };
+        #[cfg(feature = "sqlite")]
+        if self.writing() {
+            let curr_byte = if bit == 0 { curr_byte } else { curr_byte - 1 };
+            let block_header_size_bits = dist_in_bits(self.emit_byte, self.emit_bit, curr_byte, bit);
 
-        let mut encoder = DeflateEncoder::new(Cursor::new(data), Compression::best());
-        let mut compressed_data = Vec::new();
-        encoder.read_to_end(&mut compressed_data)?;
+            // block_type string to write to the database.
+            let block_type = match self.emit_block_type {
+                BlockType::NoCompression => "nocompression",
+                BlockType::FixedHuffman => "fixed",
+                BlockType::DynamicHuffman => "dynamic",
+            };
 
-        self.conn.execute("
-            INSERT INTO DeflateBlock (from_byte, from_bit, to_byte, block_type, header_len_bits, data) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
-        ", (self.emit_byte, self.emit_bit, self.to_byte, block_type, block_header_size_bits, ZeroBlob(compressed_data.len().try_into().expect("Max size for data will be 32kb, so this should always fit"))))?;
+            // nothing before the start of a member is needed, so there's no window to keep. It's always a
+            // checkpoint though, and counts as one with a window for the spacing.
+            let member_start = window.iter().all(|part| part.is_empty());
+            // empty blocks (e.g. from a flush) share their to_byte with the next block, which needs the window
+            // just as much.
+            let keep_window = member_start
+                || self.last_window_to_byte.is_none_or(|last| {
+                    last == self.to_byte || (self.to_byte - last) as u64 >= self.window_spacing
+                });
+            let window = match keep_window && !member_start {
+                true => window,
+                false => &[],
+            };
+            if keep_window {
+                self.last_window_to_byte = Some(self.to_byte);
+            }
 
-        // Get the row id off the BLOB we just inserted.
-        let rowid = self.conn.last_insert_rowid();
-        self.current_block_id = rowid;
-        // Open the BLOB we just inserted for IO.
-        let mut blob =
-            self.conn
-                .blob_open(DatabaseName::Main, "DeflateBlock", "data", rowid, false)?;
-        let mut file = Cursor::new(compressed_data);
-        // copy the vector into the SQL blob.
-        std::io::copy(&mut file, &mut blob)?;
+            let rowid = self.new_block_id()?;
+            let row = (rowid, self.emit_byte, self.emit_bit, self.to_byte, block_type, block_header_size_bits);
+            let (trees, source_id) = (self.emit_trees.clone(), self.source_id);
+            let insert = move |conn: &mut Connection, window: &[&[u8]]| -> Result<(), CorniferError> {
+                let compressed_data = match window.iter().all(|part| part.is_empty()) {
+                    true => Vec::new(),
+                    false => compress_window(window)?,
+                };
+                let (id, from_byte, from_bit, to_byte, block_type, header_len_bits) = row;
+                // the window goes in with the rest of the row, so a failed insert doesn't leave a row with no window.
+                conn.prepare_cached("
+                    INSERT INTO DeflateBlock (id, from_byte, from_bit, to_byte, block_type, header_len_bits, data, trees, source_id, member_start) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+                ")?.execute((id, from_byte, from_bit, to_byte, block_type, header_len_bits, compressed_data, trees, source_id, member_start))?;
+                Ok(())
+            };
+            // the window parts only live until the decoder moves on, so the background writer gets a copy.
+            match self.write_here()? {
+                Some((conn, _)) => insert(conn, window)?,
+                None => {
+                    let window = window.concat();
+                    self.write(move |conn, _| insert(conn, &[&window]))?;
+                }
+            }
+            #[cfg(feature = "tracing")]
+            tracing::debug!(id = rowid, from_byte = self.emit_byte, from_bit = self.emit_bit, to_byte = self.to_byte, block_type, "wrote checkpoint");
+            self.current_block_id = rowid;
+        }
 
         Ok(())
     }
 
+    // The block that's been started, with its row if its data has started too. Only means anything between
+    // on_block_start and on_block_end.
+    pub(crate) fn open_block(&self, data_started: bool) -> OpenBlock {
+        OpenBlock {
+            block_type: self.emit_block_type,
+            trees: self.emit_trees.clone(),
+            from_byte: self.emit_byte,
+            from_bit: self.emit_bit,
+            to_byte: self.to_byte,
+            id: self.current_block_id().filter(|_| data_started),
+        }
+    }
+
+    // Carry on with a block from open_block, as if on_block_start (and on_block_data_start, if it has a row) had
+    // been called for it here.
+    pub(crate) fn resume_block(&mut self, block: OpenBlock) {
+        self.emit_block_type = block.block_type;
+        self.emit_trees = block.trees;
+        self.emit_byte = block.from_byte;
+        self.emit_bit = block.from_bit;
+        self.to_byte = block.to_byte;
+        self.current_block_id = block.id.unwrap_or(0);
+    }
+
+    /// The row of the last block whose data started, if there's a checkpoint file.
+    pub fn current_block_id(&self) -> Option<i64> {
+        #[cfg(feature = "sqlite")]
+        if self.writing() && self.current_block_id != 0 {
+            return Some(self.current_block_id);
+        }
+        None
+    }
+
     // Should be called just where the block data ends
+    #[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
     pub fn on_block_end(
         &mut self,
         curr_byte: usize,
@@ -185,25 +820,787 @@ impl Checkpointer {
         to_byte: usize,
         crc32: u32
     ) -> Result<(), CorniferError> {
-        let curr_byte = if bit == 0 { curr_byte } else { curr_byte - 1 };
-        // this is the corresponding row that's already been inserted.
-        let rowid = self.current_block_id;
-        // length of the entire block (compressed)...
-        let entire_block_size_bits = dist_in_bits( self.emit_byte, self.emit_bit, curr_byte, bit);
-        // length of the block (uncompressed)...
-        let uncompressed_block_size = to_byte - self.to_byte;
-
-        // the crc32 as a string
-        let formatted_crc = format!("{crc32:x}");
-
-        self.conn.execute("
-            UPDATE DeflateBlock
-            SET crc32 = ?1,
-                len = ?2,
-                block_len_bits = ?3
-            WHERE DeflateBlock.id = ?4
-        ", (formatted_crc, uncompressed_block_size, entire_block_size_bits, rowid))?;
+        #[cfg(feature = "sqlite")]
+        if self.writing() {
+            let curr_byte = if bit == 0 { curr_byte } else { curr_byte - 1 };
+            // this is the corresponding row that's already been inserted.
+            let rowid = self.current_block_id;
+            // length of the entire block (compressed)...
+            let entire_block_size_bits = dist_in_bits( self.emit_byte, self.emit_bit, curr_byte, bit);
+            // length of the block (uncompressed)...
+            let uncompressed_block_size = to_byte - self.to_byte;
+
+            // the crc32 as a string
+            let formatted_crc = format!("{crc32:x}");
+
+            self.write(move |conn, _| {
+                conn.execute("
+                    UPDATE DeflateBlock
+                    SET crc32 = ?1,
+                        len = ?2,
+                        block_len_bits = ?3
+                    WHERE DeflateBlock.id = ?4
+                ", (formatted_crc, uncompressed_block_size, entire_block_size_bits, rowid))?;
+                Ok(())
+            })?;
+            #[cfg(feature = "tracing")]
+            tracing::debug!(id = rowid, len = uncompressed_block_size, block_len_bits = entire_block_size_bits, crc32, "finished checkpoint");
+            self.end_checkpoint()?;
+        }
+        self.finished_blocks += 1;
+
+        Ok(())
+    }
+
+    // Should be called after on_block_end, with what was in the block, when stats are being collected.
+    #[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+    pub fn on_block_stats(&mut self, literals: u64, matches: u64, longest_match: u16) -> Result<(), CorniferError> {
+        #[cfg(feature = "sqlite")]
+        {
+            let id = self.current_block_id;
+            self.write(move |conn, _| {
+                conn.execute(
+                    "UPDATE DeflateBlock SET literals = ?1, matches = ?2, longest_match = ?3 WHERE id = ?4",
+                    (literals, matches, longest_match, id),
+                )?;
+                Ok(())
+            })?;
+        }
+        Ok(())
+    }
+
+    // Should be called after on_block_end, with the CRC32 of the compressed bytes the block is in, when they're
+    // being hashed.
+    #[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+    pub fn on_block_hash(&mut self, compressed_crc32: u32) -> Result<(), CorniferError> {
+        #[cfg(feature = "sqlite")]
+        {
+            let id = self.current_block_id;
+            self.write(move |conn, _| {
+                conn.execute(
+                    "UPDATE DeflateBlock SET compressed_crc32 = ?1 WHERE id = ?2",
+                    (format!("{compressed_crc32:x}"), id),
+                )?;
+                Ok(())
+            })?;
+        }
+        Ok(())
+    }
+
+    // Should be called between two symbols of the current block.
+    #[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+    pub fn on_tick(
+        &mut self,
+        curr_byte: usize,
+        bit: u8,
+        to_byte: usize,
+        window: &[&[u8]],
+    ) -> Result<(), CorniferError> {
+        #[cfg(feature = "sqlite")]
+        if self.writing() {
+            let from_byte = if bit == 0 { curr_byte } else { curr_byte - 1 };
+            let (block_id, source_id) = (self.current_block_id, self.source_id);
+            let insert = move |conn: &mut Connection, tick_sidecar: &mut Option<TickSidecar>, window: &[&[u8]]| -> Result<(), CorniferError> {
+                let (data, sidecar_offset, sidecar_len) = match tick_sidecar.as_mut() {
+                    Some((sidecar, offset)) => {
+                        let len: usize = window.iter().map(|part| part.len()).sum();
+                        for part in window {
+                            sidecar.write_all(part)?;
+                        }
+                        // outside a batch, the row is committed as soon as it's inserted.
+                        if conn.is_autocommit() {
+                            sidecar.flush()?;
+                        }
+                        let at = *offset;
+                        *offset += len as u64;
+                        (Vec::new(), Some(at), Some(len))
+                    }
+                    None => (compress_window(window)?, None, None),
+                };
+                conn.execute(
+                    "INSERT INTO Tick (from_byte, from_bit, to_byte, block_id, data, source_id, sidecar_offset, sidecar_len) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    (from_byte, bit, to_byte, block_id, data, source_id, sidecar_offset, sidecar_len),
+                )?;
+                Ok(())
+            };
+            match self.write_here()? {
+                Some((conn, tick_sidecar)) => insert(conn, tick_sidecar, window)?,
+                None => {
+                    let window = window.concat();
+                    self.write(move |conn, tick_sidecar| insert(conn, tick_sidecar, &[&window]))?;
+                }
+            }
+            #[cfg(feature = "tracing")]
+            tracing::debug!(block_id = self.current_block_id, from_byte, from_bit = bit, to_byte, "wrote tick");
+            self.end_checkpoint()?;
+        }
+        Ok(())
+    }
+
+    // Should be called once a gzip member's footer has been read.
+    #[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+    pub fn on_member_end(&mut self, member: &GzipMember) -> Result<(), CorniferError> {
+        #[cfg(feature = "sqlite")]
+        if self.writing() {
+            let row = (member.from_byte, member.to_byte, member.len, format!("{:x}", member.crc32), member.header.name.clone(), member.header.mtime, self.source_id, member.header.span.len());
+            self.write(move |conn, _| {
+                conn.execute(
+                    "INSERT INTO GzipMember (from_byte, to_byte, len, crc32, name, mtime, source_id, header_len) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    row,
+                )?;
+                Ok(())
+            })?;
+        }
+        Ok(())
+    }
+
+    // Should be called when a corrupt gzip member has been skipped.
+    #[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+    pub fn on_gap(&mut self, gap: &Gap) -> Result<(), CorniferError> {
+        #[cfg(feature = "sqlite")]
+        if self.writing() {
+            let row = (gap.from_byte, gap.to_byte, gap.uncompressed_from, gap.uncompressed_to, gap.error.clone(), self.source_id);
+            self.write(move |conn, _| {
+                conn.execute(
+                    "INSERT INTO Gap (from_byte, to_byte, uncompressed_from, uncompressed_to, error, source_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    row,
+                )?;
+                Ok(())
+            })?;
+        }
+        // windowless blocks are decoded from the last block with a window, which mustn't be on the other side.
+        self.last_window_to_byte = None;
+        Ok(())
+    }
+
+    // How many blocks were started but never finished, e.g. because the input was cut off. After decoding
+    // all of a file successfully, this should be 0. The ones finalize removed still count. Always 0 for
+    // Checkpointer::none, which never writes any.
+    pub fn unfinished_blocks(&mut self) -> Result<u64, CorniferError> {
+        #[cfg(feature = "sqlite")]
+        self.stop_writer()?;
+        #[cfg(feature = "sqlite")]
+        if let Some(conn) = &self.conn {
+            let count: u64 =
+                conn.query_row("SELECT COUNT(*) FROM DeflateBlock WHERE len IS NULL", (), |row| row.get(0))?;
+            return Ok(count + self.removed_unfinished);
+        }
+        Ok(self.removed_unfinished)
+    }
+
+    // Forget the blocks from block_id on and the ones that were never finished, with their ticks, and the members and
+    // gaps from byte from_byte of the compressed file on, so decoding can carry on from there. For Deflator::repair.
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn truncate(&mut self, block_id: i64, from_byte: u64) -> Result<(), CorniferError> {
+        self.stop_writer()?;
+        self.commit_batch()?;
+        if let Some(conn) = self.conn.as_mut() {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "DELETE FROM Tick WHERE block_id >= ?1 OR block_id NOT IN (SELECT id FROM DeflateBlock WHERE len IS NOT NULL)",
+                (block_id,),
+            )?;
+            tx.execute("DELETE FROM DeflateBlock WHERE id >= ?1 OR len IS NULL", (block_id,))?;
+            tx.execute("DELETE FROM GzipMember WHERE from_byte >= ?1", (from_byte,))?;
+            tx.execute("DELETE FROM Gap WHERE from_byte >= ?1", (from_byte,))?;
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    // How many blocks this Checkpointer has finished writing. Blocks already in the database from before (with
+    // CreateMode::Append) don't count. Checkpointer::none counts the blocks it would have written.
+    pub fn finished_blocks(&self) -> u64 {
+        self.finished_blocks
+    }
 
+    // Should be called once the whole file has been checkpointed. Removes any blocks that were never finished, and
+    // marks the index as complete. Fails without marking it if the file isn't what set_expected said it would be.
+    pub fn finalize(&mut self, uncompressed_len: u64, crc32: u32) -> Result<(), CorniferError> {
+        if let Some(expected) = self.expected.len.filter(|len| *len != uncompressed_len) {
+            return Err(CorniferError::UnexpectedLength {
+                expected,
+                found: uncompressed_len,
+            });
+        }
+        if let Some(expected) = self.expected.crc32.filter(|crc| *crc != crc32) {
+            return Err(CorniferError::UnexpectedCrc32 { expected, found: crc32 });
+        }
+        #[cfg(feature = "sqlite")]
+        self.stop_writer()?;
+        #[cfg(feature = "sqlite")]
+        self.commit_batch()?;
+        #[cfg(feature = "sqlite")]
+        if let Some(conn) = self.conn.as_mut() {
+            let tx = conn.transaction()?;
+            let removed = remove_unfinished_blocks(&tx)?;
+            tx.execute(
+                "INSERT OR REPLACE INTO Meta (key, value) VALUES ('complete', '1'), ('uncompressed_len', ?1), ('crc32', ?2)",
+                (uncompressed_len.to_string(), format!("{crc32:x}")),
+            )?;
+            if let Some(source_id) = self.source_id {
+                tx.execute(
+                    "UPDATE Source SET complete = 1, uncompressed_len = ?1, crc32 = ?2 WHERE id = ?3",
+                    (uncompressed_len, format!("{crc32:x}"), source_id),
+                )?;
+            }
+            tx.commit()?;
+            self.removed_unfinished += removed as u64;
+        }
+        #[cfg(feature = "sqlite")]
+        self.move_into_place()?;
+        self.finalized = true;
         Ok(())
     }
+
+    // Record a file found inside the archive.
+    #[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+    pub fn on_archive_entry(&mut self, entry: &ArchiveEntry) -> Result<(), CorniferError> {
+        #[cfg(feature = "sqlite")]
+        if self.writing() {
+            let row = (entry.name.clone(), entry.size, entry.offset, entry.mtime);
+            self.write(move |conn, _| {
+                conn.execute("INSERT INTO ArchiveEntry (name, size, offset, mtime) VALUES (?1, ?2, ?3, ?4)", row)?;
+                Ok(())
+            })?;
+        }
+        Ok(())
+    }
+
+    // Should be called once a block that doesn't need a window has been completely decoded.
+    #[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+    pub fn on_independent_block(
+        &mut self,
+        block_type: &str,
+        block: IndependentBlock,
+    ) -> Result<(), CorniferError> {
+        #[cfg(feature = "sqlite")]
+        if self.writing() {
+            let formatted_crc = format!("{:x}", block.crc32);
+            let id = self.new_block_id()?;
+            let (type_name, source_id) = (block_type.to_string(), self.source_id);
+            let IndependentBlock { from_byte, from_bit, to_byte, header_len_bits, block_len_bits, len, data, .. } = block;
+
+            self.write(move |conn, _| {
+                conn.execute("
+                    INSERT INTO DeflateBlock (id, from_byte, from_bit, to_byte, block_type, crc32, len, header_len_bits, block_len_bits, data, source_id)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+                ", (id, from_byte, from_bit, to_byte, type_name, formatted_crc, len, header_len_bits, block_len_bits, data, source_id))?;
+                Ok(())
+            })?;
+            self.current_block_id = id;
+            #[cfg(feature = "tracing")]
+            tracing::debug!(id, from_byte, from_bit, to_byte, block_type, len, "wrote checkpoint");
+            self.end_checkpoint()?;
+        }
+        self.finished_blocks += 1;
+
+        Ok(())
+    }
+}
+
+/// Where a new checkpoint file for `path` is written until it's complete.
+#[cfg(feature = "sqlite")]
+pub fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".tmp");
+    PathBuf::from(name)
+}
+
+// Open <path>.tmp and lock it for as long as it's being written, so a second run writing the same checkpoint file
+// fails with IndexBusy rather than deleting the first one's. One that's there but not locked was left by a run that
+// didn't finish, and is started again. The lock is flock(2)'s, which doesn't get in the way of sqlite's own.
+#[cfg(all(feature = "sqlite", unix))]
+fn lock_temp_file(temp_path: &Path, path: &Path) -> Result<File, CorniferError> {
+    use std::os::unix::fs::MetadataExt;
+
+    loop {
+        let file = std::fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).open(temp_path)?;
+        match file.try_lock() {
+            Ok(()) => (),
+            Err(std::fs::TryLockError::WouldBlock) => {
+                return Err(CorniferError::IndexBusy {
+                    path: path.display().to_string(),
+                })
+            }
+            Err(std::fs::TryLockError::Error(e)) => return Err(e.into()),
+        }
+        // the run that had it might have finished, and moved or deleted it, while we were waiting for the lock.
+        let ours = file.metadata()?;
+        match std::fs::metadata(temp_path) {
+            Ok(there) if (there.dev(), there.ino()) == (ours.dev(), ours.ino()) => (),
+            Ok(_) => continue,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        }
+        // left over from a run that didn't finish, journal and all, or sqlite would roll it back into this one.
+        file.set_len(0)?;
+        let mut journal = temp_path.as_os_str().to_owned();
+        journal.push("-journal");
+        for leftover in [tick_sidecar_path(temp_path), PathBuf::from(journal)] {
+            if leftover.try_exists()? {
+                std::fs::remove_file(&leftover)?;
+            }
+        }
+        return Ok(file);
+    }
+}
+
+// Without flock, there's no telling whether <path>.tmp is being written, so it has to not be there.
+#[cfg(all(feature = "sqlite", not(unix)))]
+fn lock_temp_file(temp_path: &Path, path: &Path) -> Result<File, CorniferError> {
+    match std::fs::OpenOptions::new().write(true).create_new(true).open(temp_path) {
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Err(CorniferError::IndexBusy {
+            path: path.display().to_string(),
+        }),
+        result => Ok(result?),
+    }
+}
+
+/// Where tick windows go for the checkpoint file at `path`, with `IndexOptions::tick_sidecar`.
+#[cfg(feature = "sqlite")]
+pub fn tick_sidecar_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".ticks");
+    PathBuf::from(name)
+}
+
+// half-written rows look like blocks, but can't be used to read the file back.
+#[cfg(feature = "sqlite")]
+fn remove_unfinished_blocks(conn: &Connection) -> Result<usize, CorniferError> {
+    conn.execute("DELETE FROM Tick WHERE block_id IN (SELECT id FROM DeflateBlock WHERE len IS NULL)", ())?;
+    let removed = conn.execute("DELETE FROM DeflateBlock WHERE len IS NULL", ())?;
+    Ok(removed)
+}
+
+#[cfg(feature = "sqlite")]
+impl Drop for Checkpointer {
+    // if finalize wasn't called, the index stays marked as incomplete. Not an error, since stopping partway through
+    // on purpose (e.g. reading one block back) ends up here too.
+    fn drop(&mut self) {
+        // the database is needed back either way, and nothing's left to restart the writer.
+        self.writer_queue = None;
+        // panicking again here would abort, so a panic on the background writer is only logged.
+        match self.join_writer() {
+            #[cfg(feature = "tracing")]
+            Err(_) => tracing::warn!("checkpoint writer thread panicked"),
+            _ => (),
+        }
+        // never finished, so there's nothing worth keeping.
+        if let Some(temp_path) = self.temp_path.take() {
+            drop(self.conn.take());
+            drop(self.tick_sidecar.take());
+            let _ = std::fs::remove_file(tick_sidecar_path(&temp_path));
+            let _ = std::fs::remove_file(temp_path);
+            drop(self.temp_lock.take());
+            return;
+        }
+        if !self.finalized && !self.keep_unfinished {
+            match self.conn.as_ref().map(remove_unfinished_blocks) {
+                #[cfg(feature = "tracing")]
+                Some(Ok(count @ 1..)) => tracing::warn!(count, "checkpointer dropped with unfinished blocks"),
+                _ => (),
+            }
+        }
+        // the rest of the last batch.
+        let _ = self.commit_batch();
+    }
+}
+
+/**
+ * TESTS
+ */
+#[cfg(all(test, feature = "sqlite"))]
+mod test {
+    use rstest::rstest;
+
+    use crate::archive::ArchiveEntry;
+    use crate::decompress::BlockType;
+    use crate::errors::CorniferError;
+    use crate::huffman::{encode_trees, HuffmanTree};
+    use crate::store::{CheckpointStore, IndexMeta};
+    use crate::testutil::TempIndex;
+
+    use super::{
+        temp_path, tick_sidecar_path, Checkpointer, CreateMode, Expected, IndependentBlock, IndexOptions, MAX_CHECKPOINT_LEN,
+        MIN_MEMORY_BUDGET,
+    };
+
+
+    fn block_count(path: &std::path::Path) -> i64 {
+        let conn = rusqlite::Connection::open(path).unwrap();
+        conn.query_row("SELECT COUNT(*) FROM DeflateBlock", (), |row| row.get(0))
+            .unwrap()
+    }
+
+    fn add_block(checkpointer: &mut Checkpointer) {
+        checkpointer
+            .on_independent_block(
+                "bzip2",
+                IndependentBlock {
+                    from_byte: 4,
+                    from_bit: 0,
+                    to_byte: 0,
+                    header_len_bits: 80,
+                    block_len_bits: 1000,
+                    len: 100,
+                    crc32: 0,
+                    data: Vec::new(),
+                },
+            )
+            .unwrap();
+    }
+
+    #[rstest]
+    fn test_create_new_fails_if_exists() {
+        let path = TempIndex::new();
+        Checkpointer::init(&path).unwrap().finalize(0, 0).unwrap();
+        let result = Checkpointer::init(&path);
+        assert!(matches!(result, Err(CorniferError::IndexAlreadyExists { .. })));
+    }
+
+    #[rstest]
+    #[case::overwrite(CreateMode::Overwrite, 1)]
+    #[case::append(CreateMode::Append, 2)]
+    fn test_existing_index(#[case] mode: CreateMode, #[case] expected_blocks: i64) {
+        let path = TempIndex::new();
+        let mut checkpointer = Checkpointer::init(&path).unwrap();
+        add_block(&mut checkpointer);
+        checkpointer.finalize(100, 0xabc).unwrap();
+        drop(checkpointer);
+
+        let mut checkpointer = Checkpointer::open(&path, mode).unwrap();
+        add_block(&mut checkpointer);
+        checkpointer.finalize(200, 0xabc).unwrap();
+        drop(checkpointer);
+        assert_eq!(block_count(&path), expected_blocks);
+    }
+
+    #[rstest]
+    fn test_finalize() {
+        let path = TempIndex::new();
+        let mut checkpointer = Checkpointer::init(&path).unwrap();
+        add_block(&mut checkpointer);
+        // and one that never finishes.
+        checkpointer.on_block_start(200, 0, 100);
+        checkpointer.on_block_data_start(201, 3, &[&[0; 32768]]).unwrap();
+        assert_eq!(checkpointer.unfinished_blocks().unwrap(), 1);
+        assert_eq!(checkpointer.finished_blocks(), 1);
+        checkpointer.finalize(100, 0xabc).unwrap();
+        drop(checkpointer);
+
+        assert_eq!(block_count(&path), 1);
+        let meta = CheckpointStore::open(&path).unwrap().meta().unwrap();
+        assert_eq!(
+            meta,
+            IndexMeta {
+                complete: true,
+                uncompressed_len: Some(100),
+                crc32: Some(0xabc)
+            }
+        );
+    }
+
+    #[rstest]
+    #[case::len(Expected { len: Some(101), crc32: None }, false)]
+    #[case::crc(Expected { len: Some(100), crc32: Some(0xabd) }, false)]
+    #[case::both(Expected { len: Some(100), crc32: Some(0xabc) }, true)]
+    #[case::neither(Expected::default(), true)]
+    fn test_finalize_expected(#[case] expected: Expected, #[case] should_succeed: bool) {
+        let path = TempIndex::new();
+        let mut checkpointer = Checkpointer::init(&path).unwrap();
+        add_block(&mut checkpointer);
+        checkpointer.set_expected(expected);
+        assert_eq!(checkpointer.finalize(100, 0xabc).is_ok(), should_succeed);
+        drop(checkpointer);
+
+        // it never gets to where it should be if it's wrong.
+        assert_eq!(path.exists(), should_succeed);
+        if should_succeed {
+            assert!(CheckpointStore::open(&path).unwrap().meta().unwrap().complete);
+        }
+    }
+
+    #[rstest]
+    fn test_dropped_without_finalize() {
+        let path = TempIndex::new();
+        let mut checkpointer = Checkpointer::init(&path).unwrap();
+        add_block(&mut checkpointer);
+        assert!(!path.exists());
+        assert!(temp_path(&path).exists());
+        drop(checkpointer);
+        assert!(!path.exists());
+        assert!(!temp_path(&path).exists());
+    }
+
+    #[rstest]
+    #[case::create_new(CreateMode::CreateNew)]
+    #[case::overwrite(CreateMode::Overwrite)]
+    fn test_temp_file_replaced_on_finalize(#[case] mode: CreateMode) {
+        let path = TempIndex::new();
+        // an earlier run that didn't finish, and for overwriting, one that did.
+        std::fs::write(temp_path(&path), b"half a checkpoint file").unwrap();
+        if mode == CreateMode::Overwrite {
+            let mut checkpointer = Checkpointer::init(&path).unwrap();
+            add_block(&mut checkpointer);
+            checkpointer.finalize(100, 0xabc).unwrap();
+        }
+        let mut checkpointer = Checkpointer::open(&path, mode).unwrap();
+        add_block(&mut checkpointer);
+        add_block(&mut checkpointer);
+        // the old one's still there until the new one's finished.
+        assert_eq!(path.exists(), mode == CreateMode::Overwrite);
+        checkpointer.finalize(200, 0xabc).unwrap();
+        assert!(!temp_path(&path).exists());
+        assert_eq!(block_count(&path), 2);
+        // and it can still be written to afterwards.
+        checkpointer.on_archive_entry(&ArchiveEntry { name: "a".to_string(), size: 1, offset: 0, mtime: None }).unwrap();
+        drop(checkpointer);
+    }
+
+    #[rstest]
+    #[case::create_new(CreateMode::CreateNew)]
+    #[case::overwrite(CreateMode::Overwrite)]
+    fn test_temp_file_in_use(#[case] mode: CreateMode) {
+        let path = TempIndex::new();
+        let mut checkpointer = Checkpointer::open(&path, mode).unwrap();
+        add_block(&mut checkpointer);
+        // another run for the same file, while this one's still going.
+        let result = Checkpointer::open(&path, mode);
+        assert!(matches!(result, Err(CorniferError::IndexBusy { .. })));
+        checkpointer.finalize(100, 0xabc).unwrap();
+        drop(checkpointer);
+        assert_eq!(block_count(&path), 1);
+    }
+
+    #[rstest]
+    fn test_create_new_doesnt_replace() {
+        let path = TempIndex::new();
+        let mut checkpointer = Checkpointer::init(&path).unwrap();
+        add_block(&mut checkpointer);
+        // something else finished one first.
+        std::fs::write(&path, b"someone else's").unwrap();
+        let result = checkpointer.finalize(100, 0xabc);
+        assert!(matches!(result, Err(CorniferError::IndexAlreadyExists { .. })));
+        drop(checkpointer);
+        assert_eq!(std::fs::read(&path).unwrap(), b"someone else's");
+        assert!(!temp_path(&path).exists());
+    }
+
+    #[rstest]
+    fn test_keep_partial() {
+        let path = TempIndex::new();
+        let mut checkpointer = Checkpointer::init(&path).unwrap();
+        checkpointer.keep_partial().unwrap();
+        add_block(&mut checkpointer);
+        checkpointer.on_block_start(200, 0, 100);
+        checkpointer.on_block_data_start(201, 3, &[&[0; 32768]]).unwrap();
+        drop(checkpointer);
+
+        assert_eq!(block_count(&path), 1);
+        let meta = CheckpointStore::open(&path).unwrap().meta().unwrap();
+        assert!(!meta.complete);
+        assert_eq!(meta.crc32, None);
+    }
+
+    #[rstest]
+    fn test_memory_budget() {
+        let path = TempIndex::new();
+        let mut checkpointer = Checkpointer::init(&path).unwrap();
+        checkpointer.keep_partial().unwrap();
+        let result = checkpointer.set_options(IndexOptions { memory_budget: Some(100_000), ..Default::default() });
+        assert!(matches!(result, Err(CorniferError::MemoryBudgetTooSmall { budget: 100_000, minimum: MIN_MEMORY_BUDGET })));
+
+        // leaves about 650KB for the page cache, so batches of 9.
+        checkpointer.set_options(IndexOptions { memory_budget: Some(1_000_000), ..Default::default() }).unwrap();
+        let cache_size: i64 = checkpointer.conn.as_ref().unwrap().query_row("PRAGMA cache_size", (), |row| row.get(0)).unwrap();
+        assert_eq!(cache_size, -656);
+        for _ in 0..8 {
+            add_block(&mut checkpointer);
+        }
+        assert_eq!(block_count(&path), 0);
+        add_block(&mut checkpointer);
+        assert_eq!(block_count(&path), 9);
+        // the rest are committed when it's dropped.
+        add_block(&mut checkpointer);
+        drop(checkpointer);
+        assert_eq!(block_count(&path), 10);
+    }
+
+    #[rstest]
+    fn test_append_to_index_without_trees() {
+        let path = TempIndex::new();
+        // DeflateBlock as it was before the trees were stored.
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute(
+            "CREATE TABLE DeflateBlock (
+                id INTEGER PRIMARY KEY AUTOINCREMENT, from_byte INTEGER NOT NULL, from_bit INTEGER NOT NULL,
+                to_byte INTEGER NOT NULL, block_type TEXT NOT NULL, crc32 TEXT, len INTEGER,
+                header_len_bits INTEGER, block_len_bits INTEGER, data BLOB NOT NULL
+            )",
+            (),
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO DeflateBlock (from_byte, from_bit, to_byte, block_type, crc32, len, data)
+            VALUES (10, 0, 0, 'dynamic', '0', 100, x'')",
+            (),
+        )
+        .unwrap();
+        drop(conn);
+        assert!(CheckpointStore::open(&path).unwrap().get_trees(1).unwrap().is_none());
+
+        let mut checkpointer = Checkpointer::open(&path, CreateMode::Append).unwrap();
+        checkpointer.on_block_start(200, 0, 100);
+        checkpointer.set_block_type(BlockType::DynamicHuffman);
+        checkpointer.set_trees(encode_trees(HuffmanTree::fixed(), HuffmanTree::fixed_dist()));
+        checkpointer.on_block_data_start(201, 3, &[]).unwrap();
+        checkpointer.on_block_end(300, 0, 200, 0).unwrap();
+        drop(checkpointer);
+
+        let store = CheckpointStore::open(&path).unwrap();
+        assert!(store.get_trees(1).unwrap().is_none());
+        let (symbol_tree, distance_tree) = store.get_trees(2).unwrap().unwrap();
+        assert!(&symbol_tree == HuffmanTree::fixed());
+        assert!(&distance_tree == HuffmanTree::fixed_dist());
+    }
+
+    #[rstest]
+    fn test_none() {
+        let mut checkpointer = Checkpointer::none();
+        add_block(&mut checkpointer);
+        checkpointer.on_block_start(200, 0, 100);
+        checkpointer.on_block_data_start(201, 3, &[&[0; 32768]]).unwrap();
+        checkpointer.on_block_end(300, 0, 200, 0).unwrap();
+        // it still counts them, it just doesn't write them anywhere.
+        assert_eq!(checkpointer.finished_blocks(), 2);
+        assert_eq!(checkpointer.unfinished_blocks().unwrap(), 0);
+        checkpointer.finalize(200, 0xabc).unwrap();
+
+        // and it still checks what it was told to expect.
+        let mut checkpointer = Checkpointer::none();
+        checkpointer.set_expected(Expected { len: Some(201), crc32: None });
+        assert!(matches!(
+            checkpointer.finalize(200, 0xabc),
+            Err(CorniferError::UnexpectedLength { expected: 201, found: 200 })
+        ));
+    }
+
+    #[rstest]
+    fn test_busy_index() {
+        let path = TempIndex::new();
+        drop(Checkpointer::init(&path).unwrap());
+        // someone else is halfway through writing to it.
+        let other = rusqlite::Connection::open(&path).unwrap();
+        other.execute_batch("BEGIN IMMEDIATE;").unwrap();
+
+        let result = Checkpointer::open(&path, CreateMode::Append);
+        assert!(matches!(result, Err(CorniferError::IndexBusy { .. })));
+        drop(other);
+    }
+
+    #[rstest]
+    #[case::unbatched(None)]
+    #[case::batched(Some(1_000_000))]
+    fn test_tick_sidecar(#[case] memory_budget: Option<usize>) {
+        use crate::decompress::Deflator;
+        use crate::reader::CorniferByteReader;
+
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let input = include_bytes!("../testfiles/1080-0.txt.gz");
+        let path = TempIndex::new();
+        let mut checkpointer = Checkpointer::init(&path).unwrap();
+        checkpointer.set_options(IndexOptions { memory_budget, tick_sidecar: true, ..Default::default() }).unwrap();
+        assert!(tick_sidecar_path(&temp_path(&path)).exists());
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), checkpointer);
+        deflator.set_tick_trigger(|context| context.since_checkpoint >= 1000);
+        std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        drop(deflator);
+        // moved into place along with the index.
+        assert!(!tick_sidecar_path(&temp_path(&path)).exists());
+
+        let store = CheckpointStore::open(&path).unwrap();
+        let blocks = store.blocks().unwrap();
+        let ticks = store.ticks_for(&blocks[0]).unwrap();
+        assert!(ticks.len() > 30);
+        let mut sidecar_len = 0;
+        for tick in &ticks {
+            let to_byte = tick.to_byte as usize;
+            let window = store.get_tick_window(tick.id).unwrap();
+            assert_eq!(window, &text[to_byte.saturating_sub(32768)..to_byte], "tick {}", tick.id);
+            sidecar_len += window.len() as u64;
+        }
+        assert_eq!(std::fs::metadata(tick_sidecar_path(&path)).unwrap().len(), sidecar_len);
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        let in_db: i64 = conn.query_row("SELECT sum(length(data)) FROM Tick", (), |row| row.get(0)).unwrap();
+        assert_eq!(in_db, 0);
+
+        // there's nowhere to put one for an index in memory.
+        let mut checkpointer = Checkpointer::init_memory().unwrap();
+        let result = checkpointer.set_options(IndexOptions { memory_budget, tick_sidecar: true, ..Default::default() });
+        assert!(matches!(result, Err(CorniferError::IOError(e)) if e.kind() == std::io::ErrorKind::Unsupported));
+    }
+
+    // everything that's written from the background writer is the same as what's written without it.
+    #[rstest]
+    #[case::rendezvous(0, None)]
+    #[case::queue(4, None)]
+    #[case::batched(4, Some(2_000_000))]
+    fn test_background_writer(#[case] queue: usize, #[case] memory_budget: Option<usize>) {
+        use crate::decompress::Deflator;
+        use crate::reader::CorniferByteReader;
+
+        // one big block with ticks in it, and then some members of small ones.
+        let input = [
+            include_bytes!("../testfiles/texts.tar.gz").as_slice(),
+            include_bytes!("../testfiles/testCompressThenConcat.txt.gz"),
+        ]
+        .concat();
+        let index = |options: IndexOptions| {
+            let path = TempIndex::new();
+            let mut checkpointer = Checkpointer::init(&path).unwrap();
+            checkpointer.set_options(options).unwrap();
+            let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), checkpointer);
+            deflator.set_tick_trigger(|context| context.since_checkpoint >= 5000);
+            std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+            assert_eq!(deflator.checkpointer_mut().unfinished_blocks().unwrap(), 0);
+            path
+        };
+        let expected_path = index(IndexOptions { memory_budget, ..Default::default() });
+        let path = index(IndexOptions { memory_budget, background_writer: Some(queue), ..Default::default() });
+
+        let expected = CheckpointStore::open(&expected_path).unwrap();
+        let store = CheckpointStore::open(&path).unwrap();
+        assert!(store.meta().unwrap().complete);
+        assert_eq!(store.members().unwrap(), expected.members().unwrap());
+        let blocks = store.blocks().unwrap();
+        assert_eq!(blocks, expected.blocks().unwrap());
+        assert!(blocks.len() > 2);
+        let mut ticks_checked = 0;
+        for block in &blocks {
+            assert_eq!(store.get_window(block.id).unwrap(), expected.get_window(block.id).unwrap());
+            let ticks = store.ticks_for(block).unwrap();
+            assert_eq!(ticks, expected.ticks_for(block).unwrap());
+            for tick in ticks {
+                assert_eq!(store.get_tick_window(tick.id).unwrap(), expected.get_tick_window(tick.id).unwrap());
+                ticks_checked += 1;
+            }
+        }
+        assert!(ticks_checked > 0);
+    }
+
+    #[rstest]
+    fn test_background_writer_memory() {
+        // the queued windows come out of the budget too.
+        let mut checkpointer = Checkpointer::init_memory().unwrap();
+        let options = IndexOptions {
+            memory_budget: Some(MIN_MEMORY_BUDGET),
+            background_writer: Some(4),
+            ..Default::default()
+        };
+        assert!(matches!(
+            checkpointer.set_options(options),
+            Err(CorniferError::MemoryBudgetTooSmall { minimum, .. }) if minimum == MIN_MEMORY_BUDGET + 4 * MAX_CHECKPOINT_LEN
+        ));
+    }
 }
@@ -0,0 +1,1408 @@
+use std::collections::BTreeSet;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TryRecvError};
+use std::sync::Arc;
+
+use crc::{Crc, CRC_32_ISO_HDLC};
+use flate2::Compression;
+
+use crate::cache::{CacheStats, SegmentCache};
+use crate::checkpoint::Checkpointer;
+use crate::circle::crc32_combine;
+use crate::decompress::Deflator;
+use crate::errors::{CorniferError, ErrorKind};
+use crate::reader::CorniferByteReader;
+use crate::report::CorruptionReport;
+use crate::slice::{self, ExtractedMember};
+use crate::store::{Block, CheckpointStore, Gap, Member, Tick};
+#[cfg(feature = "bzip2")]
+use crate::bzip2;
+#[cfg(feature = "xz")]
+use crate::xz;
+
+static CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/**
+ * Random access to the decompressed contents of a file, using its checkpoint database.
+ *
+ * Each read decodes the block the position falls in straight out of the compressed file, so only the blocks that are
+ * actually read get decompressed. DEFLATE blocks are decoded by restarting the Deflator at the block with the window
+ * from the database; bzip2 and xz blocks are decoded on their own. DEFLATE blocks without a window are decoded from the
+ * last block before them that has one, or from the end of the block before, if that was the last one decoded. Every
+ * block is checked against its CRC in the database, like the gzip footer checks the whole member when reading from the
+ * start.
+ *
+ * A DEFLATE block with ticks in it (see Deflator::set_checkpoint_spacing) is read a part at a time instead, from
+ * the last tick before the position to the next one, with the block's trees and the window stored with the tick.
+ * The parts aren't checked, since the CRC is for the whole block.
+ */
+pub struct CheckpointedReader<R> {
+    source: R,
+    store: CheckpointStore,
+    blocks: Vec<Block>,
+    // parts of the file that were skipped while indexing, which can't be read.
+    gaps: Vec<Gap>,
+    pos: u64,
+    // index of the block we last decoded, where in it the decoded part starts, and its contents.
+    cached: Option<(usize, u64, Arc<Vec<u8>>)>,
+    // more decoded blocks, if set_cache was called.
+    cache: Option<SegmentCache>,
+    // the window for the block after the last DEFLATE block we decoded, so reading on doesn't need one stored.
+    next_window: Option<(usize, Vec<u8>)>,
+}
+
+impl<R: Read + Seek> CheckpointedReader<R> {
+    /// The index can still be being written to, in which case only the blocks finished so far can be read, and
+    /// `refresh` picks up the ones finished since.
+    pub fn open<P: AsRef<Path>>(source: R, index: P) -> Result<Self, CorniferError> {
+        Self::with_store(source, CheckpointStore::open(index)?)
+    }
+
+    /// Like `open`, for the file called `name` in a checkpoint file with several files in it. See
+    /// `Checkpointer::set_source`.
+    pub fn open_source<P: AsRef<Path>>(source: R, index: P, name: &str) -> Result<Self, CorniferError> {
+        Self::with_store(source, CheckpointStore::open_source(index, name)?)
+    }
+
+    fn with_store(source: R, store: CheckpointStore) -> Result<Self, CorniferError> {
+        let blocks = readable_blocks(&store)?;
+        let gaps = store.gaps()?;
+        let reader = Self {
+            source,
+            store,
+            blocks,
+            gaps,
+            pos: 0,
+            cached: None,
+            cache: None,
+            next_window: None,
+        };
+        // the blocks should add up to the whole file. Older checkpoint files don't say how long it is, and ones
+        // that are still being written (e.g. updated) don't know yet.
+        let meta = reader.store.meta()?;
+        if let (Some(expected), true) = (meta.uncompressed_len, meta.complete) {
+            let found = reader.uncompressed_len();
+            if found != expected {
+                return Err(CorniferError::IndexLengthMismatch { expected, found });
+            }
+        }
+        Ok(reader)
+    }
+
+    /// Read the blocks from the checkpoint database again, to see the ones finished since the reader was opened.
+    pub fn refresh(&mut self) -> Result<(), CorniferError> {
+        self.blocks = readable_blocks(&self.store)?;
+        self.gaps = self.store.gaps()?;
+        self.cached = None;
+        self.next_window = None;
+        Ok(())
+    }
+
+    /// Keep up to `budget` bytes of recently read blocks in memory, so going back to them doesn't decode them
+    /// again. Replaces any cache set before.
+    pub fn set_cache(&mut self, budget: usize) {
+        self.cache = Some(SegmentCache::new(budget));
+    }
+
+    /// Hits and misses for the cache, if there is one.
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(|c| c.stats())
+    }
+
+    /// Decompress the whole file, and check it against the length and CRC recorded in the checkpoint file.
+    /// Leaves the position at the end.
+    pub fn verify(&mut self) -> Result<(), CorniferError> {
+        let meta = self.store.meta()?;
+        let (Some(expected_len), Some(expected_crc), true) = (meta.uncompressed_len, meta.crc32, meta.complete) else {
+            return Err(CorniferError::IndexIncomplete);
+        };
+        self.pos = 0;
+        let mut crc32 = 0;
+        let mut len = 0;
+        let mut buf = vec![0; 1 << 16];
+        loop {
+            let n = self.read_internal(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            crc32 = crc32_combine(crc32, CRC32.checksum(&buf[..n]), n as u64);
+            len += n as u64;
+        }
+        if len != expected_len {
+            return Err(CorniferError::IndexLengthMismatch { expected: expected_len, found: len });
+        }
+        if crc32 != expected_crc {
+            return Err(CorniferError::IndexCrcMismatch { expected: expected_crc, found: crc32 });
+        }
+        Ok(())
+    }
+
+    /// Check the compressed file against the CRC32s of each block's compressed bytes in the checkpoint file (see
+    /// `Deflator::hash_blocks`), which finds bit rot without decompressing anything. Blocks without one are skipped,
+    /// and it returns how many were checked. Fails with `CorniferError::NoBlockHashes` if there weren't any.
+    pub fn verify_compressed(&mut self) -> Result<usize, CorniferError> {
+        let mut checked = 0;
+        let mut buf = Vec::new();
+        // the empty blocks too, which reading leaves out.
+        for block in self.store.blocks()? {
+            let Some(expected) = block.compressed_crc32 else {
+                continue;
+            };
+            // the bytes it starts and ends in are shared with the blocks either side.
+            let end = (block.from_byte * 8 + block.from_bit as u64 + block.block_len_bits).div_ceil(8);
+            buf.resize((end - block.from_byte) as usize, 0);
+            self.source.seek(SeekFrom::Start(block.from_byte))?;
+            let mismatch = || CorniferError::CompressedCrcMismatch {
+                block_id: block.id,
+                from_byte: block.from_byte,
+            };
+            match self.source.read_exact(&mut buf) {
+                Ok(()) if CRC32.checksum(&buf) == expected => checked += 1,
+                Ok(()) => return Err(mismatch()),
+                // cut short.
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Err(mismatch()),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        match checked {
+            0 => Err(CorniferError::NoBlockHashes),
+            _ => Ok(checked),
+        }
+    }
+
+    /// The blocks in the checkpoint database, in uncompressed order.
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    /// The parts of the file that were skipped while indexing, with `DeflatorOptions::recover`. Reading them fails
+    /// with `CorniferError::InGap`.
+    pub fn gaps(&self) -> &[Gap] {
+        &self.gaps
+    }
+
+    /// The gzip members, in order. Empty for other formats.
+    pub fn members(&self) -> Result<Vec<Member>, CorniferError> {
+        self.store.members()
+    }
+
+    /// The first gzip member with this file name in its header, e.g. in gzip files that were catted together.
+    /// `uncompressed_range` is where to read it from, or `extract_member` it.
+    pub fn member_by_name(&self, name: &str) -> Result<Member, CorniferError> {
+        self.members()?
+            .into_iter()
+            .find(|member| member.name.as_deref() == Some(name))
+            .ok_or_else(|| CorniferError::NoSuchMember { name: name.to_string() })
+    }
+
+    /// Length of the entire decompressed file, or as much of it as has been checkpointed so far.
+    pub fn uncompressed_len(&self) -> u64 {
+        end_of(&self.blocks, &self.gaps)
+    }
+
+    /// Copy the compressed blocks that cover `from..to` of the decompressed file into a new gzip member, without
+    /// recompressing them. With `with_window`, the 32KB before the first block goes in front of them, so they
+    /// always decode; without, it fails with `CorniferError::CantExtract` if they need it. Only for DEFLATE.
+    pub fn extract_member(
+        &mut self,
+        from: u64,
+        to: u64,
+        with_window: bool,
+    ) -> Result<(Vec<u8>, ExtractedMember), CorniferError> {
+        // the empty blocks in between too, since they're part of the stream.
+        let all = self.store.blocks()?;
+        let first = all.iter().position(|b| b.len > 0 && b.to_byte + b.len > from);
+        let last = all.iter().rposition(|b| b.len > 0 && b.to_byte < to);
+        let blocks = match (first, last) {
+            (Some(first), Some(last)) if first <= last => &all[first..=last],
+            _ => &[],
+        };
+        let mut window = Vec::new();
+        if let (Some(first), true) = (blocks.first(), with_window) {
+            window = match first.has_window {
+                true => self.store.get_window(first.id)?,
+                false => {
+                    // back to the start of the member, at most.
+                    let members = self.store.members()?;
+                    let member = members.iter().rev().find(|m| m.from_byte <= first.from_byte);
+                    let member_start = member.map_or(0, |m| m.to_byte);
+                    let window_start = first.to_byte.saturating_sub(1 << 15).max(member_start);
+                    let mut window = vec![0; (first.to_byte - window_start) as usize];
+                    self.pos = window_start;
+                    self.read_exact(&mut window)?;
+                    window
+                }
+            };
+        }
+        slice::write_member(&mut self.source, blocks, &window)
+    }
+
+    /// Where to cut the file into parts of at least `chunk_size` bytes decompressed, for `split_part`: at the first
+    /// block boundary after each `chunk_size` bytes.
+    pub fn split_points(&self, chunk_size: u64) -> Vec<Range<u64>> {
+        let mut parts = Vec::new();
+        let mut from = 0;
+        for block in &self.blocks {
+            if block.to_byte > from && block.to_byte - from >= chunk_size {
+                parts.push(from..block.to_byte);
+                from = block.to_byte;
+            }
+        }
+        if self.uncompressed_len() > from {
+            parts.push(from..self.uncompressed_len());
+        }
+        parts
+    }
+
+    /// A gzip file with `range` of the decompressed file in it, which decompresses on its own, for a range from
+    /// `split_points`. Blocks are copied as they are where they can be. The start of each member, which could
+    /// refer back to before the part, is recompressed at `level`, and so are blocks that can't be copied, like
+    /// bzip2 and xz ones.
+    pub fn split_part(&mut self, range: Range<u64>, level: Compression) -> Result<Vec<u8>, CorniferError> {
+        if let Some(gap) = self.gaps.iter().find(|g| g.uncompressed_from < range.end && range.start < g.uncompressed_to) {
+            return Err(CorniferError::InGap {
+                from: gap.uncompressed_from,
+                to: gap.uncompressed_to,
+            });
+        }
+        // the empty blocks too, since they're part of the stream.
+        let blocks: Vec<Block> = self.store.blocks()?.into_iter().filter(|b| range.contains(&b.to_byte)).collect();
+        let mut part = Vec::new();
+        let mut rest = blocks.as_slice();
+        while let Some(first) = rest.first() {
+            // runs of blocks that can be copied into one member, or that can't be copied at all.
+            let copy = slice::can_copy(first);
+            let len = 1 + rest
+                .windows(2)
+                .take_while(|w| slice::can_copy(&w[1]) == copy && (!copy || slice::follows(&w[0], &w[1])))
+                .count();
+            let (run, after) = rest.split_at(len);
+            rest = after;
+            let from = first.to_byte;
+            let to = run.last().map_or(from, |b| b.to_byte + b.len);
+            if from == to {
+                continue;
+            }
+            let recompressed = match (copy, first.member_start) {
+                (false, _) => run.len(),
+                (true, true) => 0,
+                (true, false) => run.iter().position(|b| b.to_byte >= from + (1 << 15)).unwrap_or(run.len()),
+            };
+            let head_to = run.get(recompressed).map_or(to, |b| b.to_byte);
+            let mut head = vec![0; (head_to - from) as usize];
+            self.pos = from;
+            self.read_exact(&mut head)?;
+            part.extend(slice::write_standalone(&mut self.source, &head, &run[recompressed..], level)?);
+        }
+        Ok(part)
+    }
+
+    /// Decode every block on its own, carrying on past the ones that fail, to find which parts of the file are
+    /// damaged and which can still be read. Only fails for errors that aren't about the file or checkpoint file
+    /// being wrong, e.g. failing to read it at all.
+    pub fn corruption_report(&mut self) -> Result<CorruptionReport, CorniferError> {
+        let mut report = CorruptionReport::default();
+        let mut gaps = self.gaps.clone().into_iter().peekable();
+        for index in 0..self.blocks.len() {
+            let result = self.checked_block(index);
+            let block = &self.blocks[index];
+            while let Some(gap) = gaps.next_if(|g| g.from_byte <= block.from_byte) {
+                report.on_gap(&gap);
+            }
+            match result {
+                Ok(_) => report.on_good_block(block),
+                Err(err) if matches!(err.kind(), ErrorKind::CorruptStream | ErrorKind::CorruptIndex) => {
+                    report.on_damaged_block(block, err.to_string())
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        for gap in gaps {
+            report.on_gap(&gap);
+        }
+        Ok(report)
+    }
+
+    /// Decode `k` blocks picked at random, for a sample of a file too big to decompress all of. Each block is as
+    /// likely to be picked as any other, whatever its length, and the same `seed` picks the same blocks. They come
+    /// back in the order they are in the file, with fewer than `k` if there aren't that many. Leaves the position
+    /// where it was.
+    pub fn sample_blocks(&mut self, k: usize, seed: u64) -> Result<Vec<(Block, Vec<u8>)>, CorniferError> {
+        // Floyd's algorithm, which picks k of n without a list of all n.
+        let (n, k) = (self.blocks.len(), k.min(self.blocks.len()));
+        let mut rng = SplitMix64(seed);
+        let mut picked = BTreeSet::new();
+        for j in n - k..n {
+            let index = rng.below(j + 1);
+            if !picked.insert(index) {
+                picked.insert(j);
+            }
+        }
+        picked
+            .into_iter()
+            .map(|index| Ok((self.blocks[index].clone(), self.checked_block(index)?)))
+            .collect()
+    }
+
+    /// Move the reader onto a background thread that decodes up to `blocks` blocks ahead of the last one read,
+    /// so reading on after a seek doesn't wait for each block to be decoded.
+    pub fn readahead(self, blocks: usize) -> Readahead
+    where
+        R: Send + 'static,
+    {
+        let (requests, requests_rx) = mpsc::channel();
+        let (decoded_tx, decoded) = mpsc::sync_channel(blocks);
+        let reader = Readahead {
+            blocks: self.blocks.clone(),
+            gaps: self.gaps.clone(),
+            pos: self.pos,
+            depth: blocks,
+            current: None,
+            generation: 0,
+            expected: None,
+            requests,
+            decoded,
+        };
+        // the thread stops by itself once the Readahead is dropped.
+        std::thread::spawn(move || readahead_worker(self, requests_rx, decoded_tx));
+        reader
+    }
+
+    // decode the first `len` bytes of a block, which is all of it unless it's only being read up to its first tick.
+    fn decode_deflate_block(&mut self, index: usize, len: u64) -> Result<Vec<u8>, CorniferError> {
+        let (start, window) = match self.next_window.take() {
+            Some((i, window)) if i == index => (index, window),
+            _ => {
+                // the first block of the file always has one, since it starts a member. Near the start of a
+                // member, that's where the nearest one is, and there's not even a window to look up.
+                let start = self.blocks[..=index].iter().rposition(|b| b.has_window).unwrap_or(0);
+                match self.blocks[start].member_start {
+                    true => (start, Vec::new()),
+                    false => (start, self.store.get_window(self.blocks[start].id)?),
+                }
+            }
+        };
+        let from = &self.blocks[start];
+        let block = &self.blocks[index];
+
+        let mut reader = CorniferByteReader::new(BufReader::new(&mut self.source));
+        reader.seek_to_bit(from.from_byte, from.from_bit)?;
+        let mut deflator = Deflator::resume(reader, 0, &window, Checkpointer::none())?;
+        let mut out = vec![0; len as usize];
+        let whole = len == block.len;
+        // anything between where we started and the block is decoded and thrown away.
+        let skip = block.to_byte - from.to_byte;
+        io::copy(&mut (&mut deflator).take(skip), &mut io::sink())
+            .and_then(|_| deflator.read_exact(&mut out))
+            .map_err(unwrap_io_error)?;
+        if whole {
+            self.next_window = Some((index + 1, deflator.buffer.get_window()?));
+        }
+        Ok(out)
+    }
+
+    fn decode_block(&mut self, index: usize) -> Result<Vec<u8>, CorniferError> {
+        let block = &self.blocks[index];
+        match block.block_type.as_str() {
+            "nocompression" | "fixed" | "dynamic" | "flushed" => self.decode_deflate_block(index, block.len),
+            #[cfg(feature = "bzip2")]
+            "bzip2" => bzip2::decode_block(
+                &mut self.source,
+                block.from_byte,
+                block.from_bit,
+                block.block_len_bits,
+            ),
+            #[cfg(feature = "xz")]
+            "xz" => {
+                let data = self.store.block_data(block.id)?;
+                let stream_header = data.try_into().map_err(|_| CorniferError::NotXzHeader {
+                    position: block.from_byte,
+                })?;
+                xz::decode_block(
+                    &mut self.source,
+                    &xz::XzBlock {
+                        from_byte: block.from_byte,
+                        unpadded_size: block.block_len_bits / 8,
+                        uncompressed_size: block.len,
+                        stream_header,
+                    },
+                )
+            }
+            other => Err(CorniferError::UnsupportedBlockType {
+                block_type: other.to_string(),
+            }),
+        }
+    }
+
+    fn read_internal(&mut self, buf: &mut [u8]) -> Result<usize, CorniferError> {
+        if self.pos >= self.uncompressed_len() || buf.is_empty() {
+            return Ok(0);
+        }
+        check_not_in_gap(&self.gaps, self.pos)?;
+        let index = block_at(&self.blocks, self.pos);
+        let pos = self.pos;
+        if !matches!(&self.cached, Some((i, from, data)) if *i == index && (*from..*from + data.len() as u64).contains(&pos)) {
+            let (from, len, tick) = self.segment_at(index, pos)?;
+            let data = match self.cache.as_mut().and_then(|c| c.get(from)) {
+                Some(data) => data,
+                None => {
+                    let data = Arc::new(match tick {
+                        Some(tick) => self.decode_from_tick(index, &tick, len)?,
+                        None if len == self.blocks[index].len => self.checked_block(index)?,
+                        None => self.decode_deflate_block(index, len)?,
+                    });
+                    if let Some(cache) = &mut self.cache {
+                        cache.insert(from, data.clone());
+                    }
+                    data
+                }
+            };
+            self.cached = Some((index, from, data));
+        }
+        let (_, from, data) = self.cached.as_ref().expect("Block was just decoded");
+        let num_bytes = copy_from(*from, data, self.pos, buf)?;
+        self.pos += num_bytes as u64;
+        Ok(num_bytes)
+    }
+
+    // which part of a block to decode to read pos: where it starts, how long it is and the tick it starts at, if
+    // any. That's the whole block, unless it's a DEFLATE block with ticks in it, in which case it's from the last
+    // tick at or before pos (or the start of the block) to the next one (or the end of the block).
+    fn segment_at(&mut self, index: usize, pos: u64) -> Result<(u64, u64, Option<Tick>), CorniferError> {
+        let block = &self.blocks[index];
+        let whole = (block.to_byte, block.len, None);
+        if !matches!(block.block_type.as_str(), "fixed" | "dynamic") {
+            return Ok(whole);
+        }
+        let ticks = self.store.ticks_for(block)?;
+        if ticks.is_empty() {
+            return Ok(whole);
+        }
+        let end = block.to_byte + block.len;
+        let next = ticks.partition_point(|t| t.to_byte <= pos);
+        let to = ticks.get(next).map_or(end, |t| t.to_byte);
+        match next.checked_sub(1).map(|i| &ticks[i]) {
+            None => Ok((block.to_byte, to - block.to_byte, None)),
+            Some(tick) => Ok((tick.to_byte, to - tick.to_byte, Some(tick.clone()))),
+        }
+    }
+
+    // decode `len` bytes from a tick in the middle of a block, with the block's trees and the window stored with
+    // the tick. There's nothing to check it against, since the block's CRC is for all of it.
+    fn decode_from_tick(&mut self, index: usize, tick: &Tick, len: u64) -> Result<Vec<u8>, CorniferError> {
+        let Some(trees) = self.store.get_trees(self.blocks[index].id)? else {
+            // from before the trees were kept, so the block has to be decoded from its start.
+            let data = self.checked_block(index)?;
+            let from = (tick.to_byte - self.blocks[index].to_byte) as usize;
+            return Ok(data[from..from + len as usize].to_vec());
+        };
+        let block = &self.blocks[index];
+        let to_end = tick.to_byte + len == block.to_byte + block.len;
+        let window = self.store.get_tick_window(tick.id)?;
+        let mut reader = CorniferByteReader::new(BufReader::new(&mut self.source));
+        reader.seek_to_bit(tick.from_byte, tick.from_bit)?;
+        // it's never decoded past the end of the block, so whether it's the member's last one doesn't matter, and
+        // the block's header doesn't have to be read (or fetched, see CheckpointStore::plan_fetch) to find out.
+        let mut deflator = Deflator::resume_in_block(reader, 0, &window, trees, false, Checkpointer::none())?;
+        let mut out = vec![0; len as usize];
+        deflator.read_exact(&mut out).map_err(unwrap_io_error)?;
+        if to_end {
+            self.next_window = Some((index + 1, deflator.buffer.get_window()?));
+        }
+        Ok(out)
+    }
+
+    // decode a block, and check it against its CRC.
+    fn checked_block(&mut self, index: usize) -> Result<Vec<u8>, CorniferError> {
+        let data = self.decode_block(index)?;
+        let block = &self.blocks[index];
+        if CRC32.checksum(&data) != block.crc32 {
+            return Err(CorniferError::CorruptIndexOrData { block_id: block.id });
+        }
+        Ok(data)
+    }
+}
+
+// decode blocks in order from the one asked for, until asked for a different one.
+fn readahead_worker<R: Read + Seek>(
+    mut reader: CheckpointedReader<R>,
+    requests: Receiver<(u64, usize)>,
+    decoded: SyncSender<(u64, usize, Result<Vec<u8>, CorniferError>)>,
+) {
+    let Ok(mut request) = requests.recv() else {
+        return;
+    };
+    loop {
+        let (generation, index) = request;
+        let mut next = None;
+        if index < reader.blocks.len() {
+            let result = reader.checked_block(index);
+            let failed = result.is_err();
+            if decoded.send((generation, index, result)).is_err() {
+                return;
+            }
+            if !failed {
+                next = Some((generation, index + 1));
+            }
+        }
+        // only the latest request matters, the ones before it have been seeked away from.
+        loop {
+            match requests.try_recv() {
+                Ok(newer) => next = Some(newer),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+        request = match next {
+            Some(next) => next,
+            // at the end, or stuck on an error, so wait until there's a seek.
+            None => match requests.recv() {
+                Ok(newer) => newer,
+                Err(_) => return,
+            },
+        };
+    }
+}
+
+/**
+ * A CheckpointedReader that decodes blocks ahead of where it's reading on a background thread, made by
+ * `CheckpointedReader::readahead`.
+ *
+ * Decoded blocks wait in a bounded channel until they're read. Seeking somewhere the thread isn't about to
+ * decode anyway sends it there instead, and whatever it had decoded for the old position is thrown away.
+ */
+pub struct Readahead {
+    blocks: Vec<Block>,
+    gaps: Vec<Gap>,
+    pos: u64,
+    depth: usize,
+    // index of the block we're reading from, and its contents.
+    current: Option<(usize, Vec<u8>)>,
+    // bumped on every request, so blocks decoded for an earlier one can be told apart.
+    generation: u64,
+    // the next block the thread will send for the current generation.
+    expected: Option<usize>,
+    requests: Sender<(u64, usize)>,
+    decoded: Receiver<(u64, usize, Result<Vec<u8>, CorniferError>)>,
+}
+
+impl Readahead {
+    /// The blocks in the checkpoint database, in uncompressed order.
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    /// Length of the entire decompressed file.
+    pub fn uncompressed_len(&self) -> u64 {
+        end_of(&self.blocks, &self.gaps)
+    }
+
+    fn receive(&mut self) -> Result<(usize, Vec<u8>), CorniferError> {
+        loop {
+            let (generation, index, result) = self.decoded.recv().map_err(|_| worker_stopped())?;
+            if generation != self.generation {
+                continue;
+            }
+            self.expected = result.is_ok().then_some(index + 1);
+            return result.map(|data| (index, data));
+        }
+    }
+
+    fn read_internal(&mut self, buf: &mut [u8]) -> Result<usize, CorniferError> {
+        if self.pos >= self.uncompressed_len() || buf.is_empty() {
+            return Ok(0);
+        }
+        check_not_in_gap(&self.gaps, self.pos)?;
+        let index = block_at(&self.blocks, self.pos);
+        if !matches!(&self.current, Some((i, _)) if *i == index) {
+            self.current = None;
+            // a short skip forward is quicker to read through than to start again from.
+            let on_the_way = matches!(self.expected, Some(e) if e <= index && index - e <= self.depth);
+            if !on_the_way {
+                self.generation += 1;
+                self.requests.send((self.generation, index)).map_err(|_| worker_stopped())?;
+            }
+            loop {
+                let (i, data) = self.receive()?;
+                if i == index {
+                    self.current = Some((i, data));
+                    break;
+                }
+            }
+        }
+        let (_, data) = self.current.as_ref().expect("Block was just received");
+        let num_bytes = copy_from(self.blocks[index].to_byte, data, self.pos, buf)?;
+        self.pos += num_bytes as u64;
+        Ok(num_bytes)
+    }
+}
+
+fn worker_stopped() -> CorniferError {
+    CorniferError::from(io::Error::other("the readahead thread stopped"))
+}
+
+impl Read for Readahead {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_internal(buf).map_err(io::Error::from)
+    }
+}
+
+impl Seek for Readahead {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = seek_position(self.pos, self.uncompressed_len(), pos)?;
+        Ok(self.pos)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.pos)
+    }
+}
+
+// where the last block ends, or the last gap if the file ended in one.
+fn end_of(blocks: &[Block], gaps: &[Gap]) -> u64 {
+    let blocks_end = blocks.last().map(|b| b.to_byte + b.len).unwrap_or(0);
+    let gaps_end = gaps.iter().map(|g| g.uncompressed_to).max().unwrap_or(0);
+    blocks_end.max(gaps_end)
+}
+
+// there's no checkpoint for what the corrupt member gave before it failed, so there's nothing to decode it from.
+fn check_not_in_gap(gaps: &[Gap], pos: u64) -> Result<(), CorniferError> {
+    match gaps.iter().find(|g| (g.uncompressed_from..g.uncompressed_to).contains(&pos)) {
+        Some(gap) => Err(CorniferError::InGap {
+            from: gap.uncompressed_from,
+            to: gap.uncompressed_to,
+        }),
+        None => Ok(()),
+    }
+}
+
+// the last block that starts at or before pos.
+fn block_at(blocks: &[Block], pos: u64) -> usize {
+    blocks.partition_point(|b| b.to_byte <= pos) - 1
+}
+
+// the Deflator wraps its errors up for io::Read, unwrap them again.
+fn unwrap_io_error(e: io::Error) -> CorniferError {
+    match e.into_inner().map(|inner| inner.downcast::<CorniferError>()) {
+        Some(Ok(err)) => *err,
+        Some(Err(other)) => CorniferError::from(io::Error::other(other)),
+        None => CorniferError::EOF,
+    }
+}
+
+// copy as much as fits from pos onwards, out of data decoded from `from`.
+fn copy_from(from: u64, data: &[u8], pos: u64, buf: &mut [u8]) -> Result<usize, CorniferError> {
+    let offset = (pos - from) as usize;
+    if offset >= data.len() {
+        // the block is shorter than the database says it is, or there's a gap after it.
+        return Err(CorniferError::EOF);
+    }
+    let num_bytes = buf.len().min(data.len() - offset);
+    buf[..num_bytes].copy_from_slice(&data[offset..offset + num_bytes]);
+    Ok(num_bytes)
+}
+
+// where a seek goes, the same as for a File: `End` is from the uncompressed length in the index, going past the end
+// is fine (reads there just return nothing), and going before the start is an InvalidInput error that leaves the
+// position where it was.
+pub(crate) fn seek_position(pos: u64, len: u64, to: SeekFrom) -> io::Result<u64> {
+    let new_pos = match to {
+        SeekFrom::Start(n) => Some(n),
+        SeekFrom::End(n) => len.checked_add_signed(n),
+        SeekFrom::Current(n) => pos.checked_add_signed(n),
+    };
+    new_pos.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid seek to a negative or overflowing position",
+        )
+    })
+}
+
+// empty blocks (e.g. from a zlib flush) share their to_byte with the next block, and there's nothing to read.
+fn readable_blocks(store: &CheckpointStore) -> Result<Vec<Block>, CorniferError> {
+    Ok(store.blocks()?.into_iter().filter(|b| b.len > 0).collect())
+}
+
+// SplitMix64, which is plenty for picking blocks to sample.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // a number in 0..n. Multiplying rather than taking the remainder is as near uniform as makes no difference.
+    fn below(&mut self, n: usize) -> usize {
+        ((self.next() as u128 * n as u128) >> 64) as usize
+    }
+}
+
+impl<R: Read + Seek> Read for CheckpointedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_internal(buf).map_err(io::Error::from)
+    }
+}
+
+impl<R: Read + Seek> Seek for CheckpointedReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = seek_position(self.pos, self.uncompressed_len(), pos)?;
+        Ok(self.pos)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.pos)
+    }
+}
+
+/**
+ * TESTS
+ */
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+    use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+    use rstest::rstest;
+
+    #[cfg(feature = "bzip2")]
+    use crate::bzip2;
+    #[cfg(feature = "xz")]
+    use crate::xz;
+    use crate::{
+        checkpoint::{Checkpointer, CreateMode},
+        decompress::{Deflator, DeflatorOptions},
+        errors::CorniferError,
+        reader::CorniferByteReader,
+        report::ByteRange,
+        store::{Block, CheckpointStore},
+        testutil::{flushed_gzip, index_with_spacing, TempIndex},
+    };
+
+    use super::{seek_position, CheckpointedReader};
+
+    #[cfg(feature = "bzip2")]
+    #[rstest]
+    fn test_bzip2_blocks_are_unaligned() {
+        let input: &[u8] = include_bytes!("../testfiles/1080-0x3.txt.bz2");
+        let path = TempIndex::new();
+        let mut checkpointer = Checkpointer::init(&path).unwrap();
+        bzip2::index(&mut CorniferByteReader::new(input), &mut checkpointer).unwrap();
+        drop(checkpointer);
+
+        let reader = CheckpointedReader::open(Cursor::new(input), &path).unwrap();
+        let blocks = reader.blocks();
+        assert_eq!((blocks[0].from_byte, blocks[0].from_bit), (4, 0));
+        // found by searching for 0x314159265359 in the file, it's at bit 152053.
+        assert_eq!((blocks[1].from_byte, blocks[1].from_bit), (19006, 5));
+        assert_eq!(blocks[1].to_byte, blocks[0].len);
+    }
+
+    #[cfg(feature = "bzip2")]
+    #[rstest]
+    fn test_bzip2_read_across_blocks() {
+        let input: &[u8] = include_bytes!("../testfiles/1080-0x3.txt.bz2");
+        let path = TempIndex::new();
+        let mut checkpointer = Checkpointer::init(&path).unwrap();
+        bzip2::index(&mut CorniferByteReader::new(input), &mut checkpointer).unwrap();
+        drop(checkpointer);
+
+        let expected = include_bytes!("../testfiles/1080-0.txt").repeat(3);
+        let mut reader = CheckpointedReader::open(Cursor::new(input), &path).unwrap();
+        let boundary = reader.blocks()[1].to_byte as usize;
+
+        reader.seek(SeekFrom::Start(boundary as u64 - 100)).unwrap();
+        let mut dest = vec![0; 200];
+        reader.read_exact(&mut dest).unwrap();
+        assert_eq!(dest, &expected[boundary - 100..boundary + 100]);
+
+        reader.seek(SeekFrom::End(-10)).unwrap();
+        let mut dest = Vec::new();
+        reader.read_to_end(&mut dest).unwrap();
+        assert_eq!(dest, &expected[expected.len() - 10..]);
+    }
+
+    #[cfg(feature = "xz")]
+    #[rstest]
+    fn test_xz_read_across_streams() {
+        let input: &[u8] = include_bytes!("../testfiles/1080-0-anthems.txt.xz");
+        let path = TempIndex::new();
+        let mut checkpointer = Checkpointer::init(&path).unwrap();
+        xz::index(&mut Cursor::new(input), &mut checkpointer).unwrap();
+        drop(checkpointer);
+
+        let mut expected = include_bytes!("../testfiles/1080-0.txt").to_vec();
+        expected.extend(include_bytes!("../testfiles/anthems.txt"));
+        let mut reader = CheckpointedReader::open(Cursor::new(input), &path).unwrap();
+        assert_eq!(reader.uncompressed_len(), expected.len() as u64);
+
+        let mut dest = Vec::new();
+        reader.read_to_end(&mut dest).unwrap();
+        assert_eq!(dest, expected);
+
+        // the second stream starts at 39819.
+        reader.seek(SeekFrom::Start(39800)).unwrap();
+        let mut dest = vec![0; 50];
+        reader.read_exact(&mut dest).unwrap();
+        assert_eq!(dest, &expected[39800..39850]);
+    }
+
+    #[rstest]
+    #[case::compressed(Compression::default())]
+    #[case::stored(Compression::none())]
+    fn test_gzip_read_across_blocks(#[case] level: Compression) {
+        let expected = include_bytes!("../testfiles/1080-0.txt");
+        // flushing ends the block, so there's a boundary in the middle.
+        let mut e = GzEncoder::new(Vec::new(), level);
+        e.write_all(&expected[..20000]).unwrap();
+        e.flush().unwrap();
+        e.write_all(&expected[20000..]).unwrap();
+        let input = e.finish().unwrap();
+        let input = input.as_slice();
+        let path = TempIndex::new();
+        let checkpointer = Checkpointer::init(&path).unwrap();
+        let mut deflator = Deflator::new(CorniferByteReader::new(input), checkpointer);
+        std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        drop(deflator);
+
+        let mut reader = CheckpointedReader::open(Cursor::new(input), &path).unwrap();
+        assert!(reader.blocks().len() > 1);
+        assert_eq!(reader.uncompressed_len(), expected.len() as u64);
+        assert_eq!(reader.blocks()[1].to_byte, 20000);
+
+        reader.seek(SeekFrom::Start(19900)).unwrap();
+        let mut dest = vec![0; 200];
+        reader.read_exact(&mut dest).unwrap();
+        assert_eq!(dest, &expected[19900..20100]);
+
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        let mut dest = Vec::new();
+        reader.read_to_end(&mut dest).unwrap();
+        assert_eq!(dest, expected);
+    }
+
+    #[rstest]
+    #[case::compressed(Compression::default())]
+    #[case::stored(Compression::none())]
+    fn test_extract_member(#[case] level: Compression) {
+        let expected = include_bytes!("../testfiles/1080-0.txt");
+        let mut e = GzEncoder::new(Vec::new(), level);
+        for part in expected.chunks(7001) {
+            e.write_all(part).unwrap();
+            e.flush().unwrap();
+        }
+        let input = e.finish().unwrap();
+        let path = TempIndex::new();
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), Checkpointer::init(&path).unwrap());
+        std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        drop(deflator);
+
+        let mut reader = CheckpointedReader::open(Cursor::new(input.as_slice()), &path).unwrap();
+        for with_window in [true, false] {
+            let (member, extracted) = match reader.extract_member(15000, 25000, with_window) {
+                Ok(extracted) => extracted,
+                // the blocks after the first one refer back to it, but stored blocks never do.
+                Err(CorniferError::CantExtract { .. }) if !with_window && level != Compression::none() => continue,
+                Err(err) => panic!("{err}"),
+            };
+            assert_eq!(extracted.from, 14002);
+            // the window only goes back to the start of the file.
+            assert_eq!(extracted.prefix_len, if with_window { 14002 } else { 0 });
+            let mut dest = Vec::new();
+            flate2::read::GzDecoder::new(member.as_slice()).read_to_end(&mut dest).unwrap();
+            assert_eq!(dest.len() as u64, extracted.len);
+            let blocks = &dest[extracted.prefix_len as usize..];
+            assert_eq!(blocks, &expected[14002..14002 + blocks.len()]);
+            assert!(14002 + blocks.len() >= 25000);
+        }
+        assert!(matches!(reader.extract_member(50000, 60000, true), Err(CorniferError::CantExtract { .. })));
+    }
+
+    #[rstest]
+    fn test_member_by_name() {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let parts = [("a.txt", &text[..10000]), ("b.txt", &text[10000..30000]), ("c.txt", &text[30000..])];
+        let mut input = Vec::new();
+        for (name, part) in parts {
+            let mut e = flate2::GzBuilder::new().filename(name).write(Vec::new(), Compression::default());
+            e.write_all(part).unwrap();
+            input.extend(e.finish().unwrap());
+        }
+        let path = TempIndex::new();
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), Checkpointer::init(&path).unwrap());
+        std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        drop(deflator);
+
+        let mut reader = CheckpointedReader::open(Cursor::new(input.as_slice()), &path).unwrap();
+        let names: Vec<_> = reader.members().unwrap().into_iter().map(|m| m.name.unwrap()).collect();
+        assert_eq!(names, ["a.txt", "b.txt", "c.txt"]);
+        let member = reader.member_by_name("b.txt").unwrap();
+        assert_eq!(member.uncompressed_range(), 10000..30000);
+
+        // it starts a member, so it doesn't need a window.
+        let range = member.uncompressed_range();
+        let (extracted, _) = reader.extract_member(range.start, range.end, false).unwrap();
+        let mut dest = Vec::new();
+        flate2::read::GzDecoder::new(extracted.as_slice()).read_to_end(&mut dest).unwrap();
+        assert_eq!(dest, &text[10000..30000]);
+
+        assert!(matches!(reader.member_by_name("d.txt"), Err(CorniferError::NoSuchMember { .. })));
+    }
+
+    #[rstest]
+    fn test_gap() {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let mut input = Vec::new();
+        for part in [&text[..10000], &text[10000..]] {
+            let mut e = GzEncoder::new(Vec::new(), Compression::default());
+            e.write_all(part).unwrap();
+            input.extend(e.finish().unwrap());
+        }
+        // cut the second member off halfway, so its one block never finishes.
+        input.truncate(input.len() - 5000);
+        let path = TempIndex::new();
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), Checkpointer::init(&path).unwrap());
+        deflator.set_options(DeflatorOptions {
+            recover: true,
+            ..Default::default()
+        });
+        let decoded = std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        drop(deflator);
+
+        let mut reader = CheckpointedReader::open(Cursor::new(input.as_slice()), &path).unwrap();
+        let gap = reader.gaps()[0].clone();
+        assert_eq!((gap.uncompressed_from, gap.uncompressed_to), (10000, decoded));
+        assert_eq!(reader.uncompressed_len(), decoded);
+        // the first member is fine, up to where the gap starts.
+        let mut dest = Vec::new();
+        let err = reader.read_to_end(&mut dest).unwrap_err().into_inner().unwrap().downcast::<CorniferError>().unwrap();
+        assert!(matches!(*err, CorniferError::InGap { from: 10000, .. }));
+        assert_eq!(dest, &text[..10000]);
+        let report = reader.corruption_report().unwrap();
+        assert_eq!(report.damaged[0].uncompressed, ByteRange { from: 10000, to: decoded });
+        assert_eq!(report.damaged[0].compressed.to, input.len() as u64);
+        assert_eq!(report.recoverable, [ByteRange { from: 0, to: 10000 }]);
+    }
+
+    // 1080-0.txt, flushed every 4000 bytes so there's a block boundary there, and an index of it.
+    fn index_in_4000_byte_blocks() -> (Vec<u8>, TempIndex) {
+        let input = flushed_gzip(include_bytes!("../testfiles/1080-0.txt"), 1, 4000);
+        let path = index_with_spacing(&input, 0);
+        (input, path)
+    }
+
+    #[rstest]
+    fn test_cache() {
+        let expected = include_bytes!("../testfiles/1080-0.txt");
+        let (input, path) = index_in_4000_byte_blocks();
+
+        let mut reader = CheckpointedReader::open(Cursor::new(input), &path).unwrap();
+        assert_eq!(reader.cache_stats(), None);
+        // room for two of the 4000 byte blocks.
+        reader.set_cache(8000);
+        let mut dest = vec![0; 100];
+        for start in [1000, 5000, 1000, 9000, 1000, 5000] {
+            reader.seek(SeekFrom::Start(start)).unwrap();
+            reader.read_exact(&mut dest).unwrap();
+            assert_eq!(dest, &expected[start as usize..start as usize + 100]);
+        }
+        let stats = reader.cache_stats().unwrap();
+        // 1000 was read more recently than 5000, so 5000 is the one that falls out when 9000 goes in.
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (2, 4, 2));
+        assert_eq!((stats.entries, stats.bytes), (2, 8000));
+    }
+
+    #[rstest]
+    #[case::one_at_a_time(0)]
+    #[case::a_few(3)]
+    fn test_readahead(#[case] depth: usize) {
+        let expected = include_bytes!("../testfiles/1080-0.txt");
+        let (input, path) = index_in_4000_byte_blocks();
+
+        let mut reader = CheckpointedReader::open(Cursor::new(input), &path).unwrap().readahead(depth);
+        assert_eq!(reader.blocks().len(), 10);
+        let mut dest = vec![0; 6000];
+        // read on from a seek, skip a little way forward, then seek back past where we started.
+        for start in [5000, 13000, 30000, 1000] {
+            reader.seek(SeekFrom::Start(start)).unwrap();
+            reader.read_exact(&mut dest).unwrap();
+            assert_eq!(dest, &expected[start as usize..start as usize + 6000]);
+        }
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        let mut dest = Vec::new();
+        reader.read_to_end(&mut dest).unwrap();
+        assert_eq!(dest, expected);
+    }
+
+    #[rstest]
+    #[case::start(SeekFrom::Start(5), Some(5))]
+    #[case::start_past_the_end(SeekFrom::Start(500), Some(500))]
+    #[case::end(SeekFrom::End(-10), Some(90))]
+    #[case::end_zero(SeekFrom::End(0), Some(100))]
+    #[case::past_the_end(SeekFrom::End(10), Some(110))]
+    #[case::before_the_start_from_end(SeekFrom::End(-101), None)]
+    #[case::forward(SeekFrom::Current(20), Some(60))]
+    #[case::back(SeekFrom::Current(-40), Some(0))]
+    #[case::before_the_start(SeekFrom::Current(-41), None)]
+    #[case::overflow(SeekFrom::Current(i64::MAX), Some(i64::MAX as u64 + 40))]
+    #[case::far_past_the_end(SeekFrom::Start(u64::MAX), Some(u64::MAX))]
+    fn test_seek_position(#[case] to: SeekFrom, #[case] expected: Option<u64>) {
+        let result = seek_position(40, 100, to);
+        assert_eq!(result.as_ref().ok(), expected.as_ref());
+        if let Err(e) = result {
+            assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[rstest]
+    #[case::reader(None)]
+    #[case::readahead(Some(2))]
+    fn test_seek(#[case] readahead: Option<usize>) {
+        let expected = include_bytes!("../testfiles/1080-0.txt");
+        let (input, path) = index_in_4000_byte_blocks();
+        let reader = CheckpointedReader::open(Cursor::new(input), &path).unwrap();
+        let mut reader: Box<dyn ReadSeek> = match readahead {
+            Some(depth) => Box::new(reader.readahead(depth)),
+            None => Box::new(reader),
+        };
+        let len = expected.len() as u64;
+        let mut dest = [0; 10];
+
+        // from the end, then back and forward from there, across blocks.
+        assert_eq!(reader.seek(SeekFrom::End(-10)).unwrap(), len - 10);
+        reader.read_exact(&mut dest).unwrap();
+        assert_eq!(dest, expected[len as usize - 10..]);
+        assert_eq!(reader.seek(SeekFrom::Current(-4005)).unwrap(), len - 4005);
+        reader.read_exact(&mut dest).unwrap();
+        assert_eq!(dest, expected[len as usize - 4005..len as usize - 3995]);
+        assert_eq!(reader.stream_position().unwrap(), len - 3995);
+
+        // before the start fails, and leaves the position alone.
+        assert!(reader.seek(SeekFrom::Current(-(len as i64))).is_err());
+        assert_eq!(reader.stream_position().unwrap(), len - 3995);
+
+        // past the end, reads return nothing, and it's fine to come back.
+        assert_eq!(reader.seek(SeekFrom::End(100)).unwrap(), len + 100);
+        assert_eq!(reader.read(&mut dest).unwrap(), 0);
+        reader.seek(SeekFrom::Start(3995)).unwrap();
+        reader.read_exact(&mut dest).unwrap();
+        assert_eq!(dest, expected[3995..4005]);
+    }
+
+    trait ReadSeek: Read + Seek {}
+    impl<T: Read + Seek> ReadSeek for T {}
+
+    #[rstest]
+    #[case::some(4)]
+    #[case::all(10)]
+    #[case::more_than_there_are(20)]
+    fn test_sample_blocks(#[case] k: usize) {
+        let expected = include_bytes!("../testfiles/1080-0.txt");
+        let (input, path) = index_in_4000_byte_blocks();
+
+        let mut reader = CheckpointedReader::open(Cursor::new(input), &path).unwrap();
+        let sample = reader.sample_blocks(k, 1080).unwrap();
+        assert_eq!(sample.len(), k.min(10));
+        for (block, data) in &sample {
+            assert_eq!(data, &expected[block.to_byte as usize..(block.to_byte + block.len) as usize]);
+        }
+        assert!(sample.windows(2).all(|w| w[0].0.to_byte < w[1].0.to_byte));
+        // same seed, same blocks.
+        let again: Vec<_> = reader.sample_blocks(k, 1080).unwrap().into_iter().map(|(b, _)| b.id).collect();
+        assert_eq!(again, sample.iter().map(|(b, _)| b.id).collect::<Vec<_>>());
+    }
+
+    #[rstest]
+    #[case::whole(u64::MAX, 1)]
+    #[case::every_50kb(50000, 3)]
+    #[case::every_block(1, 30)]
+    fn test_split(#[case] chunk_size: u64, #[case] num_parts: usize) {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        // a long member and a short one, in 4000 byte blocks.
+        let mut input = Vec::new();
+        for repeat in [2, 1] {
+            let mut e = GzEncoder::new(Vec::new(), Compression::default());
+            for chunk in text.repeat(repeat).chunks(4000) {
+                e.write_all(chunk).unwrap();
+                e.flush().unwrap();
+            }
+            input.extend(e.finish().unwrap());
+        }
+        let expected = text.repeat(3);
+        let path = TempIndex::new();
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), Checkpointer::init(&path).unwrap());
+        std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        drop(deflator);
+
+        let mut reader = CheckpointedReader::open(Cursor::new(input.as_slice()), &path).unwrap();
+        let parts = reader.split_points(chunk_size);
+        assert_eq!(parts.len(), num_parts);
+        assert_eq!((parts[0].start, parts[num_parts - 1].end), (0, expected.len() as u64));
+        for range in parts {
+            let part = reader.split_part(range.clone(), Compression::default()).unwrap();
+            let mut dest = Vec::new();
+            MultiGzDecoder::new(part.as_slice()).read_to_end(&mut dest).unwrap();
+            assert_eq!(dest, &expected[range.start as usize..range.end as usize]);
+            if range == (0..expected.len() as u64) {
+                // past the first 32KB, the blocks are copied.
+                assert!(part.windows(1000).any(|w| w == &input[20000..21000]));
+            }
+        }
+    }
+
+    #[cfg(feature = "bzip2")]
+    #[rstest]
+    fn test_split_bzip2() {
+        let input: &[u8] = include_bytes!("../testfiles/1080-0x3.txt.bz2");
+        let path = TempIndex::new();
+        let mut checkpointer = Checkpointer::init(&path).unwrap();
+        bzip2::index(&mut CorniferByteReader::new(input), &mut checkpointer).unwrap();
+        drop(checkpointer);
+
+        let expected = include_bytes!("../testfiles/1080-0.txt").repeat(3);
+        let mut reader = CheckpointedReader::open(Cursor::new(input), &path).unwrap();
+        let mut dest = Vec::new();
+        for range in reader.split_points(1) {
+            let part = reader.split_part(range, Compression::fast()).unwrap();
+            MultiGzDecoder::new(part.as_slice()).read_to_end(&mut dest).unwrap();
+        }
+        assert_eq!(dest, expected);
+    }
+
+    #[rstest]
+    #[case::member_starts(u64::MAX)]
+    #[case::every_10kb(10000)]
+    fn test_sparse(#[case] spacing: u64) {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        // two members, with a block every 4000 bytes.
+        let input = flushed_gzip(text, 2, 4000);
+        let expected = text.repeat(2);
+        let path = index_with_spacing(&input, spacing);
+
+        let mut reader = CheckpointedReader::open(Cursor::new(input.as_slice()), &path).unwrap();
+        let with_window: Vec<_> = reader.blocks().iter().filter(|b| b.has_window).map(|b| b.to_byte).collect();
+        let member_starts: Vec<_> = reader.blocks().iter().filter(|b| b.member_start).map(|b| b.to_byte).collect();
+        assert_eq!(member_starts, vec![0, text.len() as u64]);
+        if spacing == u64::MAX {
+            // which are only the ones that start a member, that don't need one stored.
+            assert_eq!(with_window, member_starts);
+        } else {
+            assert!(with_window.len() > 2 && with_window.len() < reader.blocks().len());
+        }
+        reader.verify().unwrap();
+
+        // somewhere in the middle, without reading up to it first.
+        let mut reader = CheckpointedReader::open(Cursor::new(input.as_slice()), &path).unwrap();
+        reader.seek(SeekFrom::Start(70000)).unwrap();
+        let mut dest = vec![0; 5000];
+        reader.read_exact(&mut dest).unwrap();
+        assert_eq!(dest, &expected[70000..75000]);
+
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        let mut dest = Vec::new();
+        reader.read_to_end(&mut dest).unwrap();
+        assert_eq!(dest, expected);
+    }
+
+    #[rstest]
+    fn test_growing_index() {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        for chunk in text.chunks(10000) {
+            e.write_all(chunk).unwrap();
+            e.flush().unwrap();
+        }
+        let input = e.finish().unwrap();
+        let path = TempIndex::new();
+        let mut checkpointer = Checkpointer::init(&path).unwrap();
+        checkpointer.use_wal().unwrap();
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), checkpointer);
+        // partway into the third block.
+        let mut dest = vec![0; 25000];
+        deflator.read_exact(&mut dest).unwrap();
+
+        // the indexer's still going.
+        let mut reader = CheckpointedReader::open(Cursor::new(input.as_slice()), &path).unwrap();
+        assert_eq!(reader.uncompressed_len(), 20000);
+        reader.seek(SeekFrom::Start(15000)).unwrap();
+        let mut dest = Vec::new();
+        reader.read_to_end(&mut dest).unwrap();
+        assert_eq!(dest, &text[15000..20000]);
+
+        std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        drop(deflator);
+        reader.refresh().unwrap();
+        assert_eq!(reader.uncompressed_len(), text.len() as u64);
+        let mut dest = Vec::new();
+        reader.read_to_end(&mut dest).unwrap();
+        assert_eq!(dest, &text[20000..]);
+        reader.verify().unwrap();
+    }
+
+    #[rstest]
+    fn test_verify() {
+        let input: &[u8] = include_bytes!("../testfiles/1080-0.txt.gz");
+        let path = TempIndex::new();
+        let mut deflator = Deflator::new(CorniferByteReader::new(input), Checkpointer::init(&path).unwrap());
+        std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        drop(deflator);
+
+        let mut reader = CheckpointedReader::open(Cursor::new(input), &path).unwrap();
+        reader.verify().unwrap();
+
+        // change the recorded crc, and it shouldn't match any more.
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute("UPDATE Meta SET value = '1234' WHERE key = 'crc32'", ()).unwrap();
+        let mut reader = CheckpointedReader::open(Cursor::new(input), &path).unwrap();
+        assert!(matches!(
+            reader.verify(),
+            Err(CorniferError::IndexCrcMismatch { expected: 0x1234, .. })
+        ));
+
+        // or the length, which is caught on open.
+        conn.execute("UPDATE Meta SET value = '5' WHERE key = 'uncompressed_len'", ()).unwrap();
+        assert!(matches!(
+            CheckpointedReader::open(Cursor::new(input), &path),
+            Err(CorniferError::IndexLengthMismatch { expected: 5, found: 39819 })
+        ));
+    }
+
+    #[rstest]
+    fn test_block_crc_mismatch() {
+        let input: &[u8] = include_bytes!("../testfiles/1080-0.txt.gz");
+        let path = TempIndex::new();
+        let mut deflator = Deflator::new(CorniferByteReader::new(input), Checkpointer::init(&path).unwrap());
+        std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        drop(deflator);
+
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute("UPDATE DeflateBlock SET crc32 = '1234'", ()).unwrap();
+        let mut reader = CheckpointedReader::open(Cursor::new(input), &path).unwrap();
+        let block_id = reader.blocks()[0].id;
+        reader.seek(SeekFrom::Start(100)).unwrap();
+        let err = reader.read(&mut [0; 10]).unwrap_err();
+        let err = err.into_inner().unwrap().downcast::<CorniferError>().unwrap();
+        assert!(matches!(*err, CorniferError::CorruptIndexOrData { block_id: id } if id == block_id));
+    }
+
+    #[rstest]
+    fn test_corruption_report() {
+        let (input, path) = index_in_4000_byte_blocks();
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute("UPDATE DeflateBlock SET crc32 = '1234' WHERE to_byte = 8000", ()).unwrap();
+        let mut reader = CheckpointedReader::open(Cursor::new(input), &path).unwrap();
+        assert!(reader.verify().is_err());
+
+        let report = reader.corruption_report().unwrap();
+        let [damaged] = &report.damaged[..] else {
+            panic!("expected one damaged range, got {:?}", report.damaged);
+        };
+        assert_eq!(damaged.uncompressed, ByteRange { from: 8000, to: 12000 });
+        assert_eq!(damaged.compressed.from, reader.blocks()[2].from_byte);
+        assert_eq!(report.last_good_checkpoint.unwrap().to_byte, 4000);
+        assert_eq!(
+            report.recoverable,
+            [ByteRange { from: 0, to: 8000 }, ByteRange { from: 12000, to: 39819 }]
+        );
+    }
+
+    #[rstest]
+    fn test_verify_incomplete() {
+        let input: &[u8] = include_bytes!("../testfiles/1080-0.txt.gz");
+        let path = TempIndex::new();
+        let mut checkpointer = Checkpointer::init(&path).unwrap();
+        checkpointer.keep_partial().unwrap();
+        let mut deflator = Deflator::new(CorniferByteReader::new(input), checkpointer);
+        let mut dest = vec![0; 100];
+        deflator.read_exact(&mut dest).unwrap();
+        drop(deflator);
+
+        let mut reader = CheckpointedReader::open(Cursor::new(input), &path).unwrap();
+        assert!(matches!(reader.verify(), Err(CorniferError::IndexIncomplete)));
+    }
+
+    #[rstest]
+    #[case::unchanged(None, None)]
+    #[case::flipped(Some(5000), None)]
+    #[case::cut_short(None, Some(5000))]
+    fn test_verify_compressed(#[case] flip: Option<usize>, #[case] cut: Option<usize>) {
+        // a block every 4000 bytes.
+        let mut input = flushed_gzip(include_bytes!("../testfiles/1080-0.txt"), 1, 4000);
+        let path = TempIndex::new();
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), Checkpointer::init(&path).unwrap());
+        deflator.hash_blocks();
+        std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        drop(deflator);
+        let blocks = CheckpointStore::open(&path).unwrap().blocks().unwrap();
+        assert!(blocks.iter().all(|b| b.compressed_crc32.is_some()));
+
+        if let Some(at) = flip {
+            input[at] ^= 0x10;
+        }
+        if let Some(len) = cut {
+            input.truncate(len);
+        }
+        // the first block with any of the damaged bytes in it.
+        let end = |b: &Block| (b.from_byte * 8 + b.from_bit as u64 + b.block_len_bits).div_ceil(8);
+        let damaged = flip.or(cut).map(|at| blocks.iter().find(|b| end(b) > at as u64).unwrap().id);
+        let mut reader = CheckpointedReader::open(Cursor::new(input.as_slice()), &path).unwrap();
+        match (reader.verify_compressed(), damaged) {
+            (Ok(checked), None) => assert_eq!(checked, blocks.len()),
+            (Err(CorniferError::CompressedCrcMismatch { block_id, .. }), Some(id)) => assert_eq!(block_id, id),
+            (result, _) => panic!("{result:?}"),
+        }
+    }
+
+    #[rstest]
+    fn test_verify_compressed_without_hashes() {
+        let input: &[u8] = include_bytes!("../testfiles/1080-0.txt.gz");
+        let path = TempIndex::new();
+        let mut deflator = Deflator::new(CorniferByteReader::new(input), Checkpointer::init(&path).unwrap());
+        std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        drop(deflator);
+        let mut reader = CheckpointedReader::open(Cursor::new(input), &path).unwrap();
+        assert!(matches!(reader.verify_compressed(), Err(CorniferError::NoBlockHashes)));
+    }
+
+    #[rstest]
+    fn test_open_source() {
+        let first: &[u8] = include_bytes!("../testfiles/1080-0.txt.gz");
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(&text[5000..15000]).unwrap();
+        let second = e.finish().unwrap();
+        let path = TempIndex::new();
+        // the first one twice, which replaces it.
+        for (name, input) in [("first.gz", first), ("second.gz", &second), ("first.gz", first)] {
+            let mut checkpointer = Checkpointer::open(&path, CreateMode::Append).unwrap();
+            checkpointer.set_source(name).unwrap();
+            let mut deflator = Deflator::new(CorniferByteReader::new(input), checkpointer);
+            std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        }
+
+        let mut reader = CheckpointedReader::open_source(Cursor::new(first), &path, "first.gz").unwrap();
+        assert_eq!(reader.uncompressed_len(), text.len() as u64);
+        reader.verify().unwrap();
+        let mut reader = CheckpointedReader::open_source(Cursor::new(second.as_slice()), &path, "second.gz").unwrap();
+        assert_eq!(reader.uncompressed_len(), 10000);
+        reader.verify().unwrap();
+        reader.seek(SeekFrom::Start(1000)).unwrap();
+        let mut dest = vec![0; 100];
+        reader.read_exact(&mut dest).unwrap();
+        assert_eq!(dest, &text[6000..6100]);
+
+        let store = CheckpointStore::open(&path).unwrap();
+        let names: Vec<_> = store.sources().unwrap().into_iter().map(|s| (s.name, s.meta.complete)).collect();
+        assert_eq!(names, [("first.gz".to_string(), true), ("second.gz".to_string(), true)]);
+        assert!(matches!(
+            CheckpointedReader::open_source(Cursor::new(first), &path, "third.gz"),
+            Err(CorniferError::UnknownSource { .. })
+        ));
+    }
+}
@@ -1,33 +1,236 @@
+use std::cmp::min;
 use std::mem;
 
 use crc::{Crc, Digest, CRC_32_ISO_HDLC};
-use rand::Rng;
 
 use crate::errors::CorniferError;
 
 static CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
 
+// multiply a vector by a matrix over GF(2), for crc32_combine.
+fn gf2_matrix_times(mat: &[u32; 32], mut vec: u32) -> u32 {
+    let mut sum = 0;
+    for row in mat {
+        if vec == 0 {
+            break;
+        }
+        if vec & 1 != 0 {
+            sum ^= row;
+        }
+        vec >>= 1;
+    }
+    sum
+}
+
+fn gf2_matrix_square(square: &mut [u32; 32], mat: &[u32; 32]) {
+    for (dest, row) in square.iter_mut().zip(mat) {
+        *dest = gf2_matrix_times(mat, *row);
+    }
+}
+
+/// The CRC32 of two pieces of data one after the other, from their CRC32s and the second one's length.
+/// This is zlib's crc32_combine: it works out what len2 zero bytes do to crc1, by repeated squaring.
+pub fn crc32_combine(mut crc1: u32, crc2: u32, mut len2: u64) -> u32 {
+    if len2 == 0 {
+        return crc1;
+    }
+    let mut even = [0u32; 32];
+    // the operator for one zero bit.
+    let mut odd = [0u32; 32];
+    odd[0] = 0xedb88320; // the reflected polynomial.
+    for (n, row) in odd.iter_mut().enumerate().skip(1) {
+        *row = 1 << (n - 1);
+    }
+    // two zero bits, then four.
+    gf2_matrix_square(&mut even, &odd);
+    gf2_matrix_square(&mut odd, &even);
+    // each time round, square for the next power of two bytes and apply it if that bit of len2 is set.
+    loop {
+        gf2_matrix_square(&mut even, &odd);
+        if len2 & 1 != 0 {
+            crc1 = gf2_matrix_times(&even, crc1);
+        }
+        len2 >>= 1;
+        if len2 == 0 {
+            break;
+        }
+        gf2_matrix_square(&mut odd, &even);
+        if len2 & 1 != 0 {
+            crc1 = gf2_matrix_times(&odd, crc1);
+        }
+        len2 >>= 1;
+        if len2 == 0 {
+            break;
+        }
+    }
+    crc1 ^ crc2
+}
+
+const ADLER_MOD: u32 = 65521;
+// largest number of bytes that can be summed before the sums could overflow a u32.
+const ADLER_NMAX: u32 = 5552;
+
+/// Adler-32, the checksum zlib streams use instead of CRC32.
+struct Adler32 {
+    a: u32,
+    b: u32,
+    // bytes since the sums were last reduced.
+    pending: u32,
+}
+
+impl Adler32 {
+    fn new() -> Self {
+        Self {
+            a: 1,
+            b: 0,
+            pending: 0,
+        }
+    }
+
+    fn update(&mut self, byte: u8) {
+        self.a += byte as u32;
+        self.b += self.a;
+        self.pending += 1;
+        if self.pending == ADLER_NMAX {
+            self.a %= ADLER_MOD;
+            self.b %= ADLER_MOD;
+            self.pending = 0;
+        }
+    }
+
+    #[cfg(feature = "simd")]
+    fn update_all(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.update(*byte);
+        }
+    }
+
+    fn finalize(&self) -> u32 {
+        ((self.b % ADLER_MOD) << 16) | (self.a % ADLER_MOD)
+    }
+
+    // the reverse of finalize.
+    fn from_value(value: u32) -> Self {
+        Self {
+            a: value & 0xFFFF,
+            b: value >> 16,
+            pending: 0,
+        }
+    }
+}
+
+// matches at least this far back are copied in wide chunks with the simd feature. Closer than that, a chunk would
+// read bytes it hasn't written yet.
+#[cfg(feature = "simd")]
+const WIDE_COPY_MIN: usize = 16;
+
+// copy N bytes from one place in buf to another, as a single fixed size load and store, which the compiler turns
+// into vector instructions.
+#[cfg(feature = "simd")]
+fn copy_chunk<const N: usize>(buf: &mut [u8], from: usize, to: usize) {
+    let chunk: [u8; N] = buf[from..from + N].try_into().unwrap();
+    buf[to..to + N].copy_from_slice(&chunk);
+}
+
+// the running value of a digest, in the form digest_with_initial takes it back. CRC_32_ISO_HDLC is reflected, so
+// digest_with_initial reverses the bits of what it's given.
+fn digest_state(digest: &Digest<'static, u32>) -> u32 {
+    crc32_state(digest.clone().finalize())
+}
+
+fn crc32_state(crc32: u32) -> u32 {
+    (crc32 ^ CRC32.algorithm.xorout).reverse_bits()
+}
+
+/// The checksums and counter that run alongside the window, i.e. what ends up in the member's footer.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CrcState {
+    pub gzip_crc32: u32,
+    pub block_crc32: u32,
+    pub adler32: u32,
+    pub member_len: u64,
+}
+
+impl CrcState {
+    /// At the start of a block `member_len` bytes into a gzip member, whose data so far has this CRC32. There's no
+    /// Adler-32 to go on, so it's no good for zlib.
+    pub fn at_block(crc32: u32, member_len: u64) -> Self {
+        Self {
+            gzip_crc32: crc32_state(crc32),
+            block_crc32: crc32_state(0),
+            adler32: 1,
+            member_len,
+        }
+    }
+}
+
+/// Everything needed to put a CircularBuffer back the way it was.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BufferSnapshot {
+    // as from get_normalized_buffer.
+    pub window: Vec<u8>,
+    pub bytes_written: usize,
+    pub crc_state: CrcState,
+}
+
 pub struct CircularBuffer {
     buffer: Vec<u8>,
     head: usize,
     gzip_digest: Digest<'static, u32>,  // this one is used to calculate the CRC of entire GZIP members.
     block_digest: Digest<'static, u32>, // calculate the CRC of individual blocks.
-    counter: u32,         // wraps
+    adler: Option<Adler32>,             // zlib streams use Adler-32 instead of CRC32. See track_adler32.
+    counter: u64,         // per member, ISIZE is this modulo 2^32.
     bytes_written: usize, // doesn't wrap.
+    window_len: usize,    // how much of the buffer is from the current member, up to its size.
+}
+
+// it shouldn't matter where the head starts, so the tests start it anywhere to make sure.
+#[cfg(test)]
+fn start_head(size: usize) -> usize {
+    use rand::Rng;
+    rand::thread_rng().gen_range(0..size)
+}
+
+#[cfg(not(test))]
+fn start_head(_size: usize) -> usize {
+    0
 }
 
 impl CircularBuffer {
     pub fn new(size: usize) -> Self {
-        let mut rng = rand::thread_rng();
         let buffer: Vec<u8> = vec![0; size];
         Self {
             buffer,
-            head: rng.gen_range(0..size), // it shouldn't matter where the head starts.
+            head: start_head(size),
             gzip_digest: CRC32.digest(),
             block_digest: CRC32.digest(),
+            adler: None,
             counter: 0,
             bytes_written: 0,
+            window_len: 0,
+        }
+    }
+
+    /// Keep an Adler-32 of everything pushed from now on, for zlib streams. Nothing else uses it, and it costs
+    /// about as much as a CRC32, so it's left out otherwise.
+    pub fn track_adler32(&mut self) {
+        self.adler.get_or_insert_with(Adler32::new);
+    }
+
+    /// Go back to how new left it, without reallocating. Whether it tracks Adler-32 stays as it was.
+    pub fn reset(&mut self) {
+        self.buffer.fill(0);
+        self.head = 0;
+        self.gzip_digest = CRC32.digest();
+        self.block_digest = CRC32.digest();
+        if let Some(adler) = &mut self.adler {
+            *adler = Adler32::new();
         }
+        self.counter = 0;
+        self.bytes_written = 0;
+        self.window_len = 0;
     }
 
     pub fn push(&mut self, byte: u8) {
@@ -35,14 +238,24 @@ impl CircularBuffer {
         self.head = (self.head + 1) % self.buffer.len();
         self.gzip_digest.update(&[byte]);
         self.block_digest.update(&[byte]);
-        self.counter = self.counter.wrapping_add(1);
+        if let Some(adler) = &mut self.adler {
+            adler.update(byte);
+        }
+        self.counter += 1;
         self.bytes_written += 1;
+        self.window_len = min(self.window_len + 1, self.buffer.len());
     }
 
     pub fn get_bytes_written(&self) -> usize {
         self.bytes_written
     }
 
+    /// The most recent byte pushed to the buffer (0 if there hasn't been one).
+    pub fn last(&self) -> u8 {
+        let len = self.buffer.len();
+        self.buffer[(self.head + len - 1) % len]
+    }
+
     /// push bytes into the buffer that are in the buffer.
     ///
     ///  * lookback - number of bytes back in the buffer to look. Max 32kb.
@@ -54,6 +267,11 @@ impl CircularBuffer {
         if lookback > 32768 {
             return Err(CorniferError::InvalidLengthDistancePair { lookback, size });
         }
+        #[cfg(feature = "simd")]
+        if lookback as usize >= WIDE_COPY_MIN && lookback as usize <= self.buffer.len() {
+            self.push_from_buffer_wide(lookback as usize, size as usize);
+            return Ok(());
+        }
         let lookback = lookback as isize;
         let len = self.buffer.len() as isize;
         for _ in 0..size {
@@ -64,20 +282,71 @@ impl CircularBuffer {
         Ok(())
     }
 
+    // push_from_buffer for distances of at least WIDE_COPY_MIN, a run at a time rather than a byte at a time: as
+    // much as can be copied before either end reaches the end of the buffer, in 32 or 16 byte chunks, with the
+    // checksums updated once for the whole run. A chunk never reaches past the bytes already written, so a match
+    // that's longer than its distance (the overlapping case) reads back what its own earlier chunks wrote.
+    #[cfg(feature = "simd")]
+    fn push_from_buffer_wide(&mut self, lookback: usize, size: usize) {
+        let len = self.buffer.len();
+        let mut remaining = size;
+        while remaining > 0 {
+            let from = (self.head + len - lookback) % len;
+            let run = min(remaining, min(len - self.head, len - from));
+            let chunk = if lookback >= 32 { 32 } else { 16 };
+            let mut done = 0;
+            while done + chunk <= run {
+                match chunk {
+                    32 => copy_chunk::<32>(&mut self.buffer, from + done, self.head + done),
+                    _ => copy_chunk::<16>(&mut self.buffer, from + done, self.head + done),
+                }
+                done += chunk;
+            }
+            for i in done..run {
+                self.buffer[self.head + i] = self.buffer[from + i];
+            }
+            let written = &self.buffer[self.head..self.head + run];
+            self.gzip_digest.update(written);
+            self.block_digest.update(written);
+            if let Some(adler) = &mut self.adler {
+                adler.update_all(written);
+            }
+            self.head = (self.head + run) % len;
+            remaining -= run;
+        }
+        self.counter += size as u64;
+        self.bytes_written += size;
+        self.window_len = min(self.window_len + size, len);
+    }
+
     /// Get the top n bytes of the buffer as a vector v.
     /// The _last_ item in v is the most _recent_ byte pushed to the buffer.
     /// The _first_ item in v is the nth most recent byte pushed to the buffer.
     pub fn head(&self, n: u16) -> Result<Vec<u8>, CorniferError> {
-        let mut v: Vec<u8> = Vec::new();
-        for i in 0..n {
-            let n1 = (n - i) as isize;
-            let head = self.head as isize;
-            let len = self.buffer.len() as isize;
-            let index = (head - n1).rem_euclid(len);
-            v.push(self.buffer[index as usize])
+        Ok(self.recent(n as usize).concat())
+    }
+
+    /// The last `n` bytes pushed, oldest first, without copying them: in two parts, since they can wrap around
+    /// the end of the buffer. `n` is at most the size of the buffer.
+    pub fn recent(&self, n: usize) -> [&[u8]; 2] {
+        let len = self.buffer.len();
+        let from = (self.head + len - n) % len;
+        match from + n <= len {
+            true => [&self.buffer[from..from + n], &[]],
+            false => [&self.buffer[from..], &self.buffer[..self.head]],
         }
+    }
 
-        Ok(v)
+    /// Copy into dest the bytes starting `back` bytes before the head, e.g. the rest of a match that's already
+    /// been pushed. Only as many as fit in dest, and never past the head.
+    pub fn copy_recent(&self, back: usize, dest: &mut [u8]) -> usize {
+        let len = self.buffer.len();
+        let n = min(min(back, len), dest.len());
+        let from = (self.head + len - min(back, len)) % len;
+        for (i, byte) in dest[..n].iter_mut().enumerate() {
+            *byte = self.buffer[(from + i) % len];
+        }
+        n
     }
 
     /// Returns the CRC32 of the data written so far, and resets the CRC32.
@@ -86,28 +355,104 @@ impl CircularBuffer {
         d.finalize()
     }
 
+    /// Returns the Adler-32 of the data written so far, and resets it. That's 1, the Adler-32 of nothing, unless
+    /// track_adler32 was called.
+    pub fn adler32(&mut self) -> u32 {
+        match &mut self.adler {
+            Some(adler) => mem::replace(adler, Adler32::new()).finalize(),
+            None => 1,
+        }
+    }
+
     pub fn block_crc32(&mut self) -> u32 {
         let d = mem::replace(&mut self.block_digest, CRC32.digest());
         d.finalize()
     }
 
     /// Return the number of bytes written so far, and resets this count.
-    pub fn counter(&mut self) -> u32 {
+    pub fn counter(&mut self) -> u64 {
         let result = self.counter;
         self.counter = 0;
         result
     }
 
+    /// Fill the buffer with a window from get_normalized_buffer, e.g. to carry on decoding from a checkpoint.
+    /// The CRCs and counters aren't touched, since the window was already counted wherever it came from.
+    pub fn load_window(&mut self, window: &[u8]) {
+        for byte in window {
+            self.buffer[self.head] = *byte;
+            self.head = (self.head + 1) % self.buffer.len();
+        }
+        self.window_len = min(self.window_len + window.len(), self.buffer.len());
+    }
+
     pub fn get_normalized_buffer(&self) -> Result<Vec<u8>, CorniferError> {
         self.head(self.buffer.len() as u16)
     }
+
+    /// Count the output as starting from here, e.g. to carry on after the end of a member without the data before it.
+    pub fn set_bytes_written(&mut self, bytes_written: usize) {
+        self.bytes_written = bytes_written;
+    }
+
+    /// A new gzip member is starting. Nothing in it can refer back to the previous member's data.
+    pub fn forget_window(&mut self) {
+        self.window_len = 0;
+    }
+
+    /// Like get_normalized_buffer, but only the part that's from the current member. Near the start of a member
+    /// that's shorter than the buffer, and nothing before it is needed to decode the rest of the member.
+    pub fn get_window(&self) -> Result<Vec<u8>, CorniferError> {
+        self.head(self.window_len as u16)
+    }
+
+    /// How far back a distance can go in the current member.
+    pub fn window_len(&self) -> usize {
+        self.window_len
+    }
+
+    /// get_window, in the two parts from recent.
+    pub fn window_parts(&self) -> [&[u8]; 2] {
+        self.recent(self.window_len)
+    }
+
+    /// Capture the window, counters and checksums, for restore_from.
+    pub fn snapshot(&self) -> Result<BufferSnapshot, CorniferError> {
+        Ok(BufferSnapshot {
+            window: self.get_normalized_buffer()?,
+            bytes_written: self.bytes_written,
+            crc_state: CrcState {
+                gzip_crc32: digest_state(&self.gzip_digest),
+                block_crc32: digest_state(&self.block_digest),
+                adler32: self.adler.as_ref().map_or(1, Adler32::finalize),
+                member_len: self.counter,
+            },
+        })
+    }
+
+    /// Put the buffer back the way a snapshot found it. Unlike load_window, carrying on from here gives the same
+    /// CRCs and counters as if the data before the window had gone through this buffer.
+    pub fn restore_from(&mut self, window: &[u8], bytes_written: usize, crc_state: CrcState) {
+        self.load_window(window);
+        self.window_len = min(self.window_len, crc_state.member_len as usize);
+        self.bytes_written = bytes_written;
+        self.counter = crc_state.member_len;
+        self.gzip_digest = CRC32.digest_with_initial(crc_state.gzip_crc32);
+        self.block_digest = CRC32.digest_with_initial(crc_state.block_crc32);
+        if let Some(adler) = &mut self.adler {
+            *adler = Adler32::from_value(crc_state.adler32);
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
+    use std::io::Write;
+
+    use flate2::{write::ZlibEncoder, Compression};
     use rstest::*;
 
-    use crate::circle::CircularBuffer;
+    use crate::circle::{crc32_combine, BufferSnapshot, CircularBuffer, CrcState, CRC32};
 
     #[rstest]
     pub fn test_get_normalized_buffer() {
@@ -155,6 +500,70 @@ mod test {
         assert_eq!(cb.get_normalized_buffer().unwrap(), expected);
     }
 
+    // long matches at distances either side of the wide copy cutoff, including ones longer than their distance
+    // and ones that wrap around the end of the buffer, come out the same as pushing the bytes one at a time.
+    #[rstest]
+    #[case::short_distance(5, 258)]
+    #[case::at_cutoff(16, 258)]
+    #[case::overlapping(20, 258)]
+    #[case::chunk_sized(32, 200)]
+    #[case::far_back(1000, 258)]
+    #[case::whole_window(32768, 258)]
+    pub fn test_push_from_buffer_long(#[case] lookback: u16, #[case] size: u16) {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let mut cb = CircularBuffer::new(32768);
+        let mut expected = CircularBuffer::new(32768);
+        cb.track_adler32();
+        expected.track_adler32();
+        for byte in text {
+            cb.push(*byte);
+            expected.push(*byte);
+        }
+        // enough matches to wrap around the buffer a few times.
+        for _ in 0..1000 {
+            cb.push_from_buffer(lookback, size).unwrap();
+            for _ in 0..size {
+                // the oldest of the last lookback bytes.
+                expected.push(expected.recent(lookback as usize)[0][0]);
+            }
+        }
+        assert_eq!(cb.get_normalized_buffer().unwrap(), expected.get_normalized_buffer().unwrap());
+        assert_eq!(cb.get_bytes_written(), expected.get_bytes_written());
+        assert_eq!(cb.counter(), expected.counter());
+        assert_eq!(cb.crc32(), expected.crc32());
+        assert_eq!(cb.block_crc32(), expected.block_crc32());
+        assert_eq!(cb.adler32(), expected.adler32());
+    }
+
+    #[rstest]
+    pub fn test_adler32() {
+        let mut cb = CircularBuffer::new(8);
+        cb.track_adler32();
+        for byte in b"Wikipedia" {
+            cb.push(*byte);
+        }
+        assert_eq!(cb.adler32(), 0x11E60398);
+        assert_eq!(cb.adler32(), 1);
+
+        // long enough that the sums have to be reduced along the way.
+        for _ in 0..100_000 {
+            cb.push(0xFF);
+        }
+        // zlib streams end with the Adler-32 of their contents.
+        let mut e = ZlibEncoder::new(Vec::new(), Compression::fast());
+        e.write_all(&[0xFF; 100_000]).unwrap();
+        let v = e.finish().unwrap();
+        let expected = u32::from_be_bytes(v[v.len() - 4..].try_into().unwrap());
+        assert_eq!(cb.adler32(), expected);
+
+        // only zlib needs it, so it's not kept unless asked for.
+        let mut cb = CircularBuffer::new(8);
+        for byte in b"Wikipedia" {
+            cb.push(*byte);
+        }
+        assert_eq!(cb.adler32(), 1);
+    }
+
     #[rstest]
     pub fn test_head() {
         let mut cb = CircularBuffer::new(8);
@@ -164,4 +573,137 @@ mod test {
         let v = cb.head(5).unwrap();
         assert_eq!(v, vec![3, 4, 5, 6, 7]);
     }
+
+    #[rstest]
+    #[case::all(5, 5, vec![3, 4, 5, 6, 7])]
+    #[case::some(5, 2, vec![3, 4])]
+    #[case::not_past_head(2, 5, vec![6, 7])]
+    #[case::wrapped(8, 8, vec![0, 1, 2, 3, 4, 5, 6, 7])]
+    pub fn test_copy_recent(#[case] back: usize, #[case] room: usize, #[case] expected: Vec<u8>) {
+        let mut cb = CircularBuffer::new(8);
+        for i in 0..8 {
+            cb.push(i);
+        }
+        let mut dest = vec![0; room];
+        let n = cb.copy_recent(back, &mut dest);
+        assert_eq!(&dest[..n], expected);
+    }
+
+    #[rstest]
+    #[case::nothing(0)]
+    #[case::some(3)]
+    #[case::all(8)]
+    pub fn test_recent(#[case] n: usize) {
+        // the head ends up everywhere in the buffer, so some of them wrap.
+        for pushed in 8..16u8 {
+            let mut cb = CircularBuffer::new(8);
+            for i in 0..pushed {
+                cb.push(i);
+            }
+            let expected: Vec<u8> = (pushed - n as u8..pushed).collect();
+            assert_eq!(cb.recent(n).concat(), expected);
+            assert_eq!(cb.head(n as u16).unwrap(), expected);
+        }
+    }
+
+    #[rstest]
+    pub fn test_load_window() {
+        let mut cb = CircularBuffer::new(8);
+        cb.load_window(&[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(cb.get_normalized_buffer().unwrap(), vec![2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(cb.get_bytes_written(), 0);
+    }
+
+    #[rstest]
+    pub fn test_get_window() {
+        let mut cb = CircularBuffer::new(8);
+        cb.push(1);
+        cb.push(2);
+        assert_eq!(cb.get_window().unwrap(), vec![1, 2]);
+        for i in 3..12 {
+            cb.push(i);
+        }
+        assert_eq!(cb.get_window().unwrap(), vec![4, 5, 6, 7, 8, 9, 10, 11]);
+        // a new member doesn't need any of it.
+        cb.forget_window();
+        assert_eq!(cb.get_window().unwrap(), Vec::<u8>::new());
+        cb.load_window(&[1, 2, 3]);
+        assert_eq!(cb.get_window().unwrap(), vec![1, 2, 3]);
+    }
+
+    #[rstest]
+    pub fn test_snapshot_restore() {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let (first, second) = text.split_at(12345);
+        let mut cb = CircularBuffer::new(32768);
+        cb.track_adler32();
+        for byte in first {
+            cb.push(*byte);
+        }
+        cb.block_crc32();
+        for byte in &second[..100] {
+            cb.push(*byte);
+        }
+        let snapshot = cb.snapshot().unwrap();
+
+        let mut restored = CircularBuffer::new(32768);
+        restored.track_adler32();
+        let BufferSnapshot { window, bytes_written, crc_state } = snapshot.clone();
+        restored.restore_from(&window, bytes_written, crc_state);
+        assert_eq!(restored.snapshot().unwrap(), snapshot);
+        for cb in [&mut cb, &mut restored] {
+            for byte in &second[100..] {
+                cb.push(*byte);
+            }
+        }
+        assert_eq!(restored.get_bytes_written(), text.len());
+        assert_eq!(restored.counter(), text.len() as u64);
+        assert_eq!(restored.crc32(), CRC32.checksum(text));
+        assert_eq!(restored.block_crc32(), CRC32.checksum(second));
+        assert_eq!(restored.adler32(), cb.adler32());
+        assert_eq!(restored.get_normalized_buffer().unwrap(), cb.get_normalized_buffer().unwrap());
+    }
+
+    #[rstest]
+    pub fn test_crc_state_at_block() {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let (first, second) = text.split_at(12345);
+        let mut cb = CircularBuffer::new(32768);
+        let crc_state = CrcState::at_block(CRC32.checksum(first), first.len() as u64);
+        cb.restore_from(first, first.len(), crc_state);
+        for byte in second {
+            cb.push(*byte);
+        }
+        assert_eq!(cb.counter(), text.len() as u64);
+        assert_eq!(cb.block_crc32(), CRC32.checksum(second));
+        assert_eq!(cb.crc32(), CRC32.checksum(text));
+    }
+
+    #[rstest]
+    pub fn test_reset() {
+        let mut cb = CircularBuffer::new(8);
+        for byte in b"hello world" {
+            cb.push(*byte);
+        }
+        cb.reset();
+        assert_eq!(cb.get_normalized_buffer().unwrap(), vec![0; 8]);
+        assert_eq!(cb.get_bytes_written(), 0);
+        assert_eq!(cb.counter(), 0);
+        assert_eq!(cb.crc32(), CRC32.checksum(b""));
+        assert_eq!(cb.adler32(), 1);
+    }
+
+    #[rstest]
+    #[case::empty_second(0)]
+    #[case::one(1)]
+    #[case::odd(12345)]
+    #[case::everything(39819)]
+    pub fn test_crc32_combine(#[case] split: usize) {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let (first, second) = text.split_at(text.len() - split);
+        assert_eq!(
+            crc32_combine(CRC32.checksum(first), CRC32.checksum(second), second.len() as u64),
+            CRC32.checksum(text)
+        );
+    }
 }
@@ -1,27 +1,5 @@
 const THIRTY_TWO_KILOBYTES: usize = 32768;
 
-// base lengths for codes from 257..=285
-static BASE_LENGTHS: [u16; 29] = [
-    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
-    163, 195, 227, 258,
-];
-
-/* Extra bits for length codes 257..=285 */
-static LENGTH_EXTRA_BITS: [u8; 29] = [
-    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
-];
-
-// base offsets for distance codes 0..=29
-static BASE_DISTS: [u16; 30] = [
-    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
-    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
-];
-
-static DIST_EXTRA_BITS: [u8; 30] = [
-    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
-    13,
-];
-
 static CODE_LENGTH_ORDER: [usize; 19] = [
     16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
 ];
@@ -29,28 +7,54 @@ static CODE_LENGTH_ORDER: [usize; 19] = [
 const MAX_SYMBOL_CODES: usize = 286;
 const MAX_DISTANCE_CODES: usize = 30;
 
-use std::cmp::min;
-use std::io::{Error, ErrorKind, Read};
+use std::borrow::Cow;
+use std::cmp::{min, Ordering};
+use std::io::{BufRead, Error, IoSliceMut, Read, Seek};
+#[cfg(feature = "sqlite")]
+use std::path::Path;
 use std::mem::{self, discriminant};
+use std::ops::Range;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
-use crate::checkpoint::Checkpointer;
-use crate::header::read_header;
-use crate::huffman::MAX_HUFFMAN_BITS;
+use crate::cancel::CancelToken;
+use crate::checkpoint::{Checkpointer, OpenBlock};
+#[cfg(feature = "sqlite")]
+use crate::checkpoint::CreateMode;
+use crate::format::{detect_format, Format};
+use crate::header::{read_header_unchecked, GzipHeader, HeaderCrcMismatch};
+use crate::huffman::{code_space, decode_trees, encode_trees, HuffmanCode, MAX_HUFFMAN_BITS, UNUSED_SYMBOL};
+use crate::stats::Stats;
 use crate::{
-    circle::CircularBuffer, errors::CorniferError, huffman::HuffmanTree, reader::CorniferByteReader,
+    circle::{crc32_combine, BufferSnapshot, CircularBuffer},
+    errors::{CorniferError, ErrorKind},
+    huffman::HuffmanTree,
+    reader::CorniferByteReader,
 };
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BlockType {
     NoCompression,
     FixedHuffman,
     DynamicHuffman,
 }
 
+// ID1, ID2 and CM (8 for DEFLATE): how every gzip member starts, for finding the next one after a corrupt one.
+const GZIP_MAGIC: [u8; 3] = [0x1f, 0x8b, 8];
+
+// max_expansion_ratio isn't checked before this much output, since a few bytes in can be a long run of zeros.
+const MIN_OUTPUT_FOR_RATIO: u64 = 1 << 20;
+
+// fixed blocks borrow the trees that are built once, dynamic blocks have their own.
+pub type Tree = Cow<'static, HuffmanTree>;
+
 #[derive(PartialEq)]
 pub enum DeflatorState {
     // read a GZIP member header.
     GZIPHeader,
+    // read the two byte zlib header.
+    ZlibHeader,
     // read a DEFLATE block header. This tells us if it's the final block; and what type of block it is.
     BlockHeader,
     // read header of non-compressed block (BTYPE=00), which tells us how many bytes to read.
@@ -63,24 +67,287 @@ pub enum DeflatorState {
     PrepareDynamicBlock,
     // if BTYPE=01, or BTYPE=10, decode the input stream.
     DecodeBlock {
-        symbol_tree: HuffmanTree,
-        distance_tree: HuffmanTree
+        symbol_tree: Tree,
+        distance_tree: Tree,
     },
     // copy bytes from the buffer to the output.
     WriteLookback {
         current: u16,
         len: u16,
-        symbol_tree: HuffmanTree,
-        distance_tree: HuffmanTree,
+        symbol_tree: Tree,
+        distance_tree: Tree,
     },
     // state that checks if we're in the final block.
     CheckIfFinalBlock,
     // read GZIP CRC and ISIZE
     GZIPFooter,
+    // read the zlib Adler-32
+    ZlibFooter,
     // we're done.
     Done,
 }
 
+/// A `DeflatorState`, without the trees, which go in `SavedState::trees`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SavedDeflatorState {
+    GZIPHeader,
+    ZlibHeader,
+    BlockHeader,
+    PrepareNonCompressedBlock,
+    NonCompressedBlock { len: u16 },
+    PrepareDynamicBlock,
+    DecodeBlock,
+    WriteLookback { current: u16, len: u16 },
+    CheckIfFinalBlock,
+    GZIPFooter,
+    ZlibFooter,
+    Done,
+}
+
+/// A decode in progress, from `Deflator::save_state`, to carry on with `Deflator::load_state`: e.g. after the
+/// process doing a long indexing job has been stopped and started again.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SavedState {
+    pub state: SavedDeflatorState,
+    pub format: Format,
+    /// Where decoding carries on from: `from_bit` bits into byte `from_byte` of the compressed file.
+    pub from_byte: usize,
+    pub from_bit: u8,
+    /// The window, and the counters and checksums the footers are checked against.
+    pub buffer: BufferSnapshot,
+    /// In the middle of a compressed block, the trees it's coded with, packed by `huffman::encode_trees`.
+    pub trees: Option<Vec<u8>>,
+    pub in_final_block: bool,
+    /// In the middle of a block, what the checkpointer needs to finish its checkpoint.
+    pub block: Option<OpenBlock>,
+    pub members: Vec<GzipMember>,
+    /// How many members have been finished, whether or not `members` kept them.
+    pub member_count: usize,
+    /// Where the member we're in started, its header, and whether the header CRC matched.
+    pub current_member: Option<(usize, GzipHeader, bool)>,
+    pub member_from_byte: usize,
+    pub block_to_byte: Option<usize>,
+    pub gaps: Vec<Gap>,
+    pub last_checkpoint_to_byte: usize,
+    pub total_crc32: u32,
+    pub finalize_on_done: bool,
+    /// Output that was decoded ahead for small reads (see `Deflator::set_staging_size`) and not read yet. It's
+    /// read first.
+    pub staged: Vec<u8>,
+}
+
+/// What to do with bytes after the last gzip member that aren't the start of another member,
+/// e.g. zero padding out to a tape block.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum TrailingData {
+    /// Stop decoding, as if the input ended after the last member.
+    Ignore,
+    /// Fail with `CorniferError::TrailingData`.
+    #[default]
+    Error,
+    /// Stop decoding, and keep the bytes so they can be had from `Deflator::trailing_data()`.
+    Return,
+}
+
+impl FromStr for TrailingData {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ignore" => Ok(TrailingData::Ignore),
+            "error" => Ok(TrailingData::Error),
+            "return" => Ok(TrailingData::Return),
+            _ => Err(format!("unknown trailing data policy {s}, expected one of ignore, error, return")),
+        }
+    }
+}
+
+/// How to treat a gzip member whose footer doesn't match what we decoded.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum FooterChecks {
+    /// Fail with `CorniferError::InvalidGZIPCRC` or `CorniferError::InvalidGZIPIsize`.
+    #[default]
+    Strict,
+    /// Keep going, but record the mismatch in the member's `GzipMember` (see `DeflatorOptions::keep_members`).
+    Lenient,
+    /// Like Lenient, but also warn about it with a `DecodeEvent::FooterMismatch` (and a tracing event).
+    Warn,
+}
+
+impl FromStr for FooterChecks {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "strict" => Ok(FooterChecks::Strict),
+            "lenient" => Ok(FooterChecks::Lenient),
+            "warn" => Ok(FooterChecks::Warn),
+            _ => Err(format!("unknown footer check policy {s}, expected one of strict, lenient, warn")),
+        }
+    }
+}
+
+/// How to treat a gzip member whose header CRC (FHCRC) doesn't match its header. Some gzip writers get it wrong,
+/// and the CRC only covers the header, so the data can still be fine.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum HeaderValidation {
+    /// Fail with `CorniferError::InvalidHeaderCRC`.
+    #[default]
+    Strict,
+    /// Keep going, but warn about it with a `DecodeEvent::HeaderCrcMismatch` (and a tracing event), and record
+    /// it in the member's `GzipMember`.
+    Warn,
+    /// Keep going, and only record it in the member's `GzipMember`.
+    Ignore,
+}
+
+impl FromStr for HeaderValidation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "strict" => Ok(HeaderValidation::Strict),
+            "warn" => Ok(HeaderValidation::Warn),
+            "ignore" => Ok(HeaderValidation::Ignore),
+            _ => Err(format!("unknown header check policy {s}, expected one of strict, warn, ignore")),
+        }
+    }
+}
+
+/// Knobs for how strict the Deflator is about its input.
+#[derive(Debug, Clone, Default)]
+pub struct DeflatorOptions {
+    pub trailing_data: TrailingData,
+    pub footer_checks: FooterChecks,
+    pub header_checks: HeaderValidation,
+    /// After a gzip member that's corrupt, carry on from the next one instead of failing. The part that was
+    /// skipped is recorded as a `Gap`.
+    pub recover: bool,
+    /// Stop with `OutputLimitExceeded` once this many bytes have been decompressed.
+    pub max_output_bytes: Option<u64>,
+    /// Stop with `OutputLimitExceeded` once the output is this many times bigger than the input read so far.
+    /// Only checked after the first MiB of output, since the start of a stream can expand a lot.
+    pub max_expansion_ratio: Option<f64>,
+    /// Stop with `DeadlineExceeded` once decoding has taken longer than this, counting from the first read. It's
+    /// checked at the same places as the cancel token, so everything checkpointed so far is complete.
+    pub deadline: Option<Duration>,
+    /// Reject streams that break RFC 1951 in ways most decoders let go: more than 286 literal/length or 30 distance
+    /// codes, Huffman codes that don't use all their bit patterns, no end-of-block code, and distances from before
+    /// the start of the member. zlib rejects these too. Over-subscribed codes and repeats past the end of the code
+    /// lengths are rejected either way.
+    pub strict: bool,
+    /// Keep every finished gzip member for `Deflator::members`. Off by default, since a file of lots of small
+    /// members (e.g. a rotated log) would keep all of them in memory; they're in the checkpoint file and the
+    /// `DecodeEvent::MemberEnded` events either way, and `Deflator::member_count` is always kept.
+    pub keep_members: bool,
+}
+
+/// How far decoding has got, in both the compressed input and the decompressed output.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StreamPosition {
+    pub compressed_byte: usize,
+    pub compressed_bit: u8,
+    pub uncompressed_byte: usize,
+}
+
+/// A gzip member we've finished decoding.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GzipMember {
+    /// Where the member starts in the compressed file.
+    pub from_byte: usize,
+    /// Where the member starts in the decompressed output.
+    pub to_byte: usize,
+    pub header: GzipHeader,
+    pub crc32: u32,
+    /// The true length of the decompressed member. The footer only has this modulo 2^32.
+    pub len: u64,
+    pub crc32_matches: bool,
+    pub isize_matches: bool,
+    /// Whether the header CRC matched the header. True if there wasn't one.
+    pub hcrc_matches: bool,
+}
+
+/// Part of a gzip file that was skipped over because it was corrupt. See `DeflatorOptions::recover`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Gap {
+    /// Where the corrupt member starts in the compressed file.
+    pub from_byte: usize,
+    /// Where the next member starts, or the end of the file.
+    pub to_byte: usize,
+    /// What the corrupt member's last block gave before the error. It's in the output, but there's no checkpoint
+    /// to read it back from. Empty if the error was between blocks.
+    pub uncompressed_from: usize,
+    pub uncompressed_to: usize,
+    /// What was wrong with the member.
+    pub error: String,
+}
+
+/// Where the Deflator is, for deciding whether to emit a tick there. See `Deflator::set_tick_trigger`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TickContext {
+    /// The byte and bit of the compressed stream the next symbol starts at.
+    pub from_byte: usize,
+    pub from_bit: u8,
+    /// How much has been decompressed so far.
+    pub to_byte: usize,
+    /// How much has been decompressed since the last block or tick checkpoint.
+    pub since_checkpoint: usize,
+    /// The last byte decompressed, e.g. to only tick after a newline.
+    pub last_byte: u8,
+}
+
+/// How far apart checkpoints that decoding can start from should be, in decompressed bytes. Blocks closer than
+/// `min` to the last one with a window don't get theirs, and blocks bigger than `max` get ticks, so however big
+/// or small the compressor made the blocks, there's a checkpoint within `max` before any byte. Ticks are at
+/// least `max - min` apart, but can be closer than `min` to the next block. `CheckpointedReader` starts decoding
+/// from the last tick before a read, so reads inside a big block don't decode it all. `min` has to be less than
+/// `max`. See `Deflator::set_checkpoint_spacing`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct CheckpointSpacing {
+    pub min: u64,
+    pub max: u64,
+}
+
+impl Default for CheckpointSpacing {
+    fn default() -> Self {
+        Self {
+            min: 64 << 10,
+            max: 4 << 20,
+        }
+    }
+}
+
+/// Something that happened while decoding, for callers using plain `Read` that still want to know where the
+/// blocks and members are. See `Deflator::record_events`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "event", rename_all = "snake_case"))]
+pub enum DecodeEvent {
+    /// A gzip member's header was read. The position is where the header starts.
+    MemberStarted { position: StreamPosition },
+    /// A gzip member's footer was read. The position is just after it.
+    MemberEnded { position: StreamPosition, len: u64, crc32: u32 },
+    /// A DEFLATE block's header was read. The position is where the header starts.
+    BlockStarted { position: StreamPosition, block_type: BlockType },
+    /// The end of a DEFLATE block was reached. The position is just after it.
+    BlockEnded { position: StreamPosition },
+    /// A gzip member's header CRC was wrong, and `HeaderValidation::Warn` said to keep going. The position is where
+    /// the header starts.
+    HeaderCrcMismatch { position: StreamPosition, expected: u16, found: u16 },
+    /// A gzip member's footer didn't match, and `FooterChecks::Warn` said to keep going. The position is just after
+    /// the footer.
+    FooterMismatch { position: StreamPosition, crc32_matches: bool, isize_matches: bool },
+    /// A corrupt gzip member was skipped, and `DeflatorOptions::recover` said to keep going.
+    Skipped { gap: Gap },
+}
+
+type TickTrigger = Box<dyn FnMut(&TickContext) -> bool + Send>;
+
 #[derive(Debug, PartialEq)]
 pub struct BlockHeader {
     block_type: BlockType,
@@ -91,21 +358,645 @@ pub struct Deflator<R> {
     pub buffer: CircularBuffer,
     state: DeflatorState,
     in_final_block: bool,
+    // gzip or zlib, which decides what wraps the DEFLATE blocks.
+    format: Format,
+    options: DeflatorOptions,
+    // gzip members we've finished, with DeflatorOptions::keep_members, and how many there have been either way.
+    members: Vec<GzipMember>,
+    member_count: usize,
+    // start and header of the member we're in the middle of.
+    // where the member started, its header, and whether the header CRC matched.
+    current_member: Option<(usize, GzipHeader, bool)>,
+    // where the last member header we tried to read starts, even if it didn't work out.
+    member_from_byte: usize,
+    // where the block we're in started in the output, until it's finished.
+    block_to_byte: Option<usize>,
+    // the parts of the file skipped because they were corrupt.
+    gaps: Vec<Gap>,
+    // set when a read fails partway through, after some bytes were already written.
+    deferred_error: Option<CorniferError>,
+    cancel_token: Option<CancelToken>,
+    // when options.deadline runs out, from the first read.
+    deadline_at: Option<Instant>,
+    tick_trigger: Option<TickTrigger>,
+    // where in the output the last block or tick checkpoint was.
+    last_checkpoint_to_byte: usize,
+    // crc32 of everything up to the end of the last member.
+    total_crc32: u32,
+    // whether to call the checkpointer's finalize once we're done. Not if we started partway through the file, or
+    // if we already have.
+    finalize_on_done: bool,
+    // spans for the member and block we're in. They're entered for each state transition, since the
+    // member or block usually lasts longer than one call to read.
+    #[cfg(feature = "tracing")]
+    member_span: tracing::Span,
+    #[cfg(feature = "tracing")]
+    block_span: tracing::Span,
+    // whatever came after the last member, if we were asked to keep it.
+    trailing_data: Option<Vec<u8>>,
+    // events since the last take_events, if we're recording them.
+    events: Option<Vec<DecodeEvent>>,
+    // what's been in the blocks so far, if we're collecting it.
+    stats: Option<Stats>,
+    // whether the reader's CRC is of each block's compressed bytes, for the checkpointer.
+    hash_blocks: bool,
+    // output decoded ahead for reads smaller than staging_size, with staged the part that hasn't been read yet.
+    staging: Vec<u8>,
+    staged: Range<usize>,
+    staging_size: usize,
     reader: CorniferByteReader<R>,
     checkpointer: Checkpointer,
 }
 
+impl<R: BufRead> Deflator<R> {
+    /// Create a Deflator for whichever wrapper format the reader turns out to contain.
+    pub fn new_auto(mut reader: R, checkpointer: Checkpointer) -> Result<Self, CorniferError> {
+        let format = detect_format(&mut reader)?;
+        Self::with_format(CorniferByteReader::new(reader), checkpointer, format)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl<R: Read + Seek> Deflator<R> {
+    /// Carry on from where a gzip file's checkpoint file stops, to add checkpoints for the members appended to the
+    /// file since (e.g. a log that's rotated into it). Reading the Deflator to the end writes them, and `members`
+    /// only has the new ones.
+    ///
+    /// Fails with `CorniferError::FileChanged` if the last member the checkpoint file knows about isn't still there.
+    pub fn update<P: AsRef<Path>>(source: R, index: P) -> Result<Self, CorniferError> {
+        let index = index.as_ref();
+        let end = crate::store::CheckpointStore::open(index)?.end()?;
+        let mut reader = CorniferByteReader::new(source);
+        // the last member's footer should still be where it was.
+        let footer_byte = end.compressed_byte - 8;
+        let changed = || CorniferError::FileChanged { position: footer_byte };
+        reader.seek_to_bit(footer_byte, 0).map_err(|_| changed())?;
+        let crc32 = reader.read_u32_le().map_err(|_| changed())?;
+        let isize = reader.read_u32_le().map_err(|_| changed())?;
+        if crc32 != end.last_member.crc32 || isize as u64 != end.last_member.len % (1 << 32) {
+            return Err(changed());
+        }
+        let checkpointer = Checkpointer::open(index, CreateMode::Append)?;
+        let mut deflator = Self::new(reader, checkpointer);
+        deflator.buffer.set_bytes_written(end.uncompressed_len as usize);
+        deflator.total_crc32 = end.crc32;
+        Ok(deflator)
+    }
+
+    /// Carry on from where a gzip file's checkpoint file stops when it was never finished, e.g. because indexing
+    /// was killed partway, instead of indexing the whole file again. The rows that weren't finished are removed,
+    /// and so is the last block that was, since it's decoded again to get going. Reading the Deflator to the end
+    /// writes the rest, and marks the checkpoint file as complete.
+    ///
+    /// If it's already complete, there's nothing to do and the Deflator is at the end.
+    pub fn repair<P: AsRef<Path>>(mut source: R, index: P) -> Result<Self, CorniferError> {
+        let index = index.as_ref();
+        let store = crate::store::CheckpointStore::open(index)?;
+        let meta = store.meta()?;
+        let blocks = store.blocks()?;
+        let gaps = store.gaps()?;
+        source.seek(std::io::SeekFrom::Start(0))?;
+        let mut magic = [0; 3];
+        if source.read_exact(&mut magic).is_err() || magic != GZIP_MAGIC {
+            return Err(CorniferError::NotGzipIndex);
+        }
+        if meta.complete {
+            // opening it to write to would mark it as incomplete.
+            let mut deflator = Self::new(CorniferByteReader::new(source), Checkpointer::none());
+            deflator.buffer.set_bytes_written(meta.uncompressed_len.unwrap_or(0) as usize);
+            deflator.total_crc32 = meta.crc32.unwrap_or(0);
+            deflator.state = DeflatorState::Done;
+            deflator.finalize_on_done = false;
+            return Ok(deflator);
+        }
+
+        // start again at the last block, which needs the CRC of its member so far for the footer, and the window
+        // before it if that isn't stored.
+        let last = blocks.len().checked_sub(1);
+        let member = last.and_then(|last| blocks[..=last].iter().rposition(|b| b.member_start));
+        let (Some(last), Some(member)) = (last, member) else {
+            // nothing to carry on from, e.g. it's from before blocks said whether they start a member.
+            let mut checkpointer = Checkpointer::open(index, CreateMode::Append)?;
+            checkpointer.truncate(0, 0)?;
+            source.seek(std::io::SeekFrom::Start(0))?;
+            return Ok(Self::new(CorniferByteReader::new(source), checkpointer));
+        };
+        let (member_start, block) = (&blocks[member], &blocks[last]);
+        let window = match (block.member_start, block.has_window) {
+            (true, _) => Vec::new(),
+            (false, true) => store.get_window(block.id)?,
+            (false, false) => {
+                let from = block.to_byte.saturating_sub(THIRTY_TWO_KILOBYTES as u64).max(member_start.to_byte);
+                let mut reader = crate::checkpointed::CheckpointedReader::open(&mut source, index)?;
+                reader.seek(std::io::SeekFrom::Start(from))?;
+                let mut window = vec![0; (block.to_byte - from) as usize];
+                reader.read_exact(&mut window)?;
+                window
+            }
+        };
+        let (mut crc32, mut total_crc32) = (0, 0);
+        for b in &blocks[..member] {
+            total_crc32 = crc32_combine(total_crc32, b.crc32, b.len);
+        }
+        for b in &blocks[member..last] {
+            crc32 = crc32_combine(crc32, b.crc32, b.len);
+        }
+        // the member's header comes after the footer of the one before, or after a gap. Reading it again means the
+        // member is recorded when it ends, as it would have been.
+        let header_from = match member.checked_sub(1).map(|i| &blocks[i]) {
+            Some(prev) => (prev.from_byte * 8 + prev.from_bit as u64 + prev.block_len_bits).div_ceil(8) + 8,
+            None => 0,
+        };
+        let header_from = gaps
+            .iter()
+            .map(|g| g.to_byte)
+            .filter(|b| *b <= member_start.from_byte)
+            .fold(header_from, u64::max);
+        drop(store);
+
+        let mut checkpointer = Checkpointer::open(index, CreateMode::Append)?;
+        checkpointer.truncate(block.id, header_from)?;
+        let mut reader = CorniferByteReader::new(source);
+        reader.seek_to_bit(header_from, 0)?;
+        let header = read_header_unchecked(&mut reader).ok();
+        let header = header.filter(|_| reader.current_byte as u64 == member_start.from_byte);
+        reader.seek_to_bit(block.from_byte, block.from_bit)?;
+        let mut deflator = Self::new(reader, checkpointer);
+        let crc_state = crate::circle::CrcState::at_block(crc32, block.to_byte - member_start.to_byte);
+        deflator.buffer.restore_from(&window, block.to_byte as usize, crc_state);
+        deflator.last_checkpoint_to_byte = block.to_byte as usize;
+        deflator.total_crc32 = total_crc32;
+        deflator.member_from_byte = header_from as usize;
+        deflator.current_member = header.map(|(header, mismatch)| (header_from as usize, header, mismatch.is_none()));
+        deflator.state = DeflatorState::BlockHeader;
+        Ok(deflator)
+    }
+}
+
+impl<R: Read + Seek> Deflator<R> {
+    /// Carry on decoding from where `save_state` left off. `source` is the same compressed file, from the start. The
+    /// checkpointer carries on writing the checkpoint file the state was saved with, opened with
+    /// `CreateMode::Append`, or is `Checkpointer::none`.
+    pub fn load_state(source: R, saved: SavedState, checkpointer: Checkpointer) -> Result<Self, CorniferError> {
+        let mut reader = CorniferByteReader::new(source);
+        reader.seek_to_bit(saved.from_byte as u64, saved.from_bit)?;
+        let mut trees = saved.trees.as_deref().map(decode_trees).transpose()?;
+        let mut trees = || match trees.take() {
+            Some((symbol_tree, distance_tree)) => Ok((Cow::Owned(symbol_tree), Cow::Owned(distance_tree))),
+            None => Err(CorniferError::InvalidHuffmanTrees),
+        };
+        let state = match saved.state {
+            SavedDeflatorState::GZIPHeader => DeflatorState::GZIPHeader,
+            SavedDeflatorState::ZlibHeader => DeflatorState::ZlibHeader,
+            SavedDeflatorState::BlockHeader => DeflatorState::BlockHeader,
+            SavedDeflatorState::PrepareNonCompressedBlock => DeflatorState::PrepareNonCompressedBlock,
+            SavedDeflatorState::NonCompressedBlock { len } => DeflatorState::NonCompressedBlock { len },
+            SavedDeflatorState::PrepareDynamicBlock => DeflatorState::PrepareDynamicBlock,
+            SavedDeflatorState::DecodeBlock => {
+                let (symbol_tree, distance_tree) = trees()?;
+                DeflatorState::DecodeBlock {
+                    symbol_tree,
+                    distance_tree,
+                }
+            }
+            SavedDeflatorState::WriteLookback { current, len } => {
+                let (symbol_tree, distance_tree) = trees()?;
+                DeflatorState::WriteLookback {
+                    current,
+                    len,
+                    symbol_tree,
+                    distance_tree,
+                }
+            }
+            SavedDeflatorState::CheckIfFinalBlock => DeflatorState::CheckIfFinalBlock,
+            SavedDeflatorState::GZIPFooter => DeflatorState::GZIPFooter,
+            SavedDeflatorState::ZlibFooter => DeflatorState::ZlibFooter,
+            SavedDeflatorState::Done => DeflatorState::Done,
+        };
+        let mut deflator = Self::new(reader, checkpointer);
+        if saved.format == Format::Zlib {
+            deflator.buffer.track_adler32();
+        }
+        let BufferSnapshot {
+            window,
+            bytes_written,
+            crc_state,
+        } = saved.buffer;
+        deflator.buffer.restore_from(&window, bytes_written, crc_state);
+        if let Some(block) = saved.block {
+            deflator.checkpointer.resume_block(block);
+        }
+        deflator.state = state;
+        deflator.format = saved.format;
+        deflator.in_final_block = saved.in_final_block;
+        deflator.members = saved.members;
+        deflator.member_count = saved.member_count;
+        deflator.current_member = saved.current_member;
+        deflator.member_from_byte = saved.member_from_byte;
+        deflator.block_to_byte = saved.block_to_byte;
+        deflator.gaps = saved.gaps;
+        deflator.last_checkpoint_to_byte = saved.last_checkpoint_to_byte;
+        deflator.total_crc32 = saved.total_crc32;
+        deflator.finalize_on_done = saved.finalize_on_done;
+        deflator.staged = 0..saved.staged.len();
+        deflator.staging = saved.staged;
+        Ok(deflator)
+    }
+}
+
+// like ?, but breaks out of the loop with the error instead of returning it.
+macro_rules! or_break {
+    ($e:expr) => {
+        match $e {
+            Ok(value) => value,
+            Err(err) => break Err(err),
+        }
+    };
+}
+
 impl<R: Read> Deflator<R> {
     pub fn new(reader: CorniferByteReader<R>, checkpointer: Checkpointer) -> Self {
         Self {
             buffer: CircularBuffer::new(THIRTY_TWO_KILOBYTES),
             state: DeflatorState::GZIPHeader,
             in_final_block: false,
+            format: Format::Gzip,
+            options: DeflatorOptions::default(),
+            members: Vec::new(),
+            member_count: 0,
+            current_member: None,
+            member_from_byte: 0,
+            block_to_byte: None,
+            gaps: Vec::new(),
+            deferred_error: None,
+            cancel_token: None,
+            deadline_at: None,
+            tick_trigger: None,
+            last_checkpoint_to_byte: 0,
+            total_crc32: 0,
+            finalize_on_done: true,
+            #[cfg(feature = "tracing")]
+            member_span: tracing::Span::none(),
+            #[cfg(feature = "tracing")]
+            block_span: tracing::Span::none(),
+            trailing_data: None,
+            events: None,
+            stats: None,
+            hash_blocks: false,
+            staging: Vec::new(),
+            staged: 0..0,
+            staging_size: 0,
             reader,
             checkpointer,
         }
     }
 
+    pub fn set_options(&mut self, options: DeflatorOptions) {
+        self.options = options;
+        self.deadline_at = None;
+    }
+
+    /// Carry on decoding from a DEFLATE block recorded in a checkpoint, instead of from the start of the file.
+    ///
+    ///  * reader - positioned at the byte the block starts in.
+    ///  * from_bit - how many bits of that byte come before the block.
+    ///  * window - the output before the block, as stored in the checkpoint (decompressed).
+    ///
+    /// The member's CRC and ISIZE can't be checked from the middle, so footer checks are lenient.
+    pub fn resume(
+        mut reader: CorniferByteReader<R>,
+        from_bit: u8,
+        window: &[u8],
+        checkpointer: Checkpointer,
+    ) -> Result<Self, CorniferError> {
+        if from_bit > 0 {
+            reader.read_n_bits_le(from_bit)?;
+        }
+        let mut deflator = Self::new(reader, checkpointer);
+        deflator.buffer.load_window(window);
+        deflator.state = DeflatorState::BlockHeader;
+        deflator.options.footer_checks = FooterChecks::Lenient;
+        deflator.finalize_on_done = false;
+        Ok(deflator)
+    }
+
+    /// Like resume, but with the CRCs and counters from a snapshot of the buffer taken at the start of the block,
+    /// so the footer can still be checked.
+    pub fn resume_from_checkpoint(
+        mut reader: CorniferByteReader<R>,
+        from_bit: u8,
+        snapshot: &BufferSnapshot,
+        checkpointer: Checkpointer,
+    ) -> Result<Self, CorniferError> {
+        if from_bit > 0 {
+            reader.read_n_bits_le(from_bit)?;
+        }
+        let mut deflator = Self::new(reader, checkpointer);
+        deflator
+            .buffer
+            .restore_from(&snapshot.window, snapshot.bytes_written, snapshot.crc_state);
+        deflator.last_checkpoint_to_byte = snapshot.bytes_written;
+        deflator.state = DeflatorState::BlockHeader;
+        deflator.finalize_on_done = false;
+        Ok(deflator)
+    }
+
+    /// Carry on decoding from a tick in the middle of a DEFLATE block, with the trees the block was coded with
+    /// (see `CheckpointStore::get_trees`).
+    ///
+    ///  * reader - positioned at the byte the tick is in.
+    ///  * from_bit - how many bits of that byte come before the tick.
+    ///  * window - the output before the tick, as stored with it (decompressed).
+    ///  * is_final - whether the block is the last in its member, i.e. the first bit of its header.
+    ///
+    /// Like resume, footer checks are lenient.
+    pub fn resume_in_block(
+        reader: CorniferByteReader<R>,
+        from_bit: u8,
+        window: &[u8],
+        (symbol_tree, distance_tree): (HuffmanTree, HuffmanTree),
+        is_final: bool,
+        checkpointer: Checkpointer,
+    ) -> Result<Self, CorniferError> {
+        let mut deflator = Self::resume(reader, from_bit, window, checkpointer)?;
+        deflator.in_final_block = is_final;
+        deflator.state = DeflatorState::DecodeBlock {
+            symbol_tree: Cow::Owned(symbol_tree),
+            distance_tree: Cow::Owned(distance_tree),
+        };
+        Ok(deflator)
+    }
+
+    /// Start again on a new stream of the same format, keeping the window's allocation, the options, the cancel token,
+    /// the tick trigger and the checkpointer. Gives back the old reader, like flate2's reset. Stats, if they're being
+    /// collected, start again from nothing.
+    ///
+    /// Checkpoints for the new stream go to the same checkpointer; use replace_checkpointer if they shouldn't.
+    pub fn reset(&mut self, reader: R) -> R {
+        let old = mem::replace(&mut self.reader, CorniferByteReader::new(reader));
+        self.buffer.reset();
+        self.state = match self.format {
+            Format::Zlib => DeflatorState::ZlibHeader,
+            _ => DeflatorState::GZIPHeader,
+        };
+        self.in_final_block = false;
+        self.members.clear();
+        self.member_count = 0;
+        self.member_from_byte = 0;
+        self.current_member = None;
+        self.block_to_byte = None;
+        self.gaps.clear();
+        self.deferred_error = None;
+        self.deadline_at = None;
+        self.last_checkpoint_to_byte = 0;
+        self.total_crc32 = 0;
+        self.finalize_on_done = true;
+        if let Some(stats) = self.stats.as_mut() {
+            *stats = Stats::default();
+        }
+        #[cfg(feature = "tracing")]
+        {
+            self.member_span = tracing::Span::none();
+            self.block_span = tracing::Span::none();
+        }
+        self.trailing_data = None;
+        if let Some(events) = self.events.as_mut() {
+            events.clear();
+        }
+        self.staged = 0..0;
+        old.into_inner()
+    }
+
+    /// Give back the reader, just after the last byte the Deflator read. Anything decoded ahead for small reads (see
+    /// set_staging_size) is dropped.
+    pub fn into_inner(self) -> R {
+        self.reader.into_inner()
+    }
+
+    /// Swap in another checkpointer, giving back the old one.
+    pub fn replace_checkpointer(&mut self, checkpointer: Checkpointer) -> Checkpointer {
+        mem::replace(&mut self.checkpointer, checkpointer)
+    }
+
+    /// Stop decoding once the token is cancelled. This is checked at the start of each block and member, so every
+    /// checkpoint in the database is complete when `CorniferError::Cancelled` comes back.
+    pub fn set_cancel_token(&mut self, token: CancelToken) {
+        self.cancel_token = Some(token);
+    }
+
+    /// Ask the trigger, between each pair of symbols in a compressed block, whether a tick should be emitted
+    /// there. Ticks are checkpoints in the middle of a block, for random access inside blocks that are big.
+    pub fn set_tick_trigger<F>(&mut self, trigger: F)
+    where
+        F: FnMut(&TickContext) -> bool + Send + 'static,
+    {
+        self.tick_trigger = Some(Box::new(trigger));
+    }
+
+    /// Space the checkpoints out by how much they decompress to, rather than one at every block. This sets the
+    /// checkpointer's window spacing and replaces the tick trigger. Fails if `min` isn't less than `max`.
+    pub fn set_checkpoint_spacing(&mut self, spacing: CheckpointSpacing) -> Result<(), CorniferError> {
+        if spacing.min >= spacing.max {
+            return Err(CorniferError::InvalidCheckpointSpacing {
+                min: spacing.min,
+                max: spacing.max,
+            });
+        }
+        self.checkpointer.set_window_spacing(spacing.min);
+        // a block without a window is decoded from up to `min` before it, so ticks make up the difference.
+        let every = (spacing.max - spacing.min) as usize;
+        self.set_tick_trigger(move |context| context.since_checkpoint >= every);
+        Ok(())
+    }
+
+    /// Keep a list of where each member and block starts and ends as they're decoded, to be collected with
+    /// take_events. Nothing is recorded unless this is called, since the list grows until it's taken.
+    pub fn record_events(&mut self) {
+        self.events.get_or_insert_with(Vec::new);
+    }
+
+    /// The events since the last call, oldest first. Always empty unless record_events was called.
+    pub fn take_events(&mut self) -> Vec<DecodeEvent> {
+        self.events.as_mut().map(mem::take).unwrap_or_default()
+    }
+
+    fn push_event(&mut self, event: DecodeEvent) {
+        if let Some(events) = self.events.as_mut() {
+            events.push(event);
+        }
+    }
+
+    /// Count the blocks, literals and matches from here on, to be looked at with `stats`. Off by default, since
+    /// it keeps a little for every block.
+    pub fn collect_stats(&mut self) {
+        self.stats.get_or_insert_with(Stats::default);
+    }
+
+    /// Record the CRC32 of the compressed bytes of each DEFLATE block from here on in the checkpoint file, so the
+    /// compressed file can be checked without decompressing it, see `CheckpointedReader::verify_compressed`. Off by
+    /// default, since it slows decoding down a little.
+    pub fn hash_blocks(&mut self) {
+        self.hash_blocks = true;
+    }
+
+    /// Decode at least this much at a time, and hand it out to reads smaller than that as they come. One state
+    /// transition only gets as far as the next match or block boundary, so reading a few bytes at a time straight
+    /// from the decoder spends most of its time going in and out of it. 32KiB is plenty.
+    ///
+    /// Off (0) by default, since decoding ahead also moves `position`, the window in `buffer`, the checkpoints, and
+    /// where a cancel or deadline stops, up to this far past what's been read.
+    pub fn set_staging_size(&mut self, size: usize) {
+        self.staging_size = size;
+    }
+
+    /// What's been decoded since collect_stats was called, if it was.
+    pub fn stats(&self) -> Option<&Stats> {
+        self.stats.as_ref()
+    }
+
+    /// For writing anything else to the checkpoint database once decoding is done.
+    pub fn checkpointer_mut(&mut self) -> &mut Checkpointer {
+        &mut self.checkpointer
+    }
+
+    /// The gzip members decoded so far. Empty unless `DeflatorOptions::keep_members` is set.
+    pub fn members(&self) -> &[GzipMember] {
+        &self.members
+    }
+
+    /// How many gzip members have been decoded so far.
+    pub fn member_count(&self) -> usize {
+        self.member_count
+    }
+
+    /// The parts of the file skipped so far, with `DeflatorOptions::recover`.
+    pub fn gaps(&self) -> &[Gap] {
+        &self.gaps
+    }
+
+    /// The CRC32 of everything decompressed, as of the end of the last member. Once the whole file has been read,
+    /// this goes in the checkpoint database along with its length.
+    pub fn total_crc32(&self) -> u32 {
+        self.total_crc32
+    }
+
+    /// Where decoding has got to. After an error, that's about where the problem is. With a staging size, reads can
+    /// be behind this by up to that much.
+    pub fn position(&self) -> StreamPosition {
+        StreamPosition {
+            compressed_byte: self.reader.current_byte,
+            compressed_bit: self.reader.current_bit,
+            uncompressed_byte: self.buffer.get_bytes_written(),
+        }
+    }
+
+    /// The up to 32KB of output before `position`, which is the dictionary the rest of the stream can refer back to,
+    /// like zlib's inflateGetDictionary. It's only ever from the current member, so it's empty at the start of one.
+    /// With `position`, it's what a checkpoint needs, for anything keeping its own instead of using sqlite.
+    pub fn window_snapshot(&self) -> Vec<u8> {
+        self.buffer.window_parts().concat()
+    }
+
+    /// Everything needed to carry on decoding from here with `load_state`, e.g. in another process once this one's
+    /// been stopped. The checkpoints written so far are committed, and kept when the checkpointer's dropped, for
+    /// the Deflator carrying on to finish (see `Checkpointer::suspend`).
+    ///
+    /// The options, tick trigger, events and stats aren't part of it, and nor is the compressed CRC of a block in
+    /// progress with `hash_blocks`, which that block goes without. If an error's waiting to be returned from the
+    /// next read, it's returned here instead.
+    pub fn save_state(&mut self) -> Result<SavedState, CorniferError> {
+        if let Some(err) = self.deferred_error.take() {
+            return Err(err);
+        }
+        let trees = |symbol_tree: &Tree, distance_tree: &Tree| Some(encode_trees(symbol_tree, distance_tree));
+        let (state, trees) = match &self.state {
+            DeflatorState::GZIPHeader => (SavedDeflatorState::GZIPHeader, None),
+            DeflatorState::ZlibHeader => (SavedDeflatorState::ZlibHeader, None),
+            DeflatorState::BlockHeader => (SavedDeflatorState::BlockHeader, None),
+            DeflatorState::PrepareNonCompressedBlock => (SavedDeflatorState::PrepareNonCompressedBlock, None),
+            DeflatorState::NonCompressedBlock { len } => (SavedDeflatorState::NonCompressedBlock { len: *len }, None),
+            DeflatorState::PrepareDynamicBlock => (SavedDeflatorState::PrepareDynamicBlock, None),
+            DeflatorState::DecodeBlock {
+                symbol_tree,
+                distance_tree,
+            } => (SavedDeflatorState::DecodeBlock, trees(symbol_tree, distance_tree)),
+            DeflatorState::WriteLookback {
+                current,
+                len,
+                symbol_tree,
+                distance_tree,
+            } => (
+                SavedDeflatorState::WriteLookback {
+                    current: *current,
+                    len: *len,
+                },
+                trees(symbol_tree, distance_tree),
+            ),
+            DeflatorState::CheckIfFinalBlock => (SavedDeflatorState::CheckIfFinalBlock, None),
+            DeflatorState::GZIPFooter => (SavedDeflatorState::GZIPFooter, None),
+            DeflatorState::ZlibFooter => (SavedDeflatorState::ZlibFooter, None),
+            DeflatorState::Done => (SavedDeflatorState::Done, None),
+        };
+        // the block's row is written once the data starts, after its header.
+        let data_started = matches!(
+            state,
+            SavedDeflatorState::NonCompressedBlock { .. }
+                | SavedDeflatorState::DecodeBlock
+                | SavedDeflatorState::WriteLookback { .. }
+        );
+        #[cfg(feature = "sqlite")]
+        self.checkpointer.suspend()?;
+        let StreamPosition {
+            compressed_byte,
+            compressed_bit,
+            ..
+        } = self.position();
+        Ok(SavedState {
+            state,
+            format: self.format,
+            from_byte: if compressed_bit == 0 { compressed_byte } else { compressed_byte - 1 },
+            from_bit: compressed_bit,
+            buffer: self.buffer.snapshot()?,
+            trees,
+            in_final_block: self.in_final_block,
+            block: self.block_to_byte.map(|_| self.checkpointer.open_block(data_started)),
+            members: self.members.clone(),
+            member_count: self.member_count,
+            current_member: self.current_member.clone(),
+            member_from_byte: self.member_from_byte,
+            block_to_byte: self.block_to_byte,
+            gaps: self.gaps.clone(),
+            last_checkpoint_to_byte: self.last_checkpoint_to_byte,
+            total_crc32: self.total_crc32,
+            finalize_on_done: self.finalize_on_done,
+            staged: self.staging[self.staged.clone()].to_vec(),
+        })
+    }
+
+    /// The bytes after the last gzip member, if `TrailingData::Return` is set and there were any.
+    pub fn trailing_data(&self) -> Option<&[u8]> {
+        self.trailing_data.as_deref()
+    }
+
+    /// Create a Deflator for a particular wrapper format. Only the formats made of DEFLATE blocks will work.
+    pub fn with_format(
+        reader: CorniferByteReader<R>,
+        checkpointer: Checkpointer,
+        format: Format,
+    ) -> Result<Self, CorniferError> {
+        let mut deflator = Self::new(reader, checkpointer);
+        deflator.state = match format {
+            Format::Gzip | Format::Bgzf => DeflatorState::GZIPHeader,
+            Format::Zlib => DeflatorState::ZlibHeader,
+            _ => return Err(CorniferError::UnsupportedFormat { format }),
+        };
+        if format == Format::Zlib {
+            deflator.buffer.track_adler32();
+        }
+        deflator.format = format;
+        Ok(deflator)
+    }
+
     pub fn read_block_header(&mut self) -> Result<BlockHeader, CorniferError> {
         let is_final = self.reader.read_bit()?;
         let block_bits = self.reader.read_n_bits_le(2)?;
@@ -123,16 +1014,22 @@ impl<R: Read> Deflator<R> {
 
     /// Decode a symbol with the given huffman tree and reader.
     pub fn decode(reader: &mut CorniferByteReader<R>, tree: &HuffmanTree) -> Result<u16, CorniferError> {
+        Self::decode_code(reader, tree).map(|code| code.symbol)
+    }
+
+    /// Like decode, but with the rest of the table entry, i.e. the base and extra bits of a length or distance.
+    pub fn decode_code(reader: &mut CorniferByteReader<R>, tree: &HuffmanTree) -> Result<HuffmanCode, CorniferError> {
         let mut byte: u16 = 0;
         let mut len = 0;
         loop {
             let bit = reader.read_bit()? as u16;
             byte = (byte << 1) | bit;
             len += 1;
-            if let Some(symbol) = tree.decode(byte, len) {
-                break Ok(symbol);
+            if let Some(code) = tree.lookup(byte, len) {
+                break Ok(code);
             };
-            if (len as u16) > MAX_HUFFMAN_BITS {
+            // no code is longer than this, and going on would read past the end of the table.
+            if (len as u16) >= MAX_HUFFMAN_BITS {
                 break Err(CorniferError::InvalidHuffmanCode {
                     code: byte,
                     position: reader.current_byte,
@@ -142,9 +1039,46 @@ impl<R: Read> Deflator<R> {
         }
     }
 
+    // the bits after a length or distance symbol.
+    fn read_extra_bits(reader: &mut CorniferByteReader<R>, code: HuffmanCode) -> Result<u16, CorniferError> {
+        if code.extra_bits == UNUSED_SYMBOL {
+            return Err(CorniferError::UnusedSymbol {
+                symbol: code.symbol,
+                position: reader.current_byte,
+            });
+        }
+        reader.read_n_bits_le(code.extra_bits)
+    }
+
     pub fn on_block_data_start(&mut self) -> Result<(), CorniferError> {
-        self.checkpointer.on_block_data_start(self.reader.current_byte, self.reader.current_bit, self.buffer.get_normalized_buffer()?)?;
+        self.checkpointer.on_block_data_start(self.reader.current_byte, self.reader.current_bit, &self.buffer.window_parts())?;
+
+        Ok(())
+    }
 
+    // Ask the tick trigger about the current position, and emit a tick if it says so. This takes the fields
+    // it needs, since it's called while the state is borrowed.
+    fn maybe_tick(
+        trigger: &mut TickTrigger,
+        last_checkpoint_to_byte: &mut usize,
+        reader: &CorniferByteReader<R>,
+        buffer: &CircularBuffer,
+        checkpointer: &mut Checkpointer,
+    ) -> Result<(), CorniferError> {
+        let to_byte = buffer.get_bytes_written();
+        let (from_byte, from_bit) = (reader.current_byte, reader.current_bit);
+        let context = TickContext {
+            // same as the checkpointer, current_byte has already moved past a partly read byte.
+            from_byte: if from_bit == 0 { from_byte } else { from_byte - 1 },
+            from_bit,
+            to_byte,
+            since_checkpoint: to_byte - *last_checkpoint_to_byte,
+            last_byte: buffer.last(),
+        };
+        if trigger(&context) {
+            checkpointer.on_tick(from_byte, from_bit, to_byte, &buffer.window_parts())?;
+            *last_checkpoint_to_byte = to_byte;
+        }
         Ok(())
     }
 
@@ -154,30 +1088,152 @@ impl<R: Read> Deflator<R> {
     ///  - depending on factors such as the input buffer length, a state may not complete in a call. in this case,
     ///    we remain in the same state (albeit with different parameters), and the function will need to be called again.
     fn state_transition(&mut self, buf: &mut [u8]) -> Result<usize, CorniferError> {
+        #[cfg(feature = "tracing")]
+        let _entered = if self.block_span.is_none() {
+            self.member_span.clone().entered()
+        } else {
+            self.block_span.clone().entered()
+        };
         let mut bytes_written = 0;
+        let at_boundary = matches!(self.state, DeflatorState::GZIPHeader | DeflatorState::BlockHeader);
+        if at_boundary && self.cancel_token.as_ref().is_some_and(|t| t.is_cancelled()) {
+            return Err(CorniferError::Cancelled {
+                position: self.reader.current_byte,
+            });
+        }
+        if at_boundary && self.deadline_at.is_some_and(|at| Instant::now() >= at) {
+            return Err(CorniferError::DeadlineExceeded {
+                compressed_byte: self.reader.current_byte,
+                uncompressed_byte: self.buffer.get_bytes_written(),
+            });
+        }
         self.state = match &mut self.state {
             // Read the header. We could have also been sent back here after the end of a previous gzip member.
             // if that gzip member was the last member, then we could expect an EOF to occur immediately. that means we're done.
             // otherwise, a GZIP header is always proceeded with a deflate block.
-            DeflatorState::GZIPHeader => match read_header(&mut self.reader) {
-                Ok(_header) => DeflatorState::BlockHeader,
-                Err(err) => match err {
-                    CorniferError::ExpectedEOF => DeflatorState::Done,
-                    _ => return Err(err),
-                },
-            },
+            // Anything after the first member that isn't another member is trailing data, which is handled
+            // according to the options.
+            DeflatorState::GZIPHeader => {
+                let member_start = self.reader.current_byte;
+                self.member_from_byte = member_start;
+                let keep_trailing_data = self.options.trailing_data == TrailingData::Return;
+                if keep_trailing_data {
+                    self.reader.begin_capture();
+                }
+                let result = read_header_unchecked(&mut self.reader);
+                let captured = self.reader.end_capture();
+                match result {
+                    Ok((header, mismatch)) => {
+                        #[cfg(feature = "tracing")]
+                        {
+                            self.member_span = tracing::info_span!(
+                                "gzip_member",
+                                index = self.member_count,
+                                from_byte = member_start
+                            );
+                        }
+                        let position = StreamPosition {
+                            compressed_byte: member_start,
+                            compressed_bit: 0,
+                            uncompressed_byte: self.buffer.get_bytes_written(),
+                        };
+                        self.push_event(DecodeEvent::MemberStarted { position });
+                        if let Some(HeaderCrcMismatch { expected, found }) = mismatch {
+                            match self.options.header_checks {
+                                HeaderValidation::Strict => {
+                                    return Err(CorniferError::InvalidHeaderCRC { expected, found });
+                                }
+                                HeaderValidation::Warn => {
+                                    #[cfg(feature = "tracing")]
+                                    tracing::warn!(from_byte = member_start, expected, found, "gzip header CRC is incorrect");
+                                    self.push_event(DecodeEvent::HeaderCrcMismatch { position, expected, found });
+                                }
+                                HeaderValidation::Ignore => (),
+                            }
+                        }
+                        self.buffer.forget_window();
+                        self.current_member = Some((member_start, header, mismatch.is_none()));
+                        DeflatorState::BlockHeader
+                    }
+                    // nothing at all isn't a gzip file, and Read would make it look like an empty one.
+                    Err(CorniferError::CleanEof) if self.reader.current_byte == 0 => return Err(CorniferError::EOF),
+                    Err(CorniferError::CleanEof) => DeflatorState::Done,
+                    // a single stray byte runs into EOF before the magic can be checked.
+                    Err(CorniferError::NotGZIPHeader | CorniferError::EOF)
+                        if self.member_count > 0 && self.reader.current_byte - member_start <= 2 =>
+                    {
+                        match self.options.trailing_data {
+                            TrailingData::Ignore => DeflatorState::Done,
+                            TrailingData::Error => {
+                                return Err(CorniferError::TrailingData {
+                                    position: member_start,
+                                })
+                            }
+                            TrailingData::Return => {
+                                let mut trailing = captured.unwrap_or_default();
+                                self.reader.read_to_end(&mut trailing)?;
+                                self.trailing_data = Some(trailing);
+                                DeflatorState::Done
+                            }
+                        }
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+            // A zlib stream is just two bytes of header and then the DEFLATE blocks.
+            DeflatorState::ZlibHeader => {
+                let cmf = self.reader.read_u8()?;
+                let flg = self.reader.read_u8()?;
+                if cmf & 0x0F != 8 || !((cmf as u16) << 8 | flg as u16).is_multiple_of(31) {
+                    return Err(CorniferError::NotZlibHeader);
+                }
+                if flg & 0b10_0000 != 0 {
+                    // FDICT; the stream refers to a dictionary we don't have.
+                    return Err(CorniferError::ZlibPresetDictionary);
+                }
+                DeflatorState::BlockHeader
+            }
             // Read a DEFLATE block. There are non-compressed, fixed, and dynamic blocks.
             // non-compressed and dynamic blocks have additional headers we need to work through, but a fixed block
             // we can proceed to decoding straight away.
             DeflatorState::BlockHeader => {
+                let block_start = self.position();
+                self.last_checkpoint_to_byte = self.buffer.get_bytes_written();
+                self.block_to_byte = Some(self.buffer.get_bytes_written());
                 self.checkpointer.on_block_start(
                     self.reader.current_byte,
                     self.reader.current_bit,
                     self.buffer.get_bytes_written(),
                 );
+                if self.hash_blocks {
+                    self.reader.begin_crc_at_bit();
+                }
                 let block_header = self.read_block_header()?;
+                #[cfg(feature = "tracing")]
+                {
+                    self.block_span = tracing::debug_span!(
+                        parent: &self.member_span,
+                        "block",
+                        reader_byte = block_start.compressed_byte,
+                        reader_bit = block_start.compressed_bit,
+                        block_type = ?block_header.block_type,
+                        is_final = block_header.is_final
+                    );
+                }
                 self.in_final_block = block_header.is_final; // read in CheckIfFinalBlock later.
                 self.checkpointer.set_block_type(block_header.block_type);
+                self.push_event(DecodeEvent::BlockStarted {
+                    position: block_start,
+                    block_type: block_header.block_type,
+                });
+                if let Some(stats) = self.stats.as_mut() {
+                    stats.on_block_start(
+                        block_start.compressed_byte,
+                        block_start.compressed_bit,
+                        block_start.uncompressed_byte,
+                        block_header.block_type,
+                    );
+                }
                 match block_header.block_type {
                     BlockType::NoCompression => DeflatorState::PrepareNonCompressedBlock,
                     BlockType::DynamicHuffman => DeflatorState::PrepareDynamicBlock,
@@ -185,11 +1241,9 @@ impl<R: Read> Deflator<R> {
                         // there are no more bits before decoding starts.
                         // so we can emit a checkpoint right away.
                         self.on_block_data_start()?;
-                        let symbol_tree = HuffmanTree::fixed();
-                        let distance_tree = HuffmanTree::fixed_dist();
                         DeflatorState::DecodeBlock {
-                            symbol_tree,
-                            distance_tree,
+                            symbol_tree: Cow::Borrowed(HuffmanTree::fixed()),
+                            distance_tree: Cow::Borrowed(HuffmanTree::fixed_dist()),
                         }
                     }
                 }
@@ -213,8 +1267,8 @@ impl<R: Read> Deflator<R> {
             // Once we know how many bytes to copy, start copying them.
             // If the input buffer is not big enough, we might need to stay in this state.
             DeflatorState::NonCompressedBlock { len: size } => {
-                let len = buf.len() as u16;
-                let num_bytes = min(*size, len);
+                // buffers can be longer than a u16, so compare before casting.
+                let num_bytes = min(*size as usize, buf.len()) as u16;
                 for i in 0..num_bytes {
                     let i = i as usize;
                     let byte = self.reader.read_u8()?;
@@ -224,6 +1278,7 @@ impl<R: Read> Deflator<R> {
                 bytes_written = num_bytes as usize;
                 let remaining_bytes = *size - num_bytes;
                 if remaining_bytes == 0 {
+                    self.checkpointer.on_block_end(self.reader.current_byte, self.reader.current_bit, self.buffer.get_bytes_written(), self.buffer.block_crc32())?;
                     DeflatorState::CheckIfFinalBlock
                 } else {
                     DeflatorState::NonCompressedBlock {
@@ -237,6 +1292,10 @@ impl<R: Read> Deflator<R> {
                 let num_literals = self.reader.read_n_bits_le(5)? + 257; // # of literal/length codes
                 let num_dists = self.reader.read_n_bits_le(5)? + 1; // # of distance codes
                 let num_code_lengths = self.reader.read_n_bits_le(4)? + 4; // # of code length codes
+                let strict = self.options.strict;
+                if strict && (num_literals as usize > MAX_SYMBOL_CODES || num_dists as usize > MAX_DISTANCE_CODES) {
+                    return Err(self.invalid_header("too many length or distance symbols"));
+                }
 
                 // first make the code length tree.
                 let mut code_lengths = [0; 19];
@@ -244,14 +1303,21 @@ impl<R: Read> Deflator<R> {
                     code_lengths[CODE_LENGTH_ORDER[i as usize]] =
                         self.reader.read_n_bits_le(3)? as u8;
                 }
+                match code_space(&code_lengths) {
+                    Ordering::Greater => return Err(self.invalid_header("invalid code lengths set")),
+                    Ordering::Less if strict => return Err(self.invalid_header("invalid code lengths set")),
+                    _ => {}
+                }
                 let cl_tree = HuffmanTree::new(&code_lengths);
 
                 // use this tree to construct the other two trees.
-                // the code lengths for the symbol and distance trees are in the same array.
-                let mut combined_cls = [0; MAX_DISTANCE_CODES + MAX_SYMBOL_CODES];
+                // the code lengths for the symbol and distance trees are in the same array. the header can say
+                // there's up to 288 and 32 of them, even though only 286 and 30 mean anything.
+                let mut combined_cls = [0; 288 + 32];
 
+                let total = (num_literals + num_dists) as usize;
                 let mut index = 0;
-                while index < (num_literals + num_dists) as usize {
+                while index < total {
                     // let last_len = 0;
                     let symbol = Self::decode(&mut self.reader, &cl_tree)? as u8;
 
@@ -281,6 +1347,9 @@ impl<R: Read> Deflator<R> {
                             to_copy = 0;
                             times_to_copy = 11 + self.reader.read_n_bits_le(7)?;
                         }
+                        if index + times_to_copy as usize > total {
+                            return Err(self.invalid_header("invalid bit length repeat"));
+                        }
 
                         for _ in 0..times_to_copy {
                             combined_cls[index] = to_copy;
@@ -289,14 +1358,23 @@ impl<R: Read> Deflator<R> {
                     }
                 }
                 let num_literals = num_literals as usize;
-                let symbol_tree = HuffmanTree::new(&combined_cls[0..num_literals]);
+                let (literal_cls, distance_cls) = (&combined_cls[0..num_literals], &combined_cls[num_literals..total]);
+                if strict && literal_cls[256] == 0 {
+                    return Err(self.invalid_header("invalid code -- missing end-of-block"));
+                }
+                self.check_code_space(literal_cls, "invalid literal/lengths set")?;
+                self.check_code_space(distance_cls, "invalid distances set")?;
+
+                let symbol_tree = HuffmanTree::literal_length(literal_cls);
+                // the distance code lengths run to the end of the old array, so checkpointed trees stay the same.
                 let distance_tree =
-                    HuffmanTree::new(&combined_cls[num_literals..combined_cls.len()]);
+                    HuffmanTree::distance(&combined_cls[num_literals..total.max(MAX_SYMBOL_CODES + MAX_DISTANCE_CODES)]);
+                self.checkpointer.set_trees(encode_trees(&symbol_tree, &distance_tree));
                 
                 self.on_block_data_start()?;
                 DeflatorState::DecodeBlock {
-                    symbol_tree,
-                    distance_tree,
+                    symbol_tree: Cow::Owned(symbol_tree),
+                    distance_tree: Cow::Owned(distance_tree),
                 }
             }
             // Start decoding a DEFLATE block. The trees used are either well-known values (fixed), or decoded from
@@ -306,49 +1384,77 @@ impl<R: Read> Deflator<R> {
                 distance_tree,
             } => {
                 let mut i = 0;
+                // errors come out of the loop rather than returning straight away, so the literals before them aren't lost.
                 let next_state = loop {
                     if i >= buf.len() {
                         // we've written all we can, but we haven't finished decoding the block.
                         // next time state_transition is called we'll pick up where we left off.
-                        break DeflatorState::DecodeBlock {
+                        break Ok(DeflatorState::DecodeBlock {
                             symbol_tree: mem::take(symbol_tree),
                             distance_tree: mem::take(distance_tree),
-                        };
+                        });
                     }
-                    let symbol = Self::decode(&mut self.reader, symbol_tree)?;
-                    if symbol < 256 {
-                        let symbol = symbol as u8;
+                    if let Some(trigger) = self.tick_trigger.as_mut() {
+                        or_break!(Self::maybe_tick(
+                            trigger,
+                            &mut self.last_checkpoint_to_byte,
+                            &self.reader,
+                            &self.buffer,
+                            &mut self.checkpointer,
+                        ));
+                    }
+                    let code = or_break!(Self::decode_code(&mut self.reader, symbol_tree));
+                    if code.symbol < 256 {
+                        let symbol = code.symbol as u8;
                         // literal
                         self.buffer.push(symbol);
                         buf[i] = symbol;
                         i += 1;
+                        if let Some(stats) = self.stats.as_mut() {
+                            stats.on_literal();
+                        }
                         continue;
                     }
-                    if symbol == 256 {
-                        self.checkpointer.on_block_end(self.reader.current_byte, self.reader.current_bit, self.buffer.get_bytes_written(), self.buffer.block_crc32())?;
-                        break DeflatorState::CheckIfFinalBlock;
+                    if code.symbol == 256 {
+                        or_break!(self.checkpointer.on_block_end(self.reader.current_byte, self.reader.current_bit, self.buffer.get_bytes_written(), self.buffer.block_crc32()));
+                        break Ok(DeflatorState::CheckIfFinalBlock);
+                    }
+                    // a length, then a distance. The tables say what they start from and how many bits to add.
+                    let len = code.base + or_break!(Self::read_extra_bits(&mut self.reader, code));
+                    let dist_code = or_break!(Self::decode_code(&mut self.reader, distance_tree));
+                    let dist = dist_code.base + or_break!(Self::read_extra_bits(&mut self.reader, dist_code));
+                    if self.options.strict && dist as usize > self.buffer.window_len() {
+                        break Err(CorniferError::DistanceTooFarBack {
+                            distance: dist,
+                            available: self.buffer.window_len(),
+                            position: self.reader.current_byte,
+                        });
+                    }
+                    if let Some(stats) = self.stats.as_mut() {
+                        stats.on_match(len);
                     }
-                    // value between 257 and 285
-                    let index = (symbol - 257) as usize;
-                    let len = BASE_LENGTHS[index];
-                    let len_bits = LENGTH_EXTRA_BITS[index];
-                    let len = len + self.reader.read_n_bits_le(len_bits)?;
-
-                    let dist_symbol = Self::decode(&mut self.reader, distance_tree)? as usize;
-                    let dist = BASE_DISTS[dist_symbol];
-                    let dist_bits = DIST_EXTRA_BITS[dist_symbol];
-                    let dist = dist + self.reader.read_n_bits_le(dist_bits)?;
-
-                    self.buffer.push_from_buffer(dist, len)?;
-                    break DeflatorState::WriteLookback {
+
+                    or_break!(self.buffer.push_from_buffer(dist, len));
+                    break Ok(DeflatorState::WriteLookback {
                         current: 0,
                         len,
                         symbol_tree: mem::take(symbol_tree),
                         distance_tree: mem::take(distance_tree),
-                    };
+                    });
                 };
                 bytes_written = i;
-                next_state
+                match next_state {
+                    Ok(state) => state,
+                    // hand back the literals, read_internal stops here and the error comes out on the next read.
+                    Err(err) if i > 0 => {
+                        self.deferred_error = Some(err);
+                        DeflatorState::DecodeBlock {
+                            symbol_tree: mem::take(symbol_tree),
+                            distance_tree: mem::take(distance_tree),
+                        }
+                    }
+                    Err(err) => return Err(err),
+                }
             }
             // A helper state for DecodeBlock, DecodeBlock will transition to this if it encounters a lookback/distance pair
             // while decoding. This is because the input buffer might not be big enough to process an entire lookback/distance
@@ -361,26 +1467,20 @@ impl<R: Read> Deflator<R> {
                 symbol_tree,
                 distance_tree,
             } => {
-                let buf_len = buf.len();
                 let len = *len;
-                let current = *current;
-                let num_bytes = min(len - current, buf_len as u16);
-
-                let head = self.buffer.head(len)?;
-
-                for i in current..current + num_bytes {
-                    buf[bytes_written] = head[i as usize];
-                    bytes_written += 1;
-                }
+                // the whole match was pushed when it was decoded, so what's left of it is the last len - current
+                // bytes of the buffer, however many reads it takes to hand them all over.
+                bytes_written = self.buffer.copy_recent((len - *current) as usize, buf);
+                let current = *current + bytes_written as u16;
 
-                if current + num_bytes == len {
+                if current == len {
                     DeflatorState::DecodeBlock {
                         symbol_tree: mem::take(symbol_tree),
                         distance_tree: mem::take(distance_tree),
                     }
                 } else {
                     DeflatorState::WriteLookback {
-                        current: current + (bytes_written as u16),
+                        current,
                         len,
                         symbol_tree: mem::take(symbol_tree),
                         distance_tree: mem::take(distance_tree),
@@ -390,7 +1490,27 @@ impl<R: Read> Deflator<R> {
             // This state is visited after a block is decoded. There is either another block (if it's not the final block),
             // or a GZIP footer.
             DeflatorState::CheckIfFinalBlock => {
-                if self.in_final_block {
+                // nothing's been read since the end of the block.
+                let position = self.position();
+                self.push_event(DecodeEvent::BlockEnded { position });
+                // the checkpoint file gets them too, for looking at across a lot of files.
+                let ended = self.stats.as_mut().and_then(|stats| {
+                    stats.on_block_end(position.compressed_byte, position.compressed_bit, position.uncompressed_byte)
+                });
+                if let Some(block) = ended {
+                    self.checkpointer.on_block_stats(block.literals, block.matches, block.longest_match)?;
+                }
+                if let Some(crc32) = self.reader.end_crc().filter(|_| self.hash_blocks) {
+                    self.checkpointer.on_block_hash(crc32)?;
+                }
+                self.block_to_byte = None;
+                #[cfg(feature = "tracing")]
+                {
+                    self.block_span = tracing::Span::none();
+                }
+                if self.in_final_block && self.format == Format::Zlib {
+                    DeflatorState::ZlibFooter
+                } else if self.in_final_block {
                     DeflatorState::GZIPFooter
                 } else {
                     DeflatorState::BlockHeader
@@ -403,40 +1523,300 @@ impl<R: Read> Deflator<R> {
                 self.reader.discard_until_next_byte();
                 // read four bytes crc32 and check
                 let crc32_expected = self.buffer.crc32();
+                let len = self.buffer.counter();
+                // it's what was decoded, so it's in the whole file's CRC even if the footer is wrong and we recover.
+                self.total_crc32 = crc32_combine(self.total_crc32, crc32_expected, len);
                 let crc32 = self.reader.read_u32_le()?;
-                if crc32_expected != crc32 {
+                let crc32_matches = crc32_expected == crc32;
+                if !crc32_matches && self.options.footer_checks == FooterChecks::Strict {
                     return Err(CorniferError::InvalidGZIPCRC {
                         position: self.reader.current_byte,
                         expected: crc32_expected,
                         found: crc32,
                     });
                 }
-                // read four bytes isize and check
-                let isize_expected = self.buffer.counter();
+                // read four bytes isize and check. ISIZE is the length modulo 2^32, so members over 4GB wrap.
+                let isize_expected = (len % (1 << 32)) as u32;
                 let isize = self.reader.read_u32_le()?;
-                if isize_expected != isize {
+                let isize_matches = isize_expected == isize;
+                if !isize_matches && self.options.footer_checks == FooterChecks::Strict {
                     return Err(CorniferError::InvalidGZIPIsize {
                         position: self.reader.current_byte,
                         expected: isize_expected,
                         found: isize,
                     });
                 }
+                // if we resumed partway through the member, we never saw its header, so there's nothing to record.
+                if let Some((from_byte, header, hcrc_matches)) = self.current_member.take() {
+                    let member = GzipMember {
+                        from_byte,
+                        to_byte: self.buffer.get_bytes_written() - len as usize,
+                        header,
+                        crc32: crc32_expected,
+                        len,
+                        crc32_matches,
+                        isize_matches,
+                        hcrc_matches,
+                    };
+                    self.checkpointer.on_member_end(&member)?;
+                    self.member_count += 1;
+                    if self.options.keep_members {
+                        self.members.push(member);
+                    }
+                    self.push_event(DecodeEvent::MemberEnded {
+                        position: self.position(),
+                        len,
+                        crc32: crc32_expected,
+                    });
+                }
+                if (!crc32_matches || !isize_matches) && self.options.footer_checks == FooterChecks::Warn {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(len, crc32_matches, isize_matches, "gzip footer doesn't match");
+                    self.push_event(DecodeEvent::FooterMismatch {
+                        position: self.position(),
+                        crc32_matches,
+                        isize_matches,
+                    });
+                }
+                #[cfg(feature = "tracing")]
+                {
+                    tracing::debug!(len, crc32_matches, isize_matches, "finished gzip member");
+                    self.member_span = tracing::Span::none();
+                }
                 DeflatorState::GZIPHeader
             }
-            // once we're done, we're done forever.
-            DeflatorState::Done => DeflatorState::Done,
-        };
+            // zlib streams end with a big-endian Adler-32, and they can't be concatenated like gzip members.
+            DeflatorState::ZlibFooter => {
+                self.reader.discard_until_next_byte();
+                let adler32_expected = self.buffer.adler32();
+                let mut adler32 = 0;
+                for _ in 0..4 {
+                    adler32 = (adler32 << 8) | self.reader.read_u8()? as u32;
+                }
+                if adler32_expected != adler32 {
+                    return Err(CorniferError::InvalidZlibAdler32 {
+                        position: self.reader.current_byte,
+                        expected: adler32_expected,
+                        found: adler32,
+                    });
+                }
+                // the CRC32 is kept up for zlib streams too, even though they don't use it.
+                let len = self.buffer.counter();
+                self.total_crc32 = crc32_combine(self.total_crc32, self.buffer.crc32(), len);
+                DeflatorState::Done
+            }
+            // once we're done, we're done forever.
+            DeflatorState::Done => DeflatorState::Done,
+        };
         Ok(bytes_written)
     }
 
-    // Implementation of Read trait that uses CorniferError instead of std::io::Error
+    // record the length and CRC of the whole file, the first time we get to the end of it.
+    fn finalize_checkpointer(&mut self) -> Result<(), CorniferError> {
+        if !self.finalize_on_done {
+            return Ok(());
+        }
+        self.finalize_on_done = false;
+        self.checkpointer.finalize(self.buffer.get_bytes_written() as u64, self.total_crc32)
+    }
+
+    // with DeflatorOptions::recover, skip from the member that failed to the next one, and record what was skipped.
+    // Otherwise, or if the error isn't one that skipping would help with, give the error back.
+    fn recover(&mut self, err: CorniferError) -> Result<(), CorniferError> {
+        let recoverable = self.options.recover
+            && matches!(self.format, Format::Gzip | Format::Bgzf)
+            && err.kind() == ErrorKind::CorruptStream
+            && !matches!(
+                err,
+                CorniferError::TrailingData { .. }
+                    | CorniferError::InGap { .. }
+                    | CorniferError::UnexpectedLength { .. } | CorniferError::UnexpectedCrc32 { .. }
+            )
+            && discriminant(&self.state) != discriminant(&DeflatorState::Done);
+        if !recoverable {
+            return Err(err);
+        }
+        // what the member got through still counts towards the whole file's CRC. Nothing if the footer already did it.
+        let len = self.buffer.counter();
+        self.total_crc32 = crc32_combine(self.total_crc32, self.buffer.crc32(), len);
+        self.current_member = None;
+        self.reader.discard_until_next_byte();
+        self.reader.skip_until(&GZIP_MAGIC)?;
+        let gap = Gap {
+            from_byte: self.member_from_byte,
+            to_byte: self.reader.current_byte,
+            uncompressed_from: self.block_to_byte.take().unwrap_or(self.buffer.get_bytes_written()),
+            uncompressed_to: self.buffer.get_bytes_written(),
+            error: err.to_string(),
+        };
+        #[cfg(feature = "tracing")]
+        {
+            tracing::warn!(from_byte = gap.from_byte, to_byte = gap.to_byte, error = gap.error, "skipped corrupt gzip member");
+            self.member_span = tracing::Span::none();
+            self.block_span = tracing::Span::none();
+        }
+        self.checkpointer.on_gap(&gap)?;
+        self.push_event(DecodeEvent::Skipped { gap: gap.clone() });
+        self.gaps.push(gap);
+        self.in_final_block = false;
+        self.state = DeflatorState::GZIPHeader;
+        Ok(())
+    }
+
+    // the reasons are zlib's, so they can be compared.
+    fn invalid_header(&self, reason: &'static str) -> CorniferError {
+        CorniferError::InvalidDynamicBlockHeader {
+            position: self.reader.current_byte,
+            reason,
+        }
+    }
+
+    // an over-subscribed code can't be decoded at all. an incomplete one is fine until one of the missing codes
+    // turns up, and zlib allows them when there's only one code, since a code of one symbol can't be complete.
+    fn check_code_space(&self, bit_lengths: &[u8], reason: &'static str) -> Result<(), CorniferError> {
+        match code_space(bit_lengths) {
+            Ordering::Greater => Err(self.invalid_header(reason)),
+            Ordering::Less if self.options.strict && bit_lengths.iter().any(|&len| len > 1) => {
+                Err(self.invalid_header(reason))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // running out of input in a block means the file was cut short there, so say where. Before the block's data
+    // starts, it has no row yet.
+    fn eof_in_block(&self, err: CorniferError) -> CorniferError {
+        let block_id = match self.state {
+            DeflatorState::PrepareNonCompressedBlock | DeflatorState::PrepareDynamicBlock => None,
+            DeflatorState::NonCompressedBlock { .. }
+            | DeflatorState::DecodeBlock { .. }
+            | DeflatorState::WriteLookback { .. } => self.checkpointer.current_block_id(),
+            _ => return err,
+        };
+        match err {
+            CorniferError::EOF => CorniferError::UnexpectedEofInBlock {
+                block_id,
+                compressed_byte: self.reader.current_byte,
+                uncompressed_byte: self.buffer.get_bytes_written(),
+            },
+            err => err,
+        }
+    }
+
+    // whether we've decompressed more than the options allow.
+    fn check_output_limits(&self) -> Result<(), CorniferError> {
+        let uncompressed_len = self.buffer.get_bytes_written() as u64;
+        let compressed_len = self.reader.current_byte as u64;
+        let too_long = self.options.max_output_bytes.is_some_and(|max| uncompressed_len > max);
+        let too_big = self.options.max_expansion_ratio.is_some_and(|ratio| {
+            uncompressed_len >= MIN_OUTPUT_FOR_RATIO && uncompressed_len as f64 > ratio * compressed_len.max(1) as f64
+        });
+        if too_long || too_big {
+            return Err(CorniferError::OutputLimitExceeded {
+                uncompressed_len,
+                compressed_len,
+            });
+        }
+        Ok(())
+    }
+
+    // Implementation of Read trait that uses CorniferError instead of std::io::Error. Fills buf from what's staged,
+    // then from the decoder, through the staging buffer while what's left of buf is smaller than it.
     fn read_internal(&mut self, buf: &mut [u8]) -> Result<usize, CorniferError> {
+        let mut bytes_written = self.unstage(buf);
+        while bytes_written < buf.len() {
+            let rest = &mut buf[bytes_written..];
+            let result = match rest.len() < self.staging_size {
+                true => self.stage().map(|()| self.unstage(rest)),
+                false => self.decode_into(rest),
+            };
+            match result {
+                Ok(0) => break,
+                Ok(n) => bytes_written += n,
+                Err(err) if bytes_written > 0 => {
+                    self.deferred_error = Some(err);
+                    break;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(bytes_written)
+    }
+
+    // how much of the output has come out of read, which is behind what's been decoded by the rest of a match and
+    // whatever's staged.
+    fn bytes_read(&self) -> usize {
+        let lookback = match self.state {
+            DeflatorState::WriteLookback { current, len, .. } => (len - current) as usize,
+            _ => 0,
+        };
+        self.buffer.get_bytes_written() - lookback - self.staged.len()
+    }
+
+    fn stage(&mut self) -> Result<(), CorniferError> {
+        let mut staging = mem::take(&mut self.staging);
+        staging.resize(self.staging_size, 0);
+        let result = self.decode_into(&mut staging);
+        self.staging = staging;
+        self.staged = 0..result?;
+        Ok(())
+    }
+
+    fn unstage(&mut self, buf: &mut [u8]) -> usize {
+        let n = buf.len().min(self.staged.len());
+        buf[..n].copy_from_slice(&self.staging[self.staged.start..self.staged.start + n]);
+        self.staged.start += n;
+        n
+    }
+
+    fn decode_into(&mut self, buf: &mut [u8]) -> Result<usize, CorniferError> {
+        // an error we hit last time, after we'd already written some bytes.
+        if let Some(err) = self.deferred_error.take() {
+            self.recover(self.eof_in_block(err))?;
+        }
+        if self.deadline_at.is_none() {
+            self.deadline_at = self.options.deadline.map(|d| Instant::now() + d);
+        }
         let mut bytes_written = 0;
-        // keep going until we've written at least one byte, or we're done.
+        // keep going until the buffer is full, or we're done. A single state transition only gets as far as the next
+        // length/distance pair or block boundary, so stopping after the first one makes reads tiny.
         // self.state_transition may return 0 even if we're not done. The only way to tell if we're done is if we're in DeflatorState::Done
-        while bytes_written == 0 {
-            bytes_written += self.state_transition(buf)?;
+        while bytes_written < buf.len() {
+            // a decompression bomb would fill the disk long before it reached the end, so it's stopped early.
+            if let Err(err) = self.check_output_limits() {
+                if bytes_written == 0 {
+                    return Err(err);
+                }
+                self.deferred_error = Some(err);
+                break;
+            }
+            // never more than one byte past the limit, so that's noticed next time round.
+            let mut to = buf.len();
+            if let Some(max) = self.options.max_output_bytes {
+                let left = max.saturating_sub(self.buffer.get_bytes_written() as u64) + 1;
+                to = to.min(bytes_written.saturating_add(left.try_into().unwrap_or(usize::MAX)));
+            }
+            match self.state_transition(&mut buf[bytes_written..to]).map_err(|err| self.eof_in_block(err)) {
+                // the state stopped partway because of an error, which comes out next time.
+                Ok(n) if self.deferred_error.is_some() => {
+                    bytes_written += n;
+                    break;
+                }
+                Ok(n) => bytes_written += n,
+                // hand back what we've got, the caller will see the error on the next read.
+                Err(err) if bytes_written > 0 => {
+                    self.deferred_error = Some(err);
+                    break;
+                }
+                Err(err) => self.recover(err)?,
+            }
             if discriminant(&self.state) == discriminant(&DeflatorState::Done) {
+                if let Err(err) = self.finalize_checkpointer() {
+                    if bytes_written == 0 {
+                        return Err(err);
+                    }
+                    self.deferred_error = Some(err);
+                }
                 break;
             }
         }
@@ -448,7 +1828,98 @@ impl<R: Read> Read for Deflator<R> {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         match self.read_internal(buf) {
             Ok(n) => std::io::Result::Ok(n),
-            Err(e) => std::io::Result::Err(Error::new(ErrorKind::Other, e)),
+            Err(e) => std::io::Result::Err(Error::from(e)),
+        }
+    }
+
+    // fill each buffer in turn, so a caller with lots of small buffers gets them all filled in one call.
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> std::io::Result<usize> {
+        let mut bytes_written = 0;
+        for buf in bufs.iter_mut().filter(|b| !b.is_empty()) {
+            let n = match self.read_internal(buf) {
+                Ok(n) => n,
+                Err(e) if bytes_written > 0 => {
+                    self.deferred_error = Some(e);
+                    break;
+                }
+                Err(e) => return Err(Error::from(e)),
+            };
+            bytes_written += n;
+            if n < buf.len() {
+                break;
+            }
+        }
+        Ok(bytes_written)
+    }
+}
+
+impl<R: Read> Deflator<R> {
+    /// Split the rest of the decompressed output at each `delimiter`, e.g. b'\n' for NDJSON. Each item is the
+    /// offset the record starts at in the decompressed output, and the record without its delimiter. Checkpoints
+    /// are written as it goes, same as reading any other way.
+    pub fn records(&mut self, delimiter: u8) -> Records<'_, R> {
+        let offset = self.bytes_read() as u64;
+        Records {
+            deflator: self,
+            delimiter,
+            buf: Vec::new(),
+            pos: 0,
+            offset,
+            done: false,
+        }
+    }
+}
+
+/// Iterator over the records in a Deflator's output. See `Deflator::records`.
+pub struct Records<'a, R> {
+    deflator: &'a mut Deflator<R>,
+    delimiter: u8,
+    // decompressed but not handed out yet, from pos on.
+    buf: Vec<u8>,
+    pos: usize,
+    // the offset of buf[pos] in the decompressed output.
+    offset: u64,
+    done: bool,
+}
+
+impl<R: Read> Iterator for Records<'_, R> {
+    type Item = Result<(u64, Vec<u8>), CorniferError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let rest = &self.buf[self.pos..];
+            if let Some(i) = rest.iter().position(|b| *b == self.delimiter) {
+                let record = (self.offset, rest[..i].to_vec());
+                self.pos += i + 1;
+                self.offset += i as u64 + 1;
+                return Some(Ok(record));
+            }
+            if self.done {
+                // the last record doesn't need a delimiter after it.
+                if rest.is_empty() {
+                    return None;
+                }
+                let record = (self.offset, rest.to_vec());
+                self.pos = self.buf.len();
+                return Some(Ok(record));
+            }
+            // keep the partial record, and read more after it.
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+            let len = self.buf.len();
+            self.buf.resize(len + THIRTY_TWO_KILOBYTES, 0);
+            match self.deflator.read_internal(&mut self.buf[len..]) {
+                Ok(n) => {
+                    self.buf.truncate(len + n);
+                    self.done = n == 0;
+                }
+                // the partial record is never going to be finished.
+                Err(e) => {
+                    self.buf.clear();
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
         }
     }
 }
@@ -456,21 +1927,52 @@ impl<R: Read> Read for Deflator<R> {
 #[cfg(test)]
 mod test {
     use std::{
-        io::{Read, Write},
+        io::{IoSliceMut, Read, Write},
         mem::discriminant,
+        time::Duration,
+    };
+    #[cfg(feature = "sqlite")]
+    use std::{
+        io::{Seek, SeekFrom},
+        sync::{Arc, Mutex},
     };
 
+    use crc::{Crc, CRC_32_ISO_HDLC};
     use flate2::{
-        write::{DeflateEncoder, GzEncoder},
+        write::{DeflateEncoder, GzEncoder, ZlibEncoder},
         Compression,
     };
     use rstest::rstest;
 
     use crate::{
+        cancel::CancelToken,
         checkpoint::Checkpointer,
-        decompress::{BlockType, Deflator},
+        decompress::{
+            BlockType, CheckpointSpacing, DecodeEvent, Deflator, DeflatorOptions, FooterChecks, Gap, HeaderValidation,
+            TrailingData,
+        },
+        errors::CorniferError,
+        format::Format,
         reader::CorniferByteReader,
+        stats::Stats,
     };
+    #[cfg(feature = "sqlite")]
+    use crate::{
+        checkpoint::CreateMode, checkpointed::CheckpointedReader, circle::CircularBuffer, decompress::TickContext,
+        store::CheckpointStore,
+        testutil::{flushed_gzip, TempIndex},
+    };
+
+    const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+    // somewhere for the checkpoints to go, for tests that only care about what's decoded. Without sqlite they
+    // don't go anywhere.
+    fn checkpointer() -> Checkpointer {
+        #[cfg(feature = "sqlite")]
+        return Checkpointer::init_memory().unwrap();
+        #[cfg(not(feature = "sqlite"))]
+        Checkpointer::none()
+    }
 
     #[rstest]
     pub fn test_read_block_header() {
@@ -480,11 +1982,11 @@ mod test {
         let v = e.finish().unwrap();
         let v = v.as_slice();
         let reader = CorniferByteReader::new(v);
-        let mut deflator = Deflator::new(reader, Checkpointer::init_memory().unwrap());
+        let mut deflator = Deflator::new(reader, checkpointer());
         let block_header = deflator.read_block_header().unwrap();
 
         assert_eq!(block_header.block_type, BlockType::FixedHuffman);
-        assert_eq!(block_header.is_final, true);
+        assert!(block_header.is_final);
     }
 
     #[rstest]
@@ -495,7 +1997,7 @@ mod test {
         let v = e.finish().unwrap();
         let v = v.as_slice();
         let reader = CorniferByteReader::new(v);
-        let mut deflator = Deflator::new(reader, Checkpointer::init_memory().unwrap());
+        let mut deflator = Deflator::new(reader, checkpointer());
 
         let mut dest: Vec<u8> = Vec::new();
 
@@ -515,7 +2017,7 @@ mod test {
         let v = e.finish().unwrap();
         let v = v.as_slice();
         let reader = CorniferByteReader::new(v);
-        let mut deflator = Deflator::new(reader, Checkpointer::init_memory().unwrap());
+        let mut deflator = Deflator::new(reader, checkpointer());
 
         let mut dest: Vec<u8> = Vec::new();
 
@@ -528,6 +2030,18 @@ mod test {
     }
 
     #[rstest]
+    pub fn test_unused_length_symbol() {
+        // a final fixed block (1, then 01 LSB first), then the code for 286 (11000110).
+        let input = [0b0001_1011, 0b0000_0011];
+        let reader = CorniferByteReader::new(input.as_slice());
+        let mut deflator = Deflator::resume(reader, 0, &[], Checkpointer::none()).unwrap();
+        let err = deflator.read_to_end(&mut Vec::new()).unwrap_err();
+        let err = err.into_inner().unwrap().downcast::<CorniferError>().unwrap();
+        assert!(matches!(*err, CorniferError::UnusedSymbol { symbol: 286, .. }));
+    }
+
+    #[rstest]
+    #[allow(clippy::unbuffered_bytes)] // reading one byte at a time is what's being tested.
     pub fn test_deflate_fixed_compressed_block_2() {
         // check bytes() works
         let v: Vec<u8> = Vec::new();
@@ -536,7 +2050,7 @@ mod test {
         let v = e.finish().unwrap();
         let v = v.as_slice();
         let reader = CorniferByteReader::new(v);
-        let deflator = Deflator::new(reader, Checkpointer::init_memory().unwrap());
+        let deflator = Deflator::new(reader, checkpointer());
 
         let mut deflator = deflator.bytes();
 
@@ -564,7 +2078,7 @@ mod test {
         let v = e.finish().unwrap();
         let v = v.as_slice();
         let reader = CorniferByteReader::new(v);
-        let mut deflator = Deflator::new(reader, Checkpointer::init_memory().unwrap());
+        let mut deflator = Deflator::new(reader, checkpointer());
         let mut dest: Vec<u8> = Vec::new();
 
         // deflator.read(&mut dest).unwrap();
@@ -585,7 +2099,7 @@ mod test {
         let v = e.finish().unwrap();
         let v = v.as_slice();
         let reader = CorniferByteReader::new(v);
-        let mut deflator = Deflator::new(reader, Checkpointer::init_memory().unwrap());
+        let mut deflator = Deflator::new(reader, checkpointer());
         let mut dest: Vec<u8> = vec![0; 0];
 
         // deflator.read(&mut dest).unwrap();
@@ -614,7 +2128,11 @@ mod test {
         let v = v.as_slice();
 
         let reader = CorniferByteReader::new(v);
-        let mut deflator = Deflator::new(reader, Checkpointer::init_memory().unwrap());
+        let mut deflator = Deflator::new(reader, checkpointer());
+        deflator.set_options(DeflatorOptions {
+            keep_members: true,
+            ..Default::default()
+        });
         let mut dest: Vec<u8> = vec![0; 0];
 
         // deflator.read(&mut dest).unwrap();
@@ -622,6 +2140,8 @@ mod test {
         let dest = String::from_utf8(dest.to_vec()).unwrap();
 
         assert_eq!(dest, "hello worldhello world2".to_string());
+        let lens: Vec<u64> = deflator.members().iter().map(|m| m.len).collect();
+        assert_eq!(lens, vec![11, 12]);
     }
 
     #[rstest]
@@ -629,7 +2149,7 @@ mod test {
         let input = include_bytes!("../testfiles/1080-0.txt.gz");
 
         let reader = CorniferByteReader::new(input.as_slice());
-        let mut deflator = Deflator::new(reader, Checkpointer::init_memory().unwrap());
+        let mut deflator = Deflator::new(reader, checkpointer());
         let mut dest: Vec<u8> = vec![0; 0];
 
         // deflator.read(&mut dest).unwrap();
@@ -637,4 +2157,1172 @@ mod test {
 
         assert_eq!(dest, include_bytes!("../testfiles/1080-0.txt"));
     }
+
+    // reads of these sizes in turn, so matches get split across reads every which way.
+    #[rstest]
+    #[case::one_byte(include_bytes!("../testfiles/1080-0.txt").to_vec(), &[1])]
+    #[case::mixed(include_bytes!("../testfiles/1080-0.txt").to_vec(), &[1, 2, 3, 100, 257, 258, 259, 4096])]
+    #[case::longer_than_buffer(vec![b'a'; 10000], &[7, 1, 257])]
+    #[case::overlapping(b"abc".repeat(5000), &[1, 5, 100])]
+    #[case::overlapping_one_byte(b"abcd".repeat(5000), &[1])]
+    pub fn test_small_reads(#[case] text: Vec<u8>, #[case] sizes: &[usize]) {
+        let mut e = GzEncoder::new(Vec::new(), Compression::best());
+        e.write_all(&text).unwrap();
+        let input = e.finish().unwrap();
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), Checkpointer::none());
+
+        let mut dest = Vec::new();
+        for size in sizes.iter().cycle() {
+            let mut buf = vec![0; *size];
+            let n = deflator.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            dest.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(dest, text);
+    }
+
+    #[rstest]
+    pub fn test_new_auto_zlib() {
+        let mut e = ZlibEncoder::new(Vec::new(), Compression::best());
+        e.write_all(include_bytes!("../testfiles/anthems.txt")).unwrap();
+        let v = e.finish().unwrap();
+
+        let mut deflator =
+            Deflator::new_auto(v.as_slice(), checkpointer()).unwrap();
+        let mut dest: Vec<u8> = Vec::new();
+        deflator.read_to_end(&mut dest).unwrap();
+
+        assert_eq!(dest, include_bytes!("../testfiles/anthems.txt"));
+    }
+
+    #[rstest]
+    pub fn test_zlib_bad_adler32() {
+        let mut e = ZlibEncoder::new(Vec::new(), Compression::best());
+        e.write_all(b"hello world").unwrap();
+        let mut v = e.finish().unwrap();
+        let last = v.len() - 1;
+        v[last] ^= 1;
+
+        let reader = CorniferByteReader::new(v.as_slice());
+        let mut deflator =
+            Deflator::with_format(reader, checkpointer(), Format::Zlib)
+                .unwrap();
+        let mut dest: Vec<u8> = Vec::new();
+        let err = deflator.read_to_end(&mut dest).unwrap_err();
+
+        assert!(err.to_string().starts_with("zlib Adler-32 is incorrect"));
+    }
+
+    #[rstest]
+    pub fn test_new_auto_rejects_bzip2() {
+        let input: &[u8] = include_bytes!("../testfiles/1080-0x3.txt.bz2");
+        let result = Deflator::new_auto(input, checkpointer());
+        assert!(matches!(
+            result,
+            Err(CorniferError::UnsupportedFormat {
+                format: Format::Bzip2
+            })
+        ));
+    }
+
+    fn gzip_with_padding() -> Vec<u8> {
+        let mut e = GzEncoder::new(Vec::new(), Compression::fast());
+        e.write_all(b"hello world").unwrap();
+        let mut v = e.finish().unwrap();
+        v.extend([0; 512]);
+        v
+    }
+
+    #[rstest]
+    #[case::ignore(TrailingData::Ignore, None)]
+    #[case::keep(TrailingData::Return, Some(vec![0; 512]))]
+    pub fn test_trailing_data(#[case] policy: TrailingData, #[case] expected: Option<Vec<u8>>) {
+        let v = gzip_with_padding();
+        let reader = CorniferByteReader::new(v.as_slice());
+        let mut deflator = Deflator::new(reader, checkpointer());
+        deflator.set_options(DeflatorOptions {
+            trailing_data: policy,
+            ..Default::default()
+        });
+        let mut dest: Vec<u8> = Vec::new();
+        deflator.read_to_end(&mut dest).unwrap();
+
+        assert_eq!(dest, b"hello world");
+        assert_eq!(deflator.trailing_data(), expected.as_deref());
+    }
+
+    #[rstest]
+    pub fn test_trailing_data_error() {
+        let v = gzip_with_padding();
+        let position = v.len() - 512;
+        let reader = CorniferByteReader::new(v.as_slice());
+        let mut deflator = Deflator::new(reader, checkpointer());
+        let mut dest: Vec<u8> = Vec::new();
+        let err = deflator.read_to_end(&mut dest).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            format!("Unexpected data after the last GZIP member at 0x{position:X}")
+        );
+    }
+
+    #[rstest]
+    #[case::strict(FooterChecks::Strict, false)]
+    #[case::lenient(FooterChecks::Lenient, true)]
+    #[case::warn(FooterChecks::Warn, true)]
+    pub fn test_footer_checks(#[case] policy: FooterChecks, #[case] should_succeed: bool) {
+        let mut e = GzEncoder::new(Vec::new(), Compression::fast());
+        e.write_all(b"hello world").unwrap();
+        let mut v = e.finish().unwrap();
+        // claim the member is one byte longer than it is.
+        let n = v.len();
+        v[n - 4..].copy_from_slice(&12u32.to_le_bytes());
+        let reader = CorniferByteReader::new(v.as_slice());
+        let mut deflator = Deflator::new(reader, checkpointer());
+        deflator.set_options(DeflatorOptions {
+            footer_checks: policy,
+            keep_members: true,
+            ..Default::default()
+        });
+        deflator.record_events();
+        let mut dest: Vec<u8> = Vec::new();
+        let result = deflator.read_to_end(&mut dest);
+
+        assert_eq!(result.is_ok(), should_succeed);
+        if should_succeed {
+            let member = &deflator.members()[0];
+            assert_eq!(member.len, 11);
+            assert!(member.crc32_matches);
+            assert!(!member.isize_matches);
+        }
+        let warned = deflator.take_events().iter().any(|event| {
+            matches!(event, DecodeEvent::FooterMismatch { crc32_matches: true, isize_matches: false, .. })
+        });
+        assert_eq!(warned, policy == FooterChecks::Warn);
+    }
+
+    #[rstest]
+    #[case::strict(HeaderValidation::Strict)]
+    #[case::warn(HeaderValidation::Warn)]
+    #[case::ignore(HeaderValidation::Ignore)]
+    pub fn test_header_checks(#[case] policy: HeaderValidation) {
+        let input = include_bytes!("../testfiles/testIncorrectHCRC.txt.gz");
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), Checkpointer::none());
+        deflator.set_options(DeflatorOptions {
+            header_checks: policy,
+            keep_members: true,
+            ..Default::default()
+        });
+        deflator.record_events();
+        let result = deflator.read_to_end(&mut Vec::new());
+
+        if policy == HeaderValidation::Strict {
+            let err = result.unwrap_err().into_inner().unwrap().downcast::<CorniferError>().unwrap();
+            assert!(matches!(*err, CorniferError::InvalidHeaderCRC { expected: 0xE8EE, found: 0xE7EE }));
+            return;
+        }
+        result.unwrap();
+        // only the first member's is wrong.
+        let matches: Vec<_> = deflator.members().iter().map(|m| m.hcrc_matches).collect();
+        assert_eq!(matches[..2], [false, true]);
+        let warnings: Vec<_> = deflator
+            .take_events()
+            .into_iter()
+            .filter(|event| matches!(event, DecodeEvent::HeaderCrcMismatch { .. }))
+            .collect();
+        match policy {
+            HeaderValidation::Warn => assert!(matches!(
+                warnings[..],
+                [DecodeEvent::HeaderCrcMismatch { expected: 0xE8EE, found: 0xE7EE, .. }]
+            )),
+            _ => assert!(warnings.is_empty()),
+        }
+    }
+
+    #[rstest]
+    #[case::recover(true)]
+    #[case::fail(false)]
+    pub fn test_recover(#[case] recover: bool) {
+        let members: Vec<Vec<u8>> = [&b"first member"[..], b"second member", b"third member"]
+            .iter()
+            .map(|text| {
+                let mut e = GzEncoder::new(Vec::new(), Compression::fast());
+                e.write_all(text).unwrap();
+                e.finish().unwrap()
+            })
+            .collect();
+        // BTYPE 0b11 for the second member's block, whose header is straight after the 10 byte member header.
+        let mut input = members.concat();
+        input[members[0].len() + 10] |= 0b110;
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), checkpointer());
+        deflator.set_options(DeflatorOptions {
+            recover,
+            ..Default::default()
+        });
+        deflator.record_events();
+        let mut dest = Vec::new();
+        let result = deflator.read_to_end(&mut dest);
+
+        if !recover {
+            let err = result.unwrap_err().into_inner().unwrap().downcast::<CorniferError>().unwrap();
+            assert!(matches!(*err, CorniferError::InvalidBlockType));
+            return;
+        }
+        result.unwrap();
+        assert_eq!(dest, b"first memberthird member");
+        let gap = Gap {
+            from_byte: members[0].len(),
+            to_byte: members[0].len() + members[1].len(),
+            uncompressed_from: 12,
+            uncompressed_to: 12,
+            error: "Block type 0b11 not supported".to_string(),
+        };
+        assert!(deflator.take_events().contains(&DecodeEvent::Skipped { gap: gap.clone() }));
+        assert_eq!(deflator.gaps(), [gap]);
+        assert_eq!(deflator.member_count(), 2);
+        assert_eq!(deflator.total_crc32(), CRC32.checksum(&dest));
+    }
+
+    #[rstest]
+    #[case::no_limits(None, None, None)]
+    #[case::generous(Some(1 << 24), Some(2000.0), None)]
+    #[case::max_output(Some(100_000), None, Some(100_001))]
+    #[case::ratio(None, Some(50.0), Some(2 << 20))]
+    pub fn test_output_limits(
+        #[case] max_output_bytes: Option<u64>,
+        #[case] max_expansion_ratio: Option<f64>,
+        #[case] stopped_by: Option<u64>,
+    ) {
+        // 4MiB of zeros compresses about a thousand to one.
+        let mut e = GzEncoder::new(Vec::new(), Compression::best());
+        e.write_all(&vec![0; 4 << 20]).unwrap();
+        let input = e.finish().unwrap();
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), Checkpointer::none());
+        deflator.set_options(DeflatorOptions {
+            max_output_bytes,
+            max_expansion_ratio,
+            ..Default::default()
+        });
+        let mut dest = Vec::new();
+        let result = deflator.read_to_end(&mut dest);
+
+        let Some(stopped_by) = stopped_by else {
+            result.unwrap();
+            assert_eq!(dest.len(), 4 << 20);
+            return;
+        };
+        let err = result.unwrap_err().into_inner().unwrap().downcast::<CorniferError>().unwrap();
+        assert!(matches!(*err, CorniferError::OutputLimitExceeded { .. }));
+        // a match can be decoded ahead of what's been read, so it stops a little before the limit, not after.
+        assert!(dest.len() as u64 <= stopped_by, "{}", dest.len());
+    }
+
+    #[rstest]
+    pub fn test_large_buffer_read_fills_buffer() {
+        let input: &[u8] = include_bytes!("../testfiles/1080-0.txt.gz");
+        let expected = include_bytes!("../testfiles/1080-0.txt");
+        let reader = CorniferByteReader::new(input);
+        let mut deflator = Deflator::new(reader, checkpointer());
+        let mut dest = vec![0; 1 << 17];
+
+        // the whole file is several blocks, and should come out in one go. The buffer is also too long for a u16.
+        let n = deflator.read(&mut dest).unwrap();
+        assert_eq!(&dest[..n], expected);
+        assert_eq!(deflator.read(&mut dest).unwrap(), 0);
+    }
+
+    #[rstest]
+    pub fn test_read_vectored() {
+        let input: &[u8] = include_bytes!("../testfiles/1080-0.txt.gz");
+        let expected = include_bytes!("../testfiles/1080-0.txt");
+        let reader = CorniferByteReader::new(input);
+        let mut deflator = Deflator::new(reader, checkpointer());
+        let (mut a, mut b, mut c) = (vec![0; 7], vec![0; 0], vec![0; 5000]);
+        let mut bufs = [
+            IoSliceMut::new(&mut a),
+            IoSliceMut::new(&mut b),
+            IoSliceMut::new(&mut c),
+        ];
+
+        let n = deflator.read_vectored(&mut bufs).unwrap();
+        assert_eq!(n, 5007);
+        assert_eq!(a, &expected[..7]);
+        assert_eq!(c, &expected[7..5007]);
+    }
+
+    #[rstest]
+    pub fn test_error_after_partial_read_is_deferred() {
+        let mut e = GzEncoder::new(Vec::new(), Compression::fast());
+        e.write_all(b"hello world").unwrap();
+        let mut v = e.finish().unwrap();
+        v.extend([0; 4]);
+        let reader = CorniferByteReader::new(v.as_slice());
+        let mut deflator = Deflator::new(reader, checkpointer());
+        let mut dest = vec![0; 100];
+
+        assert_eq!(deflator.read(&mut dest).unwrap(), 11);
+        assert!(deflator.read(&mut dest).is_err());
+    }
+
+    #[rstest]
+    #[cfg_attr(feature = "sqlite", case::with_checkpoints(true))]
+    #[case::without(false)]
+    pub fn test_eof_in_block(#[case] checkpoints: bool) {
+        let input = include_bytes!("../testfiles/1080-0.txt.gz");
+        let checkpointer = match checkpoints {
+            true => checkpointer(),
+            false => Checkpointer::none(),
+        };
+        // partway through the first block.
+        let mut deflator = Deflator::new(CorniferByteReader::new(&input[..1000]), checkpointer);
+        let mut out = Vec::new();
+        let err = deflator.read_to_end(&mut out).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+        let err = err.into_inner().unwrap().downcast::<CorniferError>().unwrap();
+        let CorniferError::UnexpectedEofInBlock { block_id, compressed_byte, uncompressed_byte } = *err else {
+            panic!("expected UnexpectedEofInBlock, got {err:?}");
+        };
+        assert_eq!(block_id, checkpoints.then_some(1));
+        assert_eq!(compressed_byte, 1000);
+        assert_eq!(uncompressed_byte, out.len());
+    }
+
+    #[rstest]
+    #[case::empty(|_| 0)]
+    #[case::in_the_header(|_| 5)]
+    #[case::in_the_footer(|len| len - 4)]
+    pub fn test_eof_outside_blocks(#[case] keep: fn(usize) -> usize) {
+        let mut e = GzEncoder::new(Vec::new(), Compression::fast());
+        e.write_all(b"hello world").unwrap();
+        let mut input = e.finish().unwrap();
+        input.truncate(keep(input.len()));
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), Checkpointer::none());
+        let err = deflator.read_to_end(&mut Vec::new()).unwrap_err();
+        let err = err.into_inner().unwrap().downcast::<CorniferError>().unwrap();
+        assert!(matches!(*err, CorniferError::EOF));
+    }
+
+    #[rstest]
+    pub fn test_clean_eof() {
+        let mut e = GzEncoder::new(Vec::new(), Compression::fast());
+        e.write_all(b"hello world").unwrap();
+        let input = e.finish().unwrap();
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), Checkpointer::none());
+        let mut out = Vec::new();
+        deflator.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"hello world");
+        // only the end of the last member is Ok(0), every time.
+        assert_eq!(deflator.read(&mut [0; 10]).unwrap(), 0);
+        assert_eq!(deflator.read(&mut [0; 10]).unwrap(), 0);
+    }
+
+    // anything a real compressor made is fine, including distances right up to the start of each member.
+    #[rstest]
+    #[case::one_member(&include_bytes!("../testfiles/1080-0.txt.gz")[..])]
+    #[case::anthems(&include_bytes!("../testfiles/anthems.txt.gz")[..])]
+    #[case::concatenated(&include_bytes!("../testfiles/testCompressThenConcat.txt.gz")[..])]
+    pub fn test_strict(#[case] input: &[u8]) {
+        let decode = |strict| {
+            let mut deflator = Deflator::new(CorniferByteReader::new(input), Checkpointer::none());
+            deflator.set_options(DeflatorOptions {
+                strict,
+                ..Default::default()
+            });
+            let mut out = Vec::new();
+            deflator.read_to_end(&mut out).unwrap();
+            out
+        };
+        assert_eq!(decode(true), decode(false));
+    }
+
+    #[rstest]
+    #[case::off(0)]
+    #[case::smaller_than_some_reads(5)]
+    #[case::bigger_than_most(4096)]
+    pub fn test_staging(#[case] staging_size: usize) {
+        let input: &[u8] = include_bytes!("../testfiles/1080-0.txt.gz");
+        let expected = include_bytes!("../testfiles/1080-0.txt");
+        let mut deflator = Deflator::new(CorniferByteReader::new(input), checkpointer());
+        deflator.set_staging_size(staging_size);
+        // some reads go through the staging buffer and some straight past it, after what's left in it.
+        let mut out: Vec<u8> = Vec::new();
+        for len in [1, 7, 3, 10000, 1].iter().cycle() {
+            let mut buf = vec![0; *len];
+            match deflator.read(&mut buf).unwrap() {
+                0 => break,
+                n => out.extend(&buf[..n]),
+            }
+        }
+        assert_eq!(out, expected);
+    }
+
+    #[rstest]
+    // up to one match further, whose end is decoded with its start.
+    #[case::off(0, 1..259)]
+    #[case::on(4096, 4096..4355)]
+    pub fn test_staging_decodes_ahead(#[case] staging_size: usize, #[case] expected: std::ops::Range<usize>) {
+        let input: &[u8] = include_bytes!("../testfiles/1080-0.txt.gz");
+        let mut deflator = Deflator::new(CorniferByteReader::new(input), checkpointer());
+        deflator.set_staging_size(staging_size);
+        deflator.read_exact(&mut [0; 1]).unwrap();
+        assert!(expected.contains(&deflator.position().uncompressed_byte));
+    }
+
+    #[rstest]
+    pub fn test_staging_error_is_deferred() {
+        let mut e = GzEncoder::new(Vec::new(), Compression::fast());
+        e.write_all(b"hello world").unwrap();
+        let mut v = e.finish().unwrap();
+        v.extend([0; 4]);
+        let mut deflator = Deflator::new(CorniferByteReader::new(v.as_slice()), checkpointer());
+        deflator.set_staging_size(4096);
+        let mut out: Vec<u8> = Vec::new();
+        let err = loop {
+            let mut buf = [0; 1];
+            match deflator.read(&mut buf) {
+                Ok(n) => out.extend(&buf[..n]),
+                Err(err) => break err,
+            }
+        };
+        // every byte before the error still comes out, one at a time.
+        assert_eq!(out, b"hello world");
+        assert!(err.into_inner().unwrap().downcast::<CorniferError>().is_ok());
+    }
+
+    #[rstest]
+    pub fn test_literals_before_error_are_returned() {
+        // no repeats, so it's one fixed block of nothing but literals.
+        let text = b"abcdefghijklmnopqrstuvwxyz";
+        let mut e = GzEncoder::new(Vec::new(), Compression::best());
+        e.write_all(text).unwrap();
+        let input = e.finish().unwrap();
+        // cut off the footer and the end of the block.
+        let input = &input[..input.len() - 10];
+        let mut deflator = Deflator::new(CorniferByteReader::new(input), checkpointer());
+        let mut dest = vec![0; 100];
+        let n = deflator.read(&mut dest).unwrap();
+        assert!(n >= 20);
+        assert_eq!(dest[..n], text[..n]);
+        assert!(deflator.read(&mut dest).is_err());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[rstest]
+    pub fn test_stored_blocks_are_finished() {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let mut e = GzEncoder::new(Vec::new(), Compression::none());
+        e.write_all(text).unwrap();
+        let input = e.finish().unwrap();
+        let path = TempIndex::new();
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), Checkpointer::init(&path).unwrap());
+        std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        assert_eq!(deflator.checkpointer_mut().unfinished_blocks().unwrap(), 0);
+        drop(deflator);
+
+        let blocks = CheckpointStore::open(&path).unwrap().blocks().unwrap();
+        assert!(blocks.len() > 1);
+        assert!(blocks.iter().all(|b| b.block_type == "nocompression"));
+        assert_eq!(blocks.iter().map(|b| b.len).sum::<u64>(), text.len() as u64);
+        // each stored block is a 5 byte header and then the data.
+        for block in &blocks {
+            assert_eq!(block.block_len_bits, block.len * 8 + block.header_len_bits);
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[rstest]
+    pub fn test_whole_file_crc_is_recorded() {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let mut input = Vec::new();
+        for part in [&text[..20000], &text[20000..]] {
+            let mut e = GzEncoder::new(Vec::new(), Compression::default());
+            e.write_all(part).unwrap();
+            input.extend(e.finish().unwrap());
+        }
+        let path = TempIndex::new();
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), Checkpointer::init(&path).unwrap());
+        std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        // the members' crcs combine into the crc of the whole thing.
+        assert_eq!(deflator.total_crc32(), CRC32.checksum(text));
+        drop(deflator);
+
+        let meta = CheckpointStore::open(&path).unwrap().meta().unwrap();
+        assert!(meta.complete);
+        assert_eq!(meta.uncompressed_len, Some(text.len() as u64));
+        assert_eq!(meta.crc32, Some(CRC32.checksum(text)));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[rstest]
+    #[case::newline(b'\n')]
+    #[case::comma(b',')]
+    pub fn test_records(#[case] delimiter: u8) {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let input = include_bytes!("../testfiles/1080-0.txt.gz");
+        let path = TempIndex::new();
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), Checkpointer::init(&path).unwrap());
+        let records: Vec<_> = deflator.records(delimiter).collect::<Result<_, _>>().unwrap();
+
+        let expected: Vec<_> = text.split(|b| *b == delimiter).collect();
+        // the text ends with a delimiter or it doesn't, and there's no empty record after it either way.
+        let expected = match expected.split_last() {
+            Some((&[], rest)) => rest,
+            _ => &expected[..],
+        };
+        assert_eq!(records.len(), expected.len());
+        for ((offset, record), expected) in records.iter().zip(expected) {
+            assert_eq!(record, expected);
+            assert_eq!(&text[*offset as usize..*offset as usize + record.len()], *expected);
+        }
+        drop(deflator);
+        // and the checkpoints were written on the way.
+        let meta = CheckpointStore::open(&path).unwrap().meta().unwrap();
+        assert_eq!(meta.uncompressed_len, Some(text.len() as u64));
+    }
+
+    #[rstest]
+    pub fn test_records_after_staged_read() {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let input = include_bytes!("../testfiles/1080-0.txt.gz");
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), checkpointer());
+        deflator.set_staging_size(4096);
+        deflator.read_exact(&mut [0; 5]).unwrap();
+        // the rest of what was staged isn't lost, and the offsets still line up.
+        let (offset, record) = deflator.records(b'\n').next().unwrap().unwrap();
+        assert_eq!(offset, 5);
+        assert_eq!(record, &text[5..text.iter().position(|b| *b == b'\n').unwrap()]);
+    }
+
+    #[rstest]
+    pub fn test_records_error() {
+        let input = include_bytes!("../testfiles/1080-0.txt.gz");
+        let mut deflator = Deflator::new(CorniferByteReader::new(&input[..1000]), checkpointer());
+        let records: Vec<_> = deflator.records(b'\n').collect();
+        // whatever came before the input ran out, then the error, then nothing.
+        assert!(records.len() > 1);
+        assert!(records[..records.len() - 1].iter().all(|r| r.is_ok()));
+        assert!(records.last().unwrap().is_err());
+    }
+
+    #[rstest]
+    pub fn test_events() {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        // two members, the first with a flush in the middle.
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(&text[..20000]).unwrap();
+        e.flush().unwrap();
+        e.write_all(&text[20000..]).unwrap();
+        let mut input = e.finish().unwrap();
+        let second_member_from = input.len();
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(b"hello").unwrap();
+        input.extend(e.finish().unwrap());
+
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), checkpointer());
+        deflator.record_events();
+        let mut out = vec![0; 10000];
+        deflator.read_exact(&mut out).unwrap();
+        let first = deflator.take_events();
+        assert!(matches!(first[0], DecodeEvent::MemberStarted { position } if position.compressed_byte == 0));
+        assert!(matches!(first[1], DecodeEvent::BlockStarted { .. }));
+        std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        let events = [first, deflator.take_events()].concat();
+        // it's all been taken.
+        assert_eq!(deflator.take_events(), Vec::new());
+
+        let starts = events.iter().filter(|e| matches!(e, DecodeEvent::BlockStarted { .. })).count();
+        let ends = events.iter().filter(|e| matches!(e, DecodeEvent::BlockEnded { .. })).count();
+        assert_eq!(starts, ends);
+        assert!(starts >= 3);
+        // each block ends where the next one starts.
+        for pair in events.windows(2) {
+            if let [DecodeEvent::BlockEnded { position: end }, DecodeEvent::BlockStarted { position: start, .. }] = pair {
+                assert_eq!(end, start);
+            }
+        }
+        let members: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                DecodeEvent::MemberStarted { position } => Some((position.compressed_byte, position.uncompressed_byte)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(members, vec![(0, 0), (second_member_from, text.len())]);
+        assert_eq!(
+            events.last(),
+            Some(&DecodeEvent::MemberEnded {
+                position: deflator.position(),
+                len: 5,
+                crc32: CRC32.checksum(b"hello"),
+            })
+        );
+    }
+
+    #[rstest]
+    pub fn test_no_events_unless_recorded() {
+        let input = include_bytes!("../testfiles/1080-0.txt.gz");
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), checkpointer());
+        std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        assert_eq!(deflator.take_events(), Vec::new());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[rstest]
+    #[case::compressed(Compression::default())]
+    #[case::stored(Compression::none())]
+    pub fn test_stats(#[case] level: Compression) {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let mut e = GzEncoder::new(Vec::new(), level);
+        e.write_all(&text[..20000]).unwrap();
+        e.flush().unwrap();
+        e.write_all(&text[20000..]).unwrap();
+        let input = e.finish().unwrap();
+
+        let path = TempIndex::new();
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), Checkpointer::init(&path).unwrap());
+        assert_eq!(deflator.stats(), None);
+        deflator.collect_stats();
+        std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        let stats = deflator.stats().unwrap();
+
+        let num_blocks = stats.stored_blocks + stats.fixed_blocks + stats.dynamic_blocks;
+        assert_eq!(num_blocks, stats.blocks.len() as u64);
+        assert_eq!(stats.uncompressed_len, text.len() as u64);
+        assert_eq!(stats.blocks.iter().map(|b| b.uncompressed_len).sum::<u64>(), stats.uncompressed_len);
+        assert_eq!(stats.blocks.iter().map(|b| b.compressed_bits).sum::<u64>(), stats.compressed_bits);
+        // the blocks are everything between the 10 byte header and the 8 byte footer.
+        assert_eq!(stats.compressed_bits.div_ceil(8), input.len() as u64 - 18);
+        assert_eq!(stats.match_lengths.iter().sum::<u64>(), stats.matches);
+        let matched: u64 = stats.match_lengths.iter().enumerate().map(|(len, n)| len as u64 * n).sum();
+        if level == Compression::none() {
+            assert_eq!(stats.stored_blocks, num_blocks);
+            assert_eq!((stats.literals, stats.matches), (0, 0));
+            assert!(stats.ratio() < 1.0);
+        } else {
+            assert_eq!(stats.literals + matched, text.len() as u64);
+            assert!(stats.matches > 0);
+            assert!(stats.ratio() > 2.0);
+        }
+        assert_eq!(stats.blocks.iter().map(|b| b.literals).sum::<u64>(), stats.literals);
+        assert_eq!(stats.blocks.iter().map(|b| b.matches).sum::<u64>(), stats.matches);
+        let longest = stats.match_lengths.iter().rposition(|n| *n > 0).unwrap_or(0);
+        assert_eq!(stats.blocks.iter().map(|b| b.longest_match).max(), Some(longest as u16));
+
+        // and each block's are in the checkpoint file.
+        let stats = stats.clone();
+        drop(deflator);
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        let mut stmt = conn.prepare("SELECT literals, matches, longest_match FROM DeflateBlock ORDER BY to_byte, id").unwrap();
+        let rows = stmt.query_map((), |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))).unwrap();
+        let rows: Vec<(u64, u64, u16)> = rows.map(Result::unwrap).collect();
+        let expected: Vec<_> = stats.blocks.iter().map(|b| (b.literals, b.matches, b.longest_match)).collect();
+        assert_eq!(rows, expected);
+        drop(stmt);
+        drop(conn);
+    }
+
+    #[rstest]
+    pub fn test_without_checkpoints() {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let input = include_bytes!("../testfiles/1080-0.txt.gz");
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), Checkpointer::none());
+        let mut out = Vec::new();
+        deflator.read_to_end(&mut out).unwrap();
+        assert_eq!(out, text);
+        assert_eq!(deflator.member_count(), 1);
+        // they're only kept if asked for.
+        assert!(deflator.members().is_empty());
+        assert_eq!(deflator.total_crc32(), CRC32.checksum(text));
+        assert_eq!(deflator.checkpointer_mut().finished_blocks(), 1);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[rstest]
+    pub fn test_update() {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let gzip = |data: &[u8]| {
+            let mut e = GzEncoder::new(Vec::new(), Compression::default());
+            e.write_all(data).unwrap();
+            e.finish().unwrap()
+        };
+        let mut input = gzip(&text[..20000]);
+        let first_len = input.len() as u64;
+        let path = TempIndex::new();
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), Checkpointer::init(&path).unwrap());
+        std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        drop(deflator);
+        assert_eq!(CheckpointStore::open(&path).unwrap().end().unwrap().compressed_byte, first_len);
+
+        // a log rotated into the end of the file.
+        input.extend(gzip(&text[20000..30000]));
+        input.extend(gzip(&text[30000..]));
+        let mut deflator = Deflator::update(std::io::Cursor::new(input.as_slice()), &path).unwrap();
+        deflator.set_options(DeflatorOptions {
+            keep_members: true,
+            ..Default::default()
+        });
+        assert_eq!(deflator.position().compressed_byte, first_len as usize);
+        std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        assert_eq!(deflator.members().len(), 2);
+        assert_eq!(deflator.members()[0].to_byte, 20000);
+        drop(deflator);
+
+        let store = CheckpointStore::open(&path).unwrap();
+        let meta = store.meta().unwrap();
+        assert!(meta.complete);
+        assert_eq!(meta.uncompressed_len, Some(text.len() as u64));
+        assert_eq!(meta.crc32, Some(CRC32.checksum(text)));
+        assert_eq!(store.members().unwrap().len(), 3);
+        assert_eq!(store.end().unwrap().compressed_byte, input.len() as u64);
+
+        // if the file's been rewritten instead, the last member isn't where it was.
+        let other = gzip(&text[1..]);
+        assert!(matches!(
+            Deflator::update(std::io::Cursor::new(other.as_slice()), &path),
+            Err(CorniferError::FileChanged { .. })
+        ));
+    }
+
+    // stopping partway, saving the state and carrying on from it in a new Deflator gives the same output, and a
+    // checkpoint file that reads back the same.
+    #[cfg(feature = "sqlite")]
+    #[rstest]
+    #[case::at_the_start(0, 0)]
+    #[case::in_a_dynamic_block(1000, 0)]
+    #[case::with_output_staged(1000, 4096)]
+    #[case::in_a_stored_block(39819 + 100, 0)]
+    #[case::in_the_last_member(2 * 39819 + 5, 0)]
+    pub fn test_save_state(#[case] stop_at: usize, #[case] staging_size: usize) {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let parts = [
+            (&text[..], Compression::default()),
+            (&text[..], Compression::none()),
+            (b"hello world", Compression::default()),
+        ];
+        let mut input = Vec::new();
+        for (part, level) in parts {
+            let mut e = GzEncoder::new(Vec::new(), level);
+            e.write_all(part).unwrap();
+            input.extend(e.finish().unwrap());
+        }
+        let expected = parts.map(|(part, _)| part).concat();
+        let path = TempIndex::new();
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), Checkpointer::init(&path).unwrap());
+        deflator.set_staging_size(staging_size);
+        deflator.set_tick_trigger(|context| context.since_checkpoint >= 10000);
+        let mut dest = vec![0; stop_at];
+        for chunk in dest.chunks_mut(100) {
+            deflator.read_exact(chunk).unwrap();
+        }
+        let saved = deflator.save_state().unwrap();
+        drop(deflator);
+        #[cfg(feature = "serde")]
+        let saved = serde_json::from_str(&serde_json::to_string(&saved).unwrap()).unwrap();
+
+        let checkpointer = Checkpointer::open(&path, CreateMode::Append).unwrap();
+        let mut deflator = Deflator::load_state(std::io::Cursor::new(input.as_slice()), saved, checkpointer).unwrap();
+        deflator.set_tick_trigger(|context| context.since_checkpoint >= 10000);
+        deflator.read_to_end(&mut dest).unwrap();
+        assert_eq!(dest, expected);
+        assert_eq!(deflator.member_count(), 3);
+        assert_eq!(deflator.total_crc32(), CRC32.checksum(&expected));
+        drop(deflator);
+
+        let store = CheckpointStore::open(&path).unwrap();
+        assert!(store.meta().unwrap().complete);
+        assert!(store.unfinished_blocks().unwrap().is_empty());
+        assert_eq!(store.blocks().unwrap().iter().map(|b| b.len).sum::<u64>(), expected.len() as u64);
+        let mut reader = CheckpointedReader::open(std::io::Cursor::new(input.as_slice()), &path).unwrap();
+        reader.seek(SeekFrom::Start(stop_at.saturating_sub(500) as u64)).unwrap();
+        let mut read_back = Vec::new();
+        reader.read_to_end(&mut read_back).unwrap();
+        assert_eq!(read_back, &expected[stop_at.saturating_sub(500)..]);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[rstest]
+    #[case::first_block(100)]
+    #[case::first_member(30000)]
+    #[case::between_members(40000)]
+    #[case::second_member(70000)]
+    #[case::complete(100000)]
+    pub fn test_repair(#[case] cut: u64) {
+        // two members in 4000 byte blocks, with a window every 10000 bytes.
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let input = flushed_gzip(text, 2, 4000);
+        let path = TempIndex::new();
+        let mut checkpointer = Checkpointer::init(&path).unwrap();
+        checkpointer.set_window_spacing(10000);
+        checkpointer.keep_partial().unwrap();
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), checkpointer);
+        std::io::copy(&mut (&mut deflator).take(cut), &mut std::io::sink()).unwrap();
+        drop(deflator);
+        if cut < text.len() as u64 * 2 {
+            // as if it had been killed partway through a block.
+            let sql = "UPDATE DeflateBlock SET len = NULL, crc32 = NULL WHERE id = (SELECT max(id) FROM DeflateBlock)";
+            rusqlite::Connection::open(&path).unwrap().execute_batch(sql).unwrap();
+        }
+
+        let mut deflator = Deflator::repair(std::io::Cursor::new(input.as_slice()), &path).unwrap();
+        std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        drop(deflator);
+        assert_eq!(crate::fsck::check_index(&path).unwrap(), Vec::new());
+        let store = CheckpointStore::open(&path).unwrap();
+        assert_eq!(store.members().unwrap().len(), 2);
+        let mut reader = CheckpointedReader::open(std::io::Cursor::new(input.as_slice()), &path).unwrap();
+        reader.verify().unwrap();
+        let mut data = Vec::new();
+        reader.rewind().unwrap();
+        reader.read_to_end(&mut data).unwrap();
+        assert!(data == text.repeat(2));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[rstest]
+    pub fn test_repair_not_gzip() {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
+        e.write_all(text).unwrap();
+        let input = e.finish().unwrap();
+        let path = TempIndex::new();
+        let checkpointer = Checkpointer::init(&path).unwrap();
+        let mut deflator = Deflator::with_format(CorniferByteReader::new(input.as_slice()), checkpointer, Format::Zlib).unwrap();
+        std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        drop(deflator);
+        assert!(matches!(
+            Deflator::repair(std::io::Cursor::new(input.as_slice()), &path),
+            Err(CorniferError::NotGzipIndex)
+        ));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[rstest]
+    pub fn test_unfinished_blocks() {
+        let input = include_bytes!("../testfiles/1080-0.txt.gz");
+        let mut deflator = Deflator::new(CorniferByteReader::new(&input[..1000]), checkpointer());
+        assert!(std::io::copy(&mut deflator, &mut std::io::sink()).is_err());
+        assert_eq!(deflator.checkpointer_mut().unfinished_blocks().unwrap(), 1);
+        // it ran out of input partway through.
+        let position = deflator.position();
+        assert_eq!(position.compressed_byte, 1000);
+        assert!(position.uncompressed_byte > 0);
+    }
+
+    #[rstest]
+    pub fn test_cancel() {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        // flushing ends the block, so there's a boundary in the middle.
+        e.write_all(&text[..20000]).unwrap();
+        e.flush().unwrap();
+        e.write_all(&text[20000..]).unwrap();
+        let input = e.finish().unwrap();
+        let reader = CorniferByteReader::new(input.as_slice());
+        let mut deflator = Deflator::new(reader, checkpointer());
+        let token = CancelToken::new();
+        deflator.set_cancel_token(token.clone());
+        let mut dest = vec![0; 10];
+
+        deflator.read_exact(&mut dest).unwrap();
+        token.cancel();
+        // we're partway through the first block, so the rest of it still comes out.
+        let mut rest = Vec::new();
+        let err = deflator.read_to_end(&mut rest).unwrap_err();
+        assert!(err.to_string().starts_with("Cancelled at"));
+        assert_eq!(rest, &text[10..20000]);
+    }
+
+    #[rstest]
+    pub fn test_deadline() {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(&text[..20000]).unwrap();
+        e.flush().unwrap();
+        e.write_all(&text[20000..]).unwrap();
+        let input = e.finish().unwrap();
+        let reader = CorniferByteReader::new(input.as_slice());
+        let mut deflator = Deflator::new(reader, checkpointer());
+        deflator.set_options(DeflatorOptions {
+            deadline: Some(Duration::from_millis(50)),
+            ..Default::default()
+        });
+        let mut dest = vec![0; 10];
+
+        deflator.read_exact(&mut dest).unwrap();
+        std::thread::sleep(Duration::from_millis(60));
+        // same as cancelling, the rest of the block still comes out.
+        let mut rest = Vec::new();
+        let err = deflator.read_to_end(&mut rest).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        let err = err.into_inner().unwrap().downcast::<CorniferError>().unwrap();
+        assert!(matches!(*err, CorniferError::DeadlineExceeded { uncompressed_byte: 20000, .. }));
+        assert_eq!(rest, &text[10..20000]);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[rstest]
+    pub fn test_resume_in_block() {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let input = include_bytes!("../testfiles/1080-0.txt.gz");
+        let path = TempIndex::new();
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), Checkpointer::init(&path).unwrap());
+        deflator.set_tick_trigger(|context| context.since_checkpoint >= 10000);
+        std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        drop(deflator);
+
+        let store = CheckpointStore::open(&path).unwrap();
+        // the whole file is one dynamic block, so every tick is in the middle of it.
+        let blocks = store.blocks().unwrap();
+        assert_eq!(blocks.len(), 1);
+        let ticks = store.ticks_for(&blocks[0]).unwrap();
+        assert!(ticks.len() >= 3);
+        for tick in ticks {
+            let trees = store.get_trees(tick.block_id).unwrap().unwrap();
+            let window = store.get_tick_window(tick.id).unwrap();
+            let reader = CorniferByteReader::new(&input[tick.from_byte as usize..]);
+            let mut deflator =
+                Deflator::resume_in_block(reader, tick.from_bit, &window, trees, true, Checkpointer::none()).unwrap();
+            let mut dest = Vec::new();
+            deflator.read_to_end(&mut dest).unwrap();
+            assert_eq!(dest, &text[tick.to_byte as usize..]);
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[rstest]
+    pub fn test_tick_trigger() {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let input = include_bytes!("../testfiles/1080-0.txt.gz");
+        let path = TempIndex::new();
+        let reader = CorniferByteReader::new(input.as_slice());
+        let mut deflator = Deflator::new(reader, Checkpointer::init(&path).unwrap());
+        // a tick at the first line break after every 4kb.
+        let seen: Arc<Mutex<Vec<TickContext>>> = Arc::default();
+        let seen_by_trigger = seen.clone();
+        deflator.set_tick_trigger(move |context| {
+            let tick = context.since_checkpoint >= 4096 && context.last_byte == b'\n';
+            if tick {
+                seen_by_trigger.lock().unwrap().push(context.clone());
+            }
+            tick
+        });
+        let mut dest = Vec::new();
+        deflator.read_to_end(&mut dest).unwrap();
+        drop(deflator);
+        assert_eq!(dest, text);
+
+        let seen = seen.lock().unwrap();
+        assert!(seen.len() >= 5);
+        let mut last = 0;
+        for context in seen.iter() {
+            assert_eq!(text[context.to_byte - 1], b'\n');
+            assert_eq!(context.to_byte - last, context.since_checkpoint);
+            last = context.to_byte;
+        }
+
+        let store = CheckpointStore::open(&path).unwrap();
+        let blocks = store.blocks().unwrap();
+        assert_eq!(blocks.len(), 1);
+        let ticks = store.ticks_for(&blocks[0]).unwrap();
+        let positions: Vec<_> = ticks.iter().map(|t| (t.from_byte as usize, t.from_bit, t.to_byte as usize)).collect();
+        let expected: Vec<_> = seen.iter().map(|c| (c.from_byte, c.from_bit, c.to_byte)).collect();
+        assert_eq!(positions, expected);
+        let window = store.get_tick_window(ticks[0].id).unwrap();
+        assert!(window.ends_with(&text[..seen[0].to_byte]));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[rstest]
+    pub fn test_resume_from_checkpoint() {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(&text[..20000]).unwrap();
+        e.flush().unwrap();
+        e.write_all(&text[20000..]).unwrap();
+        let input = e.finish().unwrap();
+        let path = TempIndex::new();
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), Checkpointer::init(&path).unwrap());
+        std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        drop(deflator);
+        let blocks = CheckpointStore::open(&path).unwrap().blocks().unwrap();
+        let block = blocks.iter().find(|b| b.to_byte == 20000 && b.len > 0).unwrap();
+
+        // what the buffer looks like at the start of the block.
+        let mut buffer = CircularBuffer::new(32768);
+        for byte in &text[..20000] {
+            buffer.push(*byte);
+        }
+        buffer.block_crc32();
+        let snapshot = buffer.snapshot().unwrap();
+
+        let mut reader = CorniferByteReader::new(&input[block.from_byte as usize..]);
+        reader.current_byte = block.from_byte as usize;
+        let mut deflator =
+            Deflator::resume_from_checkpoint(reader, block.from_bit, &snapshot, checkpointer()).unwrap();
+        // footer checks are still strict, so this fails if the CRC or ISIZE weren't carried over.
+        let mut dest = Vec::new();
+        deflator.read_to_end(&mut dest).unwrap();
+        assert_eq!(dest, &text[20000..]);
+    }
+
+    #[rstest]
+    pub fn test_window_snapshot() {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let mut input = Vec::new();
+        for part in [&text[..], b"hello world"] {
+            let mut e = GzEncoder::new(Vec::new(), Compression::default());
+            e.write_all(part).unwrap();
+            input.extend(e.finish().unwrap());
+        }
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), Checkpointer::none());
+        assert_eq!(deflator.window_snapshot(), Vec::<u8>::new());
+
+        let mut dest = vec![0; 1000];
+        deflator.read_exact(&mut dest).unwrap();
+        let end = deflator.position().uncompressed_byte;
+        assert_eq!(deflator.window_snapshot(), &text[..end]);
+        let mut dest = vec![0; 35000];
+        deflator.read_exact(&mut dest).unwrap();
+        let end = deflator.position().uncompressed_byte;
+        assert_eq!(deflator.window_snapshot(), &text[end - 32768..end]);
+
+        // partway into the second member, there's only that member's output.
+        let mut dest = vec![0; text.len() - 36000 + 5];
+        deflator.read_exact(&mut dest).unwrap();
+        let end = deflator.position().uncompressed_byte - text.len();
+        assert_eq!(deflator.window_snapshot(), &b"hello world"[..end]);
+    }
+
+    #[rstest]
+    pub fn test_reset() {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let first: &[u8] = include_bytes!("../testfiles/1080-0.txt.gz");
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(b"hello world").unwrap();
+        let second = e.finish().unwrap();
+
+        let mut deflator = Deflator::new(CorniferByteReader::new(first), checkpointer());
+        deflator.collect_stats();
+        // stop partway through, so there's state left over.
+        let mut dest = vec![0; 1000];
+        deflator.read_exact(&mut dest).unwrap();
+        assert_eq!(dest, &text[..1000]);
+
+        let old = deflator.reset(second.as_slice());
+        assert!(!old.is_empty() && old.len() < first.len());
+        assert_eq!(deflator.buffer.get_bytes_written(), 0);
+        assert_eq!(deflator.member_from_byte, 0);
+        assert_eq!(deflator.stats(), Some(&Stats::default()));
+        let mut dest = Vec::new();
+        deflator.read_to_end(&mut dest).unwrap();
+        assert_eq!(dest, b"hello world");
+        assert_eq!(deflator.member_count(), 1);
+        assert_eq!(deflator.stats().unwrap().uncompressed_len, 11);
+        // everything was read.
+        assert!(deflator.into_inner().is_empty());
+    }
+
+    // what a compressor might do with it: flushing every so often makes lots of small blocks, and long runs make
+    // a few enormous ones.
+    #[cfg(feature = "sqlite")]
+    fn spacing_input(kind: &str) -> (Vec<u8>, Vec<u8>, Format) {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let runs = |len| -> Vec<u8> { text.iter().cycle().take(len).chain(b"abc".iter().cycle().take(1_000_000)).copied().collect() };
+        let flushed = |data: Vec<u8>, every| {
+            let mut e = GzEncoder::new(Vec::new(), Compression::default());
+            for chunk in data.chunks(every) {
+                e.write_all(chunk).unwrap();
+                e.flush().unwrap();
+            }
+            (e.finish().unwrap(), data, Format::Gzip)
+        };
+        match kind {
+            "gzip" => (include_bytes!("../testfiles/1080-0.txt.gz").to_vec(), text.to_vec(), Format::Gzip),
+            "flushes" => flushed(text.repeat(8), 500),
+            // how pigz lays it out: 128KiB chunks, each ended with a sync flush.
+            "pigz" => flushed(text.repeat(16), 128 << 10),
+            "runs" => {
+                let data = runs(200_000);
+                let mut e = ZlibEncoder::new(Vec::new(), Compression::best());
+                e.write_all(&data).unwrap();
+                (e.finish().unwrap(), data, Format::Zlib)
+            }
+            "zopfli" => (include_bytes!("../testfiles/1080-0-abc.txt.gz").to_vec(), runs(60_000), Format::Gzip),
+            _ => unreachable!(),
+        }
+    }
+
+    #[rstest]
+    #[case::equal(CheckpointSpacing { min: 4096, max: 4096 })]
+    #[case::backwards(CheckpointSpacing { min: 65536, max: 4096 })]
+    fn test_checkpoint_spacing_rejected(#[case] spacing: CheckpointSpacing) {
+        let mut deflator = Deflator::new(CorniferByteReader::new(&[][..]), Checkpointer::none());
+        let result = deflator.set_checkpoint_spacing(spacing);
+        assert!(matches!(result, Err(CorniferError::InvalidCheckpointSpacing { .. })));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[rstest]
+    #[case::gzip("gzip")]
+    #[case::small_blocks("flushes")]
+    #[case::pigz("pigz")]
+    #[case::big_blocks("runs")]
+    #[case::zopfli("zopfli")]
+    pub fn test_checkpoint_spacing(#[case] kind: &str) {
+        let (input, text, format) = spacing_input(kind);
+        let spacing = CheckpointSpacing { min: 4096, max: 65536 };
+        let path = TempIndex::new();
+        let checkpointer = Checkpointer::init(&path).unwrap();
+        let mut deflator = Deflator::with_format(CorniferByteReader::new(input.as_slice()), checkpointer, format).unwrap();
+        deflator.set_checkpoint_spacing(spacing).unwrap();
+        let mut output = Vec::new();
+        deflator.read_to_end(&mut output).unwrap();
+        drop(deflator);
+        assert_eq!(output, text);
+
+        // where decoding can start from: blocks with a window, and ticks.
+        let store = CheckpointStore::open(&path).unwrap();
+        let blocks = store.blocks().unwrap();
+        let windowed: Vec<_> = blocks.iter().filter(|b| b.has_window && b.len > 0).map(|b| b.to_byte).collect();
+        assert!(windowed.windows(2).all(|w| w[1] - w[0] >= spacing.min), "{windowed:?}");
+        let mut starts = windowed.clone();
+        let mut ticks = Vec::new();
+        for block in &blocks {
+            for tick in store.ticks_for(block).unwrap() {
+                starts.push(tick.to_byte);
+                ticks.push((block.clone(), tick));
+            }
+        }
+        starts.push(text.len() as u64);
+        starts.sort_unstable();
+        assert!(starts.windows(2).all(|w| w[1] - w[0] <= spacing.max), "{starts:?}");
+        match kind {
+            "flushes" => assert!(windowed.len() * 4 < blocks.len()),
+            "runs" | "zopfli" => assert!(!ticks.is_empty(), "no ticks"),
+            _ => (),
+        }
+
+        // reading after a tick starts from it, so damage to the block before the tick doesn't matter.
+        for (block, tick) in &ticks {
+            let mut damaged = input.clone();
+            damaged[(block.from_byte + 1 + tick.from_byte) as usize / 2] ^= 0xff;
+            let mut reader = CheckpointedReader::open(std::io::Cursor::new(damaged), &path).unwrap();
+            let at = tick.to_byte as usize;
+            let len = 100.min(text.len() - at);
+            reader.seek(SeekFrom::Start(at as u64)).unwrap();
+            let mut dest = vec![0; len];
+            reader.read_exact(&mut dest).unwrap();
+            assert_eq!(dest, &text[at..at + len], "tick at {at}");
+        }
+
+        let mut reader = CheckpointedReader::open(std::io::Cursor::new(input.as_slice()), &path).unwrap();
+        for at in [0, 1000, text.len() / 3, text.len() / 2 + 7, text.len() - 100] {
+            reader.seek(SeekFrom::Start(at as u64)).unwrap();
+            let mut dest = vec![0; 100];
+            reader.read_exact(&mut dest).unwrap();
+            assert_eq!(dest, &text[at..at + 100], "at {at}");
+        }
+    }
 }
@@ -2,11 +2,41 @@ use std::string::FromUtf8Error;
 
 use thiserror::Error;
 
+use crate::format::Format;
+
+/// Broad classes of CorniferError, for callers that want to handle a class of failure without matching on
+/// every variant. The discriminants won't change.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[repr(u8)]
+pub enum ErrorKind {
+    /// Reading or writing a file failed.
+    Io = 0,
+    /// The compressed data is wrong somehow.
+    CorruptStream = 1,
+    /// The checkpoint database couldn't be read or written, or doesn't match the file.
+    CorruptIndex = 2,
+    /// Valid input, but something Cornifer doesn't handle.
+    Unsupported = 3,
+    /// Something was bigger than we're willing to deal with.
+    Limit = 4,
+    /// A CancelToken or a deadline stopped decoding.
+    Cancelled = 5,
+}
+
 #[derive(Error, Debug)]
 pub enum CorniferError {
     #[error("Buffer size too large")]
     BufferSizeTooLarge,
 
+    #[error("Stopped after decompressing {uncompressed_len} bytes from {compressed_len} bytes of input, which is more than allowed")]
+    OutputLimitExceeded { uncompressed_len: u64, compressed_len: u64 },
+
+    #[error("A memory budget of {budget} bytes is too small, indexing needs at least {minimum}")]
+    MemoryBudgetTooSmall { budget: usize, minimum: usize },
+
+    #[error("Checkpoint spacing of {min}:{max} can't be met, the minimum has to be less than the maximum")]
+    InvalidCheckpointSpacing { min: u64, max: u64 },
+
     /// Represents a failure to read from input.
     #[error("Read error")]
     ReadError { source: std::io::Error },
@@ -17,6 +47,115 @@ pub enum CorniferError {
     #[error("Header is not a GZIP header.")]
     NotGZIPHeader,
 
+    #[error("Could not work out the format of the file")]
+    UnknownFormat,
+
+    #[error("Cornifer can't checkpoint {format} files")]
+    UnsupportedFormat { format: Format },
+
+    #[error("Can't tell how big {format} files are without decompressing them")]
+    CantEstimate { format: Format },
+
+    #[error("Header is not a zlib header.")]
+    NotZlibHeader,
+
+    #[error("zlib streams with a preset dictionary are not supported")]
+    ZlibPresetDictionary,
+
+    #[error("zlib Adler-32 is incorrect at 0x{position:X}, expected 0x{expected:X} but got 0x{found:X}")]
+    InvalidZlibAdler32 {
+        position: usize,
+        expected: u32,
+        found: u32,
+    },
+
+    #[error("Header is not a BZIP2 header.")]
+    NotBZIP2Header,
+
+    #[error("Could not decode bzip2 block at 0x{position:X}:{bit}")]
+    InvalidBzip2Block { position: usize, bit: u8 },
+
+    #[error("No xz stream header at 0x{position:X}")]
+    NotXzHeader { position: u64 },
+
+    #[error("No xz stream footer at 0x{position:X}")]
+    NotXzFooter { position: u64 },
+
+    #[error("Invalid xz index at 0x{position:X}")]
+    InvalidXzIndex { position: u64 },
+
+    #[error("Could not decode xz block at 0x{position:X}")]
+    InvalidXzBlock { position: u64 },
+
+    #[error("Random access to {block_type} blocks is not supported")]
+    UnsupportedBlockType { block_type: String },
+
+    #[error("The checkpoint file {path} already exists")]
+    IndexAlreadyExists { path: String },
+
+    #[error("The checkpoint file {path} is being written to by something else")]
+    IndexBusy { path: String },
+
+    #[error("The checkpoint file doesn't cover the whole file")]
+    IndexIncomplete,
+
+    #[error("The checkpoint file says the decompressed file is {expected} bytes, but it's {found}")]
+    IndexLengthMismatch { expected: u64, found: u64 },
+
+    #[error("The checkpoint file says the decompressed file's CRC is 0x{expected:X}, but it's 0x{found:X}")]
+    IndexCrcMismatch { expected: u32, found: u32 },
+
+    #[error("Block {block_id} doesn't match the CRC in the checkpoint file, so either it or the file is corrupt")]
+    CorruptIndexOrData { block_id: i64 },
+
+    #[error("The compressed bytes of block {block_id}, from byte {from_byte}, don't match their CRC in the checkpoint file, so either it or the file is corrupt")]
+    CompressedCrcMismatch { block_id: i64, from_byte: u64 },
+
+    #[error("The checkpoint file has no CRCs of the compressed blocks to check against, it has to be indexed with them")]
+    NoBlockHashes,
+
+    #[error("The Huffman trees stored in the checkpoint file can't be read")]
+    InvalidHuffmanTrees,
+
+    #[error("Only checkpoint files for gzip files can be updated or repaired")]
+    NotGzipIndex,
+
+    #[error("Can't make a gzip member out of those blocks: {reason}")]
+    CantExtract { reason: String },
+
+    #[error("The file has changed since it was checkpointed, not just been added to (at 0x{position:X})")]
+    FileChanged { position: u64 },
+
+    #[error("The decompressed file is {found} bytes, but {expected} were expected")]
+    UnexpectedLength { expected: u64, found: u64 },
+
+    #[error("The decompressed file's CRC is 0x{found:X}, but 0x{expected:X} was expected")]
+    UnexpectedCrc32 { expected: u32, found: u32 },
+
+    #[error("Could not read the zip central directory at 0x{position:X}")]
+    InvalidZipDirectory { position: u64 },
+
+    #[error("Cancelled at 0x{position:X}")]
+    Cancelled { position: usize },
+
+    #[error("There's no file called {name} in the checkpoint file")]
+    UnknownSource { name: String },
+
+    #[error("There's no gzip member called {name} in the checkpoint file")]
+    NoSuchMember { name: String },
+
+    #[error("Ran out of time at 0x{compressed_byte:X}, after decompressing {uncompressed_byte} bytes")]
+    DeadlineExceeded { compressed_byte: usize, uncompressed_byte: usize },
+
+    #[error("Bytes {from} to {to} of the decompressed file were in a corrupt gzip member that was skipped")]
+    InGap { from: u64, to: u64 },
+
+    #[error("Unexpected data after the last GZIP member at 0x{position:X}")]
+    TrailingData { position: usize },
+
+    #[error("String at 0x{position:X} is longer than {max} bytes")]
+    StringTooLong { position: usize, max: usize },
+
     #[error("Compression method must be 8")]
     InvalidCompressionMethod,
 
@@ -50,26 +189,179 @@ pub enum CorniferError {
     #[error("Invalid length/distance code, got size {size} and lookback {lookback}")]
     InvalidLengthDistancePair { lookback: u16, size: u16 },
 
+    #[error("Distance {distance} at 0x{position:X} goes back past the start of the member, which has {available} bytes so far")]
+    DistanceTooFarBack { distance: u16, available: usize, position: usize },
+
     #[error("Tried to read too many bits at once, {num}")]
     InvalidNumberOfBits { num: u8 },
 
     #[error("Invalid Huffman code, {code} at position 0x{position:X}:{bit}")]
     InvalidHuffmanCode { code: u16, position: usize, bit: u8 },
 
+    #[error("Symbol {symbol} isn't used in DEFLATE, at position 0x{position:X}")]
+    UnusedSymbol { symbol: u16, position: usize },
+
     #[error("Invalid Dynamic Block due to attempting to copy a code length at 0")]
     InvalidDynamicBlockCodeLength,
 
+    #[error("Invalid dynamic block header at 0x{position:X}, {reason}")]
+    InvalidDynamicBlockHeader { position: usize, reason: &'static str },
+
     #[error("EOF")]
     EOF, // could be expected! maybe not.
 
-    #[error("Expected EOF")]
-    ExpectedEOF,
+    /// The input ended where another member or stream could have started, which is how it's meant to end.
+    #[error("The input ended between members")]
+    CleanEof,
+
+    /// The input ended partway through a DEFLATE block, so it's been cut short. block_id is the block's row in the
+    /// checkpoint file, if there's one being written and the block had got that far.
+    #[error("The input ended partway through a DEFLATE block, at 0x{compressed_byte:X} after decompressing {uncompressed_byte} bytes")]
+    UnexpectedEofInBlock { block_id: Option<i64>, compressed_byte: usize, uncompressed_byte: usize },
 
     /// Represents all other cases of `std::io::Error`.
     #[error(transparent)]
     IOError(#[from] std::io::Error),
 
     /// Represents any case of rusqlite::Error
+    #[cfg(feature = "sqlite")]
     #[error(transparent)]
     RusqliteError(#[from] rusqlite::Error),
+
+    /// Represents any case of object_store::Error
+    #[cfg(feature = "remote")]
+    #[error(transparent)]
+    ObjectStoreError(#[from] object_store::Error),
+
+    #[cfg(feature = "remote")]
+    #[error("{url} isn't a URL: {source}")]
+    InvalidUrl { url: String, source: url::ParseError },
+
+    /// A request for a remote file kept failing, the last time with `source`. See `remote::RetryPolicy`.
+    #[cfg(feature = "remote")]
+    #[error("Gave up reading {path} after {attempts} attempts: {source}")]
+    RetriesExhausted { path: String, attempts: u32, source: std::io::Error },
+}
+
+impl CorniferError {
+    pub fn kind(&self) -> ErrorKind {
+        use CorniferError::*;
+        match self {
+            ReadError { .. } | IOError(_) | IndexAlreadyExists { .. } | IndexBusy { .. } => ErrorKind::Io,
+            UTF8Invalid(_)
+            | NotGZIPHeader
+            | UnknownFormat
+            | NotZlibHeader
+            | InvalidZlibAdler32 { .. }
+            | NotBZIP2Header
+            | InvalidBzip2Block { .. }
+            | NotXzHeader { .. }
+            | NotXzFooter { .. }
+            | InvalidXzIndex { .. }
+            | InvalidXzBlock { .. }
+            | InvalidZipDirectory { .. }
+            | TrailingData { .. }
+            | InGap { .. }
+            | InvalidCompressionMethod
+            | InvalidHeaderCRC { .. }
+            | InvalidBlockType
+            | InvalidNonCompressedBlockHeader { .. }
+            | InvalidGZIPCRC { .. }
+            | InvalidGZIPIsize { .. }
+            | InvalidLengthDistancePair { .. }
+            | DistanceTooFarBack { .. }
+            | InvalidHuffmanCode { .. }
+            | UnusedSymbol { .. }
+            | InvalidDynamicBlockCodeLength
+            | InvalidDynamicBlockHeader { .. }
+            | UnexpectedLength { .. }
+            | UnexpectedCrc32 { .. }
+            | EOF
+            | CleanEof
+            | UnexpectedEofInBlock { .. } => ErrorKind::CorruptStream,
+            #[cfg(feature = "sqlite")]
+            RusqliteError(_) => ErrorKind::CorruptIndex,
+            #[cfg(feature = "remote")]
+            ObjectStoreError(_) | InvalidUrl { .. } | RetriesExhausted { .. } => ErrorKind::Io,
+            IndexIncomplete
+            | UnknownSource { .. }
+            | NoSuchMember { .. }
+            | IndexLengthMismatch { .. }
+            | IndexCrcMismatch { .. }
+            | CorruptIndexOrData { .. }
+            | CompressedCrcMismatch { .. }
+            | InvalidHuffmanTrees
+            | FileChanged { .. } => ErrorKind::CorruptIndex,
+            UnsupportedFormat { .. }
+            | CantEstimate { .. }
+            | ZlibPresetDictionary
+            | UnsupportedBlockType { .. }
+            | NotGzipIndex
+            | NoBlockHashes
+            | CantExtract { .. } => {
+                ErrorKind::Unsupported
+            }
+            BufferSizeTooLarge
+            | StringTooLong { .. }
+            | InvalidNumberOfBits { .. }
+            | MemoryBudgetTooSmall { .. }
+            | InvalidCheckpointSpacing { .. }
+            | OutputLimitExceeded { .. } => {
+                ErrorKind::Limit
+            }
+            Cancelled { .. } | DeadlineExceeded { .. } => ErrorKind::Cancelled,
+        }
+    }
+}
+
+// the CorniferError is kept inside, so it can be downcast back out.
+impl From<CorniferError> for std::io::Error {
+    fn from(err: CorniferError) -> Self {
+        use std::io::ErrorKind as IoKind;
+        let kind = match &err {
+            CorniferError::IOError(e) | CorniferError::ReadError { source: e } => e.kind(),
+            #[cfg(feature = "remote")]
+            CorniferError::RetriesExhausted { source: e, .. } => e.kind(),
+            CorniferError::IndexAlreadyExists { .. } => IoKind::AlreadyExists,
+            CorniferError::IndexBusy { .. } => IoKind::ResourceBusy,
+            CorniferError::InvalidCheckpointSpacing { .. } => IoKind::InvalidInput,
+            CorniferError::EOF | CorniferError::UnexpectedEofInBlock { .. } => IoKind::UnexpectedEof,
+            // not Interrupted, since read_to_end and friends retry those.
+            CorniferError::Cancelled { .. } => IoKind::Other,
+            CorniferError::DeadlineExceeded { .. } => IoKind::TimedOut,
+            err => match err.kind() {
+                ErrorKind::Unsupported => IoKind::Unsupported,
+                _ => IoKind::InvalidData,
+            },
+        };
+        std::io::Error::new(kind, err)
+    }
+}
+
+/**
+ * TESTS
+ */
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::{CorniferError, ErrorKind};
+
+    #[rstest]
+    #[case::eof(CorniferError::EOF, ErrorKind::CorruptStream, std::io::ErrorKind::UnexpectedEof)]
+    #[case::eof_in_block(CorniferError::UnexpectedEofInBlock { block_id: None, compressed_byte: 0, uncompressed_byte: 0 }, ErrorKind::CorruptStream, std::io::ErrorKind::UnexpectedEof)]
+    #[case::crc(CorniferError::InvalidGZIPCRC { position: 0, expected: 1, found: 2 }, ErrorKind::CorruptStream, std::io::ErrorKind::InvalidData)]
+    #[case::unsupported(CorniferError::ZlibPresetDictionary, ErrorKind::Unsupported, std::io::ErrorKind::Unsupported)]
+    #[case::limit(CorniferError::StringTooLong { position: 0, max: 1 }, ErrorKind::Limit, std::io::ErrorKind::InvalidData)]
+    #[case::io(CorniferError::IOError(std::io::ErrorKind::NotFound.into()), ErrorKind::Io, std::io::ErrorKind::NotFound)]
+    #[case::cancelled(CorniferError::Cancelled { position: 0 }, ErrorKind::Cancelled, std::io::ErrorKind::Other)]
+    #[case::deadline(CorniferError::DeadlineExceeded { compressed_byte: 0, uncompressed_byte: 0 }, ErrorKind::Cancelled, std::io::ErrorKind::TimedOut)]
+    fn test_kinds(#[case] err: CorniferError, #[case] kind: ErrorKind, #[case] io_kind: std::io::ErrorKind) {
+        assert_eq!(err.kind(), kind);
+        let io_err = std::io::Error::from(err);
+        assert_eq!(io_err.kind(), io_kind);
+        // and the original error is still in there.
+        let inner = io_err.into_inner().unwrap().downcast::<CorniferError>().unwrap();
+        assert_eq!(inner.kind(), kind);
+    }
 }
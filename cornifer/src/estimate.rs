@@ -0,0 +1,295 @@
+use std::fmt;
+use std::io::{BufReader, ErrorKind, Read, Seek, SeekFrom};
+#[cfg(feature = "sqlite")]
+use std::path::Path;
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::errors::CorniferError;
+use crate::format::{detect_format, Format};
+#[cfg(feature = "sqlite")]
+use crate::store::CheckpointStore;
+
+/*
+ * How big a file will be decompressed, without decompressing it. Every gzip member ends with ISIZE, its length
+ * modulo 2^32, so adding those up gives the length, as long as the members can be found.
+ *
+ * BGZF says how long each member is in its header, so they can be walked exactly. Plain gzip doesn't, so we scan
+ * for the magic bytes of a member header and take the four bytes before each one as the last member's ISIZE.
+ * Compressed data can look like a header by chance, which would throw the total off, so a header only counts if
+ * the ISIZE before it is one the member could have. DEFLATE can't expand more than 1032 to 1, so a member of c
+ * compressed bytes is at most 1032c long. A member big enough that its length could be more than 4GiB is
+ * ambiguous, because its ISIZE might have wrapped around.
+ */
+
+// the most DEFLATE can expand: a 258 byte match in each 2 bits, roughly.
+const MAX_EXPANSION: u64 = 1032;
+// a header with no optional fields, an empty DEFLATE block and the footer.
+const MIN_MEMBER_LEN: u64 = 18;
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// How `estimate_size` got its answer.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum EstimateMethod {
+    /// From a complete checkpoint file, so it's exact.
+    Index,
+    /// From walking the BGZF blocks, so it's exact.
+    Bgzf,
+    /// From scanning for gzip members and adding up their footers.
+    Footers,
+}
+
+impl fmt::Display for EstimateMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Index => "index",
+            Self::Bgzf => "bgzf",
+            Self::Footers => "footers",
+        })
+    }
+}
+
+/// How long a file is decompressed, as far as can be told without decompressing it.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct SizeEstimate {
+    pub uncompressed_len: u64,
+    /// How many gzip members there are, or BGZF blocks.
+    pub members: u64,
+    pub method: EstimateMethod,
+    /// Members that could be more than 4GiB decompressed, so their length might be out by a multiple of it.
+    pub ambiguous_members: u64,
+}
+
+impl SizeEstimate {
+    /// Whether `uncompressed_len` is definitely right. Scanning for members can be fooled by compressed data
+    /// that looks like a header, so it never is.
+    pub fn is_exact(&self) -> bool {
+        self.method != EstimateMethod::Footers && self.ambiguous_members == 0
+    }
+}
+
+/// Estimate how long a gzip or BGZF file is decompressed, from the footers of its members. Much quicker than
+/// decompressing it, since only the compressed file is read, and for BGZF only the headers and footers.
+pub fn estimate_size<R: Read + Seek>(reader: R) -> Result<SizeEstimate, CorniferError> {
+    let mut reader = BufReader::new(reader);
+    match detect_format(&mut reader)? {
+        Format::Bgzf => match walk_bgzf(&mut reader)? {
+            Some(estimate) => Ok(estimate),
+            // a member without its block size, so it's not all BGZF after all.
+            None => {
+                reader.seek(SeekFrom::Start(0))?;
+                scan_footers(&mut reader)
+            }
+        },
+        Format::Gzip => scan_footers(&mut reader),
+        format => Err(CorniferError::CantEstimate { format }),
+    }
+}
+
+/// The length recorded in a complete checkpoint file, which is exact.
+#[cfg(feature = "sqlite")]
+pub fn estimate_from_index<P: AsRef<Path>>(index: P) -> Result<SizeEstimate, CorniferError> {
+    let store = CheckpointStore::open(index)?;
+    let meta = store.meta()?;
+    let (Some(uncompressed_len), true) = (meta.uncompressed_len, meta.complete) else {
+        return Err(CorniferError::IndexIncomplete);
+    };
+    Ok(SizeEstimate {
+        uncompressed_len,
+        members: store.members()?.len() as u64,
+        method: EstimateMethod::Index,
+        ambiguous_members: 0,
+    })
+}
+
+// whether a member this long compressed could be over 4GiB decompressed, so its ISIZE might have wrapped.
+fn could_wrap(compressed_len: u64) -> bool {
+    compressed_len.saturating_mul(MAX_EXPANSION) > u32::MAX as u64
+}
+
+fn walk_bgzf<R: Read + Seek>(reader: &mut R) -> Result<Option<SizeEstimate>, CorniferError> {
+    let mut estimate = SizeEstimate {
+        uncompressed_len: 0,
+        members: 0,
+        method: EstimateMethod::Bgzf,
+        ambiguous_members: 0,
+    };
+    let mut pos = 0;
+    loop {
+        // up to XLEN, then the extra field.
+        let mut header = [0; 12];
+        match reader.read_exact(&mut header) {
+            Ok(()) => (),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(Some(estimate)),
+            Err(e) => return Err(e.into()),
+        }
+        if header[..3] != [0x1f, 0x8b, 8] || header[3] & 0b100 == 0 {
+            return Ok(None);
+        }
+        let mut extra = vec![0; u16::from_le_bytes([header[10], header[11]]) as usize];
+        reader.read_exact(&mut extra)?;
+        let Some(block_len) = bgzf_block_len(&extra) else {
+            return Ok(None);
+        };
+        reader.seek(SeekFrom::Start(pos + block_len - 4))?;
+        let mut isize = [0; 4];
+        reader.read_exact(&mut isize)?;
+        estimate.uncompressed_len += u32::from_le_bytes(isize) as u64;
+        estimate.members += 1;
+        pos += block_len;
+    }
+}
+
+// BSIZE from the "BC" subfield, which is the length of the whole member less one.
+fn bgzf_block_len(mut extra: &[u8]) -> Option<u64> {
+    while let [si1, si2, len1, len2, rest @ ..] = extra {
+        let len = u16::from_le_bytes([*len1, *len2]) as usize;
+        let data = rest.get(..len)?;
+        if [*si1, *si2] == *b"BC" && len == 2 {
+            return Some(u16::from_le_bytes([data[0], data[1]]) as u64 + 1);
+        }
+        extra = &rest[len..];
+    }
+    None
+}
+
+// whether the 10 bytes at the start of `header` could be a gzip member header.
+fn looks_like_header(header: &[u8]) -> bool {
+    header[..3] == [0x1f, 0x8b, 8]
+        // the reserved flags are 0, XFL is 0, 2 or 4, and the OS is one RFC 1952 knows.
+        && header[3] & 0xe0 == 0
+        && matches!(header[8], 0 | 2 | 4)
+        && (header[9] <= 13 || header[9] == 255)
+}
+
+fn add_member(estimate: &mut SizeEstimate, compressed_len: u64, isize: u32) {
+    estimate.uncompressed_len += isize as u64;
+    estimate.members += 1;
+    if could_wrap(compressed_len) {
+        estimate.ambiguous_members += 1;
+    }
+}
+
+fn scan_footers<R: Read>(reader: &mut R) -> Result<SizeEstimate, CorniferError> {
+    let mut estimate = SizeEstimate {
+        uncompressed_len: 0,
+        members: 0,
+        method: EstimateMethod::Footers,
+        ambiguous_members: 0,
+    };
+    // the last few bytes of the chunk before, so a header or footer split across two chunks is still seen.
+    let mut buf: Vec<u8> = Vec::with_capacity(CHUNK_SIZE + 16);
+    // where buf starts in the file, where the member we're in started, and how far we've looked for headers.
+    let (mut buf_from, mut member_from, mut scanned) = (0u64, 0u64, 0u64);
+    let mut chunk = vec![0; CHUNK_SIZE];
+    loop {
+        let n = match reader.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        buf.extend_from_slice(&chunk[..n]);
+        // a header needs the footer before it and ten bytes of its own.
+        for i in 4..buf.len().saturating_sub(9) {
+            let pos = buf_from + i as u64;
+            if pos < scanned.max(member_from + MIN_MEMBER_LEN) || !looks_like_header(&buf[i..i + 10]) {
+                continue;
+            }
+            let compressed_len = pos - member_from;
+            let isize = u32::from_le_bytes(buf[i - 4..i].try_into().expect("Four bytes"));
+            if !could_wrap(compressed_len) && isize as u64 > compressed_len * MAX_EXPANSION {
+                continue;
+            }
+            add_member(&mut estimate, compressed_len, isize);
+            member_from = pos;
+        }
+        scanned = buf_from + buf.len().saturating_sub(9) as u64;
+        let keep = buf.len().min(13);
+        buf_from += (buf.len() - keep) as u64;
+        buf.drain(..buf.len() - keep);
+    }
+    let file_len = buf_from + buf.len() as u64;
+    if file_len < member_from + MIN_MEMBER_LEN || buf.len() < 4 {
+        return Err(CorniferError::EOF);
+    }
+    let isize = u32::from_le_bytes(buf[buf.len() - 4..].try_into().expect("Four bytes"));
+    add_member(&mut estimate, file_len - member_from, isize);
+    Ok(estimate)
+}
+
+/**
+ * TESTS
+ */
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Write};
+
+    use flate2::{write::GzEncoder, Compression, GzBuilder};
+    use rstest::rstest;
+
+    use crate::errors::CorniferError;
+
+    use super::{estimate_size, EstimateMethod};
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(data).unwrap();
+        e.finish().unwrap()
+    }
+
+    // BGZF blocks, each with its BSIZE in a "BC" subfield.
+    fn bgzf(data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for part in data.chunks(10000).chain([&[][..]]) {
+            let mut block = GzBuilder::new().extra(vec![b'B', b'C', 2, 0, 0, 0]).write(Vec::new(), Compression::default());
+            block.write_all(part).unwrap();
+            let mut block = block.finish().unwrap();
+            let bsize = (block.len() - 1) as u16;
+            block[16..18].copy_from_slice(&bsize.to_le_bytes());
+            out.extend(block);
+        }
+        out
+    }
+
+    #[rstest]
+    fn test_one_member() {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let estimate = estimate_size(Cursor::new(gzip(text))).unwrap();
+        assert_eq!((estimate.uncompressed_len, estimate.members), (text.len() as u64, 1));
+        assert_eq!(estimate.method, EstimateMethod::Footers);
+        assert!(!estimate.is_exact());
+    }
+
+    #[rstest]
+    fn test_several_members() {
+        let input = include_bytes!("../testfiles/testCompressThenConcat.txt.gz");
+        let mut expected = Vec::new();
+        std::io::Read::read_to_end(&mut flate2::read::MultiGzDecoder::new(input.as_slice()), &mut expected).unwrap();
+        let estimate = estimate_size(Cursor::new(input)).unwrap();
+        assert_eq!(estimate.uncompressed_len, expected.len() as u64);
+        assert!(estimate.members > 1);
+    }
+
+    #[rstest]
+    fn test_bgzf() {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let estimate = estimate_size(Cursor::new(bgzf(text))).unwrap();
+        // four blocks of text, and the empty one at the end.
+        assert_eq!((estimate.uncompressed_len, estimate.members), (text.len() as u64, 5));
+        assert_eq!(estimate.method, EstimateMethod::Bgzf);
+        assert!(estimate.is_exact());
+    }
+
+    #[rstest]
+    #[case::bzip2(&include_bytes!("../testfiles/1080-0x3.txt.bz2")[..])]
+    #[case::truncated(&[0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 3][..])]
+    fn test_cant_estimate(#[case] input: &[u8]) {
+        let err = estimate_size(Cursor::new(input)).unwrap_err();
+        assert!(matches!(err, CorniferError::CantEstimate { .. } | CorniferError::EOF));
+    }
+}
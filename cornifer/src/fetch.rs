@@ -0,0 +1,203 @@
+use std::ops::Range;
+
+use crate::errors::CorniferError;
+use crate::store::{Block, Gap, Tick};
+
+/*
+ * Working out which parts of a compressed file are needed to read part of the decompressed one, for files that
+ * are slow or cost money to fetch, like ones in object storage. Nothing but the checkpoint file is read.
+ *
+ * Decoding starts from the last tick at or before the range, if there's one in the block the range starts in, or
+ * else from the last block at or before the range that has a window, same as CheckpointedReader. It goes on to the
+ * end of the last block in the range. Where there's something between two blocks that isn't
+ * either of them (e.g. a gzip footer and header) and the second one can be decoded on its own, the bytes in
+ * between aren't needed and it's a new range.
+ */
+
+/// Some bytes of the compressed file to fetch, from `CheckpointStore::plan_fetch`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CompressedRange {
+    /// Where to fetch from in the compressed file.
+    pub from_byte: u64,
+    /// And up to, not including.
+    pub to_byte: u64,
+    /// Decoding starts at this bit of the first byte.
+    pub from_bit: u8,
+    /// What the bytes decode to. This can start before the part that was asked for.
+    pub uncompressed: Range<u64>,
+    /// The block whose window (`CheckpointStore::get_window`) the decoder has to start with, if it needs one.
+    /// Starting from a tick, it's the block the tick is in instead, whose trees (`CheckpointStore::get_trees`) it
+    /// decodes with.
+    pub window_block: Option<i64>,
+    /// The tick the decoder starts from, in the middle of the first block, if there is one. Its window is
+    /// `CheckpointStore::get_tick_window`.
+    pub window_tick: Option<i64>,
+}
+
+// a block decoded first, that needs something from the checkpoint file to be decoded.
+fn needs_window(block: &Block) -> bool {
+    match block.block_type.as_str() {
+        "nocompression" | "fixed" | "dynamic" => !block.member_start,
+        // the stream header.
+        "xz" => true,
+        _ => false,
+    }
+}
+
+/// The ranges of the compressed file needed to decode `range` of the decompressed file, in order. `blocks` are
+/// all of them, empty ones too, in uncompressed order, and `ticks_for` gives the ticks in a block that can be
+/// decoded from.
+pub(crate) fn plan(
+    blocks: &[Block],
+    gaps: &[Gap],
+    range: Range<u64>,
+    ticks_for: impl FnOnce(&Block) -> Result<Vec<Tick>, CorniferError>,
+) -> Result<Vec<CompressedRange>, CorniferError> {
+    if let Some(gap) = gaps.iter().find(|g| g.uncompressed_from < range.end && range.start < g.uncompressed_to) {
+        return Err(CorniferError::InGap {
+            from: gap.uncompressed_from,
+            to: gap.uncompressed_to,
+        });
+    }
+    let first = blocks.iter().position(|b| b.len > 0 && b.to_byte + b.len > range.start);
+    let last = blocks.iter().rposition(|b| b.len > 0 && b.to_byte < range.end);
+    let (first, last) = match (first, last) {
+        (Some(first), Some(last)) if first <= last && !range.is_empty() => (first, last),
+        _ => return Ok(Vec::new()),
+    };
+    let mut ranges: Vec<CompressedRange> = Vec::new();
+    let tick = ticks_for(&blocks[first])?.into_iter().rfind(|t| t.to_byte <= range.start);
+    let start = match tick {
+        // the rest of the block from the tick, then on from the block after.
+        Some(tick) => {
+            let block = &blocks[first];
+            ranges.push(CompressedRange {
+                from_byte: tick.from_byte,
+                to_byte: block_end(block),
+                from_bit: tick.from_bit,
+                uncompressed: tick.to_byte..block.to_byte + block.len,
+                window_block: Some(block.id),
+                window_tick: Some(tick.id),
+            });
+            first + 1
+        }
+        None => blocks[..=first].iter().rposition(|b| b.has_window).unwrap_or(0),
+    };
+    for block in &blocks[start..=last] {
+        let end = block_end(block);
+        let uncompressed_end = block.to_byte + block.len;
+        match ranges.last_mut() {
+            // carry on decoding, whether it's because the block's straight after or because it has to be.
+            Some(current) if block.from_byte <= current.to_byte || !block.has_window => {
+                current.to_byte = current.to_byte.max(end);
+                current.uncompressed.end = uncompressed_end;
+            }
+            _ => ranges.push(CompressedRange {
+                from_byte: block.from_byte,
+                to_byte: end,
+                from_bit: block.from_bit,
+                uncompressed: block.to_byte..uncompressed_end,
+                window_block: needs_window(block).then_some(block.id),
+                window_tick: None,
+            }),
+        }
+    }
+    Ok(ranges)
+}
+
+// the byte after the block's last bit.
+fn block_end(block: &Block) -> u64 {
+    (block.from_byte * 8 + block.from_bit as u64 + block.block_len_bits).div_ceil(8)
+}
+
+/**
+ * TESTS
+ */
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+    use flate2::{write::GzEncoder, Compression};
+    use rstest::rstest;
+
+    use crate::{
+        checkpoint::Checkpointer, checkpointed::CheckpointedReader, decompress::Deflator, reader::CorniferByteReader,
+        store::CheckpointStore,
+        testutil::{flushed_gzip, index_with_spacing, TempIndex},
+    };
+
+    #[rstest]
+    #[case::start(0, 100, 1)]
+    #[case::middle(25000, 27000, 1)]
+    #[case::across_members(38000, 42000, 2)]
+    #[case::second_member(60000, 70000, 1)]
+    #[case::everything(0, 79638, 2)]
+    fn test_plan_fetch(#[case] from: u64, #[case] to: u64, #[case] num_ranges: usize) {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        // two members, with a block every 4000 bytes and a window every 10000.
+        let input = flushed_gzip(text, 2, 4000);
+        let path = index_with_spacing(&input, 10000);
+        let expected = text.repeat(2);
+        let store = CheckpointStore::open(&path).unwrap();
+        let ranges = store.plan_fetch(from..to).unwrap();
+        assert_eq!(ranges.len(), num_ranges);
+        assert!(ranges[0].uncompressed.start <= from && ranges[ranges.len() - 1].uncompressed.end >= to);
+        // nothing but the ranges, and it still reads back.
+        let mut fetched = vec![0; input.len()];
+        for range in &ranges {
+            let (from, to) = (range.from_byte as usize, range.to_byte as usize);
+            fetched[from..to].copy_from_slice(&input[from..to]);
+            if let Some(id) = range.window_block {
+                assert!(!store.get_window(id).unwrap().is_empty());
+            }
+        }
+        let mut reader = CheckpointedReader::open(Cursor::new(fetched), &path).unwrap();
+        reader.seek(SeekFrom::Start(from)).unwrap();
+        let mut dest = vec![0; (to - from) as usize];
+        reader.read_exact(&mut dest).unwrap();
+        assert_eq!(dest, &expected[from as usize..to as usize]);
+    }
+
+    #[rstest]
+    fn test_plan_fetch_from_tick() {
+        // one big block, with a tick every 10000 bytes.
+        let text = include_bytes!("../testfiles/1080-0.txt").repeat(3);
+        let mut e = GzEncoder::new(Vec::new(), Compression::best());
+        e.write_all(&text).unwrap();
+        let input = e.finish().unwrap();
+        let path = TempIndex::new();
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), Checkpointer::init(&path).unwrap());
+        deflator.set_tick_trigger(|context| context.since_checkpoint >= 10000);
+        std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        drop(deflator);
+
+        let store = CheckpointStore::open(&path).unwrap();
+        let (from, to) = (55000, 56000);
+        let ranges = store.plan_fetch(from..to).unwrap();
+        let block = store.blocks().unwrap().into_iter().find(|b| b.to_byte <= from && from < b.to_byte + b.len).unwrap();
+        let tick = store.ticks_for(&block).unwrap().into_iter().rfind(|t| t.to_byte <= from).unwrap();
+        assert_eq!((ranges[0].from_byte, ranges[0].from_bit), (tick.from_byte, tick.from_bit));
+        assert_eq!(ranges[0].uncompressed.start, tick.to_byte);
+        assert_eq!((ranges[0].window_block, ranges[0].window_tick), (Some(block.id), Some(tick.id)));
+
+        let mut fetched = vec![0; input.len()];
+        for range in &ranges {
+            let (from, to) = (range.from_byte as usize, range.to_byte as usize);
+            fetched[from..to].copy_from_slice(&input[from..to]);
+        }
+        let mut reader = CheckpointedReader::open(Cursor::new(fetched), &path).unwrap();
+        reader.seek(SeekFrom::Start(from)).unwrap();
+        let mut dest = vec![0; (to - from) as usize];
+        reader.read_exact(&mut dest).unwrap();
+        assert_eq!(dest, &text[from as usize..to as usize]);
+    }
+
+    #[rstest]
+    fn test_plan_fetch_nothing() {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let path = index_with_spacing(&flushed_gzip(text, 2, 4000), 10000);
+        let store = CheckpointStore::open(&path).unwrap();
+        assert_eq!(store.plan_fetch(100..100).unwrap(), Vec::new());
+        assert_eq!(store.plan_fetch(text.len() as u64 * 2..u64::MAX).unwrap(), Vec::new());
+    }
+}
@@ -0,0 +1,133 @@
+use std::fmt::Display;
+use std::io::BufRead;
+use std::str::FromStr;
+
+use crate::errors::CorniferError;
+
+/// The compressed formats Cornifer knows about. Not all of them can be checkpointed.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Format {
+    Gzip,
+    // gzip with a "BC" extra field in every member, as used by htslib. It's still gzip.
+    Bgzf,
+    Zlib,
+    Zip,
+    Bzip2,
+    Xz,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "gzip" | "gz" => Ok(Format::Gzip),
+            "bgzf" => Ok(Format::Bgzf),
+            "zlib" => Ok(Format::Zlib),
+            "zip" => Ok(Format::Zip),
+            "bzip2" | "bz2" => Ok(Format::Bzip2),
+            "xz" => Ok(Format::Xz),
+            _ => Err(format!(
+                "unknown format {s}, expected one of gzip, bgzf, zlib, zip, bzip2, xz"
+            )),
+        }
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Format::Gzip => "gzip",
+            Format::Bgzf => "bgzf",
+            Format::Zlib => "zlib",
+            Format::Zip => "zip",
+            Format::Bzip2 => "bzip2",
+            Format::Xz => "xz",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// Work out the format of a file from its first few bytes. Nothing is consumed from the reader.
+pub fn detect_format<R: BufRead>(reader: &mut R) -> Result<Format, CorniferError> {
+    let magic = reader.fill_buf()?;
+
+    if magic.starts_with(&[0x1f, 0x8b]) {
+        // BGZF sets FEXTRA, and the first extra subfield is "BC".
+        let fextra = magic.get(3).is_some_and(|flg| flg & 0b100 != 0);
+        if fextra && magic.get(12..14) == Some(b"BC") {
+            return Ok(Format::Bgzf);
+        }
+        return Ok(Format::Gzip);
+    }
+    if magic.starts_with(&[0xFD, b'7', b'z', b'X', b'Z', 0x00]) {
+        return Ok(Format::Xz);
+    }
+    if magic.starts_with(b"BZh") && magic.get(3).is_some_and(|l| (b'1'..=b'9').contains(l)) {
+        return Ok(Format::Bzip2);
+    }
+    // local file header, end of central directory (an empty archive), or a spanned archive.
+    if [b"PK\x03\x04", b"PK\x05\x06", b"PK\x07\x08"]
+        .iter()
+        .any(|m| magic.starts_with(*m))
+    {
+        return Ok(Format::Zip);
+    }
+    // zlib has no magic as such, just CM = 8, a window of at most 32KB, and a header check.
+    if let [cmf, flg, ..] = magic {
+        let header = ((*cmf as u16) << 8) | *flg as u16;
+        if cmf & 0x0F == 8 && cmf >> 4 <= 7 && header.is_multiple_of(31) {
+            return Ok(Format::Zlib);
+        }
+    }
+
+    Err(CorniferError::UnknownFormat)
+}
+
+/**
+ * TESTS
+ */
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, Write};
+
+    use flate2::{write::ZlibEncoder, Compression};
+    use rstest::rstest;
+
+    use crate::errors::CorniferError;
+
+    use super::{detect_format, Format};
+
+    #[rstest]
+    #[case::gzip(include_bytes!("../testfiles/helloworld.gz"), Format::Gzip)]
+    #[case::bzip2(include_bytes!("../testfiles/1080-0x3.txt.bz2"), Format::Bzip2)]
+    #[case::xz(include_bytes!("../testfiles/1080-0-anthems.txt.xz"), Format::Xz)]
+    #[case::zip(b"PK\x03\x04\x14\x00\x00\x00", Format::Zip)]
+    // empty BGZF block, as written at the end of every BGZF file.
+    #[case::bgzf(
+        &[0x1f, 0x8b, 8, 4, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0, 0x1b, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        Format::Bgzf
+    )]
+    fn test_detect_format(#[case] input: &[u8], #[case] expected: Format) {
+        let mut reader = input;
+        assert_eq!(detect_format(&mut reader).unwrap(), expected);
+        // nothing should have been consumed.
+        assert_eq!(reader.fill_buf().unwrap(), input);
+    }
+
+    #[rstest]
+    fn test_detect_zlib() {
+        let mut e = ZlibEncoder::new(Vec::new(), Compression::best());
+        e.write_all(b"hello world").unwrap();
+        let v = e.finish().unwrap();
+        assert_eq!(detect_format(&mut v.as_slice()).unwrap(), Format::Zlib);
+    }
+
+    #[rstest]
+    fn test_detect_unknown() {
+        let input: &[u8] = b"hello world";
+        let result = detect_format(&mut &input[..]);
+        assert!(matches!(result, Err(CorniferError::UnknownFormat)));
+    }
+}
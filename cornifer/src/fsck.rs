@@ -0,0 +1,272 @@
+use std::fmt;
+use std::path::Path;
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::errors::CorniferError;
+use crate::schema::SCHEMA_VERSION;
+use crate::store::{Block, CheckpointStore, Member};
+
+/*
+ * Checking a checkpoint file is consistent with itself, without the compressed file: every row finished, the
+ * blocks in order and not overlapping, ticks inside the blocks they're for, and windows the size they should be.
+ * CheckpointedReader catches most of these too, but only once it reads the part of the file that's wrong.
+ *
+ * Each problem says whether deleting the rows it's about would fix it, or whether the file has to be indexed
+ * again, since nothing else in it can be trusted.
+ */
+
+/// How to fix a `Problem`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum Fix {
+    /// Deleting the row fixes it, and everything else can still be read. For an index that was never finished,
+    /// `Deflator::repair` carries on with it.
+    Repair,
+    /// Index the file again.
+    Rebuild,
+}
+
+/// Something wrong with a checkpoint file, from `check_index`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Problem {
+    /// The table the row it's about is in, if it's about one.
+    pub table: Option<&'static str>,
+    pub id: Option<i64>,
+    pub message: String,
+    pub fix: Fix,
+}
+
+impl Problem {
+    fn row(table: &'static str, id: i64, fix: Fix, message: String) -> Self {
+        Self {
+            table: Some(table),
+            id: Some(id),
+            message,
+            fix,
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.table, self.id) {
+            (Some(table), Some(id)) => write!(f, "{table} {id}: {}", self.message),
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Everything wrong with the checkpoint file at `path`, or nothing if it's fine. Fails if it can't be read at all,
+/// e.g. it isn't a checkpoint file.
+pub fn check_index<P: AsRef<Path>>(path: P) -> Result<Vec<Problem>, CorniferError> {
+    let path = path.as_ref();
+    let store = CheckpointStore::open(path)?;
+    let mut problems = Vec::new();
+    let version = store.schema_version()?;
+    if version > SCHEMA_VERSION {
+        // there's no telling what's meant to be in it.
+        problems.push(Problem {
+            table: None,
+            id: None,
+            message: format!("it's schema version {version}, newer than this cornifer knows ({SCHEMA_VERSION})"),
+            fix: Fix::Rebuild,
+        });
+        return Ok(problems);
+    }
+
+    for id in store.unfinished_blocks()? {
+        problems.push(Problem::row("DeflateBlock", id, Fix::Repair, "it was never finished".to_string()));
+    }
+    for tick in store.orphan_ticks()? {
+        let message = format!("its block, {}, isn't there", tick.block_id);
+        problems.push(Problem::row("Tick", tick.id, Fix::Repair, message));
+    }
+
+    let sources = store.sources()?;
+    if sources.is_empty() {
+        check_source(&store, &mut problems)?;
+    }
+    for source in sources {
+        check_source(&CheckpointStore::open_source(path, &source.name)?, &mut problems)?;
+    }
+    Ok(problems)
+}
+
+// the checks for one file's rows.
+fn check_source(store: &CheckpointStore, problems: &mut Vec<Problem>) -> Result<(), CorniferError> {
+    let blocks = store.blocks()?;
+    let gaps = store.gaps()?;
+    let members = store.members()?;
+
+    let mut end = 0;
+    let mut prev: Option<&Block> = None;
+    for block in &blocks {
+        let mut rebuild =
+            |message: String| problems.push(Problem::row("DeflateBlock", block.id, Fix::Rebuild, message));
+        // a gap is the only thing that can come between two blocks.
+        let gap_end = gaps.iter().filter(|g| g.uncompressed_from == end).map(|g| g.uncompressed_to).max();
+        if block.to_byte < end {
+            rebuild(format!("it starts at {} decompressed, before the block before it ends at {end}", block.to_byte));
+        } else if block.to_byte > end && gap_end != Some(block.to_byte) {
+            rebuild(format!("it starts at {}, but nothing covers {end}..{} before it", block.to_byte, block.to_byte));
+        }
+        if let Some(prev) = prev {
+            let prev_end = start_bit(prev) + prev.block_len_bits;
+            if start_bit(block) < prev_end {
+                rebuild(format!(
+                    "it starts at bit {} of the compressed file, before block {} ends at {prev_end}",
+                    start_bit(block),
+                    prev.id
+                ));
+            }
+        }
+        check_window(store, block, &members, problems)?;
+        end = end.max(block.to_byte + block.len);
+        prev = Some(block);
+    }
+    end = gaps.iter().map(|g| g.uncompressed_to).fold(end, u64::max);
+
+    // a member's first block starts straight after its header.
+    for member in &members {
+        let Some(data_from) = member.data_from_byte() else {
+            continue;
+        };
+        if !blocks.iter().any(|b| b.member_start && b.from_byte == data_from && b.from_bit == 0) {
+            let message = format!("its header ends at {data_from}, but none of its blocks start there");
+            problems.push(Problem::row("GzipMember", member.id, Fix::Rebuild, message));
+        }
+    }
+
+    let meta = store.meta()?;
+    if !meta.complete {
+        // everything in it is fine as far as it goes, it just doesn't go to the end of the file.
+        problems.push(Problem {
+            table: None,
+            id: None,
+            message: "it was never finished, so it stops partway through the file".to_string(),
+            fix: Fix::Repair,
+        });
+    }
+    if let (Some(expected), true) = (meta.uncompressed_len, meta.complete) {
+        if expected != end {
+            problems.push(Problem {
+                table: None,
+                id: None,
+                message: format!("it's marked as complete at {expected} bytes, but the blocks add up to {end}"),
+                fix: Fix::Rebuild,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn start_bit(block: &Block) -> u64 {
+    block.from_byte * 8 + block.from_bit as u64
+}
+
+// the block's window and its ticks' windows decode, and are as long as the data before them in the member.
+fn check_window(
+    store: &CheckpointStore,
+    block: &Block,
+    members: &[Member],
+    problems: &mut Vec<Problem>,
+) -> Result<(), CorniferError> {
+    if !matches!(block.block_type.as_str(), "nocompression" | "fixed" | "dynamic") {
+        return Ok(());
+    }
+    // where the member starts, and whether that's certain: the member a block is in isn't recorded until it ends.
+    // Empty blocks at the end of a member start where the next one does, but only its first block starts it.
+    let member = members
+        .iter()
+        .rev()
+        .find(|m| m.to_byte < block.to_byte || (m.to_byte == block.to_byte && block.member_start));
+    let member_start = member.map_or(0, |m| m.to_byte);
+    let exact = member.is_some_and(|m| block.to_byte <= m.to_byte + m.len) || members.is_empty();
+    let check = |table: &'static str, id: i64, to_byte: u64, window: Result<Vec<u8>, CorniferError>| {
+        let window = match window {
+            Ok(window) => window,
+            Err(e) => return Some(Problem::row(table, id, Fix::Rebuild, format!("its window doesn't decode: {e}"))),
+        };
+        let expected = (to_byte - member_start).min(1 << 15);
+        let len = window.len() as u64;
+        match len == expected || (!exact && len < expected) {
+            true => None,
+            false => Some(Problem::row(table, id, Fix::Rebuild, format!("its window is {len} bytes, not {expected}"))),
+        }
+    };
+
+    if block.has_window && !block.member_start {
+        problems.extend(check("DeflateBlock", block.id, block.to_byte, store.get_window(block.id)));
+    }
+    let block_end = start_bit(block) + block.block_len_bits;
+    for tick in store.ticks_for(block)? {
+        let tick_bit = tick.from_byte * 8 + tick.from_bit as u64;
+        let in_block = (block.to_byte..=block.to_byte + block.len).contains(&tick.to_byte)
+            && (start_bit(block)..block_end).contains(&tick_bit);
+        if !in_block {
+            let message = format!("it's at {} decompressed, outside block {}", tick.to_byte, block.id);
+            problems.push(Problem::row("Tick", tick.id, Fix::Repair, message));
+            continue;
+        }
+        problems.extend(check("Tick", tick.id, tick.to_byte, store.get_tick_window(tick.id)));
+    }
+    Ok(())
+}
+
+/**
+ * TESTS
+ */
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+    use rusqlite::Connection;
+
+    use crate::testutil::{flushed_gzip, index_with_spacing};
+
+    use super::{check_index, Fix};
+
+    #[rstest]
+    #[case::spaced(10000)]
+    // so the empty blocks at the end of the first member have one too.
+    #[case::every_block(0)]
+    fn test_clean(#[case] window_spacing: u64) {
+        // two members in 4000 byte blocks.
+        let input = flushed_gzip(include_bytes!("../testfiles/1080-0.txt"), 2, 4000);
+        let path = index_with_spacing(&input, window_spacing);
+        assert_eq!(check_index(&path).unwrap(), Vec::new());
+    }
+
+    #[rstest]
+    #[case::unfinished("UPDATE DeflateBlock SET len = NULL WHERE id = 42", Some(("DeflateBlock", 42)), Fix::Repair)]
+    #[case::orphan_tick(
+        "PRAGMA foreign_keys = OFF; INSERT INTO Tick (from_byte, from_bit, to_byte, block_id, data) VALUES (0, 0, 0, 1000, x'')",
+        Some(("Tick", 1)),
+        Fix::Repair
+    )]
+    #[case::overlap("UPDATE DeflateBlock SET to_byte = to_byte - 10 WHERE id = 5", Some(("DeflateBlock", 5)), Fix::Rebuild)]
+    #[case::hole("UPDATE DeflateBlock SET to_byte = to_byte + 10 WHERE id = 5", Some(("DeflateBlock", 5)), Fix::Rebuild)]
+    #[case::compressed_overlap("UPDATE DeflateBlock SET from_byte = 0 WHERE id = 2", Some(("DeflateBlock", 2)), Fix::Rebuild)]
+    #[case::window_too_long(
+        "UPDATE DeflateBlock SET data = (SELECT data FROM DeflateBlock WHERE id = 13) WHERE id = 28",
+        Some(("DeflateBlock", 28)),
+        Fix::Rebuild
+    )]
+    #[case::bad_window("UPDATE DeflateBlock SET data = x'ffff' WHERE id = 6", Some(("DeflateBlock", 6)), Fix::Rebuild)]
+    #[case::header_len("UPDATE GzipMember SET header_len = 11 WHERE id = 2", Some(("GzipMember", 2)), Fix::Rebuild)]
+    #[case::wrong_length("UPDATE Meta SET value = '5' WHERE key = 'uncompressed_len'", None, Fix::Rebuild)]
+    #[case::newer("PRAGMA user_version = 1000", None, Fix::Rebuild)]
+    #[case::incomplete("UPDATE Meta SET value = '0' WHERE key = 'complete'", None, Fix::Repair)]
+    fn test_problems(#[case] sql: &str, #[case] row: Option<(&str, i64)>, #[case] fix: Fix) {
+        let input = flushed_gzip(include_bytes!("../testfiles/1080-0.txt"), 2, 4000);
+        let path = index_with_spacing(&input, 10000);
+        Connection::open(&path).unwrap().execute_batch(sql).unwrap();
+        let problems = check_index(&path).unwrap();
+        // it can have knock-on effects on the rows after it.
+        assert!(!problems.is_empty());
+        assert_eq!(problems[0].table.zip(problems[0].id), row, "{problems:?}");
+        assert!(problems.iter().all(|p| p.fix == fix), "{problems:?}");
+    }
+}
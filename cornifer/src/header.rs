@@ -1,25 +1,74 @@
 use std::io::Read;
+use std::ops::Range;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 use crate::{errors::CorniferError, reader::CorniferByteReader};
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct GzipHeader {
-    text: bool,
-    name: Option<String>,
-    comment: Option<String>,
-    mtime: u32,
-    extra: ExtraFlag,
-    os: OperatingSystem,
+    pub text: bool,
+    pub name: Option<String>,
+    pub comment: Option<String>,
+    // seconds since the epoch, 0 if there isn't one.
+    pub mtime: u32,
+    pub extra: ExtraFlag,
+    pub os: OperatingSystem,
+    // the FEXTRA subfields, e.g. "BC" for BGZF. Empty if FEXTRA isn't set.
+    pub extra_fields: Vec<ExtraField>,
+    pub has_hcrc: bool,
+    /// Where the header is in the file. The DEFLATE data starts straight after it.
+    pub span: Range<usize>,
+    /// The header exactly as it was in the file, from the magic bytes to the header CRC if there is one. For
+    /// writing it out again as it was, e.g. with an XFL or OS that `extra` and `os` don't have a name for.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_hex", deserialize_with = "deserialize_hex"))]
+    pub raw: Vec<u8>,
 }
 
-#[derive(PartialEq, Debug)]
+impl GzipHeader {
+    /// The XFL byte as it was in the header.
+    pub fn xfl_byte(&self) -> u8 {
+        self.raw[8]
+    }
+
+    /// The OS byte as it was in the header.
+    pub fn os_byte(&self) -> u8 {
+        self.raw[9]
+    }
+}
+
+#[cfg(feature = "serde")]
+fn serialize_hex<S: serde::Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&bytes.iter().map(|b| format!("{b:02x}")).collect::<String>())
+}
+
+#[cfg(feature = "serde")]
+fn deserialize_hex<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let hex = String::deserialize(deserializer)?;
+    let byte = |i| u8::from_str_radix(hex.get(i..i + 2).unwrap_or("not hex"), 16).map_err(serde::de::Error::custom);
+    (0..hex.len()).step_by(2).map(byte).collect()
+}
+
+/// A subfield of the FEXTRA field, see RFC 1952 section 2.3.1.1.
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ExtraField {
+    pub id: [u8; 2],
+    pub data: Vec<u8>,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum ExtraFlag {
     SlowestAlgorithm,
     FastestAlgorithm,
     Unknown,
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum OperatingSystem {
     Fat,
     Unix,
@@ -28,18 +77,82 @@ pub enum OperatingSystem {
     Unknown, // rest not included
 }
 
+/// Split the FEXTRA field into subfields. Anything that doesn't fit the subfield layout is left out.
+fn parse_extra_fields(extra: &[u8]) -> Vec<ExtraField> {
+    let mut fields = Vec::new();
+    let mut rest = extra;
+    while let [si1, si2, len1, len2, tail @ ..] = rest {
+        let len = u16::from_le_bytes([*len1, *len2]) as usize;
+        if tail.len() < len {
+            break;
+        }
+        fields.push(ExtraField {
+            id: [*si1, *si2],
+            data: tail[..len].to_vec(),
+        });
+        rest = &tail[len..];
+    }
+    fields
+}
+
+// the header of the gzip we write: DEFLATE, no flags, no mtime, no extra flags, unknown OS.
+#[cfg(any(feature = "sqlite", feature = "writer"))]
+pub(crate) const GZIP_HEADER: [u8; 10] = [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+
+// longest FNAME or FCOMMENT we'll read. Anything longer is almost certainly not really a gzip header.
+const MAX_HEADER_STRING_LEN: usize = 1 << 16;
+
+/// The header CRC (FHCRC) that was in the header, and the one it should have been.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct HeaderCrcMismatch {
+    pub expected: u16,
+    pub found: u16,
+}
+
 /**
  * Read a Header struct out of a corniferReader
  */
 pub fn read_header<R: Read>(sr: &mut CorniferByteReader<R>) -> Result<GzipHeader, CorniferError> {
+    match read_header_unchecked(sr)? {
+        (_, Some(HeaderCrcMismatch { expected, found })) => Err(CorniferError::InvalidHeaderCRC { expected, found }),
+        (header, None) => Ok(header),
+    }
+}
+
+/// Like read_header, but a wrong header CRC comes back alongside the header instead of as an error, for the
+/// caller to decide what to do about. Some gzip writers get it wrong.
+pub fn read_header_unchecked<R: Read>(
+    sr: &mut CorniferByteReader<R>,
+) -> Result<(GzipHeader, Option<HeaderCrcMismatch>), CorniferError> {
+    let from_byte = sr.current_byte;
+    // the caller might be keeping a copy already, in case it's trailing data rather than a header. Then the header
+    // is the end of theirs, and it has to be left going.
+    let outer_capture = sr.captured().map(<[u8]>::len);
+    if outer_capture.is_none() {
+        sr.begin_capture();
+    }
+    let result = read_header_fields(sr);
+    let raw = match outer_capture {
+        Some(start) => sr.captured().map(|captured| captured[start..].to_vec()),
+        None => sr.end_capture(),
+    };
+    let (mut header, mismatch) = result?;
+    header.raw = raw.unwrap_or_default();
+    header.span = from_byte..sr.current_byte;
+    Ok((header, mismatch))
+}
+
+fn read_header_fields<R: Read>(
+    sr: &mut CorniferByteReader<R>,
+) -> Result<(GzipHeader, Option<HeaderCrcMismatch>), CorniferError> {
     sr.begin_crc();
     // id1 and id2
-    // btw if the first byte fails, we handle that differently, it might be an
-    // expected EOF
+    // btw if the first byte fails, we handle that differently, it might be a
+    // clean EOF
     let id1 = match sr.read_u8() {
         Ok(byte) => byte,
         Err(err) => match err {
-            CorniferError::EOF => return Err(CorniferError::ExpectedEOF),
+            CorniferError::EOF => return Err(CorniferError::CleanEof),
             _ => return Err(err),
         },
     };
@@ -80,44 +193,50 @@ pub fn read_header<R: Read>(sr: &mut CorniferByteReader<R>) -> Result<GzipHeader
     };
 
     // if fextra set...
-    if fextra == 1 {
+    let extra = if fextra == 1 {
         // read two bytes, this is the length of the extra data.
         let xlen = sr.read_u16_le()?;
-        // read and discard, we're not using the extra data for now.
-        for _ in 0..xlen {
-            sr.read_u8()?;
-        }
-    }
+        sr.read_exact_n(xlen as usize)?
+    } else {
+        Vec::new()
+    };
     // if fname set...
     let name = match fname {
-        1 => Some(sr.read_null_terminated_string()?),
+        1 => Some(sr.read_null_terminated_string_limited(MAX_HEADER_STRING_LEN)?),
         _ => None,
     };
     // if fcomment set...
     let comment = match fcomment {
-        1 => Some(sr.read_null_terminated_string()?),
+        1 => Some(sr.read_null_terminated_string_limited(MAX_HEADER_STRING_LEN)?),
         _ => None,
     };
     let hcrc_actual = sr.end_crc().expect("Header always should exist");
+    let mut mismatch = None;
     if fhcrc == 1 {
         let truncated = hcrc_actual as u16;
         let hcrc = sr.read_u16_le()?;
         if hcrc != truncated {
-            return Err(CorniferError::InvalidHeaderCRC {
+            mismatch = Some(HeaderCrcMismatch {
                 expected: truncated,
                 found: hcrc,
             });
         }
     }
 
-    Ok(GzipHeader {
+    let header = GzipHeader {
         text: ftext == 1,
         name,
         comment,
         mtime,
         extra: xfl,
         os,
-    })
+        extra_fields: parse_extra_fields(&extra),
+        has_hcrc: fhcrc == 1,
+        // filled in by read_header_unchecked.
+        span: 0..0,
+        raw: Vec::new(),
+    };
+    Ok((header, mismatch))
 }
 
 /**  
@@ -128,7 +247,10 @@ mod test {
     use rstest::rstest;
 
     use crate::{
-        header::{read_header, GzipHeader},
+        errors::CorniferError,
+        header::{
+            read_header, read_header_unchecked, ExtraField, ExtraFlag, GzipHeader, HeaderCrcMismatch, OperatingSystem,
+        },
         reader::CorniferByteReader,
     };
 
@@ -143,6 +265,16 @@ mod test {
         };
     }
 
+    #[rstest]
+    fn read_header_bails_on_long_name() {
+        // FNAME set, and no null for far too long.
+        let mut inner = vec![0x1f, 0x8b, 8, 0b1000, 0, 0, 0, 0, 0, 3];
+        inner.extend(vec![b'a'; 1 << 17]);
+        let mut sr = CorniferByteReader::new(inner.as_slice());
+        let h = read_header(&mut sr);
+        assert!(matches!(h, Err(CorniferError::StringTooLong { position: 10, .. })));
+    }
+
     #[rstest]
     fn read_header_bails_on_not_deflate() {
         let inner: &[u8] = &[0x1f, 0x8b, 4];
@@ -168,7 +300,11 @@ mod test {
                     name: None,
                     mtime: 0,
                     extra: crate::header::ExtraFlag::Unknown,
-                    os: crate::header::OperatingSystem::Unix
+                    os: crate::header::OperatingSystem::Unix,
+                    extra_fields: vec![],
+                    has_hcrc: false,
+                    span: 0..10,
+                    raw: inner[..10].to_vec(),
                 }
             ),
             Err(e) => panic!("{}", e),
@@ -189,7 +325,14 @@ mod test {
                     name: Some("filename".to_string()),
                     mtime: 1677648839,
                     extra: crate::header::ExtraFlag::Unknown,
-                    os: crate::header::OperatingSystem::Unix
+                    os: crate::header::OperatingSystem::Unix,
+                    extra_fields: vec![ExtraField {
+                        id: *b"ab",
+                        data: b"cde".to_vec()
+                    }],
+                    has_hcrc: false,
+                    span: 0..46,
+                    raw: inner[..46].to_vec(),
                 }
             ),
             Err(e) => panic!("{}", e),
@@ -210,7 +353,16 @@ mod test {
                     name: Some("stCompressThenConcat.txt.1".to_string()),
                     mtime: 1274320850,
                     extra: crate::header::ExtraFlag::FastestAlgorithm,
-                    os: crate::header::OperatingSystem::Unix
+                    os: crate::header::OperatingSystem::Unix,
+                    extra_fields: vec![ExtraField {
+                        id: *b"U8",
+                        data: "gzip extra field with some Unicode dingbats in it:  \n U+25BA ►\n U+25CF ●\n U+2605 ★\n U+263A ☺\n U+263C ☼\n U+2660 ♠\n U+2665 ♥\n U+266B ♫\nte"
+                            .as_bytes()
+                            .to_vec()
+                    }],
+                    has_hcrc: true,
+                    span: 0..233,
+                    raw: inner[..233].to_vec(),
                 }
             ),
             Err(e) => panic!("{}", e),
@@ -230,4 +382,55 @@ mod test {
             ),
         }
     }
+
+    #[rstest]
+    fn read_header_unchecked_returns_incorrect_hcrc() {
+        let inner: &[u8] = include_bytes!("../testfiles/testIncorrectHCRC.txt.gz");
+        let mut sr = CorniferByteReader::new(Box::new(inner));
+        let (header, mismatch) = read_header_unchecked(&mut sr).unwrap();
+        assert!(header.has_hcrc);
+        assert_eq!(mismatch, Some(HeaderCrcMismatch { expected: 0xE8EE, found: 0xE7EE }));
+        // and the rest of the file carries on from after the header.
+        assert_eq!(sr.current_byte, 233);
+    }
+
+    #[rstest]
+    fn read_header_reads_extra_subfields() {
+        // an empty BGZF block.
+        let inner: &[u8] = &[
+            0x1f, 0x8b, 8, 4, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0, 0x1b, 0,
+        ];
+        let mut sr = CorniferByteReader::new(Box::new(inner));
+        let header = read_header(&mut sr).unwrap();
+        assert_eq!(
+            header.extra_fields,
+            vec![ExtraField {
+                id: *b"BC",
+                data: vec![0x1b, 0]
+            }]
+        );
+    }
+
+    #[rstest]
+    fn read_header_keeps_raw_bytes() {
+        // two bytes of something else first, and an XFL and OS without names.
+        let inner: &[u8] = &[0xaa, 0xbb, 0x1f, 0x8b, 8, 0, 1, 2, 3, 4, 7, 42, 0xcc];
+        let mut sr = CorniferByteReader::new(inner);
+        sr.read_u16_le().unwrap();
+        let header = read_header(&mut sr).unwrap();
+        assert_eq!((&header.extra, &header.os), (&ExtraFlag::Unknown, &OperatingSystem::Unknown));
+        assert_eq!((header.xfl_byte(), header.os_byte()), (7, 42));
+        assert_eq!(header.span, 2..12);
+        assert_eq!(header.raw, &inner[2..12]);
+    }
+
+    #[rstest]
+    fn read_header_leaves_capture_going() {
+        let inner: &[u8] = include_bytes!("../testfiles/test.gz");
+        let mut sr = CorniferByteReader::new(inner);
+        sr.begin_capture();
+        let header = read_header(&mut sr).unwrap();
+        assert_eq!(header.raw, &inner[..46]);
+        assert_eq!(sr.end_capture().unwrap(), &inner[..46]);
+    }
 }
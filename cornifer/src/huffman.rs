@@ -1,52 +1,156 @@
+use std::cmp::Ordering;
+use std::sync::OnceLock;
+
+use crate::errors::CorniferError;
+
 pub const MAX_HUFFMAN_BITS: u16 = 15;
 const LUT_SIZE: usize = 2_i32.pow(MAX_HUFFMAN_BITS as u32) as usize;
+// most codes a literal/length or distance tree can have.
+const MAX_SYMBOLS: usize = 288;
+const MAX_DISTANCES: usize = 32;
+
+// base lengths for codes from 257..=285
+static BASE_LENGTHS: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+
+/* Extra bits for length codes 257..=285 */
+static LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+// base offsets for distance codes 0..=29
+static BASE_DISTS: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+
+static DIST_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+// extra_bits for the symbols a tree can have codes for that DEFLATE doesn't use: 286 and 287 for lengths, 30
+// and 31 for distances.
+pub const UNUSED_SYMBOL: u8 = u8::MAX;
 
-#[derive(PartialEq, Default)]
+// the trees for fixed blocks never change, so they're only built the first time they're needed.
+static FIXED: OnceLock<HuffmanTree> = OnceLock::new();
+static FIXED_DIST: OnceLock<HuffmanTree> = OnceLock::new();
+
+#[derive(PartialEq, Default, Clone)]
 pub struct HuffmanTree {
     // lut: HashMap<u16, HuffmanCode, BuildHasherDefault<NoHashHasher<u16>>>,
     lut: Vec<Option<HuffmanCode>>,
+    // what the tree was made from, without the zeros on the end.
+    code_lengths: Vec<u8>,
+    // the code for each of those symbols, for encode.
+    codes: Vec<u16>,
 }
 
+/// An entry in the lookup table. For length and distance symbols, the value is `base` plus the next
+/// `extra_bits` bits; for anything else, `base` is the symbol and there are no extra bits.
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub struct HuffmanCode {
     pub symbol: u16,
     pub len: u8,
+    pub base: u16,
+    pub extra_bits: u8,
 }
 
-impl HuffmanTree {
-    pub fn new(bit_lengths: &[u8]) -> Self {
-        // Count the number of codes for each code length.  Let
-        // bl_count[N] be the number of codes of length N, N >= 1.
-        // note: bl_count[0] must be 0.
-        let mut bl_count = [0_u16; (MAX_HUFFMAN_BITS + 1) as usize];
-        for &len in bit_lengths {
-            let len = len as usize;
-            bl_count[len] += 1;
-        }
-        bl_count[0] = 0;
+/// A symbol and its code, from `HuffmanTree::codes`. The code is the low `len` bits of `code`, most significant bit
+/// first, which is the order they're read from the stream in.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SymbolCode {
+    pub symbol: u16,
+    pub len: u8,
+    pub code: u16,
+}
 
-        // 2)  Find the numerical value of the smallest code for each
-        // code length:
-        let mut next_code = [0_u16; (MAX_HUFFMAN_BITS + 1) as usize];
+// what the symbols of a tree mean.
+#[derive(Copy, Clone)]
+enum Alphabet {
+    // code lengths, or anything else that's just the symbol.
+    Plain,
+    LiteralLength,
+    Distance,
+}
 
-        let mut code: u16 = 0;
-        for bits in 1..=MAX_HUFFMAN_BITS {
-            let bits = bits as usize;
-            code = (code + bl_count[bits - 1]) << 1;
-            next_code[bits] = code;
+impl Alphabet {
+    // base and extra bits.
+    fn meaning(self, symbol: u16) -> (u16, u8) {
+        let i = symbol as usize;
+        match self {
+            Alphabet::LiteralLength if symbol > 256 => match (BASE_LENGTHS.get(i - 257), LENGTH_EXTRA_BITS.get(i - 257)) {
+                (Some(&base), Some(&bits)) => (base, bits),
+                _ => (0, UNUSED_SYMBOL),
+            },
+            Alphabet::Distance => match (BASE_DISTS.get(i), DIST_EXTRA_BITS.get(i)) {
+                (Some(&base), Some(&bits)) => (base, bits),
+                _ => (0, UNUSED_SYMBOL),
+            },
+            _ => (symbol, 0),
         }
-        // Assign numerical values to all codes.
-        let mut final_codes = vec![0_u16; bit_lengths.len()];
-        for (i, &len) in bit_lengths.iter().enumerate() {
-            if len != 0 {
-                let len = len as usize;
-                let code = next_code[len];
-                next_code[len] += 1;
-                final_codes[i] = code;
-            } else {
-                final_codes[i] = 0;
-            }
+    }
+}
+
+// the code for each symbol, as RFC 1951 section 3.2.2 assigns them from the code lengths. 0 for symbols without
+// a code.
+fn canonical_codes(bit_lengths: &[u8]) -> Vec<u16> {
+    // Count the number of codes for each code length.  Let
+    // bl_count[N] be the number of codes of length N, N >= 1.
+    // note: bl_count[0] must be 0.
+    let mut bl_count = [0_u16; (MAX_HUFFMAN_BITS + 1) as usize];
+    for &len in bit_lengths {
+        let len = len as usize;
+        bl_count[len] += 1;
+    }
+    bl_count[0] = 0;
+
+    // 2)  Find the numerical value of the smallest code for each
+    // code length:
+    let mut next_code = [0_u16; (MAX_HUFFMAN_BITS + 1) as usize];
+
+    let mut code: u16 = 0;
+    for bits in 1..=MAX_HUFFMAN_BITS {
+        let bits = bits as usize;
+        code = (code + bl_count[bits - 1]) << 1;
+        next_code[bits] = code;
+    }
+    // Assign numerical values to all codes.
+    let mut final_codes = vec![0_u16; bit_lengths.len()];
+    for (i, &len) in bit_lengths.iter().enumerate() {
+        if len != 0 {
+            let len = len as usize;
+            let code = next_code[len];
+            next_code[len] += 1;
+            final_codes[i] = code;
+        } else {
+            final_codes[i] = 0;
         }
+    }
+    final_codes
+}
+
+impl HuffmanTree {
+    pub fn new(bit_lengths: &[u8]) -> Self {
+        Self::with_alphabet(bit_lengths, Alphabet::Plain)
+    }
+
+    /// A literal/length tree, whose entries have the base length and extra bits of each length symbol.
+    pub fn literal_length(bit_lengths: &[u8]) -> Self {
+        Self::with_alphabet(bit_lengths, Alphabet::LiteralLength)
+    }
+
+    /// A distance tree, whose entries have the base distance and extra bits of each symbol.
+    pub fn distance(bit_lengths: &[u8]) -> Self {
+        Self::with_alphabet(bit_lengths, Alphabet::Distance)
+    }
+
+    fn with_alphabet(bit_lengths: &[u8], alphabet: Alphabet) -> Self {
+        let final_codes = canonical_codes(bit_lengths);
         // put them in the lookup table.
         let mut lut = vec![None; LUT_SIZE];
         for i in 0..bit_lengths.len() {
@@ -54,43 +158,148 @@ impl HuffmanTree {
             let code = final_codes[i] as usize;
             let i = i as u16;
             if len > 0 {
-                lut[code] = Some(HuffmanCode { symbol: i, len });
+                let (base, extra_bits) = alphabet.meaning(i);
+                lut[code] = Some(HuffmanCode { symbol: i, len, base, extra_bits });
             }
         }
 
-        Self { lut }
+        let used = bit_lengths.iter().rposition(|&len| len > 0).map_or(0, |i| i + 1);
+        Self {
+            lut,
+            code_lengths: bit_lengths[..used].to_vec(),
+            codes: final_codes[..used].to_vec(),
+        }
     }
 
-    pub fn fixed() -> Self {
-        let mut test_values: Vec<u8> = vec![];
-        for (next, bit_len) in [(143, 8), (255, 9), (279, 7), (287, 8)] {
-            test_values.resize(next + 1, bit_len);
-        }
+    /// The same as new. The other half of `export`.
+    pub fn from_code_lengths(code_lengths: &[u8]) -> Self {
+        Self::new(code_lengths)
+    }
 
-        Self::new(&test_values)
+    /// The literal/length tree for fixed blocks (BTYPE=01).
+    pub fn fixed() -> &'static Self {
+        FIXED.get_or_init(|| {
+            let mut test_values: Vec<u8> = vec![];
+            for (next, bit_len) in [(143, 8), (255, 9), (279, 7), (287, 8)] {
+                test_values.resize(next + 1, bit_len);
+            }
+            Self::literal_length(&test_values)
+        })
     }
 
-    pub fn fixed_dist() -> Self {
-        let test_values_dist: Vec<u8> = vec![5; 31];
-        Self::new(&test_values_dist)
+    /// The distance tree for fixed blocks.
+    pub fn fixed_dist() -> &'static Self {
+        FIXED_DIST.get_or_init(|| Self::distance(&[5; 31]))
     }
 
     pub fn decode(&self, code: u16, len: u8) -> Option<u16> {
-        let code = code as usize;
-        let lookup = self.lut[code]?;
-        if len == lookup.len {
-            Some(lookup.symbol)
-        } else {
-            None
+        self.lookup(code, len).map(|entry| entry.symbol)
+    }
+
+    /// The whole table entry for a code, if there's a symbol with that code.
+    pub fn lookup(&self, code: u16, len: u8) -> Option<HuffmanCode> {
+        let entry = self.lut[code as usize]?;
+        (len == entry.len).then_some(entry)
+    }
+
+    /// The lookup table `lookup` uses, indexed by code: the entry for each code, or None where no symbol has it.
+    /// Codes are read most significant bit first and are at most `MAX_HUFFMAN_BITS` long, and an entry is only
+    /// for its code at its own length, e.g. the entry at 0b010 could be for the 3 bit code 010 or the 2 bit code 10.
+    /// `codes` is usually easier to work with.
+    pub fn get_lut(&self) -> &[Option<HuffmanCode>] {
+        &self.lut
+    }
+
+    /// The code assigned to every symbol that has one, in symbol order, e.g. for showing how a block was coded.
+    pub fn codes(&self) -> impl Iterator<Item = SymbolCode> + '_ {
+        let codes = self.code_lengths.iter().zip(&self.codes).enumerate();
+        codes.filter(|(_, (&len, _))| len > 0).map(|(symbol, (&len, &code))| SymbolCode {
+            symbol: symbol as u16,
+            len,
+            code,
+        })
+    }
+
+    /// The code for a symbol and how many bits long it is, for `BitWriter::put_code`. None if the symbol doesn't
+    /// have a code in this tree. The other half of `decode`.
+    pub fn encode(&self, symbol: u16) -> Option<(u16, u8)> {
+        let len = *self.code_lengths.get(symbol as usize)?;
+        (len > 0).then(|| (self.codes[symbol as usize], len))
+    }
+
+    /// The code length of each symbol, which is all it takes to make the tree again with `from_code_lengths`.
+    /// Symbols after the last one with a code are left off.
+    pub fn export(&self) -> &[u8] {
+        &self.code_lengths
+    }
+}
+
+/// The literal/length symbol for a match length (3 to 258), and the value and number of its extra bits.
+pub fn length_symbol(len: u16) -> Option<(u16, u16, u8)> {
+    let i = BASE_LENGTHS.iter().rposition(|&base| base <= len)?;
+    let extra = len - BASE_LENGTHS[i];
+    // 258 has a symbol of its own, so 227 + 31 isn't one.
+    (extra < 1 << LENGTH_EXTRA_BITS[i]).then_some((257 + i as u16, extra, LENGTH_EXTRA_BITS[i]))
+}
+
+/// The distance symbol for a distance (1 to 32768), and the value and number of its extra bits.
+pub fn distance_symbol(dist: u16) -> Option<(u16, u16, u8)> {
+    let i = BASE_DISTS.iter().rposition(|&base| base <= dist)?;
+    let extra = dist - BASE_DISTS[i];
+    (extra < 1 << DIST_EXTRA_BITS[i]).then_some((i as u16, extra, DIST_EXTRA_BITS[i]))
+}
+
+/// How much of the code space some code lengths use: Less for an incomplete code, with bit patterns that don't
+/// decode to anything, Equal for a complete one, and Greater for one with more codes than there's room for.
+pub fn code_space(bit_lengths: &[u8]) -> Ordering {
+    let mut count = [0_i64; (MAX_HUFFMAN_BITS + 1) as usize];
+    for &len in bit_lengths {
+        count[len as usize] += 1;
+    }
+    // how many codes of each length there's still room for.
+    let mut left: i64 = 1;
+    for &codes in &count[1..] {
+        left = (left << 1) - codes;
+        if left < 0 {
+            return Ordering::Greater;
         }
     }
+    match left {
+        0 => Ordering::Equal,
+        _ => Ordering::Less,
+    }
+}
 
-    #[cfg(test)]
-    pub fn get_lut(&self) -> &Vec<Option<HuffmanCode>> {
-        return &self.lut;
+/// Pack the trees of a dynamic block into a couple of hundred bytes, to store with its checkpoint: the number
+/// of literal/length codes as a u16 (LE), the number of distance codes as a u8, then every code length as a
+/// nibble, low nibble first.
+pub fn encode_trees(symbol_tree: &HuffmanTree, distance_tree: &HuffmanTree) -> Vec<u8> {
+    let (symbols, distances) = (symbol_tree.export(), distance_tree.export());
+    let mut blob = Vec::with_capacity(3 + (symbols.len() + distances.len()).div_ceil(2));
+    blob.extend((symbols.len() as u16).to_le_bytes());
+    blob.push(distances.len() as u8);
+    for pair in [symbols, distances].concat().chunks(2) {
+        blob.push(pair[0] | pair.get(1).unwrap_or(&0) << 4);
     }
+    blob
+}
 
-    pub fn export(&self) {}
+/// Make the trees again from what `encode_trees` packed them into.
+pub fn decode_trees(blob: &[u8]) -> Result<(HuffmanTree, HuffmanTree), CorniferError> {
+    let [a, b, c, nibbles @ ..] = blob else {
+        return Err(CorniferError::InvalidHuffmanTrees);
+    };
+    let num_symbols = u16::from_le_bytes([*a, *b]) as usize;
+    let num_distances = *c as usize;
+    let total = num_symbols + num_distances;
+    if num_symbols > MAX_SYMBOLS || num_distances > MAX_DISTANCES || nibbles.len() != total.div_ceil(2) {
+        return Err(CorniferError::InvalidHuffmanTrees);
+    }
+    let lengths: Vec<u8> = nibbles.iter().flat_map(|byte| [byte & 0xf, byte >> 4]).take(total).collect();
+    Ok((
+        HuffmanTree::literal_length(&lengths[..num_symbols]),
+        HuffmanTree::distance(&lengths[num_symbols..]),
+    ))
 }
 
 /**
@@ -98,10 +307,12 @@ impl HuffmanTree {
  */
 #[cfg(test)]
 mod test {
-    use crate::huffman::HuffmanCode;
+    use crate::huffman::{HuffmanCode, UNUSED_SYMBOL};
     use rstest::*;
 
-    use super::HuffmanTree;
+    use std::cmp::Ordering;
+
+    use super::{code_space, decode_trees, distance_symbol, encode_trees, length_symbol, HuffmanTree, SymbolCode};
 
     #[rstest]
     pub fn test_lut_values_correct() {
@@ -123,9 +334,9 @@ mod test {
            7       4       1111
         */
         assert_eq!(codes[0b01], None);
-        assert_eq!(codes[0b010], Some(HuffmanCode { symbol: 0, len: 3 }));
-        assert_eq!(codes[0b1111], Some(HuffmanCode { symbol: 7, len: 4 }));
-        assert_eq!(codes[0b00], Some(HuffmanCode { symbol: 5, len: 2 }));
+        assert_eq!(codes[0b010], Some(HuffmanCode { symbol: 0, len: 3, base: 0, extra_bits: 0 }));
+        assert_eq!(codes[0b1111], Some(HuffmanCode { symbol: 7, len: 4, base: 7, extra_bits: 0 }));
+        assert_eq!(codes[0b00], Some(HuffmanCode { symbol: 5, len: 2, base: 5, extra_bits: 0 }));
     }
 
     #[rstest]
@@ -152,9 +363,9 @@ mod test {
         let codes = tree.get_lut();
 
         assert_eq!(codes[0b01], None);
-        assert_eq!(codes[0b010], Some(HuffmanCode { symbol: 1, len: 3 }));
-        assert_eq!(codes[0b1111], Some(HuffmanCode { symbol: 10, len: 4 }));
-        assert_eq!(codes[0b00], Some(HuffmanCode { symbol: 7, len: 2 }));
+        assert_eq!(codes[0b010], Some(HuffmanCode { symbol: 1, len: 3, base: 1, extra_bits: 0 }));
+        assert_eq!(codes[0b1111], Some(HuffmanCode { symbol: 10, len: 4, base: 10, extra_bits: 0 }));
+        assert_eq!(codes[0b00], Some(HuffmanCode { symbol: 7, len: 2, base: 7, extra_bits: 0 }));
     }
 
     #[rstest]
@@ -198,10 +409,10 @@ mod test {
         let tree = HuffmanTree::new(&test_values);
 
         let codes = tree.get_lut();
-        assert_eq!(codes[0b1011], Some(HuffmanCode { symbol: 27, len: 4 }));
+        assert_eq!(codes[0b1011], Some(HuffmanCode { symbol: 27, len: 4, base: 27, extra_bits: 0 }));
         assert_eq!(
             codes[0b11111111110],
-            Some(HuffmanCode { symbol: 5, len: 11 })
+            Some(HuffmanCode { symbol: 5, len: 11, base: 5, extra_bits: 0 })
         );
     }
 
@@ -221,29 +432,117 @@ mod test {
         280 - 287     8          11000000 through
                                 11000111
          */
-        assert_eq!(codes[0b110001], Some(HuffmanCode { symbol: 1, len: 8 }));
+        assert_eq!(codes[0b110001], Some(HuffmanCode { symbol: 1, len: 8, base: 1, extra_bits: 0 }));
         assert_eq!(
             codes[0b11000111],
             Some(HuffmanCode {
                 symbol: 287,
-                len: 8
+                len: 8,
+                base: 0,
+                extra_bits: UNUSED_SYMBOL
             })
         );
         assert_eq!(
             codes[0b111111110],
             Some(HuffmanCode {
                 symbol: 254,
-                len: 9
+                len: 9,
+                base: 254,
+                extra_bits: 0
             })
         );
         assert_eq!(
             codes[0b0000000],
             Some(HuffmanCode {
                 symbol: 256,
-                len: 7
+                len: 7,
+                base: 256,
+                extra_bits: 0
+            })
+        );
+        // lengths 11 and 12, then 115 to 130.
+        assert_eq!(
+            codes[0b0001001],
+            Some(HuffmanCode {
+                symbol: 265,
+                len: 7,
+                base: 11,
+                extra_bits: 1
+            })
+        );
+        assert_eq!(
+            codes[0b11000000],
+            Some(HuffmanCode {
+                symbol: 280,
+                len: 8,
+                base: 115,
+                extra_bits: 4
+            })
+        );
+        let distances = HuffmanTree::fixed_dist().get_lut();
+        assert_eq!(
+            distances[0b11101],
+            Some(HuffmanCode {
+                symbol: 29,
+                len: 5,
+                base: 24577,
+                extra_bits: 13
             })
         );
+        assert_eq!(distances[0b11110].unwrap().extra_bits, UNUSED_SYMBOL);
         assert_eq!(codes[0b1111111111], None);
+        // it's only built once.
+        assert!(std::ptr::eq(tree, HuffmanTree::fixed()));
+    }
+
+    #[rstest]
+    pub fn test_codes() {
+        let test_values: [u8; 8] = [0, 3, 3, 3, 0, 2, 4, 4];
+        let tree = HuffmanTree::new(&test_values);
+        let codes: Vec<SymbolCode> = tree.codes().collect();
+        let expected = [(1, 3, 0b010), (2, 3, 0b011), (3, 3, 0b100), (5, 2, 0b00), (6, 4, 0b1010), (7, 4, 0b1011)];
+        assert_eq!(codes, expected.map(|(symbol, len, code)| SymbolCode { symbol, len, code }));
+        // every code is in the lookup table, at its own length.
+        for SymbolCode { symbol, len, code } in codes {
+            assert_eq!(tree.get_lut()[code as usize].map(|entry| (entry.symbol, entry.len)), Some((symbol, len)));
+        }
+        assert_eq!(HuffmanTree::fixed().codes().count(), 288);
+        assert_eq!(HuffmanTree::fixed().codes().nth(256), Some(SymbolCode { symbol: 256, len: 7, code: 0 }));
+    }
+
+    #[rstest]
+    pub fn test_encode() {
+        let test_values: [u8; 8] = [0, 3, 3, 3, 0, 2, 4, 4];
+        let tree = HuffmanTree::new(&test_values);
+        assert_eq!(tree.encode(0), None);
+        assert_eq!(tree.encode(6), Some((0b1010, 4)));
+        assert_eq!(tree.encode(8), None);
+        for symbol in 0..288 {
+            let (code, len) = HuffmanTree::fixed().encode(symbol).unwrap();
+            assert_eq!(HuffmanTree::fixed().decode(code, len), Some(symbol));
+        }
+    }
+
+    // every length and distance goes to a symbol whose base and extra bits give it back.
+    #[rstest]
+    pub fn test_length_and_distance_symbols() {
+        let entry = |tree: &HuffmanTree, symbol| {
+            let (code, len) = tree.encode(symbol).unwrap();
+            tree.lookup(code, len).unwrap()
+        };
+        for len in 3..=258 {
+            let (symbol, extra, bits) = length_symbol(len).unwrap();
+            let entry = entry(HuffmanTree::fixed(), symbol);
+            assert_eq!((entry.base + extra, entry.extra_bits), (len, bits));
+        }
+        assert_eq!(length_symbol(258), Some((285, 0, 0)));
+        assert_eq!(length_symbol(2), None);
+        for dist in 1..=32768 {
+            let (symbol, extra, bits) = distance_symbol(dist).unwrap();
+            let entry = entry(HuffmanTree::fixed_dist(), symbol);
+            assert_eq!((entry.base + extra, entry.extra_bits), (dist, bits));
+        }
+        assert_eq!(distance_symbol(0), None);
     }
 
     #[rstest]
@@ -254,4 +553,37 @@ mod test {
         assert_eq!(tree.decode(0b10, 2), None);
         assert_eq!(tree.decode(0b010, 3), Some(0));
     }
+
+    #[rstest]
+    #[case::complete(&[3, 3, 3, 3, 3, 2, 4, 4], Ordering::Equal)]
+    #[case::one_code(&[0, 1], Ordering::Less)]
+    #[case::empty(&[0, 0, 0], Ordering::Less)]
+    #[case::gap(&[2, 2, 2], Ordering::Less)]
+    #[case::over_subscribed(&[1, 1, 1], Ordering::Greater)]
+    #[case::longest(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 15], Ordering::Equal)]
+    pub fn test_code_space(#[case] bit_lengths: &[u8], #[case] expected: Ordering) {
+        assert_eq!(code_space(bit_lengths), expected);
+    }
+
+    #[rstest]
+    pub fn test_trees_round_trip() {
+        let symbols = [
+            11_u8, 12, 11, 12, 0, 11, 9, 8, 7, 7, 7, 6, 6, 6, 5, 5, 4, 5, 4, 4, 4, 4, 3, 4, 4, 4,
+            4, 4, 4, 6, 0, 0, 0,
+        ];
+        let tree = HuffmanTree::from_code_lengths(&symbols);
+        // the zeros on the end don't make any difference to the tree.
+        assert_eq!(tree.export(), &symbols[..30]);
+        assert!(HuffmanTree::from_code_lengths(tree.export()) == tree);
+
+        let (fixed, fixed_dist) = (HuffmanTree::fixed(), HuffmanTree::fixed_dist());
+        let blob = encode_trees(fixed, fixed_dist);
+        assert_eq!(blob.len(), 3 + (288 + 31_usize).div_ceil(2));
+        let (symbol_tree, distance_tree) = decode_trees(&blob).unwrap();
+        assert!(&symbol_tree == fixed);
+        assert!(&distance_tree == fixed_dist);
+
+        assert!(decode_trees(&blob[..blob.len() - 1]).is_err());
+        assert!(decode_trees(&[]).is_err());
+    }
 }
@@ -1,7 +1,50 @@
+pub mod archive;
+pub mod bits;
+#[cfg(feature = "bzip2")]
+pub mod bzip2;
+pub mod cache;
+pub mod cancel;
 pub mod checkpoint;
+#[cfg(feature = "sqlite")]
+pub mod checkpointed;
 pub mod circle;
 pub mod decompress;
 pub mod errors;
+pub mod estimate;
+#[cfg(feature = "sqlite")]
+pub mod fetch;
+pub mod format;
+#[cfg(feature = "sqlite")]
+pub mod fsck;
 pub mod header;
 pub mod huffman;
+#[cfg(feature = "sqlite")]
+pub mod multi;
+#[cfg(any(unix, windows))]
+pub mod positional;
+#[cfg(all(test, feature = "sqlite"))]
+mod proptests;
 pub mod reader;
+#[cfg(feature = "remote")]
+pub mod remote;
+#[cfg(feature = "sqlite")]
+pub mod report;
+pub mod schema;
+pub mod sink;
+#[cfg(feature = "sqlite")]
+pub mod slice;
+pub mod stats;
+#[cfg(feature = "sqlite")]
+pub mod store;
+#[cfg(all(test, feature = "sqlite"))]
+#[path = "../tests/common/mod.rs"]
+mod testutil;
+// testutil names this crate `cornifer`, like the integration tests and benchmarks it's shared with.
+#[cfg(all(test, feature = "sqlite"))]
+extern crate self as cornifer;
+pub mod throttle;
+pub mod transform;
+#[cfg(feature = "writer")]
+pub mod writer;
+#[cfg(feature = "xz")]
+pub mod xz;
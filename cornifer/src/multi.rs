@@ -0,0 +1,157 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::checkpointed::{seek_position, CheckpointedReader};
+
+/*
+ * Several checkpointed files read as one, e.g. a dataset that was written out in parts. Each part keeps its own
+ * reader, and a read or seek goes to whichever part the position is in.
+ */
+
+/// The decompressed contents of several files one after the other, as if they were one file.
+pub struct MultiReader<R> {
+    parts: Vec<CheckpointedReader<R>>,
+    // where each part starts, and then where the last one ends.
+    starts: Vec<u64>,
+    pos: u64,
+}
+
+impl<R: Read + Seek> MultiReader<R> {
+    pub fn new(parts: Vec<CheckpointedReader<R>>) -> Self {
+        let mut starts = vec![0];
+        for part in &parts {
+            starts.push(starts[starts.len() - 1] + part.uncompressed_len());
+        }
+        Self { parts, starts, pos: 0 }
+    }
+
+    /// Length of all the parts put together.
+    pub fn uncompressed_len(&self) -> u64 {
+        self.starts[self.starts.len() - 1]
+    }
+
+    pub fn parts(&self) -> &[CheckpointedReader<R>] {
+        &self.parts
+    }
+
+    pub fn into_parts(self) -> Vec<CheckpointedReader<R>> {
+        self.parts
+    }
+
+    /// Which part `pos` is in, and where it is in that part. None at the end or past it.
+    pub fn locate(&self, pos: u64) -> Option<(usize, u64)> {
+        if pos >= self.uncompressed_len() {
+            return None;
+        }
+        // the last part starting at or before pos, which skips over empty ones.
+        let part = self.starts.partition_point(|start| *start <= pos) - 1;
+        Some((part, pos - self.starts[part]))
+    }
+}
+
+impl<R: Read + Seek> Read for MultiReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut bytes_read = 0;
+        // on into the next part if this one ends before the buffer does.
+        while let Some((part, offset)) = self.locate(self.pos).filter(|_| bytes_read < buf.len()) {
+            let reader = &mut self.parts[part];
+            reader.seek(SeekFrom::Start(offset))?;
+            let n = match reader.read(&mut buf[bytes_read..]) {
+                Ok(0) => break,
+                Ok(n) => n,
+                // hand back what we've got, the caller will see the error on the next read.
+                Err(_) if bytes_read > 0 => break,
+                Err(e) => return Err(e),
+            };
+            bytes_read += n;
+            self.pos += n as u64;
+        }
+        Ok(bytes_read)
+    }
+}
+
+impl<R: Read + Seek> Seek for MultiReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = seek_position(self.pos, self.uncompressed_len(), pos)?;
+        Ok(self.pos)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.pos)
+    }
+}
+
+/**
+ * TESTS
+ */
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+    use flate2::{write::GzEncoder, Compression};
+    use rstest::rstest;
+
+    use crate::{
+        checkpoint::Checkpointer, checkpointed::CheckpointedReader, decompress::Deflator, reader::CorniferByteReader,
+        testutil::TempIndex,
+    };
+
+    use super::MultiReader;
+
+    type Part = CheckpointedReader<Cursor<Vec<u8>>>;
+
+    // the text in parts this long, each one gzipped and indexed on its own.
+    fn indexed_parts(lens: &[usize]) -> (Vec<Part>, Vec<TempIndex>) {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let mut from = 0;
+        let mut readers = Vec::new();
+        let mut paths = Vec::new();
+        for len in lens {
+            let mut e = GzEncoder::new(Vec::new(), Compression::default());
+            e.write_all(&text[from..from + len]).unwrap();
+            let input = e.finish().unwrap();
+            from += len;
+            let path = TempIndex::new();
+            let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), Checkpointer::init(&path).unwrap());
+            std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+            drop(deflator);
+            readers.push(CheckpointedReader::open(Cursor::new(input), &path).unwrap());
+            paths.push(path);
+        }
+        (readers, paths)
+    }
+
+    #[rstest]
+    #[case::even(&[10000, 10000, 10000])]
+    #[case::with_empty_part(&[15000, 0, 5000, 0])]
+    #[case::one(&[39819])]
+    fn test_read_across_parts(#[case] lens: &[usize]) {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let total: usize = lens.iter().sum();
+        let (readers, _indexes) = indexed_parts(lens);
+        let mut reader = MultiReader::new(readers);
+        assert_eq!(reader.uncompressed_len(), total as u64);
+
+        let mut dest = Vec::new();
+        reader.read_to_end(&mut dest).unwrap();
+        assert_eq!(dest, &text[..total]);
+        // either side of where one part ends and the next starts.
+        for at in [0, 9990, 14995, total as u64 - 10] {
+            reader.seek(SeekFrom::Start(at)).unwrap();
+            let mut dest = vec![0; 10];
+            reader.read_exact(&mut dest).unwrap();
+            assert_eq!(dest, &text[at as usize..at as usize + 10]);
+        }
+        assert_eq!(reader.read(&mut [0; 10]).unwrap(), 0);
+    }
+
+    #[rstest]
+    fn test_locate() {
+        let (readers, _indexes) = indexed_parts(&[100, 0, 50]);
+        let reader = MultiReader::new(readers);
+        assert_eq!(reader.locate(0), Some((0, 0)));
+        assert_eq!(reader.locate(99), Some((0, 99)));
+        // the empty part is skipped.
+        assert_eq!(reader.locate(100), Some((2, 0)));
+        assert_eq!(reader.locate(150), None);
+    }
+}
@@ -0,0 +1,125 @@
+use std::borrow::Borrow;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+
+/**
+ * A cursor over a file that reads with pread (read_at on Unix, seek_read on Windows), so several of them can
+ * share one file handle without fighting over its position, e.g. one CheckpointedReader per thread.
+ */
+pub struct PositionalReader<F> {
+    file: F,
+    pos: u64,
+}
+
+impl<F: Borrow<File>> PositionalReader<F> {
+    /// Start reading at the beginning of the file. `file` can be a `&File`, an `Arc<File>` or the `File` itself.
+    pub fn new(file: F) -> Self {
+        Self { file, pos: 0 }
+    }
+
+    pub fn into_inner(self) -> F {
+        self.file
+    }
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+// this moves the file's own position, but nothing that shares it through here uses that.
+#[cfg(windows)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
+
+impl<F: Borrow<File>> Read for PositionalReader<F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = read_at(self.file.borrow(), buf, self.pos)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<F: Borrow<File>> Seek for PositionalReader<F> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(n) => self.file.borrow().metadata()?.len().checked_add_signed(n),
+            SeekFrom::Current(n) => self.pos.checked_add_signed(n),
+        };
+        match new_pos {
+            Some(n) => {
+                self.pos = n;
+                Ok(n)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+/**
+ * TESTS
+ */
+#[cfg(all(test, feature = "sqlite"))]
+mod test {
+    use std::fs::File;
+    use std::io::{BufReader, Read, Seek, SeekFrom};
+    use std::sync::Arc;
+
+    use rstest::rstest;
+
+    use crate::{
+        checkpoint::Checkpointer, checkpointed::CheckpointedReader, decompress::Deflator, reader::CorniferByteReader,
+        testutil::TempIndex,
+    };
+
+    use super::PositionalReader;
+
+    #[rstest]
+    fn test_cursors_share_a_file() {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let file = File::open("testfiles/1080-0.txt").unwrap();
+        let mut a = PositionalReader::new(&file);
+        let mut b = PositionalReader::new(&file);
+        a.seek(SeekFrom::Start(1000)).unwrap();
+        b.seek(SeekFrom::End(-10)).unwrap();
+        let mut dest = [0; 10];
+        a.read_exact(&mut dest).unwrap();
+        assert_eq!(dest, text[1000..1010]);
+        b.read_exact(&mut dest).unwrap();
+        assert_eq!(dest, text[text.len() - 10..]);
+        // and a carries on from where it was.
+        a.read_exact(&mut dest).unwrap();
+        assert_eq!(dest, text[1010..1020]);
+        assert_eq!(b.read(&mut dest).unwrap(), 0);
+    }
+
+    #[rstest]
+    fn test_checkpointed_readers_on_threads() {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let input: &[u8] = include_bytes!("../testfiles/1080-0.txt.gz");
+        let path = TempIndex::new();
+        let mut deflator = Deflator::new(CorniferByteReader::new(input), Checkpointer::init(&path).unwrap());
+        std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        drop(deflator);
+
+        let file = Arc::new(File::open("testfiles/1080-0.txt.gz").unwrap());
+        std::thread::scope(|scope| {
+            for start in [0, 10000, 30000] {
+                let (file, path) = (file.clone(), &path);
+                scope.spawn(move || {
+                    let source = BufReader::new(PositionalReader::new(file));
+                    let mut reader = CheckpointedReader::open(source, path).unwrap();
+                    reader.seek(SeekFrom::Start(start)).unwrap();
+                    let mut dest = vec![0; 500];
+                    reader.read_exact(&mut dest).unwrap();
+                    assert_eq!(dest, &text[start as usize..start as usize + 500]);
+                });
+            }
+        });
+    }
+}
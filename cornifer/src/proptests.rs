@@ -0,0 +1,167 @@
+/*
+ * Property tests: random data, compressed by flate2 with random settings, indexed, then read back from the
+ * index at random offsets. The fixed tests elsewhere only cover the handful of files in testfiles/, which is how
+ * resuming from a checkpoint can be wrong without anything noticing.
+ *
+ * There are also blocks written with our own encoder, which can say things flate2 never would, like matches
+ * reaching all the way back to the start.
+ */
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+use crc::{Crc, CRC_32_ISO_HDLC};
+use flate2::{write::GzEncoder, Compression};
+use proptest::prelude::*;
+
+use crate::{
+    bits::BitWriter,
+    checkpoint::Checkpointer,
+    checkpointed::CheckpointedReader,
+    decompress::Deflator,
+    header::GZIP_HEADER,
+    huffman::{distance_symbol, length_symbol, HuffmanTree},
+    reader::CorniferByteReader,
+    testutil::TempIndex,
+};
+
+static CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+// a gzip member: its data, the compression level, and where to flush (which ends the block there).
+#[derive(Debug, Clone)]
+struct Member {
+    data: Vec<u8>,
+    level: u32,
+    flushes: Vec<usize>,
+}
+
+impl Member {
+    fn compress(&self) -> Vec<u8> {
+        let mut e = GzEncoder::new(Vec::new(), Compression::new(self.level));
+        let mut flushes: Vec<_> = self.flushes.iter().map(|f| f % (self.data.len() + 1)).collect();
+        flushes.sort_unstable();
+        let mut from = 0;
+        for to in flushes {
+            e.write_all(&self.data[from..to]).unwrap();
+            e.flush().unwrap();
+            from = to;
+        }
+        e.write_all(&self.data[from..]).unwrap();
+        e.finish().unwrap()
+    }
+}
+
+// random bytes don't compress, so there are runs of a short pattern too, which make matches (some of them long).
+fn data() -> impl Strategy<Value = Vec<u8>> {
+    let noise = prop::collection::vec(any::<u8>(), 0..3000);
+    let runs = (prop::collection::vec(any::<u8>(), 1..8), 1..2000usize)
+        .prop_map(|(pattern, len)| pattern.iter().cycle().take(len).copied().collect::<Vec<_>>());
+    prop::collection::vec(prop_oneof![noise, runs], 0..12).prop_map(|parts| parts.concat())
+}
+
+fn member() -> impl Strategy<Value = Member> {
+    (data(), 0..=9u32, prop::collection::vec(any::<usize>(), 0..6))
+        .prop_map(|(data, level, flushes)| Member { data, level, flushes })
+}
+
+// something for a fixed block to say: a literal, or a match of some length some way back into what's been said.
+#[derive(Debug, Clone)]
+enum Token {
+    Literal(u8),
+    Match { len: u16, back: prop::sample::Index },
+}
+
+fn token() -> impl Strategy<Value = Token> {
+    prop_oneof![
+        any::<u8>().prop_map(Token::Literal),
+        (3..=258u16, any::<prop::sample::Index>()).prop_map(|(len, back)| Token::Match { len, back }),
+    ]
+}
+
+// a gzip member with the tokens in one fixed block, written with HuffmanTree::encode, and what it decompresses to.
+fn fixed_member(tokens: &[Token]) -> (Vec<u8>, Vec<u8>) {
+    let (symbol_tree, distance_tree) = (HuffmanTree::fixed(), HuffmanTree::fixed_dist());
+    let put_symbol = |writer: &mut BitWriter, tree: &HuffmanTree, symbol| {
+        let (code, len) = tree.encode(symbol).unwrap();
+        writer.put_code(code, len);
+    };
+    let mut writer = BitWriter::default();
+    writer.put_bytes(&GZIP_HEADER);
+    // final, fixed.
+    writer.put(0b011, 3);
+    let mut data: Vec<u8> = Vec::new();
+    for token in tokens {
+        match token {
+            Token::Literal(byte) => {
+                put_symbol(&mut writer, symbol_tree, *byte as u16);
+                data.push(*byte);
+            }
+            // nothing to refer back to yet.
+            Token::Match { .. } if data.is_empty() => {}
+            Token::Match { len, back } => {
+                let dist = back.index(data.len().min(32768)) + 1;
+                let (symbol, extra, bits) = length_symbol(*len).unwrap();
+                put_symbol(&mut writer, symbol_tree, symbol);
+                writer.put(extra as u64, bits as u32);
+                let (symbol, extra, bits) = distance_symbol(dist as u16).unwrap();
+                put_symbol(&mut writer, distance_tree, symbol);
+                writer.put(extra as u64, bits as u32);
+                for _ in 0..*len {
+                    data.push(data[data.len() - dist]);
+                }
+            }
+        }
+    }
+    put_symbol(&mut writer, symbol_tree, 256);
+    writer.align();
+    writer.put_bytes(&CRC32.checksum(&data).to_le_bytes());
+    writer.put_bytes(&(data.len() as u32).to_le_bytes());
+    (writer.finish(), data)
+}
+
+
+proptest! {
+    // each case writes an index, so not the default 256 of them.
+    #![proptest_config(ProptestConfig::with_cases(48))]
+
+    #[test]
+    fn test_random_access_matches_input(
+        members in prop::collection::vec(member(), 1..4),
+        spacing in prop_oneof![Just(0u64), Just(5000), Just(u64::MAX)],
+        reads in prop::collection::vec((any::<prop::sample::Index>(), 0..10000usize), 1..8),
+    ) {
+        let input: Vec<u8> = members.iter().flat_map(|m| m.compress()).collect();
+        let expected: Vec<u8> = members.iter().flat_map(|m| m.data.iter().copied()).collect();
+
+        let path = TempIndex::new();
+        let mut checkpointer = Checkpointer::init(&path).unwrap();
+        checkpointer.set_window_spacing(spacing);
+        let mut deflator = Deflator::new(CorniferByteReader::new(input.as_slice()), checkpointer);
+        let mut indexed = Vec::new();
+        deflator.read_to_end(&mut indexed).unwrap();
+        drop(deflator);
+        prop_assert_eq!(&indexed, &expected);
+
+        let mut reader = CheckpointedReader::open(Cursor::new(input.as_slice()), &path).unwrap();
+        prop_assert_eq!(reader.uncompressed_len(), expected.len() as u64);
+        for (offset, len) in reads {
+            let offset = offset.index(expected.len() + 1);
+            let len = len.min(expected.len() - offset);
+            reader.seek(SeekFrom::Start(offset as u64)).unwrap();
+            let mut dest = vec![0; len];
+            reader.read_exact(&mut dest).unwrap();
+            prop_assert_eq!(&dest[..], &expected[offset..offset + len], "reading {} bytes at {}", len, offset);
+        }
+        reader.verify().unwrap();
+    }
+}
+
+proptest! {
+    // a fixed block made with HuffmanTree::encode decodes to what it was made from, matches and all.
+    #[test]
+    fn test_encoded_block_round_trips(tokens in prop::collection::vec(token(), 0..500)) {
+        let (member, expected) = fixed_member(&tokens);
+        let mut deflator = Deflator::new(CorniferByteReader::new(member.as_slice()), Checkpointer::none());
+        let mut decoded = Vec::new();
+        deflator.read_to_end(&mut decoded).unwrap();
+        prop_assert_eq!(decoded, expected);
+    }
+}
@@ -1,4 +1,6 @@
-use std::io::Read;
+use std::collections::VecDeque;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::{Deref, DerefMut};
 
 use crc::{Crc, Digest, CRC_32_ISO_HDLC};
 
@@ -13,10 +15,14 @@ pub struct CorniferByteReader<R> {
     // the current byte, for use when reading individual bits.
     buffer: u8,
     // reference to internal reader. This has ownership over the reader;
-    // once it's passed to this, there's no getting it back.
+    // into_inner gives it back.
     inner: R,
     // a crc32 digest. The crc object is static.
     digest: Option<Digest<'static, u32>>,
+    // a copy of the bytes read, while capturing.
+    capture: Option<Vec<u8>>,
+    // bytes peek_n_bits had to read from inner, which haven't been read from this yet.
+    lookahead: VecDeque<u8>,
 }
 
 impl<R: Read> CorniferByteReader<R> {
@@ -27,24 +33,53 @@ impl<R: Read> CorniferByteReader<R> {
             buffer: 0,
             inner: reader,
             digest: None,
+            capture: None,
+            lookahead: VecDeque::new(),
         }
     }
 
+    /// Give back the inner reader. Nothing is read ahead, so it's just after the last byte read (the rest of a
+    /// partly read byte is lost).
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
     fn read_exact_internal(&mut self, buf: &mut [u8]) -> Result<(), CorniferError> {
-        let l = buf.len();
-        match self.inner.read_exact(buf) {
+        // anything that was peeked at comes first.
+        let from_lookahead = buf.len().min(self.lookahead.len());
+        for (dest, byte) in buf.iter_mut().zip(self.lookahead.drain(..from_lookahead)) {
+            *dest = byte;
+        }
+        match self.inner.read_exact(&mut buf[from_lookahead..]) {
             Ok(_) => (),
             Err(e) => match e.kind() {
                 std::io::ErrorKind::UnexpectedEof => return Err(CorniferError::EOF),
                 _ => return Err(CorniferError::from(e)),
             },
         }
+        self.after_read(buf);
+
+        Ok(())
+    }
+
+    fn after_read(&mut self, buf: &[u8]) {
         if let Some(digest) = &mut self.digest {
             digest.update(buf);
         }
-        self.current_byte += l;
+        if let Some(capture) = &mut self.capture {
+            capture.extend_from_slice(buf);
+        }
+        self.current_byte += buf.len();
+    }
 
-        Ok(())
+    /// Read everything left in the input, returning the number of bytes read.
+    pub fn read_to_end(&mut self, buf: &mut Vec<u8>) -> Result<usize, CorniferError> {
+        let start = buf.len();
+        buf.extend(self.lookahead.drain(..));
+        self.inner.read_to_end(buf)?;
+        self.after_read(&buf[start..]);
+
+        Ok(buf.len() - start)
     }
 
     pub fn read_u8(&mut self) -> Result<u8, CorniferError> {
@@ -81,16 +116,69 @@ impl<R: Read> CorniferByteReader<R> {
         Ok(s)
     }
 
+    /// Like read_null_terminated_string, but gives up after max bytes without a null.
+    pub fn read_null_terminated_string_limited(&mut self, max: usize) -> Result<String, CorniferError> {
+        let start = self.current_byte;
+        let mut v: Vec<u8> = vec![];
+        loop {
+            match self.read_u8()? {
+                0 => break,
+                _ if v.len() == max => return Err(CorniferError::StringTooLong { position: start, max }),
+                i => v.push(i),
+            }
+        }
+        let s = String::from_utf8(v)?;
+
+        Ok(s)
+    }
+
+    /// Read exactly n bytes. The Vec grows as the bytes come in, so a bogus length in a corrupt file runs into
+    /// EOF instead of allocating all of it up front.
+    pub fn read_exact_n(&mut self, n: usize) -> Result<Vec<u8>, CorniferError> {
+        const CHUNK: usize = 1 << 16;
+        let mut v = Vec::with_capacity(n.min(CHUNK));
+        while v.len() < n {
+            let start = v.len();
+            v.resize(start + (n - start).min(CHUNK), 0);
+            self.read_exact_internal(&mut v[start..])?;
+        }
+        Ok(v)
+    }
+
     pub fn begin_crc(&mut self) {
         let digest = CRC32.digest();
         self.digest = Some(digest);
     }
 
+    /// Like begin_crc, but counting the byte that's partly been read too, if there is one. So it covers every byte
+    /// with any of the bits read from here on in it.
+    pub fn begin_crc_at_bit(&mut self) {
+        let mut digest = CRC32.digest();
+        if self.current_bit != 0 {
+            digest.update(&[self.buffer]);
+        }
+        self.digest = Some(digest);
+    }
+
     pub fn end_crc(&mut self) -> Option<u32> {
         let result = self.digest.take();
         result.map(|d| d.finalize())
     }
 
+    /// Start keeping a copy of every byte read, e.g. in case they turn out not to be what we expected.
+    pub fn begin_capture(&mut self) {
+        self.capture = Some(Vec::new());
+    }
+
+    pub fn end_capture(&mut self) -> Option<Vec<u8>> {
+        self.capture.take()
+    }
+
+    /// What's been captured so far, carrying on capturing.
+    pub fn captured(&self) -> Option<&[u8]> {
+        self.capture.as_deref()
+    }
+
     pub fn read_bit(&mut self) -> Result<u8, CorniferError> {
         if self.current_bit == 0 {
             self.buffer = self.read_u8()?;
@@ -112,10 +200,154 @@ impl<R: Read> CorniferByteReader<R> {
         Ok(value)
     }
 
-    pub fn discard_until_next_byte(&mut self) {
+    // the ith byte after the current one, without reading it.
+    fn peek_byte(&mut self, i: usize) -> Result<u8, CorniferError> {
+        while self.lookahead.len() <= i {
+            let mut byte = [0; 1];
+            match self.inner.read_exact(&mut byte) {
+                Ok(_) => self.lookahead.push_back(byte[0]),
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Err(CorniferError::EOF),
+                Err(e) => return Err(CorniferError::from(e)),
+            }
+        }
+        Ok(self.lookahead[i])
+    }
+
+    /// What read_n_bits_le(n) would return, without moving along.
+    pub fn peek_n_bits(&mut self, n: u8) -> Result<u16, CorniferError> {
+        if n > 16 {
+            return Err(CorniferError::InvalidNumberOfBits { num: n });
+        }
+        let mut value: u32 = 0;
+        let mut got = 0;
+        if self.current_bit != 0 {
+            value = (self.buffer >> self.current_bit) as u32;
+            got = 8 - self.current_bit;
+        }
+        let mut i = 0;
+        while got < n {
+            value |= (self.peek_byte(i)? as u32) << got;
+            got += 8;
+            i += 1;
+        }
+        Ok((value & ((1 << n) - 1)) as u16)
+    }
+
+    /// Read up to where `pattern` next starts, leaving it unread. Returns false if the input ended first, with all
+    /// of it read.
+    pub fn skip_until(&mut self, pattern: &[u8]) -> Result<bool, CorniferError> {
+        loop {
+            let mut matched = 0;
+            while matched < pattern.len() {
+                match self.peek_byte(matched) {
+                    Ok(byte) if byte == pattern[matched] => matched += 1,
+                    Ok(_) => break,
+                    Err(CorniferError::EOF) => {
+                        let mut rest = Vec::new();
+                        self.read_to_end(&mut rest)?;
+                        return Ok(false);
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+            if matched == pattern.len() {
+                return Ok(true);
+            }
+            self.read_u8()?;
+        }
+    }
+
+    /// Skip the rest of a partly read byte, returning how many bits were skipped.
+    pub fn align_to_byte(&mut self) -> u8 {
+        let skipped = if self.current_bit == 0 { 0 } else { 8 - self.current_bit };
         // the next call to read_bit() will read another byte, thus
         // discarding any leftover bits in the current byte.
         self.current_bit = 0;
+        skipped
+    }
+
+    pub fn discard_until_next_byte(&mut self) {
+        self.align_to_byte();
+    }
+}
+
+impl<R: Read + Seek> CorniferByteReader<R> {
+    /// Move to a bit of the input, e.g. a checkpoint's from_byte and from_bit. Bits are counted from the LSB,
+    /// like DEFLATE does. A CRC or capture that's in progress carries on as if the bytes in between were never there.
+    pub fn seek_to_bit(&mut self, byte: u64, bit: u8) -> Result<(), CorniferError> {
+        if bit > 7 {
+            return Err(CorniferError::InvalidNumberOfBits { num: bit });
+        }
+        self.inner.seek(SeekFrom::Start(byte))?;
+        self.lookahead.clear();
+        self.current_byte = byte as usize;
+        self.current_bit = 0;
+        if bit > 0 {
+            self.read_n_bits_le(bit)?;
+        }
+        Ok(())
+    }
+}
+
+/**
+ * A CorniferByteReader that can be moved around by bit position, e.g. to go back and re-read a block header.
+ * Everything else the byte reader does is available on this too.
+ *
+ * A CRC or capture that's in progress when seeking starts again from the new position, so it always covers
+ * bytes that are next to each other in the input.
+ */
+pub struct CorniferSeekableReader<R> {
+    reader: CorniferByteReader<R>,
+}
+
+impl<R: Read + Seek> CorniferSeekableReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: CorniferByteReader::new(reader),
+        }
+    }
+
+    /// How many bits into the input we are. Bits are counted from the LSB of each byte, like DEFLATE does.
+    pub fn bit_position(&self) -> u64 {
+        let byte = self.reader.current_byte as u64;
+        match self.reader.current_bit {
+            0 => byte * 8,
+            // current_byte has already moved past a partly read byte.
+            bit => (byte - 1) * 8 + bit as u64,
+        }
+    }
+
+    /// Move to a number of bits into the input.
+    pub fn seek_bits(&mut self, position: u64) -> Result<(), CorniferError> {
+        let crc_in_progress = self.reader.end_crc().is_some();
+        let capture_in_progress = self.reader.end_capture().is_some();
+        self.reader.seek_to_bit(position / 8, (position % 8) as u8)?;
+        if crc_in_progress {
+            self.reader.begin_crc();
+        }
+        if capture_in_progress {
+            self.reader.begin_capture();
+        }
+        Ok(())
+    }
+
+    /// The byte reader, e.g. to hand to a Deflator.
+    pub fn into_byte_reader(self) -> CorniferByteReader<R> {
+        self.reader
+    }
+}
+
+impl<R> Deref for CorniferSeekableReader<R> {
+    type Target = CorniferByteReader<R>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.reader
+    }
+}
+
+impl<R> DerefMut for CorniferSeekableReader<R> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.reader
     }
 }
 
@@ -124,15 +356,18 @@ impl<R: Read> CorniferByteReader<R> {
  */
 #[cfg(test)]
 mod test {
+    use std::io::Cursor;
+
     use rstest::*;
 
-    use super::CorniferByteReader;
+    use crate::errors::CorniferError;
+
+    use super::{CorniferByteReader, CorniferSeekableReader, CRC32};
 
     #[fixture]
     pub fn reader1() -> CorniferByteReader<&'static [u8]> {
         let inner: &[u8] = &[5, 6, 7, 0, 1, 2, 3, 4];
-        let sr = CorniferByteReader::new(inner);
-        sr
+        CorniferByteReader::new(inner)
     }
 
     #[rstest]
@@ -190,7 +425,7 @@ mod test {
 
     #[rstest]
     pub fn test_crc32_one_byte() {
-        let inner: &[u8] = &[b'h'];
+        let inner: &[u8] = b"h";
         let mut sr = CorniferByteReader::new(inner);
         sr.begin_crc();
         sr.read_u8().expect("known value");
@@ -200,7 +435,7 @@ mod test {
 
     #[rstest]
     pub fn test_crc32() {
-        let inner: &[u8] = &[b'h', b'e', b'l', b'l', b'o'];
+        let inner: &[u8] = b"hello";
         let mut sr = CorniferByteReader::new(inner);
         sr.begin_crc();
         for _ in 0..inner.len() {
@@ -210,6 +445,21 @@ mod test {
         assert_eq!(result, 0x3610A686);
     }
 
+    #[rstest]
+    #[case::at_a_byte(0, b"ell")]
+    #[case::partway(3, b"ell")]
+    pub fn test_crc32_at_bit(#[case] bits: u8, #[case] expected: &[u8]) {
+        let inner: &[u8] = b"hello";
+        let mut sr = CorniferByteReader::new(inner);
+        sr.read_u8().unwrap();
+        sr.read_n_bits_le(bits).unwrap();
+        sr.begin_crc_at_bit();
+        // into the last byte, which counts even though it's not all read.
+        sr.read_n_bits_le(8).unwrap();
+        sr.read_n_bits_le(8 + 3 - bits).unwrap();
+        assert_eq!(sr.end_crc(), Some(CRC32.checksum(expected)));
+    }
+
     #[rstest]
     pub fn test_crc32_long() {
         let inner: &[u8] = include_bytes!("../testfiles/testCompressThenConcat.txt.gz");
@@ -222,6 +472,25 @@ mod test {
         assert_eq!(result, 0xFFDFCA91);
     }
 
+    #[rstest]
+    pub fn test_capture(mut reader1: CorniferByteReader<&'static [u8]>) {
+        reader1.read_u8().unwrap();
+        reader1.begin_capture();
+        reader1.read_u16_le().unwrap();
+        assert_eq!(reader1.end_capture(), Some(vec![6, 7]));
+        reader1.read_u8().unwrap();
+        assert_eq!(reader1.end_capture(), None);
+    }
+
+    #[rstest]
+    pub fn test_read_to_end(mut reader1: CorniferByteReader<&'static [u8]>) {
+        reader1.read_u16_le().unwrap();
+        let mut rest = vec![];
+        assert_eq!(reader1.read_to_end(&mut rest).unwrap(), 6);
+        assert_eq!(rest, vec![7, 0, 1, 2, 3, 4]);
+        assert_eq!(reader1.current_byte, 8);
+    }
+
     #[rstest]
     pub fn test_read_bit() {
         let inner: &[u8] = &[0b10011001, 0b00011100];
@@ -263,4 +532,95 @@ mod test {
         assert_eq!(sr.read_n_bits_le(2).unwrap(), 0b10);
         assert_eq!(sr.read_n_bits_le(2).unwrap(), 0b01);
     }
+
+    #[rstest]
+    pub fn test_peek_n_bits() {
+        let inner: &[u8] = &[0b10011001, 0b00011100, 0b11110000];
+        let mut sr = CorniferByteReader::new(inner);
+        assert_eq!(sr.peek_n_bits(4).unwrap(), 0b1001);
+        assert_eq!(sr.current_byte, 0);
+        assert_eq!(sr.read_n_bits_le(3).unwrap(), 0b001);
+        // across the byte boundary.
+        assert_eq!(sr.peek_n_bits(12).unwrap(), 0b0011_1001_0011);
+        assert_eq!(sr.peek_n_bits(16).unwrap(), 0b0000_0011_1001_0011);
+        assert_eq!(sr.read_n_bits_le(12).unwrap(), 0b0011_1001_0011);
+        assert_eq!(sr.current_byte, 2);
+        sr.align_to_byte();
+        assert_eq!(sr.read_u8().unwrap(), 0b11110000);
+        assert!(sr.peek_n_bits(1).is_err());
+        assert!(sr.peek_n_bits(17).is_err());
+    }
+
+    #[rstest]
+    pub fn test_align_to_byte() {
+        let inner: &[u8] = &[0b10011001, 0b00011100];
+        let mut sr = CorniferByteReader::new(inner);
+        assert_eq!(sr.align_to_byte(), 0);
+        sr.read_n_bits_le(3).unwrap();
+        assert_eq!(sr.align_to_byte(), 5);
+        assert_eq!(sr.read_n_bits_le(3).unwrap(), 0b100);
+    }
+
+    #[rstest]
+    pub fn test_seek_to_bit() {
+        let mut sr = CorniferByteReader::new(Cursor::new(vec![5, 6, 0b10011001, 0b00011100]));
+        sr.peek_n_bits(16).unwrap();
+        sr.seek_to_bit(2, 3).unwrap();
+        assert_eq!((sr.current_byte, sr.current_bit), (3, 3));
+        assert_eq!(sr.read_n_bits_le(5).unwrap(), 0b10011);
+        sr.seek_to_bit(1, 0).unwrap();
+        assert_eq!(sr.read_u8().unwrap(), 6);
+        assert!(sr.seek_to_bit(1, 8).is_err());
+    }
+
+    #[rstest]
+    pub fn test_seek_bits() {
+        let mut sr = CorniferSeekableReader::new(Cursor::new(vec![5, 6, 0b10011001, 0b00011100]));
+        assert_eq!(sr.bit_position(), 0);
+        sr.seek_bits(19).unwrap();
+        assert_eq!(sr.bit_position(), 19);
+        assert_eq!(sr.read_n_bits_le(5).unwrap(), 0b10011);
+        assert_eq!(sr.bit_position(), 24);
+
+        // the CRC starts again wherever we seek to.
+        sr.begin_crc();
+        sr.read_u8().unwrap();
+        sr.seek_bits(8).unwrap();
+        sr.read_u8().unwrap();
+        let mut expected = CorniferByteReader::new(&[6u8][..]);
+        expected.begin_crc();
+        expected.read_u8().unwrap();
+        assert_eq!(sr.end_crc(), expected.end_crc());
+        assert_eq!(sr.into_byte_reader().current_byte, 2);
+    }
+
+    #[rstest]
+    pub fn test_read_null_terminated_string_limited() {
+        let inner: &[u8] = b"hello\0world\0";
+        let mut sr = CorniferByteReader::new(inner);
+        assert_eq!(sr.read_null_terminated_string_limited(5).unwrap(), "hello");
+        let err = sr.read_null_terminated_string_limited(4).unwrap_err();
+        assert!(matches!(err, CorniferError::StringTooLong { position: 6, max: 4 }));
+    }
+
+    #[rstest]
+    pub fn test_skip_until() {
+        let mut reader = CorniferByteReader::new([1, 0x1f, 2, 0x1f, 0x8b, 8, 3].as_slice());
+        assert!(reader.skip_until(&[0x1f, 0x8b, 8]).unwrap());
+        assert_eq!(reader.current_byte, 3);
+        assert_eq!(reader.read_u8().unwrap(), 0x1f);
+        assert!(!reader.skip_until(&[0x1f, 0x8b, 8]).unwrap());
+        assert_eq!(reader.current_byte, 7);
+    }
+
+    #[rstest]
+    pub fn test_read_exact_n(mut reader1: CorniferByteReader<&'static [u8]>) {
+        reader1.read_bit().unwrap();
+        reader1.align_to_byte();
+        assert_eq!(reader1.read_exact_n(3).unwrap(), vec![6, 7, 0]);
+        assert_eq!(reader1.current_byte, 4);
+        assert_eq!(reader1.read_exact_n(0).unwrap(), Vec::<u8>::new());
+        // much more than there is.
+        assert!(matches!(reader1.read_exact_n(1 << 40), Err(CorniferError::EOF)));
+    }
 }
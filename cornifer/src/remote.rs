@@ -0,0 +1,612 @@
+use std::future::Future;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::stream::{BoxStream, StreamExt};
+use futures::FutureExt;
+use object_store::client::{HttpError, HttpErrorKind};
+use object_store::{path::Path, GetOptions, GetRange, ObjectStore};
+use tokio::runtime::Runtime;
+use url::Url;
+
+use crate::errors::CorniferError;
+
+/*
+ * Reading a file that's in S3, Google Cloud Storage or Azure without downloading it first. Random access asks
+ * for the ranges it needs, a chunk at a time. Indexing reads the whole file in order, so that's one GET that
+ * streams it instead, see `set_streaming`.
+ *
+ * object_store is async, so each reader keeps a small tokio runtime to wait on it with. Clones share it.
+ *
+ * Networks being what they are, every request has a time limit, and one that fails in a way that might not happen
+ * again (a timeout, a dropped connection, a 5xx) is tried again after a pause, see `RetryPolicy`. A streaming GET
+ * that fails partway is started again from where it got to.
+ */
+
+// a ranged GET is slow enough to start that asking for a lot less than this isn't worth it.
+const DEFAULT_CHUNK_SIZE: usize = 1 << 20;
+
+/// Time limits and retries for `ObjectReader`'s requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// How long to wait to connect. Only used by `ObjectReader::open_with`, which passes it to the HTTP client.
+    pub connect_timeout: Duration,
+    /// How long a request, or each chunk of a streaming GET, can take before it's given up on and tried again.
+    pub read_timeout: Duration,
+    /// How many times to try a request again before failing with `RetriesExhausted`. 0 doesn't retry at all.
+    pub max_retries: u32,
+    /// How long to wait before the first retry. It doubles each time after that, up to `max_backoff`.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(5),
+            read_timeout: Duration::from_secs(30),
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    // how long to wait before retry number `attempt`, counting from 0.
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff.saturating_mul(1 << attempt.min(16)).min(self.max_backoff)
+    }
+}
+
+pub struct ObjectReader {
+    store: Arc<dyn ObjectStore>,
+    path: Path,
+    runtime: Arc<Runtime>,
+    len: u64,
+    pos: u64,
+    // the last bytes fetched, which start at buffer_from.
+    buffer: Bytes,
+    buffer_from: u64,
+    chunk_size: usize,
+    streaming: bool,
+    stream: Option<BoxStream<'static, object_store::Result<Bytes>>>,
+    retry: RetryPolicy,
+}
+
+impl ObjectReader {
+    /// Read from a URL like s3://bucket/path/file.gz, gs://bucket/... (or gcs://), az://container/... or
+    /// file:///path. Credentials and the region are taken from the usual AWS_, GOOGLE_ and AZURE_ environment
+    /// variables.
+    pub fn open(url: &str) -> Result<Self, CorniferError> {
+        Self::open_with(url, RetryPolicy::default())
+    }
+
+    /// Like `open`, with time limits and retries other than the default ones.
+    pub fn open_with(url: &str, retry: RetryPolicy) -> Result<Self, CorniferError> {
+        let parsed = match url.strip_prefix("gcs://") {
+            Some(rest) => Url::parse(&format!("gs://{rest}")),
+            None => Url::parse(url),
+        };
+        let parsed = parsed.map_err(|source| CorniferError::InvalidUrl {
+            url: url.to_string(),
+            source,
+        })?;
+        let options = std::env::vars()
+            .filter(|(k, _)| ["AWS_", "GOOGLE_", "AZURE_"].iter().any(|prefix| k.starts_with(prefix)))
+            .map(|(k, v)| (k.to_ascii_lowercase(), v));
+        // object_store's HTTP client times out too, which has to be longer than ours to leave the retries to us.
+        let timeouts = [
+            ("connect_timeout".to_string(), format!("{}ms", retry.connect_timeout.as_millis())),
+            ("timeout".to_string(), format!("{}ms", retry.read_timeout.as_millis() + 1000)),
+        ];
+        let (store, path) = object_store::parse_url_opts(&parsed, options.chain(timeouts))?;
+        Self::new_with(Arc::from(store), path, retry)
+    }
+
+    /// Read `path` from a store that's already set up.
+    pub fn new(store: Arc<dyn ObjectStore>, path: Path) -> Result<Self, CorniferError> {
+        Self::new_with(store, path, RetryPolicy::default())
+    }
+
+    /// Like `new`, with time limits and retries other than the default ones. The connect timeout is up to the
+    /// store.
+    pub fn new_with(store: Arc<dyn ObjectStore>, path: Path, retry: RetryPolicy) -> Result<Self, CorniferError> {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        let len = with_retries(&retry, &path, || wait(&runtime, retry.read_timeout, store.head(&path)))?.size;
+        Ok(Self {
+            store,
+            path,
+            runtime: Arc::new(runtime),
+            len,
+            pos: 0,
+            buffer: Bytes::new(),
+            buffer_from: 0,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            streaming: false,
+            stream: None,
+            retry,
+        })
+    }
+
+    /// How long the file was when it was opened.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The least to ask for in each ranged GET.
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        self.chunk_size = chunk_size.max(1);
+    }
+
+    /// Read with one GET that streams everything from the current position to the end, instead of a ranged GET
+    /// per chunk. Seeking somewhere the stream hasn't got to yet starts another one there.
+    pub fn set_streaming(&mut self, streaming: bool) {
+        self.streaming = streaming;
+        self.stream = None;
+    }
+
+    /// Change the time limits and retries for the requests from here on.
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.retry = retry;
+    }
+
+    fn buffer_to(&self) -> u64 {
+        self.buffer_from + self.buffer.len() as u64
+    }
+
+    // get the bytes at pos into the buffer, wanting at least `want` of them, retrying if that fails.
+    fn fill(&mut self, want: usize) -> io::Result<()> {
+        let (retry, path) = (self.retry, self.path.clone());
+        with_retries(&retry, &path, || {
+            let result = self.try_fill(want);
+            if result.is_err() {
+                // a stream that failed partway is started again from where it got to.
+                self.stream = None;
+            }
+            result
+        })
+        .map_err(|err| match err {
+            CorniferError::IOError(err) => err,
+            err => err.into(),
+        })
+    }
+
+    fn try_fill(&mut self, want: usize) -> io::Result<()> {
+        let timeout = self.retry.read_timeout;
+        if self.streaming {
+            // carry on from the end of the buffer if that's where we're reading, or start again.
+            if self.stream.is_none() || self.pos != self.buffer_to() {
+                let options = GetOptions {
+                    range: Some(GetRange::Offset(self.pos)),
+                    ..Default::default()
+                };
+                let result = wait(&self.runtime, timeout, self.store.get_opts(&self.path, options))?;
+                self.stream = Some(result.into_stream());
+                self.buffer_from = self.pos;
+                self.buffer = Bytes::new();
+            }
+            let stream = self.stream.as_mut().expect("Started above");
+            let chunk = wait(&self.runtime, timeout, stream.next().map(Option::transpose))?;
+            self.buffer_from = self.buffer_to();
+            self.buffer = match chunk {
+                Some(chunk) => chunk,
+                None => {
+                    self.stream = None;
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+            };
+            return Ok(());
+        }
+        let to = (self.pos + want.max(self.chunk_size) as u64).min(self.len);
+        self.buffer = wait(&self.runtime, timeout, self.store.get_range(&self.path, self.pos..to))?;
+        self.buffer_from = self.pos;
+        Ok(())
+    }
+}
+
+// wait for a request, for no longer than `timeout`.
+fn wait<T>(runtime: &Runtime, timeout: Duration, request: impl Future<Output = object_store::Result<T>>) -> io::Result<T> {
+    match runtime.block_on(async { tokio::time::timeout(timeout, request).await }) {
+        Ok(result) => result.map_err(to_io),
+        Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, format!("no response in {timeout:?}"))),
+    }
+}
+
+// whether trying again might work: it timed out, the connection failed, or the server did (see to_io).
+fn is_transient(err: &io::Error) -> bool {
+    use io::ErrorKind::*;
+    matches!(
+        err.kind(),
+        TimedOut
+            | Interrupted
+            | ConnectionRefused
+            | ConnectionReset
+            | ConnectionAborted
+            | NotConnected
+            | BrokenPipe
+            | ResourceBusy
+    )
+}
+
+// run `request` until it works, it fails in a way that won't get better, or the retries run out.
+fn with_retries<T>(
+    retry: &RetryPolicy,
+    path: &Path,
+    mut request: impl FnMut() -> io::Result<T>,
+) -> Result<T, CorniferError> {
+    let mut attempt = 0;
+    loop {
+        match request() {
+            Ok(result) => return Ok(result),
+            Err(err) if is_transient(&err) && attempt < retry.max_retries => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(%path, attempt, error = %err, "retrying a request");
+                std::thread::sleep(retry.backoff(attempt));
+                attempt += 1;
+            }
+            Err(source) if is_transient(&source) => {
+                return Err(CorniferError::RetriesExhausted {
+                    path: path.to_string(),
+                    attempts: attempt + 1,
+                    source,
+                })
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+// a new reader for the same file, at the start, sharing the store and the runtime.
+impl Clone for ObjectReader {
+    fn clone(&self) -> Self {
+        Self {
+            store: self.store.clone(),
+            path: self.path.clone(),
+            runtime: self.runtime.clone(),
+            len: self.len,
+            pos: 0,
+            buffer: Bytes::new(),
+            buffer_from: 0,
+            chunk_size: self.chunk_size,
+            streaming: self.streaming,
+            stream: None,
+            retry: self.retry,
+        }
+    }
+}
+
+fn to_io(err: object_store::Error) -> io::Error {
+    match err {
+        object_store::Error::NotFound { .. } => io::Error::new(io::ErrorKind::NotFound, err),
+        // these aren't going to go away by asking again.
+        object_store::Error::PermissionDenied { .. } | object_store::Error::Unauthenticated { .. } => {
+            io::Error::new(io::ErrorKind::PermissionDenied, err)
+        }
+        object_store::Error::InvalidPath { .. } => io::Error::new(io::ErrorKind::InvalidInput, err),
+        object_store::Error::NotSupported { .. } | object_store::Error::NotImplemented => {
+            io::Error::new(io::ErrorKind::Unsupported, err)
+        }
+        err => io::Error::new(transient_kind(&err).unwrap_or(io::ErrorKind::Other), err),
+    }
+}
+
+// the io::ErrorKind for a failure that might not happen again, from the errors underneath an object_store one:
+// its HTTP client's, the filesystem's, or a 5xx from the server, which is ResourceBusy.
+fn transient_kind(err: &(dyn std::error::Error + 'static)) -> Option<io::ErrorKind> {
+    let mut next = Some(err);
+    while let Some(err) = next {
+        if let Some(err) = err.downcast_ref::<HttpError>() {
+            return match err.kind() {
+                HttpErrorKind::Timeout => Some(io::ErrorKind::TimedOut),
+                HttpErrorKind::Connect => Some(io::ErrorKind::NotConnected),
+                HttpErrorKind::Request => Some(io::ErrorKind::ConnectionReset),
+                HttpErrorKind::Interrupted => Some(io::ErrorKind::Interrupted),
+                _ => None,
+            };
+        }
+        if let Some(err) = err.downcast_ref::<io::Error>() {
+            return Some(err.kind()).filter(|_| is_transient(err));
+        }
+        // object_store doesn't export the error with the status in it, but it starts its message.
+        if let Some(status) = err.to_string().strip_prefix("Server returned non-2xx status code: ") {
+            return Some(io::ErrorKind::ResourceBusy).filter(|_| status.starts_with('5'));
+        }
+        next = err.source();
+    }
+    None
+}
+
+impl Read for ObjectReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        if !(self.buffer_from..self.buffer_to()).contains(&self.pos) {
+            self.fill(buf.len())?;
+        }
+        let from = (self.pos - self.buffer_from) as usize;
+        let n = buf.len().min(self.buffer.len() - from);
+        buf[..n].copy_from_slice(&self.buffer[from..from + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for ObjectReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(n) => self.len.checked_add_signed(n),
+            SeekFrom::Current(n) => self.pos.checked_add_signed(n),
+        };
+        match new_pos {
+            Some(n) => {
+                self.pos = n;
+                Ok(n)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+/**
+ * TESTS
+ */
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Seek, SeekFrom};
+    use std::ops::Range;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    use futures::stream::BoxStream;
+    use object_store::client::{HttpError, HttpErrorKind};
+    use object_store::throttle::{ThrottleConfig, ThrottledStore};
+    use object_store::{
+        memory::InMemory, path::Path, GetOptions, GetResult, ListResult, MultipartUpload, ObjectMeta, ObjectStore,
+        PutMultipartOptions, PutOptions, PutPayload, PutResult,
+    };
+    use rstest::rstest;
+
+    use crate::errors::CorniferError;
+    use crate::{checkpoint::Checkpointer, checkpointed::CheckpointedReader, decompress::Deflator, reader::CorniferByteReader};
+    use crate::testutil::TempIndex;
+
+    use super::{is_transient, to_io, ObjectReader, RetryPolicy};
+
+    fn put(store: &dyn ObjectStore, data: &[u8]) -> Path {
+        let path = Path::from("logs/1080-0.txt.gz");
+        futures::executor::block_on(store.put(&path, PutPayload::from(data.to_vec()))).unwrap();
+        path
+    }
+
+    fn stored(data: &[u8]) -> ObjectReader {
+        let store = Arc::new(InMemory::new());
+        let path = put(store.as_ref(), data);
+        ObjectReader::new(store, path).unwrap()
+    }
+
+    // quick to give up, for the tests that fail on purpose.
+    fn impatient(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            read_timeout: Duration::from_millis(50),
+            max_retries,
+            initial_backoff: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        }
+    }
+
+    fn generic(source: Box<dyn std::error::Error + Send + Sync>) -> object_store::Error {
+        object_store::Error::Generic { store: "test", source }
+    }
+
+    // what object_store's HTTP client fails with.
+    fn http(kind: HttpErrorKind) -> object_store::Error {
+        generic(Box::new(HttpError::new(kind, std::io::Error::other("network trouble"))))
+    }
+
+    // a store whose GETs fail, like a flaky network would (or a proxy that won't let them through, if they aren't
+    // transient), until `failures` of them have.
+    #[derive(Debug)]
+    struct FlakyStore {
+        inner: InMemory,
+        failures: AtomicU32,
+        transient: bool,
+    }
+
+    impl FlakyStore {
+        fn fail(&self) -> object_store::Result<()> {
+            match self.failures.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1)) {
+                Ok(_) if self.transient => Err(http(HttpErrorKind::Request)),
+                Ok(_) => Err(generic("blocked by the proxy".into())),
+                Err(_) => Ok(()),
+            }
+        }
+    }
+
+    impl std::fmt::Display for FlakyStore {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "FlakyStore")
+        }
+    }
+
+    #[async_trait]
+    impl ObjectStore for FlakyStore {
+        async fn put_opts(&self, location: &Path, payload: PutPayload, opts: PutOptions) -> object_store::Result<PutResult> {
+            self.inner.put_opts(location, payload, opts).await
+        }
+
+        async fn put_multipart_opts(
+            &self,
+            location: &Path,
+            opts: PutMultipartOptions,
+        ) -> object_store::Result<Box<dyn MultipartUpload>> {
+            self.inner.put_multipart_opts(location, opts).await
+        }
+
+        async fn get_opts(&self, location: &Path, options: GetOptions) -> object_store::Result<GetResult> {
+            self.fail()?;
+            self.inner.get_opts(location, options).await
+        }
+
+        async fn get_range(&self, location: &Path, range: Range<u64>) -> object_store::Result<Bytes> {
+            self.fail()?;
+            self.inner.get_range(location, range).await
+        }
+
+        async fn delete(&self, location: &Path) -> object_store::Result<()> {
+            self.inner.delete(location).await
+        }
+
+        fn list(&self, prefix: Option<&Path>) -> BoxStream<'static, object_store::Result<ObjectMeta>> {
+            self.inner.list(prefix)
+        }
+
+        async fn list_with_delimiter(&self, prefix: Option<&Path>) -> object_store::Result<ListResult> {
+            self.inner.list_with_delimiter(prefix).await
+        }
+
+        async fn copy(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+            self.inner.copy(from, to).await
+        }
+
+        async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+            self.inner.copy_if_not_exists(from, to).await
+        }
+    }
+
+    #[rstest]
+    #[case::ranges(false)]
+    #[case::streaming(true)]
+    fn test_seek_and_read(#[case] streaming: bool) {
+        let data: Vec<u8> = (0..10000u32).map(|i| (i % 251) as u8).collect();
+        let mut reader = stored(&data);
+        reader.set_chunk_size(1000);
+        reader.set_streaming(streaming);
+        assert_eq!(reader.len(), 10000);
+        for (at, len) in [(0, 10), (5, 3000), (9990, 10), (2000, 1), (0, 10000)] {
+            reader.seek(SeekFrom::Start(at)).unwrap();
+            let mut dest = vec![0; len];
+            reader.read_exact(&mut dest).unwrap();
+            assert_eq!(dest, &data[at as usize..at as usize + len]);
+        }
+        assert_eq!(reader.read(&mut [0; 10]).unwrap(), 0);
+    }
+
+    #[rstest]
+    fn test_index_and_read_back() {
+        let input = include_bytes!("../testfiles/1080-0.txt.gz");
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let path = TempIndex::new();
+
+        let mut reader = stored(input);
+        reader.set_streaming(true);
+        let mut deflator = Deflator::new(CorniferByteReader::new(reader.clone()), Checkpointer::init(&path).unwrap());
+        std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        drop(deflator);
+
+        reader.set_streaming(false);
+        reader.set_chunk_size(4096);
+        let mut checkpointed = CheckpointedReader::open(reader, &path).unwrap();
+        checkpointed.seek(SeekFrom::Start(20000)).unwrap();
+        let mut dest = vec![0; 500];
+        checkpointed.read_exact(&mut dest).unwrap();
+        assert_eq!(dest, &text[20000..20500]);
+    }
+
+    // requests that fail a few times are tried again, and once the retries run out that's what the error says.
+    #[rstest]
+    #[case::recovers(2, 3, false, None)]
+    #[case::recovers_streaming(2, 3, true, None)]
+    #[case::gives_up(4, 3, false, Some(4))]
+    #[case::no_retries(1, 0, false, Some(1))]
+    fn test_retries(
+        #[case] failures: u32,
+        #[case] max_retries: u32,
+        #[case] streaming: bool,
+        #[case] gave_up_after: Option<u32>,
+    ) {
+        let data: Vec<u8> = (0..10000u32).map(|i| (i % 251) as u8).collect();
+        let store = Arc::new(FlakyStore {
+            inner: InMemory::new(),
+            failures: AtomicU32::new(0),
+            transient: true,
+        });
+        let path = put(store.as_ref(), &data);
+        let mut reader = ObjectReader::new_with(store.clone(), path, impatient(max_retries)).unwrap();
+        reader.set_streaming(streaming);
+        store.failures.store(failures, Ordering::SeqCst);
+        let mut dest = vec![0; 5000];
+        match (reader.read_exact(&mut dest), gave_up_after) {
+            (Ok(()), None) => assert_eq!(dest, &data[..5000]),
+            (Err(err), Some(attempts)) => {
+                let err = err.into_inner().unwrap().downcast::<CorniferError>().unwrap();
+                assert!(matches!(*err, CorniferError::RetriesExhausted { attempts: a, .. } if a == attempts), "{err}");
+            }
+            (result, _) => panic!("{result:?}"),
+        }
+    }
+
+    // only a timeout, a connection that failed or a 5xx is worth asking again for.
+    #[rstest]
+    #[case::timeout(http(HttpErrorKind::Timeout), true)]
+    #[case::connect(http(HttpErrorKind::Connect), true)]
+    #[case::decode(http(HttpErrorKind::Decode), false)]
+    #[case::server_error(generic("Server returned non-2xx status code: 503 Service Unavailable: ".into()), true)]
+    #[case::client_error(generic("Server returned non-2xx status code: 400 Bad Request: ".into()), false)]
+    #[case::io(generic(Box::new(std::io::Error::from(std::io::ErrorKind::ConnectionReset))), true)]
+    #[case::other(generic("blocked by the proxy".into()), false)]
+    #[case::not_found(object_store::Error::NotFound { path: "x".to_string(), source: "gone".into() }, false)]
+    fn test_transient(#[case] err: object_store::Error, #[case] transient: bool) {
+        assert_eq!(is_transient(&to_io(err)), transient);
+    }
+
+    #[rstest]
+    fn test_no_retry_when_it_wont_help() {
+        let store = Arc::new(FlakyStore {
+            inner: InMemory::new(),
+            failures: AtomicU32::new(0),
+            transient: false,
+        });
+        let path = put(store.as_ref(), b"hello world");
+        let mut reader = ObjectReader::new_with(store.clone(), path, impatient(3)).unwrap();
+        store.failures.store(1, Ordering::SeqCst);
+        let err = reader.read(&mut [0; 5]).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Other);
+        // the next one works, so it gave up after the first.
+        assert_eq!(store.failures.load(Ordering::SeqCst), 0);
+        assert_eq!(reader.read(&mut [0; 5]).unwrap(), 5);
+    }
+
+    // a request that doesn't answer in time is given up on, rather than waited for forever.
+    #[rstest]
+    fn test_read_timeout() {
+        let inner = InMemory::new();
+        let path = put(&inner, b"hello world");
+        let config = ThrottleConfig {
+            wait_get_per_call: Duration::from_millis(500),
+            ..ThrottleConfig::default()
+        };
+        let store = Arc::new(ThrottledStore::new(inner, config));
+        // HEAD is slow too, so the reader's made with plenty of time for that.
+        let mut reader = ObjectReader::new(store, path).unwrap();
+        reader.set_retry_policy(impatient(1));
+        let err = reader.read(&mut [0; 5]).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        let err = err.into_inner().unwrap().downcast::<CorniferError>().unwrap();
+        assert!(matches!(*err, CorniferError::RetriesExhausted { attempts: 2, .. }), "{err}");
+    }
+}
@@ -0,0 +1,110 @@
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::store::{Block, Gap};
+
+/*
+ * What's damaged in a file that doesn't match its checkpoint file, and what can still be read back out of it,
+ * for tools that try to repair or salvage it. Made by `CheckpointedReader::corruption_report`.
+ */
+
+/// A range of bytes, including from but not to.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ByteRange {
+    pub from: u64,
+    pub to: u64,
+}
+
+/// A part of the file that couldn't be decoded.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DamagedRange {
+    /// Where it is in the compressed file. Blocks don't have to start or end on a byte, so the bytes at either
+    /// end can be shared with the blocks next to it.
+    pub compressed: ByteRange,
+    /// The part of the decompressed file that's lost with it.
+    pub uncompressed: ByteRange,
+    pub error: String,
+}
+
+/// A checkpoint that was restored and decoded without any problems.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct GoodCheckpoint {
+    pub block_id: i64,
+    pub from_byte: u64,
+    pub from_bit: u8,
+    pub to_byte: u64,
+}
+
+impl From<&Block> for GoodCheckpoint {
+    fn from(block: &Block) -> Self {
+        Self {
+            block_id: block.id,
+            from_byte: block.from_byte,
+            from_bit: block.from_bit,
+            to_byte: block.to_byte,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct CorruptionReport {
+    /// Everything that couldn't be decoded, including members skipped while indexing, in compressed order.
+    pub damaged: Vec<DamagedRange>,
+    /// The last checkpoint before the first damaged range, i.e. where decoding the file from the start would
+    /// last have been fine. None if the first block is damaged.
+    pub last_good_checkpoint: Option<GoodCheckpoint>,
+    /// The parts of the decompressed file that can still be read, in order.
+    pub recoverable: Vec<ByteRange>,
+}
+
+impl CorruptionReport {
+    /// Whether nothing was found to be wrong.
+    pub fn is_clean(&self) -> bool {
+        self.damaged.is_empty()
+    }
+
+    // a block that decoded fine. Blocks come in order, so it either carries on the last range or starts another.
+    pub(crate) fn on_good_block(&mut self, block: &Block) {
+        let to = block.to_byte + block.len;
+        match self.recoverable.last_mut() {
+            Some(range) if range.to == block.to_byte => range.to = to,
+            _ => self.recoverable.push(ByteRange { from: block.to_byte, to }),
+        }
+        if self.damaged.is_empty() {
+            self.last_good_checkpoint = Some(block.into());
+        }
+    }
+
+    pub(crate) fn on_damaged_block(&mut self, block: &Block, error: String) {
+        let end_bit = block.from_byte * 8 + block.from_bit as u64 + block.block_len_bits;
+        self.damaged.push(DamagedRange {
+            compressed: ByteRange {
+                from: block.from_byte,
+                to: end_bit.div_ceil(8),
+            },
+            uncompressed: ByteRange {
+                from: block.to_byte,
+                to: block.to_byte + block.len,
+            },
+            error,
+        });
+    }
+
+    pub(crate) fn on_gap(&mut self, gap: &Gap) {
+        self.damaged.push(DamagedRange {
+            compressed: ByteRange {
+                from: gap.from_byte,
+                to: gap.to_byte,
+            },
+            uncompressed: ByteRange {
+                from: gap.uncompressed_from,
+                to: gap.uncompressed_to,
+            },
+            error: gap.error.clone(),
+        });
+    }
+}
@@ -0,0 +1,270 @@
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+/*
+ * The tables in a checkpoint file and what their columns mean. The Checkpointer makes the tables from these, so
+ * they're also what `cornifer schema` prints for anything else that wants to read the file.
+ *
+ * Adding a column, a table or a block type bumps SCHEMA_VERSION. Readers should ignore columns and tables they
+ * don't know, but can't read blocks of a type they don't know.
+ */
+
+/// Stored in the checkpoint file's `PRAGMA user_version`. Files from before it was recorded have 0.
+pub const SCHEMA_VERSION: u32 = 8;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Column {
+    pub name: &'static str,
+    /// INTEGER, TEXT or BLOB.
+    pub sql_type: &'static str,
+    pub not_null: bool,
+    /// An INTEGER primary key is an AUTOINCREMENT one.
+    pub primary_key: bool,
+    pub description: &'static str,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Table {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub columns: &'static [Column],
+    /// e.g. foreign keys, as SQL.
+    pub constraints: &'static [&'static str],
+}
+
+/// A row of the Meta table.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct MetaKey {
+    pub key: &'static str,
+    pub description: &'static str,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Schema {
+    pub version: u32,
+    pub tables: &'static [Table],
+    pub meta_keys: &'static [MetaKey],
+}
+
+pub const SCHEMA: Schema = Schema {
+    version: SCHEMA_VERSION,
+    tables: TABLES,
+    meta_keys: META_KEYS,
+};
+
+const fn column(name: &'static str, sql_type: &'static str, not_null: bool, description: &'static str) -> Column {
+    Column {
+        name,
+        sql_type,
+        not_null,
+        primary_key: false,
+        description,
+    }
+}
+
+const ID: Column = Column {
+    name: "id",
+    sql_type: "INTEGER",
+    not_null: false,
+    primary_key: true,
+    description: "Not guaranteed to be sequential.",
+};
+
+// the rows of a file in a checkpoint file that has several.
+const SOURCE_ID: Column = column(
+    "source_id",
+    "INTEGER",
+    false,
+    "The Source the row is for. NULL in a checkpoint file of just one file.",
+);
+
+const TABLES: &[Table] = &[
+    Table {
+        name: "DeflateBlock",
+        description: "A checkpoint at the start of a block, which decoding can start from. Despite the name, also bzip2 and xz blocks.",
+        columns: &[
+            ID,
+            column("from_byte", "INTEGER", true, "The byte of the compressed stream the block starts at."),
+            column("from_bit", "INTEGER", true, "The bit of that byte it starts at. DEFLATE counts bits from the LSB of the byte, bzip2 counts them from the MSB."),
+            column("to_byte", "INTEGER", true, "The byte of the uncompressed stream the block starts at."),
+            column("block_type", "TEXT", true, "\"nocompression\", \"fixed\", \"dynamic\", \"bzip2\" or \"xz\". Or \"flushed\", for the DEFLATE blocks between two full flushes, written by `cornifer recompress`."),
+            column("crc32", "TEXT", false, "CRC-32 of the block's decompressed data, in hex."),
+            column("len", "INTEGER", false, "Length of the block in the uncompressed stream, in bytes."),
+            column("header_len_bits", "INTEGER", false, "Length of the block's header, in bits."),
+            column("block_len_bits", "INTEGER", false, "Length of the entire block including the header, in bits, in the compressed stream."),
+            column("data", "BLOB", true, "What's needed to decode the block, besides the block itself. For DEFLATE, the 32KiB before the block (or less, near the start of a member), compressed, so it's never empty; an empty one means the window was left out to save space, unless it's a member_start block. For xz, the stream header. Empty for bzip2."),
+            column("trees", "BLOB", false, "For dynamic blocks, the Huffman trees from the block header, so decoding can start from a tick in the middle of the block. NULL for everything else."),
+            column("literals", "INTEGER", false, "For DEFLATE blocks indexed with --stats, how many literals the block has. 0 for stored blocks. NULL without --stats."),
+            column("matches", "INTEGER", false, "Same, for how many matches (back-references) it has."),
+            column("longest_match", "INTEGER", false, "Same, for the length of its longest match. 0 if there weren't any."),
+            SOURCE_ID,
+            column("member_start", "INTEGER", false, "1 for the first block of a gzip member (or zlib or raw DEFLATE stream), which doesn't need a window, so its data is empty. Decoding can always start from one. 0 or NULL otherwise."),
+            column("compressed_crc32", "TEXT", false, "For DEFLATE blocks indexed with --hash-blocks, CRC-32 of the compressed bytes the block is in, from the one it starts in to the one it ends in, in hex. For checking the compressed file without decompressing it. NULL otherwise."),
+        ],
+        constraints: &[],
+    },
+    Table {
+        name: "ArchiveEntry",
+        description: "A file in a tar or zip archive.",
+        columns: &[
+            ID,
+            column("name", "TEXT", true, "Path of the file in the archive."),
+            column("size", "INTEGER", true, "Uncompressed size of the file."),
+            column("offset", "INTEGER", true, "For tar, where the file starts in the uncompressed stream. For zip, where its local header starts in the zip file."),
+            column("mtime", "INTEGER", false, "Seconds since the epoch, if there is one."),
+        ],
+        constraints: &[],
+    },
+    Table {
+        name: "GzipMember",
+        description: "A gzip member, including its footer.",
+        columns: &[
+            ID,
+            column("from_byte", "INTEGER", true, "Where the member starts in the compressed stream."),
+            column("to_byte", "INTEGER", true, "Where the member starts in the uncompressed stream."),
+            column("len", "INTEGER", true, "Uncompressed length of the member."),
+            column("crc32", "TEXT", true, "CRC-32 of the member's decompressed data, in hex."),
+            column("name", "TEXT", false, "The file name from the member's header."),
+            column("mtime", "INTEGER", true, "The mtime from the member's header, 0 if there isn't one."),
+            SOURCE_ID,
+            column("header_len", "INTEGER", false, "How many bytes the member's header takes up, so its DEFLATE data starts at from_byte + header_len. NULL in files from before it was recorded."),
+        ],
+        constraints: &[],
+    },
+    Table {
+        name: "Tick",
+        description: "A checkpoint in the middle of a dynamic DEFLATE block, for blocks too big to decode from the start every time.",
+        columns: &[
+            ID,
+            column("from_byte", "INTEGER", true, "Same as DeflateBlock."),
+            column("from_bit", "INTEGER", true, "Same as DeflateBlock."),
+            column("to_byte", "INTEGER", true, "Same as DeflateBlock."),
+            column("block_id", "INTEGER", true, "The DeflateBlock it's in, which has the Huffman trees to decode with."),
+            column("data", "BLOB", true, "The data before the tick, compressed like DeflateBlock's. Empty if it's in the sidecar file instead."),
+            SOURCE_ID,
+            column("sidecar_offset", "INTEGER", false, "For files indexed with --tick-sidecar, where the data before the tick starts in <checkpoint file>.ticks, uncompressed. NULL otherwise."),
+            column("sidecar_len", "INTEGER", false, "Same, for how long it is."),
+        ],
+        constraints: &["FOREIGN KEY (block_id) REFERENCES DeflateBlock (id)"],
+    },
+    Table {
+        name: "Gap",
+        description: "A gzip member that couldn't be decoded and was skipped, when indexing with recovery on.",
+        columns: &[
+            ID,
+            column("from_byte", "INTEGER", true, "Where the member starts in the compressed stream."),
+            column("to_byte", "INTEGER", true, "Where the next member starts, or the end of the file. Nothing in between was decoded."),
+            column("uncompressed_from", "INTEGER", true, "Where the part of the uncompressed stream that no checkpoint covers because of it starts."),
+            column("uncompressed_to", "INTEGER", true, "And where it ends. Often the same as uncompressed_from."),
+            column("error", "TEXT", true, "What was wrong with the member."),
+            SOURCE_ID,
+        ],
+        constraints: &[],
+    },
+    Table {
+        name: "Source",
+        description: "A file checkpointed into a checkpoint file that has several, e.g. with `cornifer index --database`.",
+        columns: &[
+            ID,
+            column("name", "TEXT", true, "What the file was called when it was indexed, which is how it's looked up."),
+            column("complete", "INTEGER", true, "1 once the whole file was checkpointed, 0 until then. Like Meta's, but for the one file."),
+            column("uncompressed_len", "INTEGER", false, "Length of the entire decompressed file, once it's complete."),
+            column("crc32", "TEXT", false, "CRC-32 of the entire decompressed file, in hex, once it's complete."),
+        ],
+        constraints: &["UNIQUE (name)"],
+    },
+    Table {
+        name: "Meta",
+        description: "Facts about the whole file, see meta_keys.",
+        columns: &[
+            Column {
+                name: "key",
+                sql_type: "TEXT",
+                not_null: false,
+                primary_key: true,
+                description: "One of meta_keys.",
+            },
+            column("value", "TEXT", true, "Its value, as text."),
+        ],
+        constraints: &[],
+    },
+];
+
+const META_KEYS: &[MetaKey] = &[
+    MetaKey {
+        key: "complete",
+        description: "\"1\" once the whole file was checkpointed, \"0\" until then.",
+    },
+    MetaKey {
+        key: "uncompressed_len",
+        description: "Length of the entire decompressed file.",
+    },
+    MetaKey {
+        key: "crc32",
+        description: "CRC-32 of the entire decompressed file, in hex.",
+    },
+];
+
+impl Table {
+    /// The CREATE TABLE IF NOT EXISTS statement for it.
+    pub fn create_sql(&self) -> String {
+        let columns = self.columns.iter().map(|c| {
+            let mut sql = format!("{} {}", c.name, c.sql_type);
+            match (c.primary_key, c.sql_type) {
+                (true, "INTEGER") => sql.push_str(" PRIMARY KEY AUTOINCREMENT"),
+                (true, _) => sql.push_str(" PRIMARY KEY"),
+                _ => (),
+            }
+            if c.not_null {
+                sql.push_str(" NOT NULL");
+            }
+            sql
+        });
+        let lines: Vec<_> = columns.chain(self.constraints.iter().map(|c| c.to_string())).collect();
+        format!("CREATE TABLE IF NOT EXISTS {} (\n    {}\n)", self.name, lines.join(",\n    "))
+    }
+}
+
+/**
+ * TESTS
+ */
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::{SCHEMA, TABLES};
+
+    #[rstest]
+    fn test_create_sql() {
+        let tick = TABLES.iter().find(|t| t.name == "Tick").unwrap();
+        assert_eq!(
+            tick.create_sql(),
+            "CREATE TABLE IF NOT EXISTS Tick (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    from_byte INTEGER NOT NULL,
+    from_bit INTEGER NOT NULL,
+    to_byte INTEGER NOT NULL,
+    block_id INTEGER NOT NULL,
+    data BLOB NOT NULL,
+    source_id INTEGER,
+    sidecar_offset INTEGER,
+    sidecar_len INTEGER,
+    FOREIGN KEY (block_id) REFERENCES DeflateBlock (id)
+)"
+        );
+    }
+
+    #[rstest]
+    fn test_every_column_is_described() {
+        for table in SCHEMA.tables {
+            assert!(!table.description.is_empty(), "{}", table.name);
+            for column in table.columns {
+                assert!(!column.description.is_empty(), "{}.{}", table.name, column.name);
+            }
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use crc::{Crc, Digest, CRC_32_ISO_HDLC};
+#[cfg(feature = "sha256")]
+use sha2::{Digest as _, Sha256};
+
+/*
+ * Places for decompressed data to go, so one pass over it can write it out and hash it at the same time.
+ * A Sink is a writer or a hash, and a Tee writes everything to each of several of them.
+ */
+
+static CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum HashAlgorithm {
+    #[cfg(feature = "sha256")]
+    Sha256,
+    Crc32,
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            #[cfg(feature = "sha256")]
+            "sha256" => Ok(Self::Sha256),
+            "crc32" => Ok(Self::Crc32),
+            #[cfg(feature = "sha256")]
+            _ => Err(format!("unknown hash {s}, it can be sha256 or crc32")),
+            #[cfg(not(feature = "sha256"))]
+            _ => Err(format!("unknown hash {s}, it can be crc32 (sha256 needs cornifer's sha256 feature)")),
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            #[cfg(feature = "sha256")]
+            Self::Sha256 => "sha256",
+            Self::Crc32 => "crc32",
+        })
+    }
+}
+
+/// What a HashSink worked out, in lowercase hex.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Hashed {
+    pub algorithm: HashAlgorithm,
+    pub hex: String,
+}
+
+enum HashState {
+    #[cfg(feature = "sha256")]
+    Sha256(Sha256),
+    Crc32(Digest<'static, u32>),
+}
+
+/// Hashes everything written to it.
+pub struct HashSink {
+    state: HashState,
+}
+
+impl HashSink {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        let state = match algorithm {
+            #[cfg(feature = "sha256")]
+            HashAlgorithm::Sha256 => HashState::Sha256(Sha256::new()),
+            HashAlgorithm::Crc32 => HashState::Crc32(CRC32.digest()),
+        };
+        Self { state }
+    }
+
+    pub fn finish(self) -> Hashed {
+        match self.state {
+            #[cfg(feature = "sha256")]
+            HashState::Sha256(hasher) => Hashed {
+                algorithm: HashAlgorithm::Sha256,
+                hex: format!("{:x}", hasher.finalize()),
+            },
+            HashState::Crc32(digest) => Hashed {
+                algorithm: HashAlgorithm::Crc32,
+                hex: format!("{:08x}", digest.finalize()),
+            },
+        }
+    }
+}
+
+impl Write for HashSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.state {
+            #[cfg(feature = "sha256")]
+            HashState::Sha256(hasher) => hasher.update(buf),
+            HashState::Crc32(digest) => digest.update(buf),
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub enum Sink {
+    /// A file, stdout, or anything else that takes the data.
+    Writer(Box<dyn Write>),
+    Hash(HashSink),
+}
+
+impl Sink {
+    /// Flush a writer, or give a hash's result.
+    pub fn finish(self) -> io::Result<Option<Hashed>> {
+        match self {
+            Self::Writer(mut writer) => writer.flush().map(|_| None),
+            Self::Hash(hash) => Ok(Some(hash.finish())),
+        }
+    }
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Writer(writer) => writer.write(buf),
+            Self::Hash(hash) => hash.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Writer(writer) => writer.flush(),
+            Self::Hash(hash) => hash.flush(),
+        }
+    }
+}
+
+/// Writes everything to each of its sinks, in order. A sink failing fails the write, even if the ones before it
+/// have already had the data.
+#[derive(Default)]
+pub struct Tee {
+    sinks: Vec<Sink>,
+}
+
+impl Tee {
+    pub fn new(sinks: Vec<Sink>) -> Self {
+        Self { sinks }
+    }
+
+    pub fn push(&mut self, sink: Sink) {
+        self.sinks.push(sink);
+    }
+
+    /// Flush the writers, and give each hash's result in the order they were added.
+    pub fn finish(self) -> io::Result<Vec<Hashed>> {
+        let mut hashes = Vec::new();
+        for sink in self.sinks {
+            hashes.extend(sink.finish()?);
+        }
+        Ok(hashes)
+    }
+}
+
+impl Write for Tee {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // all of it, so every sink gets the same bytes.
+        for sink in &mut self.sinks {
+            sink.write_all(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.sinks.iter_mut().try_for_each(|sink| sink.flush())
+    }
+}
+
+/**
+ * TESTS
+ */
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    use rstest::rstest;
+
+    use super::{HashAlgorithm, HashSink, Hashed, Sink, Tee};
+
+    // a writer the test can still look at after giving it away.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[rstest]
+    #[cfg_attr(
+        feature = "sha256",
+        case::sha256(HashAlgorithm::Sha256, "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9")
+    )]
+    #[case::crc32(HashAlgorithm::Crc32, "0d4a1185")]
+    fn test_hash(#[case] algorithm: HashAlgorithm, #[case] expected: &str) {
+        let mut hash = HashSink::new(algorithm);
+        hash.write_all(b"hello ").unwrap();
+        hash.write_all(b"world").unwrap();
+        assert_eq!(hash.finish(), Hashed { algorithm, hex: expected.to_string() });
+    }
+
+    #[rstest]
+    fn test_tee() {
+        let (a, b) = (Shared::default(), Shared::default());
+        let mut tee = Tee::new(vec![Sink::Writer(Box::new(a.clone())), Sink::Hash(HashSink::new(HashAlgorithm::Crc32))]);
+        tee.push(Sink::Writer(Box::new(b.clone())));
+        tee.push(Sink::Hash(HashSink::new(HashAlgorithm::Crc32)));
+        std::io::copy(&mut b"hello world".as_slice(), &mut tee).unwrap();
+        let hashes = tee.finish().unwrap();
+        assert_eq!(*a.0.lock().unwrap(), b"hello world");
+        assert_eq!(*b.0.lock().unwrap(), b"hello world");
+        assert_eq!(hashes.len(), 2);
+        assert!(hashes.iter().all(|h| h.hex == "0d4a1185"), "{hashes:?}");
+    }
+
+    #[rstest]
+    #[cfg_attr(feature = "sha256", case("sha256", Some(HashAlgorithm::Sha256)))]
+    #[cfg_attr(not(feature = "sha256"), case("sha256", None))]
+    #[case("crc32", Some(HashAlgorithm::Crc32))]
+    #[case("md5", None)]
+    fn test_parse_algorithm(#[case] s: &str, #[case] expected: Option<HashAlgorithm>) {
+        assert_eq!(s.parse().ok(), expected);
+        if let Some(algorithm) = expected {
+            assert_eq!(algorithm.to_string(), s);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crc::{Crc, CRC_32_ISO_HDLC};
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+
+use crate::bits::BitWriter;
+use crate::checkpoint::Checkpointer;
+use crate::circle::crc32_combine;
+use crate::decompress::Deflator;
+use crate::errors::CorniferError;
+use crate::header::GZIP_HEADER;
+use crate::reader::CorniferByteReader;
+use crate::store::Block;
+
+/*
+ * Making a gzip member out of some of the DEFLATE blocks in a file, by copying their compressed bits as they are
+ * and putting a new header and footer around them. Nothing is recompressed, so it's as quick as copying.
+ *
+ * Blocks can refer back to the 32KB before them, so copied on their own they might not decode. Putting the window
+ * in front of them, as a stored block, fixes that at the cost of the window being in the output too.
+ */
+
+static CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// A gzip member made by `CheckpointedReader::extract_member`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ExtractedMember {
+    /// Where the first block starts in the decompressed file.
+    pub from: u64,
+    /// How much of the start of the member is the window before the first block, rather than the blocks.
+    pub prefix_len: u64,
+    /// Length of the member decompressed, including the prefix.
+    pub len: u64,
+    pub crc32: u32,
+}
+
+/// Copy `blocks`, which have to be one after the other in the same stream, into a gzip member, with `window`
+/// in front of them as a stored block unless it's empty. The footer comes from the blocks' CRCs.
+pub(crate) fn write_member<R: Read + Seek>(
+    source: &mut R,
+    blocks: &[Block],
+    window: &[u8],
+) -> Result<(Vec<u8>, ExtractedMember), CorniferError> {
+    let cant = |reason: &str| CorniferError::CantExtract { reason: reason.to_string() };
+    let Some(first) = blocks.first() else {
+        return Err(cant("there are no blocks there"));
+    };
+    if !blocks.iter().all(can_copy) {
+        return Err(cant("only DEFLATE blocks can be copied into a gzip member"));
+    }
+    if !blocks.windows(2).all(|w| follows(&w[0], &w[1])) {
+        return Err(cant("the blocks aren't all in the same member"));
+    }
+
+    let mut writer = BitWriter::default();
+    writer.put_bytes(&GZIP_HEADER);
+    if !window.is_empty() {
+        // not final, stored, then the lengths from the next byte.
+        writer.put(0, 3);
+        writer.align();
+        let len = window.len() as u16;
+        writer.put_bytes(&len.to_le_bytes());
+        writer.put_bytes(&(!len).to_le_bytes());
+        writer.put_bytes(window);
+    }
+    copy_blocks(source, &mut writer, blocks)?;
+    let (crc32, len) = write_footer(&mut writer, window, blocks);
+    let member = writer.finish();
+
+    // if the blocks need something before them that isn't there, the footer won't match what they decode to.
+    let mut deflator = Deflator::new(CorniferByteReader::new(member.as_slice()), Checkpointer::none());
+    if std::io::copy(&mut deflator, &mut std::io::sink()).is_err() {
+        return Err(cant("the blocks refer back to data before the first one, so the window has to go in front of them"));
+    }
+    let extracted = ExtractedMember {
+        from: first.to_byte,
+        prefix_len: window.len() as u64,
+        len,
+        crc32,
+    };
+    Ok((member, extracted))
+}
+
+// copy `blocks`, one after the other in the same stream, onto the end of `writer`. The last one is marked as the
+// last in the member.
+fn copy_blocks<R: Read + Seek>(source: &mut R, writer: &mut BitWriter, blocks: &[Block]) -> Result<(), CorniferError> {
+    let (Some(first), Some(last)) = (blocks.first(), blocks.last()) else {
+        return Ok(());
+    };
+    // just the bytes the blocks are in.
+    let end_bit = start_bit(last) + last.block_len_bits;
+    let mut compressed = vec![0; (end_bit.div_ceil(8) - first.from_byte) as usize];
+    source.seek(SeekFrom::Start(first.from_byte))?;
+    source.read_exact(&mut compressed)?;
+
+    for (i, block) in blocks.iter().enumerate() {
+        // each block's first bit says whether it's the last one.
+        let is_last = i == blocks.len() - 1;
+        let from = start_bit(block) - first.from_byte * 8;
+        writer.put(is_last as u64, 1);
+        if block.block_type == "nocompression" {
+            // the lengths and data start at the next byte, which is a different number of bits on here unless
+            // the blocks started on the same bit of a byte they do here. So they're copied from the next byte.
+            writer.put(0, 2);
+            writer.align();
+            let data_from = (from + 3).div_ceil(8) as usize;
+            let data_to = ((from + block.block_len_bits) / 8) as usize;
+            writer.put_bytes(&compressed[data_from..data_to]);
+        } else {
+            writer.copy(&compressed, from + 1, block.block_len_bits - 1);
+        }
+    }
+    Ok(())
+}
+
+// the gzip footer for `before` followed by `blocks`, and the CRC and length in it.
+fn write_footer(writer: &mut BitWriter, before: &[u8], blocks: &[Block]) -> (u32, u64) {
+    writer.align();
+    let mut crc32 = CRC32.checksum(before);
+    let mut len = before.len() as u64;
+    for block in blocks {
+        crc32 = crc32_combine(crc32, block.crc32, block.len);
+        len += block.len;
+    }
+    writer.put_bytes(&crc32.to_le_bytes());
+    writer.put_bytes(&(len as u32).to_le_bytes());
+    (crc32, len)
+}
+
+fn start_bit(block: &Block) -> u64 {
+    block.from_byte * 8 + block.from_bit as u64
+}
+
+/// Whether `next` starts right where `prev` ends, in the same stream.
+pub(crate) fn follows(prev: &Block, next: &Block) -> bool {
+    start_bit(prev) + prev.block_len_bits == start_bit(next)
+}
+
+/// Whether `write_standalone` can copy these blocks, rather than them having to be recompressed.
+pub(crate) fn can_copy(block: &Block) -> bool {
+    matches!(block.block_type.as_str(), "nocompression" | "fixed" | "dynamic")
+}
+
+/// A gzip member that decodes on its own: `head` compressed, then `blocks` copied as they are. `head` has to be
+/// what's decoded from the start of the member up to the first of `blocks`, at least 32KB of it unless that's
+/// the start of the stream, so nothing they refer back to is missing. `blocks` have to be one after the other in
+/// the same stream, and can be empty.
+pub(crate) fn write_standalone<R: Read + Seek>(
+    source: &mut R,
+    head: &[u8],
+    blocks: &[Block],
+    level: Compression,
+) -> Result<Vec<u8>, CorniferError> {
+    let mut writer = BitWriter::default();
+    writer.put_bytes(&GZIP_HEADER);
+    let mut encoder = DeflateEncoder::new(Vec::new(), level);
+    encoder.write_all(head)?;
+    let compressed = match blocks.is_empty() {
+        true => encoder.finish()?,
+        // a sync flush ends on a byte, without marking anything as the last block, so the copied ones go on after.
+        false => {
+            encoder.flush()?;
+            std::mem::take(encoder.get_mut())
+        }
+    };
+    writer.put_bytes(&compressed);
+    copy_blocks(source, &mut writer, blocks)?;
+    write_footer(&mut writer, head, blocks);
+    Ok(writer.finish())
+}
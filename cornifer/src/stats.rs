@@ -0,0 +1,145 @@
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::decompress::BlockType;
+
+/// Longest match DEFLATE allows.
+pub const MAX_MATCH_LEN: usize = 258;
+
+/// One DEFLATE block, as decoded. See `Stats::blocks`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct BlockStats {
+    /// Where the block header starts in the compressed file.
+    pub from_byte: usize,
+    pub from_bit: u8,
+    /// Where the block starts in the decompressed output.
+    pub to_byte: usize,
+    pub block_type: BlockType,
+    /// Size of the block in the compressed file, header included.
+    pub compressed_bits: u64,
+    pub uncompressed_len: u64,
+    /// Literals and matches in the block. Both 0 for stored blocks.
+    pub literals: u64,
+    pub matches: u64,
+    /// Length of the longest match, 0 if there weren't any.
+    pub longest_match: u16,
+}
+
+impl BlockStats {
+    /// Uncompressed bytes per compressed byte. 0 for empty blocks.
+    pub fn ratio(&self) -> f64 {
+        ratio(self.compressed_bits, self.uncompressed_len)
+    }
+}
+
+/**
+ * What was in the DEFLATE blocks a Deflator decoded, e.g. to see why a file doesn't compress well or makes
+ * a big index. Only collected after `Deflator::collect_stats`.
+ */
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Stats {
+    pub stored_blocks: u64,
+    pub fixed_blocks: u64,
+    pub dynamic_blocks: u64,
+    /// Literals in Huffman coded blocks. Bytes in stored blocks aren't counted.
+    pub literals: u64,
+    pub matches: u64,
+    /// `match_lengths[n]` is the number of matches that were n bytes long.
+    pub match_lengths: Vec<u64>,
+    /// Total size of the blocks, so headers and footers around them aren't counted.
+    pub compressed_bits: u64,
+    pub uncompressed_len: u64,
+    pub blocks: Vec<BlockStats>,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            stored_blocks: 0,
+            fixed_blocks: 0,
+            dynamic_blocks: 0,
+            literals: 0,
+            matches: 0,
+            match_lengths: vec![0; MAX_MATCH_LEN + 1],
+            compressed_bits: 0,
+            uncompressed_len: 0,
+            blocks: Vec::new(),
+        }
+    }
+}
+
+impl Stats {
+    /// Uncompressed bytes per compressed byte, over all the blocks.
+    pub fn ratio(&self) -> f64 {
+        ratio(self.compressed_bits, self.uncompressed_len)
+    }
+
+    // positions are where the reader is, like StreamPosition.
+    pub(crate) fn on_block_start(&mut self, reader_byte: usize, reader_bit: u8, to_byte: usize, block_type: BlockType) {
+        match block_type {
+            BlockType::NoCompression => self.stored_blocks += 1,
+            BlockType::FixedHuffman => self.fixed_blocks += 1,
+            BlockType::DynamicHuffman => self.dynamic_blocks += 1,
+        }
+        let from = bit_offset(reader_byte, reader_bit);
+        self.blocks.push(BlockStats {
+            from_byte: (from / 8) as usize,
+            from_bit: (from % 8) as u8,
+            to_byte,
+            block_type,
+            compressed_bits: 0,
+            uncompressed_len: 0,
+            literals: 0,
+            matches: 0,
+            longest_match: 0,
+        });
+    }
+
+    // the block being decoded. None after resuming a Deflator in the middle of one, since it never started.
+    fn current_block(&mut self) -> Option<&mut BlockStats> {
+        self.blocks.last_mut().filter(|b| b.compressed_bits == 0)
+    }
+
+    pub(crate) fn on_literal(&mut self) {
+        self.literals += 1;
+        if let Some(block) = self.current_block() {
+            block.literals += 1;
+        }
+    }
+
+    pub(crate) fn on_match(&mut self, len: u16) {
+        self.matches += 1;
+        self.match_lengths[len as usize] += 1;
+        if let Some(block) = self.current_block() {
+            block.matches += 1;
+            block.longest_match = block.longest_match.max(len);
+        }
+    }
+
+    // gives the block that ended, if it started after the stats did.
+    pub(crate) fn on_block_end(&mut self, reader_byte: usize, reader_bit: u8, end_to_byte: usize) -> Option<&BlockStats> {
+        let block = self.blocks.last_mut().filter(|b| b.compressed_bits == 0)?;
+        block.compressed_bits = bit_offset(reader_byte, reader_bit) - (block.from_byte * 8 + block.from_bit as usize) as u64;
+        block.uncompressed_len = (end_to_byte - block.to_byte) as u64;
+        self.compressed_bits += block.compressed_bits;
+        self.uncompressed_len += block.uncompressed_len;
+        Some(block)
+    }
+}
+
+// once any of a byte has been read, the reader is already past it, so the byte the bits are in is the one before.
+fn bit_offset(reader_byte: usize, reader_bit: u8) -> u64 {
+    match reader_bit {
+        0 => reader_byte as u64 * 8,
+        bit => (reader_byte as u64 - 1) * 8 + bit as u64,
+    }
+}
+
+fn ratio(compressed_bits: u64, uncompressed_len: u64) -> f64 {
+    if compressed_bits == 0 {
+        return 0.0;
+    }
+    uncompressed_len as f64 * 8.0 / compressed_bits as f64
+}
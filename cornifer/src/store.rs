@@ -0,0 +1,699 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use flate2::read::DeflateDecoder;
+use rusqlite::{types::Type, Connection, OpenFlags, OptionalExtension, Row};
+
+use crate::{
+    archive::ArchiveEntry,
+    errors::CorniferError,
+    checkpoint::tick_sidecar_path,
+    fetch::{self, CompressedRange},
+    huffman::{decode_trees, HuffmanTree},
+};
+
+/*
+ * Reads a checkpoint database back, so nothing outside of this file needs to know the schema
+ * (see setup_connection in checkpoint.rs for what each column is).
+ */
+
+/// A block as recorded in the checkpoint database.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Block {
+    pub id: i64,
+    pub from_byte: u64,
+    pub from_bit: u8,
+    pub to_byte: u64,
+    pub block_type: String,
+    pub crc32: u32,
+    pub len: u64,
+    pub header_len_bits: u64,
+    pub block_len_bits: u64,
+    /// Whether the block can be decoded on its own. DEFLATE blocks can't if their window was left out, and have
+    /// to be decoded from an earlier block that has one.
+    pub has_window: bool,
+    /// Whether it's the first block of a gzip member, which decodes without a window. These are the best places
+    /// to start decoding from, since nothing has to be stored for them.
+    pub member_start: bool,
+    /// CRC32 of the compressed bytes the block is in, if it was indexed with them hashed. See
+    /// `Deflator::hash_blocks`.
+    pub compressed_crc32: Option<u32>,
+}
+
+/// A checkpoint inside a block.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Tick {
+    pub id: i64,
+    pub from_byte: u64,
+    pub from_bit: u8,
+    pub to_byte: u64,
+    pub block_id: i64,
+}
+
+/// A gzip member as recorded in the checkpoint database.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Member {
+    pub id: i64,
+    pub from_byte: u64,
+    pub to_byte: u64,
+    pub len: u64,
+    pub crc32: u32,
+    pub name: Option<String>,
+    // seconds since the epoch, 0 if there isn't one.
+    pub mtime: u32,
+    /// How long the member's header is, so its DEFLATE data starts at `from_byte + header_len`. None for indexes
+    /// from before it was recorded.
+    pub header_len: Option<u64>,
+}
+
+impl Member {
+    /// Where the member is in the decompressed file.
+    pub fn uncompressed_range(&self) -> Range<u64> {
+        self.to_byte..self.to_byte + self.len
+    }
+
+    /// Where the member's DEFLATE data starts in the compressed file, if its header length was recorded.
+    pub fn data_from_byte(&self) -> Option<u64> {
+        self.header_len.map(|len| self.from_byte + len)
+    }
+}
+
+/// A corrupt gzip member that was skipped while indexing. See `DeflatorOptions::recover`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Gap {
+    pub id: i64,
+    pub from_byte: u64,
+    pub to_byte: u64,
+    /// The uncompressed bytes that can't be read back, because no checkpoint covers them.
+    pub uncompressed_from: u64,
+    pub uncompressed_to: u64,
+    pub error: String,
+}
+
+/// What the index says about the whole file.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct IndexMeta {
+    /// Whether the whole file was checkpointed. If not, the blocks only cover part of it.
+    pub complete: bool,
+    pub uncompressed_len: Option<u64>,
+    pub crc32: Option<u32>,
+}
+
+/// A file in a checkpoint file that has several. See `Checkpointer::set_source`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Source {
+    pub id: i64,
+    pub name: String,
+    pub meta: IndexMeta,
+}
+
+/// Where a complete gzip checkpoint file stops, for carrying on with the members added to the file since.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct IndexEnd {
+    /// Just after the last member's footer, in the compressed file.
+    pub compressed_byte: u64,
+    /// Length and CRC of everything decompressed up to there.
+    pub uncompressed_len: u64,
+    pub crc32: u32,
+    pub last_member: Member,
+}
+
+// crc32s are stored as hex strings.
+fn get_crc32(row: &Row, idx: usize) -> rusqlite::Result<u32> {
+    let s: String = row.get(idx)?;
+    u32::from_str_radix(&s, 16)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(idx, Type::Text, Box::new(e)))
+}
+
+// same, for a column that can be NULL.
+fn get_optional_crc32(row: &Row, idx: usize) -> rusqlite::Result<Option<u32>> {
+    match row.get::<_, Option<String>>(idx)? {
+        Some(_) => get_crc32(row, idx).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn get_source(row: &Row) -> rusqlite::Result<Source> {
+    let crc32: Option<String> = row.get(4)?;
+    Ok(Source {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        meta: IndexMeta {
+            complete: row.get(2)?,
+            uncompressed_len: row.get(3)?,
+            crc32: crc32.and_then(|crc32| u32::from_str_radix(&crc32, 16).ok()),
+        },
+    })
+}
+
+// from SELECT id, from_byte, from_bit, to_byte, block_id FROM Tick.
+fn get_tick(row: &Row) -> rusqlite::Result<Tick> {
+    Ok(Tick {
+        id: row.get(0)?,
+        from_byte: row.get(1)?,
+        from_bit: row.get(2)?,
+        to_byte: row.get(3)?,
+        block_id: row.get(4)?,
+    })
+}
+
+pub struct CheckpointStore {
+    conn: Connection,
+    // only read the rows for this file, if the database has several.
+    source: Option<Source>,
+    // for finding the tick sidecar, if the ticks are in one.
+    path: PathBuf,
+}
+
+impl CheckpointStore {
+    /// Open a checkpoint database for reading. If it has several files in it, that's all of them at once, which
+    /// is only any use for looking at them; `open_source` reads one of them.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, CorniferError> {
+        let conn = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        Ok(Self {
+            conn,
+            source: None,
+            path: path.as_ref().to_path_buf(),
+        })
+    }
+
+    /// Open the checkpoints for the file called `name`, in a checkpoint database with several files in it.
+    pub fn open_source<P: AsRef<Path>>(path: P, name: &str) -> Result<Self, CorniferError> {
+        let mut store = Self::open(path)?;
+        let unknown = || CorniferError::UnknownSource { name: name.to_string() };
+        store.source = Some(store.sources()?.into_iter().find(|s| s.name == name).ok_or_else(unknown)?);
+        Ok(store)
+    }
+
+    /// The files in the database, for one with several. Empty otherwise.
+    pub fn sources(&self) -> Result<Vec<Source>, CorniferError> {
+        if !self.has_table("Source")? {
+            return Ok(Vec::new());
+        }
+        let mut stmt = self.conn.prepare("SELECT id, name, complete, uncompressed_len, crc32 FROM Source ORDER BY id")?;
+        let sources = stmt.query_map((), get_source)?.collect::<Result<Vec<_>, _>>()?;
+        Ok(sources)
+    }
+
+    // the condition for a row to be for the file we're reading.
+    fn source_filter(&self) -> String {
+        match &self.source {
+            Some(source) => format!("source_id = {}", source.id),
+            None => "1".to_string(),
+        }
+    }
+
+    /// The blocks, in uncompressed order.
+    pub fn blocks(&self) -> Result<Vec<Block>, CorniferError> {
+        // older databases don't say which blocks start a member, and always stored a window for them.
+        let member_start = match self.has_column("DeflateBlock", "member_start")? {
+            true => "coalesce(member_start, 0)",
+            false => "0",
+        };
+        let compressed_crc32 = match self.has_column("DeflateBlock", "compressed_crc32")? {
+            true => "compressed_crc32",
+            false => "NULL",
+        };
+        // rows for blocks that never finished can't be read back, so leave them out.
+        let mut stmt = self.conn.prepare(&format!(
+            "
+            SELECT id, from_byte, from_bit, to_byte, block_type, crc32, len, header_len_bits, block_len_bits,
+                block_type NOT IN ('nocompression', 'fixed', 'dynamic') OR length(data) > 0 OR {member_start},
+                {member_start}, {compressed_crc32}
+            FROM DeflateBlock
+            WHERE len IS NOT NULL AND {}
+            ORDER BY to_byte, id
+        ",
+            self.source_filter()
+        ))?;
+        let blocks = stmt
+            .query_map((), |row| {
+                Ok(Block {
+                    id: row.get(0)?,
+                    from_byte: row.get(1)?,
+                    from_bit: row.get(2)?,
+                    to_byte: row.get(3)?,
+                    block_type: row.get(4)?,
+                    crc32: get_crc32(row, 5)?,
+                    len: row.get(6)?,
+                    header_len_bits: row.get(7)?,
+                    block_len_bits: row.get(8)?,
+                    has_window: row.get(9)?,
+                    member_start: row.get(10)?,
+                    compressed_crc32: get_optional_crc32(row, 11)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(blocks)
+    }
+
+    /// Where the block that decompresses to byte `pos` of the decompressed file starts in the compressed file,
+    /// as a byte and a bit. None if no finished block covers it. The block might need the ones before it to be
+    /// decoded first, see `Block::has_window`.
+    pub fn compressed_for_uncompressed(&self, pos: u64) -> Result<Option<(u64, u8)>, CorniferError> {
+        let found = self
+            .conn
+            .query_row(
+                &format!(
+                    "
+                SELECT from_byte, from_bit FROM DeflateBlock
+                WHERE len IS NOT NULL AND to_byte <= ?1 AND to_byte + len > ?1 AND {}
+                ORDER BY to_byte DESC, id DESC
+                LIMIT 1
+            ",
+                    self.source_filter()
+                ),
+                (pos,),
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        Ok(found)
+    }
+
+    /// The other way round: where the block that byte `pos` of the compressed file is in starts in the
+    /// decompressed file. None if it's not in a finished block, e.g. it's in a gzip header or footer.
+    pub fn uncompressed_for_compressed(&self, pos: u64) -> Result<Option<u64>, CorniferError> {
+        // a byte can have the end of one block and the start of the next in it, it's in the first of them.
+        let found = self
+            .conn
+            .query_row(
+                &format!(
+                    "
+                SELECT to_byte FROM DeflateBlock
+                WHERE len IS NOT NULL AND from_byte * 8 + from_bit <= ?1 * 8 + 7
+                    AND from_byte * 8 + from_bit + block_len_bits > ?1 * 8 AND {}
+                ORDER BY from_byte, from_bit, id
+                LIMIT 1
+            ",
+                    self.source_filter()
+                ),
+                (pos,),
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(found)
+    }
+
+    /// The parts of the compressed file needed to read `range` of the decompressed file, e.g. to fetch just those
+    /// from remote storage. Fails with `CorniferError::InGap` if some of it can't be read.
+    pub fn plan_fetch(&self, range: Range<u64>) -> Result<Vec<CompressedRange>, CorniferError> {
+        fetch::plan(&self.blocks()?, &self.gaps()?, range, |block| {
+            // ticks can only be decoded from with the block's trees, which older checkpoint files don't have.
+            match self.get_trees(block.id)? {
+                Some(_) => self.ticks_for(block),
+                None => Ok(Vec::new()),
+            }
+        })
+    }
+
+    /// The ticks inside a block, in uncompressed order.
+    pub fn ticks_for(&self, block: &Block) -> Result<Vec<Tick>, CorniferError> {
+        // older databases don't have the table at all.
+        if !self.has_table("Tick")? {
+            return Ok(Vec::new());
+        }
+        let mut stmt = self.conn.prepare(
+            "
+            SELECT id, from_byte, from_bit, to_byte, block_id
+            FROM Tick
+            WHERE block_id = ?1
+            ORDER BY to_byte
+        ",
+        )?;
+        let ticks = stmt
+            .query_map((block.id,), get_tick)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ticks)
+    }
+
+    /// The gzip members, in order. Empty for other formats.
+    pub fn members(&self) -> Result<Vec<Member>, CorniferError> {
+        if !self.has_table("GzipMember")? {
+            return Ok(Vec::new());
+        }
+        let header_len = match self.has_column("GzipMember", "header_len")? {
+            true => "header_len",
+            false => "NULL",
+        };
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT id, from_byte, to_byte, len, crc32, name, mtime, {header_len} FROM GzipMember WHERE {} ORDER BY to_byte",
+            self.source_filter()
+        ))?;
+        let members = stmt
+            .query_map((), |row| {
+                Ok(Member {
+                    id: row.get(0)?,
+                    from_byte: row.get(1)?,
+                    to_byte: row.get(2)?,
+                    len: row.get(3)?,
+                    crc32: get_crc32(row, 4)?,
+                    name: row.get(5)?,
+                    mtime: row.get(6)?,
+                    header_len: row.get(7)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(members)
+    }
+
+    /// The parts of the file skipped while indexing, in order. Empty for indexes from before they were recorded.
+    pub fn gaps(&self) -> Result<Vec<Gap>, CorniferError> {
+        if !self.has_table("Gap")? {
+            return Ok(Vec::new());
+        }
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT id, from_byte, to_byte, uncompressed_from, uncompressed_to, error FROM Gap WHERE {} ORDER BY from_byte",
+            self.source_filter()
+        ))?;
+        let gaps = stmt
+            .query_map((), |row| {
+                Ok(Gap {
+                    id: row.get(0)?,
+                    from_byte: row.get(1)?,
+                    to_byte: row.get(2)?,
+                    uncompressed_from: row.get(3)?,
+                    uncompressed_to: row.get(4)?,
+                    error: row.get(5)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(gaps)
+    }
+
+    /// The files found in a tar or zip, in the order they were found.
+    pub fn entries(&self) -> Result<Vec<ArchiveEntry>, CorniferError> {
+        if !self.has_table("ArchiveEntry")? {
+            return Ok(Vec::new());
+        }
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name, size, offset, mtime FROM ArchiveEntry ORDER BY id")?;
+        let entries = stmt
+            .query_map((), |row| {
+                Ok(ArchiveEntry {
+                    name: row.get(0)?,
+                    size: row.get(1)?,
+                    offset: row.get(2)?,
+                    mtime: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(entries)
+    }
+
+    /// What the index says about the whole file. Indexes from before this was recorded aren't complete.
+    pub fn meta(&self) -> Result<IndexMeta, CorniferError> {
+        // as of when it was opened, so it's looked up again in case it's still being written.
+        if let Some(source) = &self.source {
+            let source = self.conn.query_row(
+                "SELECT id, name, complete, uncompressed_len, crc32 FROM Source WHERE id = ?1",
+                (source.id,),
+                get_source,
+            )?;
+            return Ok(source.meta);
+        }
+        if !self.has_table("Meta")? {
+            return Ok(IndexMeta::default());
+        }
+        let mut meta = IndexMeta::default();
+        let mut stmt = self.conn.prepare("SELECT key, value FROM Meta")?;
+        let mut rows = stmt.query(())?;
+        while let Some(row) = rows.next()? {
+            let key: String = row.get(0)?;
+            let value: String = row.get(1)?;
+            match key.as_str() {
+                "complete" => meta.complete = value == "1",
+                "uncompressed_len" => meta.uncompressed_len = value.parse().ok(),
+                "crc32" => meta.crc32 = u32::from_str_radix(&value, 16).ok(),
+                _ => (),
+            }
+        }
+        Ok(meta)
+    }
+
+    /// Where the index stops. Only complete indexes of gzip files have one.
+    pub fn end(&self) -> Result<IndexEnd, CorniferError> {
+        let meta = self.meta()?;
+        let (Some(uncompressed_len), Some(crc32), true) = (meta.uncompressed_len, meta.crc32, meta.complete) else {
+            return Err(CorniferError::IndexIncomplete);
+        };
+        let (Some(last_member), Some(last_block)) = (self.members()?.pop(), self.blocks()?.pop()) else {
+            return Err(CorniferError::NotGzipIndex);
+        };
+        // the footer comes after the last block, once it's padded out to a whole byte.
+        let end_bit = last_block.from_byte * 8 + last_block.from_bit as u64 + last_block.block_len_bits;
+        Ok(IndexEnd {
+            compressed_byte: end_bit.div_ceil(8) + 8,
+            uncompressed_len,
+            crc32,
+            last_member,
+        })
+    }
+
+    /// The data column of a block, as it's stored.
+    pub fn block_data(&self, block_id: i64) -> Result<Vec<u8>, CorniferError> {
+        let data = self.conn.query_row(
+            "SELECT data FROM DeflateBlock WHERE id = ?1",
+            (block_id,),
+            |row| row.get(0),
+        )?;
+        Ok(data)
+    }
+
+    /// The uncompressed data before a DEFLATE block, which it needs to be decoded.
+    /// Other block types don't have one, and get their data column as-is. Empty if the window was left out,
+    /// see Block::has_window.
+    pub fn get_window(&self, block_id: i64) -> Result<Vec<u8>, CorniferError> {
+        let (block_type, data): (String, Vec<u8>) = self.conn.query_row(
+            "SELECT block_type, data FROM DeflateBlock WHERE id = ?1",
+            (block_id,),
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        match block_type.as_str() {
+            "nocompression" | "fixed" | "dynamic" if !data.is_empty() => {
+                let mut window = Vec::new();
+                DeflateDecoder::new(data.as_slice()).read_to_end(&mut window)?;
+                Ok(window)
+            }
+            _ => Ok(data),
+        }
+    }
+
+    /// The uncompressed data before a tick.
+    pub fn get_tick_window(&self, tick_id: i64) -> Result<Vec<u8>, CorniferError> {
+        let sidecar = match self.has_column("Tick", "sidecar_offset")? {
+            true => "sidecar_offset, sidecar_len",
+            false => "NULL, NULL",
+        };
+        let (data, sidecar_offset, sidecar_len): (Vec<u8>, Option<u64>, Option<usize>) = self.conn.query_row(
+            &format!("SELECT data, {sidecar} FROM Tick WHERE id = ?1"),
+            (tick_id,),
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+        // indexed with IndexOptions::tick_sidecar, so it's in <path>.ticks as it is.
+        if let (Some(offset), Some(len)) = (sidecar_offset, sidecar_len) {
+            let mut file = File::open(tick_sidecar_path(&self.path))?;
+            file.seek(SeekFrom::Start(offset))?;
+            let mut window = vec![0; len];
+            file.read_exact(&mut window)?;
+            return Ok(window);
+        }
+        let mut window = Vec::new();
+        DeflateDecoder::new(data.as_slice()).read_to_end(&mut window)?;
+        Ok(window)
+    }
+
+    /// The literal/length and distance trees a DEFLATE block was coded with, for decoding from a tick in the
+    /// middle of it. None for stored blocks, and for dynamic blocks in databases from before the trees were kept.
+    pub fn get_trees(&self, block_id: i64) -> Result<Option<(HuffmanTree, HuffmanTree)>, CorniferError> {
+        let has_trees: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM pragma_table_info('DeflateBlock') WHERE name = 'trees'",
+            (),
+            |row| row.get(0),
+        )?;
+        let (block_type, trees): (String, Option<Vec<u8>>) = if has_trees > 0 {
+            self.conn.query_row(
+                "SELECT block_type, trees FROM DeflateBlock WHERE id = ?1",
+                (block_id,),
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?
+        } else {
+            let block_type = self.conn.query_row(
+                "SELECT block_type FROM DeflateBlock WHERE id = ?1",
+                (block_id,),
+                |row| row.get(0),
+            )?;
+            (block_type, None)
+        };
+        match (block_type.as_str(), trees) {
+            ("fixed", _) => Ok(Some((HuffmanTree::fixed().clone(), HuffmanTree::fixed_dist().clone()))),
+            ("dynamic", Some(trees)) => decode_trees(&trees).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// The schema version the file was written with, see `schema::SCHEMA_VERSION`.
+    pub fn schema_version(&self) -> Result<u32, CorniferError> {
+        Ok(self.conn.query_row("PRAGMA user_version", (), |row| row.get(0))?)
+    }
+
+    /// The blocks that were started but never finished, e.g. by indexing that was stopped partway. `blocks` leaves
+    /// them out.
+    pub fn unfinished_blocks(&self) -> Result<Vec<i64>, CorniferError> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT id FROM DeflateBlock WHERE (len IS NULL OR crc32 IS NULL OR block_len_bits IS NULL) AND {} ORDER BY id",
+            self.source_filter()
+        ))?;
+        let ids = stmt.query_map((), |row| row.get(0))?.collect::<Result<Vec<_>, _>>()?;
+        Ok(ids)
+    }
+
+    /// Ticks whose block isn't in the database.
+    pub fn orphan_ticks(&self) -> Result<Vec<Tick>, CorniferError> {
+        if !self.has_table("Tick")? {
+            return Ok(Vec::new());
+        }
+        let mut stmt = self.conn.prepare(&format!(
+            "
+            SELECT id, from_byte, from_bit, to_byte, block_id
+            FROM Tick
+            WHERE block_id NOT IN (SELECT id FROM DeflateBlock) AND {}
+            ORDER BY id
+        ",
+            self.source_filter()
+        ))?;
+        let ticks = stmt
+            .query_map((), get_tick)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ticks)
+    }
+
+    fn has_column(&self, table: &str, column: &str) -> Result<bool, CorniferError> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM pragma_table_info(?1) WHERE name = ?2",
+            (table, column),
+            |row| row.get(0),
+        )?;
+        Ok(count > 0)
+    }
+
+    fn has_table(&self, name: &str) -> Result<bool, CorniferError> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            (name,),
+            |row| row.get(0),
+        )?;
+        Ok(count > 0)
+    }
+}
+
+/**
+ * TESTS
+ */
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+
+    use crc::{Crc, CRC_32_ISO_HDLC};
+    use flate2::{write::GzEncoder, Compression};
+    use rstest::rstest;
+
+    use crate::checkpoint::Checkpointer;
+    use crate::decompress::Deflator;
+    use crate::reader::CorniferByteReader;
+    use crate::testutil::{flushed_gzip, index_with_spacing, TempIndex};
+
+    use super::CheckpointStore;
+
+    const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+
+    #[rstest]
+    fn test_read_back_gzip() {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        // two members, the first with two blocks.
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(&text[..20000]).unwrap();
+        e.flush().unwrap();
+        e.write_all(&text[20000..]).unwrap();
+        let mut input = e.finish().unwrap();
+        let second_member_from = input.len() as u64;
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(b"hello").unwrap();
+        input.extend(e.finish().unwrap());
+
+        let path = TempIndex::new();
+        let mut deflator = Deflator::new(
+            CorniferByteReader::new(input.as_slice()),
+            Checkpointer::init(&path).unwrap(),
+        );
+        let mut out = Vec::new();
+        deflator.read_to_end(&mut out).unwrap();
+        drop(deflator);
+
+        let store = CheckpointStore::open(&path).unwrap();
+        let blocks = store.blocks().unwrap();
+        // the flush leaves an empty stored block behind it.
+        assert!(blocks.len() >= 3);
+        assert_eq!(blocks.iter().map(|b| b.len).sum::<u64>(), out.len() as u64);
+        let last = blocks.last().unwrap();
+        assert_eq!(last.crc32, CRC32.checksum(b"hello"));
+        assert_eq!(store.ticks_for(last).unwrap(), Vec::new());
+
+        let second = blocks
+            .iter()
+            .find(|b| b.to_byte == 20000 && b.len > 0)
+            .unwrap();
+        // the window only goes back as far as the start of the member.
+        let window = store.get_window(second.id).unwrap();
+        assert_eq!(window, &text[..20000]);
+        // so the first block of each member doesn't have one, and doesn't need one.
+        assert_eq!(store.get_window(blocks[0].id).unwrap(), Vec::<u8>::new());
+        assert_eq!(store.get_window(last.id).unwrap(), Vec::<u8>::new());
+        assert!(blocks[0].member_start && blocks[0].has_window);
+        assert!(!second.member_start);
+
+        let members = store.members().unwrap();
+        assert_eq!(members.len(), 2);
+        assert_eq!(
+            (members[0].from_byte, members[0].to_byte, members[0].len),
+            (0, 0, text.len() as u64)
+        );
+        assert_eq!(members[0].crc32, CRC32.checksum(text));
+        assert_eq!(
+            (members[1].from_byte, members[1].to_byte, members[1].len),
+            (second_member_from, text.len() as u64, 5)
+        );
+        // flate2 writes plain 10 byte headers, and each member's first block comes straight after.
+        assert_eq!(members[1].header_len, Some(10));
+        assert_eq!(last.from_byte, second_member_from + 10);
+        assert_eq!(members[1].data_from_byte(), Some(last.from_byte));
+        assert!(store.entries().unwrap().is_empty());
+    }
+
+    #[rstest]
+    fn test_offset_translation() {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let input = flushed_gzip(text, 1, 4000);
+        let path = index_with_spacing(&input, 0);
+
+        let store = CheckpointStore::open(&path).unwrap();
+        let blocks = store.blocks().unwrap();
+        for block in blocks.iter().filter(|b| b.len > 0) {
+            let from = Some((block.from_byte, block.from_bit));
+            assert_eq!(store.compressed_for_uncompressed(block.to_byte).unwrap(), from);
+            assert_eq!(store.compressed_for_uncompressed(block.to_byte + block.len - 1).unwrap(), from);
+        }
+        // the middle of a block, away from the bytes it shares with the blocks either side.
+        for block in blocks.iter().filter(|b| b.block_len_bits >= 24) {
+            let middle = (block.from_byte * 8 + block.from_bit as u64 + block.block_len_bits / 2) / 8;
+            assert_eq!(store.uncompressed_for_compressed(middle).unwrap(), Some(block.to_byte));
+        }
+        assert_eq!(store.compressed_for_uncompressed(text.len() as u64).unwrap(), None);
+        // the header and footer aren't in a block.
+        assert_eq!(store.uncompressed_for_compressed(0).unwrap(), None);
+        assert_eq!(store.uncompressed_for_compressed(input.len() as u64 - 1).unwrap(), None);
+    }
+}
@@ -0,0 +1,89 @@
+use std::io::{self, Read, Seek, SeekFrom};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/**
+ * A reader that sleeps to keep its average throughput under a limit, so indexing a big file
+ * on shared storage doesn't hog it. The limit is averaged over the whole time since the
+ * reader was created.
+ */
+pub struct ThrottledReader<R> {
+    inner: R,
+    // None means no limit.
+    bytes_per_second: Option<f64>,
+    start: Instant,
+    bytes_read: u64,
+}
+
+impl<R> ThrottledReader<R> {
+    pub fn new(inner: R, bytes_per_second: Option<f64>) -> Self {
+        Self {
+            inner,
+            bytes_per_second,
+            start: Instant::now(),
+            bytes_read: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for ThrottledReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(rate) = self.bytes_per_second.filter(|_| !buf.is_empty()) else {
+            return self.inner.read(buf);
+        };
+        // read a tenth of a second's worth at most, so the sleeps stay short and even.
+        let max_len = ((rate / 10.0) as usize).clamp(1, buf.len());
+        let n = self.inner.read(&mut buf[..max_len])?;
+        self.bytes_read += n as u64;
+
+        let due = Duration::from_secs_f64(self.bytes_read as f64 / rate);
+        let elapsed = self.start.elapsed();
+        if due > elapsed {
+            sleep(due - elapsed);
+        }
+        Ok(n)
+    }
+}
+
+impl<R: Seek> Seek for ThrottledReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+/**
+ * TESTS
+ */
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+    use std::time::{Duration, Instant};
+
+    use rstest::rstest;
+
+    use super::ThrottledReader;
+
+    #[rstest]
+    fn test_throttle() {
+        let input = [7u8; 2000];
+        let mut reader = ThrottledReader::new(input.as_slice(), Some(10_000.0));
+        let start = Instant::now();
+        let mut dest = Vec::new();
+        reader.read_to_end(&mut dest).unwrap();
+
+        assert_eq!(dest, input);
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[rstest]
+    fn test_no_limit() {
+        let input = [7u8; 2000];
+        let mut reader = ThrottledReader::new(input.as_slice(), None);
+        let mut dest = vec![0; 4000];
+        assert_eq!(reader.read(&mut dest).unwrap(), 2000);
+    }
+}
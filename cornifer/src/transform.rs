@@ -0,0 +1,163 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+/**
+ * A reader that undoes a wrapper around the compressed file as it's read, e.g. XOR with a key or AES-CTR, so
+ * decrypt-then-inflate doesn't need a decrypted copy of the file first. It goes between the file and the
+ * CorniferByteReader (or CheckpointedReader), which only ever see the bytes underneath.
+ *
+ * Transforms are told where in the file each piece is, so they work with seeking as long as any byte can be
+ * worked out from its offset alone, which is true of stream ciphers in counter mode but not of CBC.
+ */
+pub trait ByteTransform {
+    /// Turn `buf`, which was read from `offset` in the wrapped file, into the bytes underneath, in place.
+    fn apply(&mut self, offset: u64, buf: &mut [u8]);
+}
+
+// a closure will do for a one-off.
+impl<F: FnMut(u64, &mut [u8])> ByteTransform for F {
+    fn apply(&mut self, offset: u64, buf: &mut [u8]) {
+        self(offset, buf)
+    }
+}
+
+/// XOR with a key that repeats from the start of the file. An empty key leaves the bytes alone.
+#[derive(Debug, Clone)]
+pub struct Xor {
+    key: Vec<u8>,
+}
+
+impl Xor {
+    pub fn new(key: &[u8]) -> Self {
+        Self { key: key.to_vec() }
+    }
+}
+
+impl ByteTransform for Xor {
+    fn apply(&mut self, offset: u64, buf: &mut [u8]) {
+        if self.key.is_empty() {
+            return;
+        }
+        let start = (offset % self.key.len() as u64) as usize;
+        for (byte, key) in buf.iter_mut().zip(self.key.iter().cycle().skip(start)) {
+            *byte ^= key;
+        }
+    }
+}
+
+pub struct TransformReader<R, T> {
+    inner: R,
+    transform: T,
+    // where the next read is from in the wrapped file.
+    pos: u64,
+}
+
+impl<R, T: ByteTransform> TransformReader<R, T> {
+    /// Undo `transform` on everything read from `inner`, which has to be at the start of the file.
+    pub fn new(inner: R, transform: T) -> Self {
+        Self::at(inner, transform, 0)
+    }
+
+    /// Like new, for `inner` that's already `pos` bytes into the file.
+    pub fn at(inner: R, transform: T, pos: u64) -> Self {
+        Self { inner, transform, pos }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read, T: ByteTransform> Read for TransformReader<R, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.transform.apply(self.pos, &mut buf[..n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Seek, T: ByteTransform> Seek for TransformReader<R, T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = self.inner.seek(pos)?;
+        Ok(self.pos)
+    }
+}
+
+/**
+ * TESTS
+ */
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    use rstest::rstest;
+
+    use super::{ByteTransform, TransformReader, Xor};
+    use crate::checkpoint::Checkpointer;
+    use crate::decompress::Deflator;
+    use crate::reader::CorniferByteReader;
+
+    // something like a stream cipher in counter mode: each byte's mask depends only on its offset.
+    fn keystream(offset: u64, buf: &mut [u8]) {
+        for (i, byte) in buf.iter_mut().enumerate() {
+            let n = (offset + i as u64).wrapping_mul(0x9e3779b97f4a7c15);
+            *byte ^= (n >> 56) as u8;
+        }
+    }
+
+    fn wrapped(transform: &mut impl ByteTransform) -> Vec<u8> {
+        let mut input = include_bytes!("../testfiles/1080-0.txt.gz").to_vec();
+        // the transforms here are their own inverse.
+        transform.apply(0, &mut input);
+        input
+    }
+
+    #[rstest]
+    #[case::xor(Box::new(Xor::new(b"not a real key")))]
+    #[case::keystream(Box::new(keystream))]
+    #[case::empty_key(Box::new(Xor::new(b"")))]
+    fn test_decompress_wrapped(#[case] mut transform: Box<dyn ByteTransform>) {
+        let input = wrapped(&mut |offset, buf: &mut [u8]| transform.apply(offset, buf));
+        let reader = TransformReader::new(input.as_slice(), move |offset, buf: &mut [u8]| transform.apply(offset, buf));
+        let mut deflator = Deflator::new(CorniferByteReader::new(reader), Checkpointer::none());
+        let mut dest = Vec::new();
+        deflator.read_to_end(&mut dest).unwrap();
+        assert_eq!(dest, include_bytes!("../testfiles/1080-0.txt"));
+    }
+
+    // reads after a seek are undone from the right place in the key.
+    #[rstest]
+    fn test_seek() {
+        let plain = include_bytes!("../testfiles/1080-0.txt.gz");
+        let input = wrapped(&mut Xor::new(b"abc"));
+        let mut reader = TransformReader::new(Cursor::new(input), Xor::new(b"abc"));
+        for at in [100, 7, 1000, 0] {
+            reader.seek(SeekFrom::Start(at)).unwrap();
+            let mut dest = [0; 10];
+            reader.read_exact(&mut dest).unwrap();
+            assert_eq!(&dest, &plain[at as usize..at as usize + 10]);
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[rstest]
+    fn test_checkpointed_wrapped() {
+        use crate::checkpointed::CheckpointedReader;
+        use crate::testutil::TempIndex;
+
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let input = wrapped(&mut keystream);
+        let path = TempIndex::new();
+        let reader = TransformReader::new(input.as_slice(), keystream);
+        let mut deflator = Deflator::new(CorniferByteReader::new(reader), Checkpointer::init(&path).unwrap());
+        std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+        drop(deflator);
+
+        let reader = TransformReader::new(Cursor::new(input), keystream);
+        let mut checkpointed = CheckpointedReader::open(reader, &path).unwrap();
+        checkpointed.seek(SeekFrom::Start(20000)).unwrap();
+        let mut dest = vec![0; 500];
+        checkpointed.read_exact(&mut dest).unwrap();
+        assert_eq!(dest, &text[20000..20500]);
+    }
+}
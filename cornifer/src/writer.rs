@@ -0,0 +1,211 @@
+use std::io::{self, Write};
+
+use crc::{Crc, Digest, CRC_32_ISO_HDLC};
+use flate2::{Compress, Compression, FlushCompress, Status};
+
+use crate::checkpoint::{Checkpointer, IndependentBlock};
+use crate::circle::crc32_combine;
+use crate::decompress::GzipMember;
+use crate::errors::CorniferError;
+use crate::header::{ExtraFlag, GzipHeader, OperatingSystem, GZIP_HEADER};
+
+/*
+ * Writing gzip that's quick to read back from anywhere. Every so often there's a full flush, after which nothing
+ * refers back to what came before, so decoding can start there without a window. The flush points go straight
+ * into a checkpoint file as they're written, so there's no indexing the file afterwards.
+ *
+ * Each row is the run of DEFLATE blocks between two flush points, with the block type "flushed" and no window.
+ * A full flush costs a few bytes, plus the matches that would have reached back past it, so with a flush every
+ * megabyte or so the file is hardly any bigger.
+ */
+
+static CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// How much uncompressed data `GzipWriter` puts between flush points, unless told otherwise.
+pub const DEFAULT_FLUSH_INTERVAL: u64 = 1 << 20;
+
+// how much compressed data is written out at once.
+const OUT_CHUNK: usize = 64 * 1024;
+
+/// Writes one gzip member, with a full flush every `interval` bytes of uncompressed data, and checkpoints each
+/// run of blocks between them. `finish` has to be called at the end to write the footer.
+pub struct GzipWriter<W: Write> {
+    inner: W,
+    compress: Compress,
+    out: Vec<u8>,
+    checkpointer: Checkpointer,
+    interval: u64,
+    // the whole member so far.
+    len: u64,
+    crc32: u32,
+    // the run of blocks since the last flush.
+    run: Digest<'static, u32>,
+    run_len: u64,
+    run_from_byte: u64,
+}
+
+impl<W: Write> GzipWriter<W> {
+    pub fn new(mut inner: W, checkpointer: Checkpointer, level: Compression, interval: u64) -> io::Result<Self> {
+        inner.write_all(&GZIP_HEADER)?;
+        Ok(Self {
+            inner,
+            compress: Compress::new(level, false),
+            out: Vec::with_capacity(OUT_CHUNK),
+            checkpointer,
+            interval: interval.max(1),
+            len: 0,
+            crc32: 0,
+            run: CRC32.digest(),
+            run_len: 0,
+            run_from_byte: GZIP_HEADER.len() as u64,
+        })
+    }
+
+    /// Where we are in the compressed file.
+    pub fn compressed_len(&self) -> u64 {
+        GZIP_HEADER.len() as u64 + self.compress.total_out()
+    }
+
+    // compress `input`, and write out whatever comes out.
+    fn deflate(&mut self, mut input: &[u8], flush: FlushCompress) -> io::Result<()> {
+        loop {
+            self.out.clear();
+            let before = self.compress.total_in();
+            let status = self.compress.compress_vec(input, &mut self.out, flush).map_err(io::Error::other)?;
+            input = &input[(self.compress.total_in() - before) as usize..];
+            self.inner.write_all(&self.out)?;
+            // like zlib, there's nothing more to come once there's room left over.
+            let done = match flush {
+                FlushCompress::Finish => status == Status::StreamEnd,
+                _ => input.is_empty() && self.out.len() < self.out.capacity(),
+            };
+            if done {
+                return Ok(());
+            }
+        }
+    }
+
+    // end the run of blocks, and checkpoint it.
+    fn end_run(&mut self, flush: FlushCompress) -> Result<(), CorniferError> {
+        self.deflate(&[], flush)?;
+        let to_byte = self.compressed_len();
+        let crc32 = std::mem::replace(&mut self.run, CRC32.digest()).finalize();
+        // nothing to read back in an empty one, e.g. at the end of a file that's a whole number of runs long.
+        if self.run_len > 0 {
+            self.checkpointer.on_independent_block(
+                "flushed",
+                IndependentBlock {
+                    from_byte: self.run_from_byte as usize,
+                    from_bit: 0,
+                    to_byte: (self.len - self.run_len) as usize,
+                    header_len_bits: 0,
+                    block_len_bits: ((to_byte - self.run_from_byte) * 8) as isize,
+                    len: self.run_len as usize,
+                    crc32,
+                    data: Vec::new(),
+                },
+            )?;
+        }
+        self.crc32 = crc32_combine(self.crc32, crc32, self.run_len);
+        self.run_from_byte = to_byte;
+        self.run_len = 0;
+        Ok(())
+    }
+
+    /// Write the last of the data and the footer, and mark the checkpoint file as complete. Gives back the
+    /// writer, and the member that was written.
+    pub fn finish(mut self) -> Result<(W, GzipMember), CorniferError> {
+        self.end_run(FlushCompress::Finish)?;
+        self.inner.write_all(&self.crc32.to_le_bytes())?;
+        self.inner.write_all(&(self.len as u32).to_le_bytes())?;
+        self.inner.flush()?;
+        let member = GzipMember {
+            from_byte: 0,
+            to_byte: 0,
+            header: GzipHeader {
+                text: false,
+                name: None,
+                comment: None,
+                mtime: 0,
+                extra: ExtraFlag::Unknown,
+                os: OperatingSystem::Unknown,
+                extra_fields: Vec::new(),
+                has_hcrc: false,
+                span: 0..GZIP_HEADER.len(),
+                raw: GZIP_HEADER.to_vec(),
+            },
+            crc32: self.crc32,
+            len: self.len,
+            crc32_matches: true,
+            isize_matches: true,
+            hcrc_matches: true,
+        };
+        self.checkpointer.on_member_end(&member)?;
+        self.checkpointer.finalize(self.len, self.crc32)?;
+        Ok((self.inner, member))
+    }
+}
+
+impl<W: Write> Write for GzipWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // only up to the next flush point, so every run is the same length.
+        let n = (self.interval - self.run_len).min(buf.len() as u64) as usize;
+        self.deflate(&buf[..n], FlushCompress::None)?;
+        self.run.update(&buf[..n]);
+        self.run_len += n as u64;
+        self.len += n as u64;
+        if self.run_len == self.interval {
+            self.end_run(FlushCompress::Full).map_err(io::Error::other)?;
+        }
+        Ok(n)
+    }
+
+    // only flushes the writer underneath, since flushing the compressor would make a flush point out of place.
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/**
+ * TESTS
+ */
+#[cfg(all(test, feature = "sqlite"))]
+mod test {
+    use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+    use flate2::{read::GzDecoder, Compression};
+    use rstest::rstest;
+
+    use crate::{checkpoint::Checkpointer, checkpointed::CheckpointedReader, testutil::TempIndex};
+
+    use super::GzipWriter;
+
+    #[rstest]
+    #[case::runs(4096)]
+    #[case::exact(39819)]
+    #[case::one_run(1 << 20)]
+    fn test_write_and_read_back(#[case] interval: u64) {
+        let text = include_bytes!("../testfiles/1080-0.txt");
+        let path = TempIndex::new();
+        let mut writer = GzipWriter::new(Vec::new(), Checkpointer::init(&path).unwrap(), Compression::default(), interval).unwrap();
+        // in uneven pieces, so they don't line up with the flush points.
+        for part in text.chunks(3000) {
+            writer.write_all(part).unwrap();
+        }
+        let (output, member) = writer.finish().unwrap();
+        assert_eq!(member.len, text.len() as u64);
+
+        let mut dest = Vec::new();
+        GzDecoder::new(output.as_slice()).read_to_end(&mut dest).unwrap();
+        assert_eq!(dest, text);
+
+        let mut reader = CheckpointedReader::open(Cursor::new(output.as_slice()), &path).unwrap();
+        assert_eq!(reader.blocks().len() as u64, (text.len() as u64).div_ceil(interval));
+        assert!(reader.blocks().iter().all(|b| b.block_type == "flushed" && b.from_bit == 0 && b.has_window));
+        reader.verify().unwrap();
+        reader.seek(SeekFrom::Start(30000)).unwrap();
+        let mut dest = vec![0; 5000];
+        reader.read_exact(&mut dest).unwrap();
+        assert_eq!(dest, &text[30000..35000]);
+    }
+}
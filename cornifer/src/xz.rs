@@ -0,0 +1,365 @@
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+
+use crc::{Crc, CRC_32_ISO_HDLC};
+use xz2::read::XzDecoder;
+
+use crate::checkpoint::{Checkpointer, IndependentBlock};
+use crate::errors::CorniferError;
+
+/*
+ * xz files already carry an index of their blocks at the end of each stream, so unlike gzip
+ * and bzip2 we don't need to scan the compressed data to find them. We walk the streams
+ * backwards from the end of the file, reading each stream's footer and then its index.
+ *
+ * Like bzip2, blocks don't depend on each other, and a single block is decoded by wrapping
+ * it up in a standalone stream. The block's check type is only in the stream header, so the
+ * stream header gets stored alongside each block in place of a window.
+ */
+
+static CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+const HEADER_MAGIC: [u8; 6] = [0xFD, b'7', b'z', b'X', b'Z', 0x00];
+const FOOTER_MAGIC: [u8; 2] = [b'Y', b'Z'];
+pub const STREAM_HEADER_LEN: usize = 12;
+const STREAM_FOOTER_LEN: usize = 12;
+
+/// A block, as described by its stream's index.
+#[derive(Debug, PartialEq, Clone)]
+pub struct XzBlock {
+    pub from_byte: u64,
+    // size of the block header, compressed data and check, but not the padding.
+    pub unpadded_size: u64,
+    pub uncompressed_size: u64,
+    pub stream_header: [u8; STREAM_HEADER_LEN],
+}
+
+/// What we found out about the decompressed file while indexing it.
+#[derive(Debug, PartialEq)]
+pub struct XzSummary {
+    pub crc32: u32,
+    pub len: usize,
+    pub blocks: usize,
+}
+
+/// Blocks and the index are padded to a multiple of four bytes.
+fn padded(size: u64) -> u64 {
+    size.div_ceil(4) * 4
+}
+
+/// Keeps a CRC32 of everything read through it.
+struct Crc32Reader<R> {
+    inner: R,
+    digest: crc::Digest<'static, u32>,
+}
+
+impl<R: Read> Read for Crc32Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.digest.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Read one byte of the index of the stream at `position`. Running out means the index is shorter than it says.
+fn read_index_byte<R: Read>(reader: &mut R, position: u64) -> Result<u8, CorniferError> {
+    let mut byte = [0];
+    match reader.read_exact(&mut byte) {
+        Ok(()) => Ok(byte[0]),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Err(CorniferError::InvalidXzIndex { position }),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Read a variable length integer, 7 bits at a time, least significant first.
+fn read_multibyte<R: Read>(reader: &mut R, position: u64) -> Result<u64, CorniferError> {
+    let mut value = 0;
+    for i in 0..9 {
+        let byte = read_index_byte(reader, position)?;
+        value |= ((byte & 0x7F) as u64) << (i * 7);
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(CorniferError::InvalidXzIndex { position })
+}
+
+fn write_multibyte(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_at<R: Read + Seek>(
+    source: &mut R,
+    position: u64,
+    buf: &mut [u8],
+) -> Result<(), CorniferError> {
+    source.seek(SeekFrom::Start(position))?;
+    source.read_exact(buf)?;
+    Ok(())
+}
+
+fn stored_crc(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes.try_into().expect("CRC32 is always four bytes"))
+}
+
+/// Parse a stream index, returning the (unpadded size, uncompressed size) of each block. It's read as it's
+/// parsed rather than all at once, since its length comes from the footer and can say anything up to 16 GiB.
+fn read_stream_index<R: Read + Seek>(
+    source: &mut R,
+    index_start: u64,
+    index_len: u64,
+) -> Result<Vec<(u64, u64)>, CorniferError> {
+    let invalid = CorniferError::InvalidXzIndex {
+        position: index_start,
+    };
+    source.seek(SeekFrom::Start(index_start))?;
+    let mut contents = Crc32Reader {
+        inner: BufReader::new(source.by_ref().take(index_len - 4)),
+        digest: CRC32.digest(),
+    };
+    if read_index_byte(&mut contents, index_start)? != 0 {
+        return Err(invalid);
+    }
+
+    let count = read_multibyte(&mut contents, index_start)?;
+    let mut records = Vec::new();
+    for _ in 0..count {
+        let unpadded_size = read_multibyte(&mut contents, index_start)?;
+        let uncompressed_size = read_multibyte(&mut contents, index_start)?;
+        records.push((unpadded_size, uncompressed_size));
+    }
+    // then up to three bytes of padding, which leaves the reader at the CRC.
+    let mut padding = Vec::new();
+    contents.by_ref().take(4).read_to_end(&mut padding)?;
+    if padding.len() > 3 || padding.iter().any(|b| *b != 0) {
+        return Err(invalid);
+    }
+    let crc32 = contents.digest.finalize();
+    let mut stored = [0; 4];
+    source.read_exact(&mut stored)?;
+    if crc32 != stored_crc(&stored) {
+        return Err(invalid);
+    }
+    Ok(records)
+}
+
+/// Find all the blocks in an xz file (which may contain several concatenated streams) using the stream indexes.
+pub fn read_index<R: Read + Seek>(source: &mut R) -> Result<Vec<XzBlock>, CorniferError> {
+    let mut end = source.seek(SeekFrom::End(0))?;
+    let mut streams = Vec::new();
+    while end > 0 {
+        // streams can be followed by padding, in multiples of four null bytes.
+        let mut word = [0; 4];
+        if end >= 4 {
+            read_at(source, end - 4, &mut word)?;
+            if word == [0; 4] {
+                end -= 4;
+                continue;
+            }
+        }
+        if end < (STREAM_HEADER_LEN + STREAM_FOOTER_LEN) as u64 {
+            return Err(CorniferError::NotXzFooter { position: end });
+        }
+
+        let footer_start = end - STREAM_FOOTER_LEN as u64;
+        let mut footer = [0; STREAM_FOOTER_LEN];
+        read_at(source, footer_start, &mut footer)?;
+        if footer[10..12] != FOOTER_MAGIC
+            || CRC32.checksum(&footer[4..10]) != stored_crc(&footer[0..4])
+        {
+            return Err(CorniferError::NotXzFooter {
+                position: footer_start,
+            });
+        }
+        // backward size is stored as (real size / 4) - 1
+        let index_len = (stored_crc(&footer[4..8]) as u64 + 1) * 4;
+        let index_start =
+            footer_start
+                .checked_sub(index_len)
+                .ok_or(CorniferError::NotXzFooter {
+                    position: footer_start,
+                })?;
+        let records = read_stream_index(source, index_start, index_len)?;
+
+        let blocks_len: u64 = records.iter().map(|(unpadded, _)| padded(*unpadded)).sum();
+        let stream_start = index_start
+            .checked_sub(blocks_len + STREAM_HEADER_LEN as u64)
+            .ok_or(CorniferError::InvalidXzIndex {
+                position: index_start,
+            })?;
+        let mut stream_header = [0; STREAM_HEADER_LEN];
+        read_at(source, stream_start, &mut stream_header)?;
+        if stream_header[0..6] != HEADER_MAGIC
+            || stream_header[6..8] != footer[8..10]
+            || CRC32.checksum(&stream_header[6..8]) != stored_crc(&stream_header[8..12])
+        {
+            return Err(CorniferError::NotXzHeader {
+                position: stream_start,
+            });
+        }
+
+        let mut from_byte = stream_start + STREAM_HEADER_LEN as u64;
+        let blocks: Vec<XzBlock> = records
+            .into_iter()
+            .map(|(unpadded_size, uncompressed_size)| {
+                let block = XzBlock {
+                    from_byte,
+                    unpadded_size,
+                    uncompressed_size,
+                    stream_header,
+                };
+                from_byte += padded(unpadded_size);
+                block
+            })
+            .collect();
+        streams.push(blocks);
+        end = stream_start;
+    }
+
+    Ok(streams.into_iter().rev().flatten().collect())
+}
+
+/// Decode a single block by wrapping it up in a standalone single-block stream.
+pub fn decode_block<R: Read + Seek>(
+    source: &mut R,
+    block: &XzBlock,
+) -> Result<Vec<u8>, CorniferError> {
+    let mut stream = block.stream_header.to_vec();
+    stream.resize(STREAM_HEADER_LEN + padded(block.unpadded_size) as usize, 0);
+    read_at(source, block.from_byte, &mut stream[STREAM_HEADER_LEN..])?;
+
+    // an index with just this block in it.
+    let mut index = vec![0x00];
+    write_multibyte(&mut index, 1);
+    write_multibyte(&mut index, block.unpadded_size);
+    write_multibyte(&mut index, block.uncompressed_size);
+    index.resize(padded(index.len() as u64) as usize, 0);
+    index.extend(CRC32.checksum(&index).to_le_bytes());
+
+    let mut footer = ((index.len() / 4 - 1) as u32).to_le_bytes().to_vec();
+    footer.extend(&block.stream_header[6..8]);
+    stream.extend(index);
+    stream.extend(CRC32.checksum(&footer).to_le_bytes());
+    stream.extend(footer);
+    stream.extend(FOOTER_MAGIC);
+
+    let mut out = Vec::new();
+    XzDecoder::new(stream.as_slice())
+        .read_to_end(&mut out)
+        .map_err(|_| CorniferError::InvalidXzBlock {
+            position: block.from_byte,
+        })?;
+    Ok(out)
+}
+
+/// Write a checkpoint for every block in an xz file. Each block is decoded along the way, so
+/// it's checked against the index and its CRC32 can be recorded.
+pub fn index<R: Read + Seek>(
+    source: &mut R,
+    checkpointer: &mut Checkpointer,
+) -> Result<XzSummary, CorniferError> {
+    let blocks = read_index(source)?;
+    let mut digest = CRC32.digest();
+    let mut to_byte = 0;
+
+    for block in &blocks {
+        // the block header size is the first byte of the block, as (real size / 4) - 1
+        let mut header_size = [0; 1];
+        read_at(source, block.from_byte, &mut header_size)?;
+        let header_len = (header_size[0] as usize + 1) * 4;
+
+        let data = decode_block(source, block)?;
+        digest.update(&data);
+        checkpointer.on_independent_block(
+            "xz",
+            IndependentBlock {
+                from_byte: block.from_byte as usize,
+                from_bit: 0,
+                to_byte,
+                header_len_bits: header_len as isize * 8,
+                block_len_bits: block.unpadded_size as isize * 8,
+                len: data.len(),
+                crc32: CRC32.checksum(&data),
+                data: block.stream_header.to_vec(),
+            },
+        )?;
+        to_byte += data.len();
+    }
+
+    let crc32 = digest.finalize();
+    checkpointer.finalize(to_byte as u64, crc32)?;
+    Ok(XzSummary {
+        crc32,
+        len: to_byte,
+        blocks: blocks.len(),
+    })
+}
+
+/**
+ * TESTS
+ */
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use rstest::rstest;
+
+    use crate::errors::CorniferError;
+
+    use super::{decode_block, read_index, CRC32};
+
+    #[rstest]
+    fn test_read_index_across_streams() {
+        let input: &[u8] = include_bytes!("../testfiles/1080-0-anthems.txt.xz");
+        let blocks = read_index(&mut Cursor::new(input)).unwrap();
+        // as reported by xz -lvv
+        let offsets: Vec<(u64, u64)> = blocks
+            .iter()
+            .map(|b| (b.from_byte, b.uncompressed_size))
+            .collect();
+        assert_eq!(
+            offsets,
+            vec![(12, 16384), (6832, 16384), (12868, 7051), (15924, 1983)]
+        );
+        assert_eq!(blocks[0].unpadded_size, 12 + 6797 + 8);
+    }
+
+    #[rstest]
+    fn test_decode_block() {
+        let input: &[u8] = include_bytes!("../testfiles/1080-0-anthems.txt.xz");
+        let mut source = Cursor::new(input);
+        let blocks = read_index(&mut source).unwrap();
+
+        let data = decode_block(&mut source, &blocks[1]).unwrap();
+        assert_eq!(
+            data,
+            &include_bytes!("../testfiles/1080-0.txt")[16384..32768]
+        );
+        // this one is in the second stream, with a different check type.
+        let data = decode_block(&mut source, &blocks[3]).unwrap();
+        assert_eq!(data, include_bytes!("../testfiles/anthems.txt"));
+    }
+
+    // the backward size in the footer is all there is to go on for where the index starts, so a corrupt one
+    // can point anywhere before it.
+    #[rstest]
+    fn test_read_index_bails_on_bad_backward_size() {
+        let mut input = include_bytes!("../testfiles/1080-0-anthems.txt.xz").to_vec();
+        let footer = input.len() - 12;
+        input[footer + 4..footer + 8].copy_from_slice(&2000u32.to_le_bytes());
+        let crc = CRC32.checksum(&input[footer + 4..footer + 10]);
+        input[footer..footer + 4].copy_from_slice(&crc.to_le_bytes());
+        let result = read_index(&mut Cursor::new(input));
+        assert!(matches!(result, Err(CorniferError::InvalidXzIndex { .. })), "{result:?}");
+    }
+
+    #[rstest]
+    fn test_read_index_bails_on_non_xz_file() {
+        let input: &[u8] = include_bytes!("../testfiles/1080-0.txt.gz");
+        let result = read_index(&mut Cursor::new(input));
+        assert!(matches!(result, Err(CorniferError::NotXzFooter { .. })));
+    }
+}
@@ -0,0 +1,91 @@
+/*
+ * Shared by the unit tests, the conformance tests, the benchmarks and cornifer-cli's tests. All but the conformance
+ * tests pull this file in with #[path], since nothing outside tests/ can see it otherwise.
+ */
+#![allow(dead_code)]
+
+use std::io::Write;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+use cornifer::checkpoint::Checkpointer;
+use cornifer::decompress::Deflator;
+use cornifer::reader::CorniferByteReader;
+use flate2::{write::GzEncoder, Compression};
+
+/// A fresh path for a checkpoint file in the temp directory. Dropping it deletes the file, along with the
+/// `.tmp` and `.ticks` files and sqlite's journals that get written next to it.
+pub struct TempIndex {
+    path: PathBuf,
+}
+
+impl TempIndex {
+    pub fn new() -> Self {
+        let path = std::env::temp_dir().join(format!("cornifer-test-{}.sqlite3", rand::random::<u64>()));
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Another file next to the checkpoint file, e.g. `with_suffix(".tmp")`.
+    pub fn with_suffix(&self, suffix: &str) -> PathBuf {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(suffix);
+        PathBuf::from(name)
+    }
+}
+
+impl Default for TempIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for TempIndex {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TempIndex {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempIndex {
+    fn drop(&mut self) {
+        for suffix in ["", ".tmp", ".ticks", ".tmp.ticks", "-journal", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(self.with_suffix(suffix));
+        }
+    }
+}
+
+/// `text` gzipped `members` times over, one member after another, and flushed every `flush_every` bytes so
+/// there's a block boundary there.
+pub fn flushed_gzip(text: &[u8], members: usize, flush_every: usize) -> Vec<u8> {
+    let mut input = Vec::new();
+    for _ in 0..members {
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        for chunk in text.chunks(flush_every) {
+            e.write_all(chunk).unwrap();
+            e.flush().unwrap();
+        }
+        input.extend(e.finish().unwrap());
+    }
+    input
+}
+
+/// An index of the whole of `input`, with a window every `spacing` bytes (0 for every block).
+pub fn index_with_spacing(input: &[u8], spacing: u64) -> TempIndex {
+    let path = TempIndex::new();
+    let mut checkpointer = Checkpointer::init(&path).unwrap();
+    checkpointer.set_window_spacing(spacing);
+    let mut deflator = Deflator::new(CorniferByteReader::new(input), checkpointer);
+    std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+    path
+}
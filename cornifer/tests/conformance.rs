@@ -0,0 +1,165 @@
+/*
+ * Checks every checkpoint in the indexes of the bundled testfiles: restoring each one and decoding up to the
+ * next has to give the same bytes flate2 does, with the CRC that was recorded. Also checks the checkpoint
+ * database's schema and the blocks found in a couple of the files against the golden files in tests/golden/,
+ * so the format doesn't change by accident.
+ *
+ * If it's meant to change, run with CORNIFER_UPDATE_GOLDEN=1 to write the golden files again, and check the diff.
+ */
+use std::io::Read;
+use std::path::Path;
+
+use crc::{Crc, CRC_32_ISO_HDLC};
+use flate2::read::MultiGzDecoder;
+use rstest::rstest;
+
+mod common;
+
+use common::TempIndex;
+use cornifer::{
+    checkpoint::Checkpointer,
+    decompress::Deflator,
+    reader::CorniferByteReader,
+    store::{Block, CheckpointStore},
+};
+
+const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+// a tick every this many bytes, so the big blocks have a few.
+const TICK_EVERY: usize = 5000;
+
+fn index(input: &[u8]) -> TempIndex {
+    let path = TempIndex::new();
+    let mut deflator = Deflator::new(CorniferByteReader::new(input), Checkpointer::init(&path).unwrap());
+    deflator.set_tick_trigger(|context| context.since_checkpoint >= TICK_EVERY);
+    std::io::copy(&mut deflator, &mut std::io::sink()).unwrap();
+    path
+}
+
+fn read_exactly<R: Read>(reader: &mut R, len: u64) -> Vec<u8> {
+    let mut data = vec![0; len as usize];
+    reader.read_exact(&mut data).unwrap();
+    data
+}
+
+// the first bit of a block's header says whether it's the last block in its member.
+fn is_final(input: &[u8], block: &Block) -> bool {
+    (input[block.from_byte as usize] >> block.from_bit) & 1 == 1
+}
+
+#[rstest]
+#[case::single_block("1080-0.txt.gz")]
+#[case::anthems("anthems.txt.gz")]
+#[case::members("testCompressThenConcat.txt.gz")]
+#[case::hello_world("helloworld.gz")]
+#[case::tar("texts.tar.gz")]
+fn test_every_checkpoint_restores(#[case] file_name: &str) {
+    let input = std::fs::read(Path::new("testfiles").join(file_name)).unwrap();
+    let mut expected = Vec::new();
+    MultiGzDecoder::new(input.as_slice()).read_to_end(&mut expected).unwrap();
+    let path = index(&input);
+    let store = CheckpointStore::open(&path).unwrap();
+
+    let blocks = store.blocks().unwrap();
+    assert_eq!(blocks.iter().map(|b| b.len).sum::<u64>(), expected.len() as u64);
+    let mut ticks_checked = 0;
+    for block in &blocks {
+        let from = block.to_byte as usize;
+        let to = from + block.len as usize;
+        let window = store.get_window(block.id).unwrap();
+        let reader = CorniferByteReader::new(&input[block.from_byte as usize..]);
+        let mut deflator = Deflator::resume(reader, block.from_bit, &window, Checkpointer::none()).unwrap();
+        let data = read_exactly(&mut deflator, block.len);
+        assert_eq!(CRC32.checksum(&data), block.crc32, "block {} has the wrong CRC", block.id);
+        assert_eq!(data, &expected[from..to], "block {}", block.id);
+
+        // each tick decodes up to the next one, or the end of the block.
+        let ticks = store.ticks_for(block).unwrap();
+        let ends = ticks.iter().skip(1).map(|t| t.to_byte as usize).chain([to]);
+        for (tick, end) in ticks.iter().zip(ends) {
+            let window = store.get_tick_window(tick.id).unwrap();
+            let trees = store.get_trees(block.id).unwrap().expect("Ticks are only in Huffman coded blocks");
+            let reader = CorniferByteReader::new(&input[tick.from_byte as usize..]);
+            let mut deflator =
+                Deflator::resume_in_block(reader, tick.from_bit, &window, trees, is_final(&input, block), Checkpointer::none())
+                    .unwrap();
+            let data = read_exactly(&mut deflator, (end - tick.to_byte as usize) as u64);
+            assert_eq!(data, &expected[tick.to_byte as usize..end], "tick {}", tick.id);
+            ticks_checked += 1;
+        }
+    }
+    if expected.len() > 2 * TICK_EVERY {
+        assert!(ticks_checked > 0);
+    }
+}
+
+// compare with a file in tests/golden/, or write it if CORNIFER_UPDATE_GOLDEN is set.
+fn check_golden(name: &str, actual: &str) {
+    let path = Path::new("tests/golden").join(name);
+    if std::env::var_os("CORNIFER_UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+    let golden = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(actual, golden, "{} has changed, see the top of tests/conformance.rs", path.display());
+}
+
+#[rstest]
+fn test_schema() {
+    let input = std::fs::read("testfiles/1080-0.txt.gz").unwrap();
+    let path = index(&input);
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    // the columns rather than the CREATE statements, so reformatting the SQL doesn't count as a change.
+    let mut stmt = conn
+        .prepare(
+            "
+            SELECT m.name, c.name, c.type, c.\"notnull\", c.pk
+            FROM sqlite_master m JOIN pragma_table_info(m.name) c
+            WHERE m.type = 'table' AND m.name NOT LIKE 'sqlite_%'
+            ORDER BY m.name, c.cid
+        ",
+        )
+        .unwrap();
+    let columns = stmt
+        .query_map((), |row| {
+            let (table, column, kind, not_null, pk): (String, String, String, bool, i64) =
+                (row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?);
+            let mut line = format!("{table}.{column} {kind}");
+            if not_null {
+                line.push_str(" NOT NULL");
+            }
+            if pk > 0 {
+                line.push_str(" PRIMARY KEY");
+            }
+            Ok(line + "\n")
+        })
+        .unwrap()
+        .collect::<Result<String, _>>()
+        .unwrap();
+    check_golden("schema.txt", &columns);
+    let version: u32 = conn.query_row("PRAGMA user_version", (), |row| row.get(0)).unwrap();
+    assert_eq!(version, cornifer::schema::SCHEMA_VERSION);
+}
+
+#[rstest]
+#[case("1080-0.txt.gz")]
+#[case("testCompressThenConcat.txt.gz")]
+fn test_blocks(#[case] file_name: &str) {
+    let input = std::fs::read(Path::new("testfiles").join(file_name)).unwrap();
+    let path = index(&input);
+    let store = CheckpointStore::open(&path).unwrap();
+    let mut lines = String::new();
+    for block in store.blocks().unwrap() {
+        lines += &format!(
+            "block {}:{} -> {} {} len {} crc32 {:08x} header {} bits, {} bits\n",
+            block.from_byte, block.from_bit, block.to_byte, block.block_type, block.len, block.crc32, block.header_len_bits, block.block_len_bits
+        );
+        for tick in store.ticks_for(&block).unwrap() {
+            lines += &format!("  tick {}:{} -> {}\n", tick.from_byte, tick.from_bit, tick.to_byte);
+        }
+    }
+    for member in store.members().unwrap() {
+        lines += &format!("member {} -> {} len {} crc32 {:08x}\n", member.from_byte, member.to_byte, member.len, member.crc32);
+    }
+    check_golden(&format!("{file_name}.txt"), &lines);
+}
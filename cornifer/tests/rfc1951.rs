@@ -0,0 +1,118 @@
+/*
+ * Raw deflate streams from zlib's test/infcover.c, each wrapped in a gzip member, with what zlib's inflate makes
+ * of them. In strict mode the Deflator has to accept and reject exactly the streams zlib does, and give the same
+ * reason when it's a dynamic block header that's wrong. The default mode lets some of the bad headers through, and
+ * the streams fail later on instead; which check catches them is pinned too, so it doesn't change by accident.
+ */
+use std::io::Read;
+
+use crc::{Crc, CRC_32_ISO_HDLC};
+use rstest::rstest;
+
+use cornifer::{
+    checkpoint::Checkpointer,
+    decompress::{Deflator, DeflatorOptions},
+    errors::CorniferError,
+    reader::CorniferByteReader,
+};
+
+const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+// ok streams have the length and CRC of what they decompress to, the rest the message zlib fails with.
+type Zlib = Result<(usize, u32), &'static str>;
+
+fn parse_hex(hex: &str) -> Vec<u8> {
+    hex.split_whitespace().map(|b| u8::from_str_radix(b, 16).unwrap()).collect()
+}
+
+fn gzip(raw: &[u8], zlib: Zlib) -> Vec<u8> {
+    let mut input = vec![0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff];
+    input.extend_from_slice(raw);
+    let (len, crc) = zlib.unwrap_or_default();
+    input.extend(crc.to_le_bytes());
+    input.extend((len as u32).to_le_bytes());
+    input
+}
+
+fn decode(input: &[u8], strict: bool) -> Result<Vec<u8>, CorniferError> {
+    let mut deflator = Deflator::new(CorniferByteReader::new(input), Checkpointer::none());
+    deflator.set_options(DeflatorOptions {
+        strict,
+        ..Default::default()
+    });
+    let mut out = Vec::new();
+    match deflator.read_to_end(&mut out) {
+        Ok(_) => Ok(out),
+        Err(e) => Err(*e.into_inner().unwrap().downcast::<CorniferError>().unwrap()),
+    }
+}
+
+// the window end stream is a stored block and then a long run of zero bits.
+fn window_end() -> String {
+    format!("ed c0 81 0 0 0 0 80 a0 fd a9 17 a9 {}6", "0 ".repeat(87))
+}
+
+#[rstest]
+#[case::fixed("3 0", Ok((0, 0)))]
+#[case::stored("1 1 0 fe ff 0", Ok((1, 0xd202ef8d)))]
+#[case::split_window_update("63 18 68 30 d0 0 0", Ok((257, 0xb7bfa0ce)))]
+#[case::window_wrap("63 18 5 40 c 0", Ok((262, 0xb8ea39f0)))]
+#[case::pull_17("5 c0 21 d 0 0 0 80 b0 fe 6d 2f 91 6c", Ok((0, 0)))]
+#[case::long_code("5 e0 81 91 24 cb b2 2c 49 e2 f 2e 8b 9a 47 56 9f fb fe ec d2 ff 1f", Ok((0, 0)))]
+#[case::length_extra("ed c0 1 1 0 0 0 40 20 ff 57 1b 42 2c 4f", Ok((516, 0xac727b17)))]
+#[case::long_distance_and_extra(
+    "ed cf c1 b1 2c 47 10 c4 30 fa 6f 35 1d 1 82 59 3d fb be 2e 2a fc f c",
+    Ok((518, 0x838d92af))
+)]
+#[case::window_end(&window_end(), Ok((89785, 0x8dc40bfc)))]
+#[case::fast_type_return("2 8 20 80 0 3 0", Ok((0, 0)))]
+#[case::bad_stored_lengths("0 0 0 0 0", Err("invalid stored block lengths"))]
+#[case::bad_block_type("6", Err("invalid block type"))]
+#[case::too_many_symbols("fc 0 0", Err("too many length or distance symbols"))]
+#[case::incomplete_code_lengths("4 0 fe ff", Err("invalid code lengths set"))]
+#[case::repeat_past_the_end("4 0 24 49 0", Err("invalid bit length repeat"))]
+#[case::repeat_past_the_end_2("4 0 24 e9 ff ff", Err("invalid bit length repeat"))]
+#[case::missing_end_of_block("4 0 24 e9 ff 6d", Err("invalid code -- missing end-of-block"))]
+#[case::bad_literal_lengths("4 80 49 92 24 49 92 24 71 ff ff 93 11 0", Err("invalid literal/lengths set"))]
+#[case::bad_distances("4 80 49 92 24 49 92 24 f b4 ff ff c3 84", Err("invalid distances set"))]
+#[case::bad_literal_length_code("4 c0 81 8 0 0 0 0 20 7f eb b 0 0", Err("invalid literal/length code"))]
+#[case::bad_distance_code("2 7e ff ff", Err("invalid distance code"))]
+#[case::too_far_back("c c0 81 0 0 0 0 0 90 ff 6b 4 0", Err("invalid distance too far back"))]
+fn test_strict_agrees_with_zlib(#[case] raw: &str, #[case] zlib: Zlib) {
+    let input = gzip(&parse_hex(raw), zlib);
+    match (decode(&input, true), zlib) {
+        (Ok(out), Ok((len, crc))) => {
+            assert_eq!(out.len(), len);
+            assert_eq!(CRC32.checksum(&out), crc);
+        }
+        (Err(CorniferError::InvalidDynamicBlockHeader { reason, .. }), Err(message)) => assert_eq!(reason, message),
+        (Err(_), Err(_)) => {}
+        (got, _) => panic!("zlib says {zlib:?}, got {got:?}"),
+    }
+}
+
+// without strict, the dynamic block headers zlib rejects get through unless they're over-subscribed or repeat past
+// the end, and then the streams fail later on, for other reasons.
+#[rstest]
+#[case::too_many_symbols("fc 0 0", None)]
+#[case::incomplete_code_lengths("4 0 fe ff", None)]
+#[case::repeat_past_the_end_2("4 0 24 e9 ff ff", Some("invalid bit length repeat"))]
+#[case::missing_end_of_block("4 0 24 e9 ff 6d", None)]
+#[case::bad_literal_lengths("4 80 49 92 24 49 92 24 71 ff ff 93 11 0", Some("invalid literal/lengths set"))]
+#[case::bad_distances("4 80 49 92 24 49 92 24 f b4 ff ff c3 84", None)]
+#[case::too_far_back("c c0 81 0 0 0 0 0 90 ff 6b 4 0", None)]
+fn test_default_mode(#[case] raw: &str, #[case] header_error: Option<&str>) {
+    let input = gzip(&parse_hex(raw), Err(""));
+    match decode(&input, false) {
+        Err(CorniferError::InvalidDynamicBlockHeader { reason, .. }) => assert_eq!(Some(reason), header_error),
+        Err(_) => assert_eq!(header_error, None),
+        Ok(out) => panic!("decoded {} bytes", out.len()),
+    }
+}
+
+#[rstest]
+fn test_too_far_back() {
+    let input = gzip(&parse_hex("c c0 81 0 0 0 0 0 90 ff 6b 4 0"), Err(""));
+    let err = decode(&input, true).unwrap_err();
+    assert!(matches!(err, CorniferError::DistanceTooFarBack { available: 0, .. }), "{err:?}");
+}
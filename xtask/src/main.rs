@@ -0,0 +1,63 @@
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode, Stdio};
+
+/*
+ * Jobs for working on cornifer that aren't building or testing it, run with `cargo xtask <job>`.
+ *
+ *   assets [DIR]   man pages and shell completions for packages, in DIR (target/assets by default). They're made
+ *                  by the cornifer binary itself, from its clap definitions, so it's built first.
+ */
+
+const SHELLS: [(&str, &str); 3] = [("bash", "cornifer.bash"), ("zsh", "_cornifer"), ("fish", "cornifer.fish")];
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        ["assets"] => assets(&workspace_root().join("target/assets")),
+        ["assets", dir] => assets(Path::new(dir)),
+        _ => Err("usage: cargo xtask assets [DIR]".to_string()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn workspace_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).parent().expect("xtask is in the workspace").to_path_buf()
+}
+
+fn assets(dir: &Path) -> Result<(), String> {
+    let man = dir.join("man");
+    let completions = dir.join("completions");
+    for dir in [&man, &completions] {
+        std::fs::create_dir_all(dir).map_err(|e| format!("couldn't create {}: {e}", dir.display()))?;
+    }
+    cornifer(&[OsStr::new("man"), OsStr::new("--out-dir"), man.as_os_str()], None)?;
+    for (shell, file_name) in SHELLS {
+        let path = completions.join(file_name);
+        let file = std::fs::File::create(&path).map_err(|e| format!("couldn't create {}: {e}", path.display()))?;
+        cornifer(&[OsStr::new("completions"), OsStr::new(shell)], Some(file))?;
+    }
+    println!("Wrote the man pages to {} and the completions to {}", man.display(), completions.display());
+    Ok(())
+}
+
+// run the cornifer binary, built from this workspace, with `args`.
+fn cornifer(args: &[&OsStr], stdout: Option<std::fs::File>) -> Result<(), String> {
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let mut command = Command::new(cargo);
+    command.current_dir(workspace_root()).args(["run", "--quiet", "--package", "cornifer-cli", "--"]).args(args);
+    if let Some(file) = stdout {
+        command.stdout(Stdio::from(file));
+    }
+    match command.status() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(format!("cornifer {args:?} failed: {status}")),
+        Err(e) => Err(format!("couldn't run cargo: {e}")),
+    }
+}